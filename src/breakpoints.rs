@@ -0,0 +1,1099 @@
+//! Breakpoints requested by the debug client, and the hardware units they use on the target.
+
+use crate::debug_info::DebugInfo;
+use crate::dwt::{self, Access};
+use crate::exceptions::{self, ExceptionFilter};
+use crate::target::CoreAccess;
+use debugserver_types::{
+    Breakpoint, BreakpointEventBody, DataBreakpoint, DataBreakpointAccessType, FunctionBreakpoint,
+    Source, SourceBreakpoint,
+};
+use log::{debug, info, warn};
+use probe_rs::Architecture;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// How a breakpoint was requested by the client.
+#[derive(Debug)]
+enum Request {
+    Source {
+        source: Box<Source>,
+        info: SourceBreakpoint,
+    },
+    Function(FunctionBreakpoint),
+    Data(DataBreakpoint),
+    /// A breakpoint on a function of an exception filter.
+    Exception(&'static ExceptionFilter),
+}
+
+impl Request {
+    fn condition(&self) -> Option<&str> {
+        let condition = match self {
+            Request::Source { info, .. } => info.condition.as_deref(),
+            Request::Function(info) => info.condition.as_deref(),
+            Request::Data(info) => info.condition.as_deref(),
+            Request::Exception(_) => None,
+        };
+
+        condition.filter(|condition| !condition.trim().is_empty())
+    }
+
+    fn hit_condition(&self) -> Option<&str> {
+        let hit_condition = match self {
+            Request::Source { info, .. } => info.hit_condition.as_deref(),
+            Request::Function(info) => info.hit_condition.as_deref(),
+            Request::Data(info) => info.hit_condition.as_deref(),
+            Request::Exception(_) => None,
+        };
+
+        hit_condition.filter(|hit_condition| !hit_condition.trim().is_empty())
+    }
+
+    fn log_message(&self) -> Option<&str> {
+        match self {
+            Request::Source { info, .. } => info.log_message.as_deref(),
+            Request::Function(_) | Request::Data(_) | Request::Exception(_) => None,
+        }
+    }
+}
+
+/// A breakpoint the core halted at, with the settings which decide if the core stays halted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HitBreakpoint {
+    pub id: i64,
+    /// Expression which has to be true for the breakpoint to stop.
+    pub condition: Option<String>,
+    /// Condition on the number of hits, like `>= 10` or `% 2`.
+    pub hit_condition: Option<String>,
+    /// Message to log instead of stopping, for logpoints.
+    pub log_message: Option<String>,
+}
+
+#[derive(Debug)]
+struct BreakpointInfo {
+    id: i64,
+    request: Request,
+    /// Addresses of the breakpoint. A function breakpoint can match several functions.
+    addresses: Vec<u32>,
+    /// Set once the breakpoint is armed on the target.
+    verified: bool,
+    /// Reason why the breakpoint could not be set.
+    message: Option<String>,
+    /// Number of times the breakpoint was hit with a true condition.
+    hits: u64,
+}
+
+impl BreakpointInfo {
+    fn to_breakpoint(&self) -> Breakpoint {
+        let (source, line, column) = match &self.request {
+            Request::Source { source, info } => {
+                (Some((**source).clone()), Some(info.line), info.column)
+            }
+            Request::Function(_) | Request::Data(_) | Request::Exception(_) => (None, None, None),
+        };
+
+        Breakpoint {
+            id: Some(self.id),
+            column,
+            end_column: None,
+            line,
+            end_line: None,
+            message: self.message.clone(),
+            verified: self.verified,
+            source,
+        }
+    }
+
+    fn to_hit(&self) -> HitBreakpoint {
+        HitBreakpoint {
+            id: self.id,
+            condition: self.request.condition().map(str::to_owned),
+            hit_condition: self.request.hit_condition().map(str::to_owned),
+            log_message: self.request.log_message().map(str::to_owned),
+        }
+    }
+
+    fn get_event_body(&self) -> BreakpointEventBody {
+        BreakpointEventBody {
+            reason: "changed".to_owned(),
+            breakpoint: self.to_breakpoint(),
+        }
+    }
+}
+
+/// The `BKPT #0` instruction in Thumb mode.
+const THUMB_BKPT: [u8; 2] = [0x00, 0xbe];
+
+#[derive(Debug)]
+enum BreakpointKind {
+    /// The breakpoint uses a unit of the Flash Patch and Breakpoint unit.
+    Hardware,
+    /// The code at the address is replaced by a `BKPT` instruction.
+    Software { original: Vec<u8> },
+}
+
+#[derive(Debug)]
+struct ArmedBreakpoint {
+    kind: BreakpointKind,
+    /// Number of breakpoints at this address.
+    users: usize,
+}
+
+#[derive(Debug)]
+struct Comparators {
+    /// The id of the data breakpoint using each comparator.
+    users: Vec<Option<i64>>,
+    /// The largest mask the comparators support.
+    max_mask: u32,
+    version: dwt::Version,
+}
+
+#[derive(Debug, Default)]
+pub struct Breakpoints {
+    /// Breakpoints of each source file, as last set by the client.
+    source_breakpoints: HashMap<PathBuf, Vec<BreakpointInfo>>,
+    /// Function breakpoints, as last set by the client.
+    function_breakpoints: Vec<BreakpointInfo>,
+    /// Data breakpoints, as last set by the client.
+    data_breakpoints: Vec<BreakpointInfo>,
+    /// Breakpoints on the functions of the enabled exception filters. They are not
+    /// visible to the client.
+    exception_breakpoints: Vec<BreakpointInfo>,
+    /// Vector catch bits of the enabled exception filters.
+    vector_catch: u32,
+    /// Breakpoints which are set on the target, by address.
+    armed: HashMap<u32, ArmedBreakpoint>,
+    /// Number of hardware breakpoint units of the core, read when they are first needed.
+    hardware_units: Option<u32>,
+    /// Comparators of the DWT, read when they are first needed.
+    comparators: Option<Comparators>,
+    /// Breakpoint used internally while stepping.
+    temporary: Option<u32>,
+    next_id: i64,
+}
+
+impl Breakpoints {
+    /// Replace the breakpoints of the source file at `path` with `requested`.
+    ///
+    /// Breakpoints which were already set keep their id, breakpoints which are no longer
+    /// requested are removed from the target. Without a core, new breakpoints stay
+    /// pending until [`set_pending`](Breakpoints::set_pending) is called.
+    pub fn set_source_breakpoints(
+        &mut self,
+        core: Option<&mut dyn CoreAccess>,
+        debug_info: Option<&DebugInfo>,
+        path: &Path,
+        source: &Source,
+        requested: &[SourceBreakpoint],
+    ) -> Vec<Breakpoint> {
+        let previous = self.source_breakpoints.remove(path).unwrap_or_default();
+
+        let breakpoints = self.replace(
+            core,
+            previous,
+            requested,
+            |request, bp| {
+                matches!(request, Request::Source { info, .. }
+                    if info.line == bp.line && info.column == bp.column)
+            },
+            |bp| Request::Source {
+                source: Box::new(source.clone()),
+                info: bp.clone(),
+            },
+            |bp| {
+                let address = debug_info.and_then(|di| {
+                    di.get_breakpoint_location(path, bp.line as u64, bp.column.map(|c| c as u64))
+                        .unwrap_or(None)
+                });
+
+                match address {
+                    Some(address) => {
+                        debug!("Found source location: {:#010x}!", address);
+                        Ok(vec![address as u32])
+                    }
+                    None => {
+                        warn!("Failed to find location for breakpoint {:?}", bp);
+                        Err("No code found for this location".to_owned())
+                    }
+                }
+            },
+        );
+
+        let response = breakpoints.iter().map(|bp| bp.to_breakpoint()).collect();
+
+        self.source_breakpoints.insert(path.to_owned(), breakpoints);
+
+        response
+    }
+
+    /// Replace the function breakpoints with `requested`. The names are resolved like
+    /// described for [`DebugInfo::find_functions`], and the breakpoints are placed
+    /// after the prologue of every matching function.
+    pub fn set_function_breakpoints(
+        &mut self,
+        core: Option<&mut dyn CoreAccess>,
+        debug_info: Option<&DebugInfo>,
+        requested: &[FunctionBreakpoint],
+    ) -> Vec<Breakpoint> {
+        let previous = std::mem::take(&mut self.function_breakpoints);
+
+        let breakpoints = self.replace(
+            core,
+            previous,
+            requested,
+            |request, bp| matches!(request, Request::Function(info) if info.name == bp.name),
+            |bp| Request::Function(bp.clone()),
+            |bp| {
+                let functions = match debug_info.map(|di| di.find_functions(&bp.name)) {
+                    Some(Ok(functions)) => functions,
+                    Some(Err(e)) => {
+                        warn!("Failed to find functions for '{}': {}", bp.name, e);
+                        Vec::new()
+                    }
+                    None => Vec::new(),
+                };
+
+                if functions.is_empty() {
+                    return Err(format!("No function found for '{}'", bp.name));
+                }
+
+                Ok(functions
+                    .iter()
+                    .map(|function| {
+                        debug!(
+                            "Found function {} at {:#010x}, breakpoint at {:#010x}",
+                            function.name, function.address, function.breakpoint_address
+                        );
+
+                        function.breakpoint_address as u32
+                    })
+                    .collect())
+            },
+        );
+
+        let response = breakpoints.iter().map(|bp| bp.to_breakpoint()).collect();
+
+        self.function_breakpoints = breakpoints;
+
+        response
+    }
+
+    /// Enable the exception filters `filters`, and disable all others.
+    ///
+    /// Returns messages for the user about filters which can't be enabled. Without a
+    /// core, the filters are enabled by [`set_pending`](Breakpoints::set_pending).
+    pub fn set_exception_breakpoints(
+        &mut self,
+        mut core: Option<&mut dyn CoreAccess>,
+        debug_info: Option<&DebugInfo>,
+        filters: &[&'static ExceptionFilter],
+    ) -> Vec<String> {
+        let previous = std::mem::take(&mut self.exception_breakpoints);
+        let requested: Vec<_> = filters
+            .iter()
+            .copied()
+            .filter(|filter| !filter.functions.is_empty())
+            .collect();
+
+        let breakpoints = self.replace(
+            core.as_deref_mut().map(|core| core as &mut dyn CoreAccess),
+            previous,
+            &requested,
+            |request, filter| matches!(request, Request::Exception(f) if f.filter == filter.filter),
+            |filter| Request::Exception(filter),
+            |filter| {
+                let addresses: Vec<u32> = filter
+                    .functions
+                    .iter()
+                    .filter_map(|name| debug_info?.find_functions(name).ok())
+                    .flatten()
+                    .map(|function| function.breakpoint_address as u32)
+                    .collect();
+
+                if addresses.is_empty() {
+                    return Err(format!(
+                        "No function found for the exception filter '{}'",
+                        filter.label
+                    ));
+                }
+
+                Ok(addresses)
+            },
+        );
+
+        let mut messages: Vec<String> = breakpoints
+            .iter()
+            .filter_map(|bp| bp.message.clone())
+            .collect();
+
+        self.exception_breakpoints = breakpoints;
+        self.vector_catch = filters
+            .iter()
+            .fold(0, |bits, filter| bits | filter.vector_catch);
+
+        if let Some(core) = core {
+            if let Err(e) = self.set_vector_catch(core) {
+                messages.push(format!("Unable to enable the vector catch: {}", e));
+            }
+        }
+
+        messages
+    }
+
+    /// The exception filter of the breakpoints `ids`, if one of them belongs to one.
+    pub fn exception_filter(&self, ids: &[i64]) -> Option<&'static ExceptionFilter> {
+        self.exception_breakpoints
+            .iter()
+            .filter(|bp| ids.contains(&bp.id))
+            .find_map(|bp| match bp.request {
+                Request::Exception(filter) => Some(filter),
+                _ => None,
+            })
+    }
+
+    fn set_vector_catch(&mut self, core: &mut dyn CoreAccess) -> Result<(), probe_rs::Error> {
+        let demcr = core.read_word_32(dwt::DEMCR)?;
+        let value = (demcr & !exceptions::VECTOR_CATCH_MASK) | self.vector_catch;
+
+        if value != demcr {
+            debug!("Setting vector catch to {:#x}", self.vector_catch);
+            core.write_word_32(dwt::DEMCR, value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Replace the data breakpoints with `requested`. The data is identified by the
+    /// ids returned by [`data_id`], and each data breakpoint uses as many DWT
+    /// comparators as are needed to watch exactly the bytes of the data.
+    pub fn set_data_breakpoints(
+        &mut self,
+        mut core: Option<&mut dyn CoreAccess>,
+        requested: &[DataBreakpoint],
+    ) -> Vec<Breakpoint> {
+        let mut previous = std::mem::take(&mut self.data_breakpoints);
+
+        // All comparators are released first, so that they can be distributed again.
+        if let (Some(comparators), Some(core)) = (&mut self.comparators, core.as_deref_mut()) {
+            for bp in &previous {
+                if let Err(e) = release(core, comparators, bp.id) {
+                    warn!("Failed to remove data breakpoint {}: {}", bp.id, e);
+                }
+            }
+        }
+
+        let mut breakpoints = Vec::with_capacity(requested.len());
+
+        for bp in requested {
+            // A breakpoint on the same data keeps its id and hit count.
+            let kept = previous.iter().position(|p| {
+                matches!(&p.request, Request::Data(info)
+                    if info.data_id == bp.data_id && info.access_type == bp.access_type)
+            });
+
+            let mut breakpoint = match kept {
+                Some(index) => previous.swap_remove(index),
+                None => {
+                    let id = self.next_id;
+                    self.next_id += 1;
+
+                    BreakpointInfo {
+                        id,
+                        request: Request::Data(bp.clone()),
+                        addresses: Vec::new(),
+                        verified: false,
+                        message: None,
+                        hits: 0,
+                    }
+                }
+            };
+
+            breakpoint.request = Request::Data(bp.clone());
+            breakpoint.verified = false;
+            breakpoint.message = None;
+
+            match core.as_deref_mut() {
+                Some(core) => match self.arm_data(core, breakpoint.id, bp) {
+                    Ok(()) => breakpoint.verified = true,
+                    Err(message) => breakpoint.message = Some(message),
+                },
+                // Without a core, the breakpoint stays pending.
+                None => {
+                    if let Err(message) = parse_data_id(&bp.data_id) {
+                        breakpoint.message = Some(message);
+                    }
+                }
+            }
+
+            breakpoints.push(breakpoint);
+        }
+
+        let response = breakpoints.iter().map(|bp| bp.to_breakpoint()).collect();
+
+        self.data_breakpoints = breakpoints;
+
+        response
+    }
+
+    /// Replace the breakpoints `previous` with `requested`. Breakpoints for which `same`
+    /// finds a previous one are kept, the addresses of new breakpoints are found with
+    /// `resolve`, which returns a message for the user if there are none.
+    fn replace<R>(
+        &mut self,
+        mut core: Option<&mut dyn CoreAccess>,
+        mut previous: Vec<BreakpointInfo>,
+        requested: &[R],
+        same: impl Fn(&Request, &R) -> bool,
+        request: impl Fn(&R) -> Request,
+        mut resolve: impl FnMut(&R) -> Result<Vec<u32>, String>,
+    ) -> Vec<BreakpointInfo> {
+        // Breakpoints which are requested again are kept as they are.
+        let kept: Vec<Option<BreakpointInfo>> = requested
+            .iter()
+            .map(|bp| {
+                previous
+                    .iter()
+                    .position(|p| same(&p.request, bp))
+                    .map(|index| previous.swap_remove(index))
+            })
+            .collect();
+
+        // All remaining breakpoints were removed by the client. They are removed first,
+        // so that their hardware units are available for the new ones.
+        for removed in previous {
+            if let (true, Some(core)) = (removed.verified, core.as_deref_mut()) {
+                for address in removed.addresses {
+                    debug!("Removing breakpoint at {:#010x}", address);
+
+                    if let Err(e) = self.disarm(core, address) {
+                        warn!("Failed to remove breakpoint at {:#010x}: {}", address, e);
+                    }
+                }
+            }
+        }
+
+        let mut breakpoints = Vec::with_capacity(requested.len());
+
+        for (bp, kept) in requested.iter().zip(kept) {
+            if let Some(mut existing) = kept {
+                existing.request = request(bp);
+
+                // Hardware units might have become available in the meantime.
+                if let (false, false, Some(core)) = (
+                    existing.addresses.is_empty(),
+                    existing.verified,
+                    core.as_deref_mut(),
+                ) {
+                    if self.arm_all(core, &existing.addresses).is_ok() {
+                        existing.verified = true;
+                        existing.message = None;
+                    }
+                }
+
+                breakpoints.push(existing);
+                continue;
+            }
+
+            let id = self.next_id;
+            self.next_id += 1;
+
+            let mut breakpoint = BreakpointInfo {
+                id,
+                request: request(bp),
+                addresses: Vec::new(),
+                verified: false,
+                message: None,
+                hits: 0,
+            };
+
+            match resolve(bp) {
+                Ok(addresses) => {
+                    if let Some(core) = core.as_deref_mut() {
+                        match self.arm_all(core, &addresses) {
+                            Ok(()) => breakpoint.verified = true,
+                            Err(message) => breakpoint.message = Some(message),
+                        }
+                    }
+
+                    breakpoint.addresses = addresses;
+                }
+                Err(message) => breakpoint.message = Some(message),
+            }
+
+            breakpoints.push(breakpoint);
+        }
+
+        breakpoints
+    }
+
+    /// Arm breakpoints which were requested before the target was available.
+    ///
+    /// Returns the bodies of the `breakpoint` events for all breakpoints which changed.
+    pub fn set_pending(&mut self, core: &mut dyn CoreAccess) -> Vec<BreakpointEventBody> {
+        let mut source_breakpoints = std::mem::take(&mut self.source_breakpoints);
+        let mut function_breakpoints = std::mem::take(&mut self.function_breakpoints);
+
+        let mut events = Vec::new();
+
+        for bp in source_breakpoints
+            .values_mut()
+            .flatten()
+            .chain(function_breakpoints.iter_mut())
+        {
+            if bp.addresses.is_empty() || bp.verified {
+                continue;
+            }
+
+            match self.arm_all(core, &bp.addresses) {
+                Ok(()) => {
+                    bp.verified = true;
+                    bp.message = None;
+                }
+                Err(message) => bp.message = Some(message),
+            }
+
+            events.push(bp.get_event_body());
+        }
+
+        self.source_breakpoints = source_breakpoints;
+        self.function_breakpoints = function_breakpoints;
+
+        let mut data_breakpoints = std::mem::take(&mut self.data_breakpoints);
+
+        for bp in data_breakpoints.iter_mut().filter(|bp| !bp.verified) {
+            if let Request::Data(info) = &bp.request {
+                match self.arm_data(core, bp.id, info) {
+                    Ok(()) => {
+                        bp.verified = true;
+                        bp.message = None;
+                    }
+                    Err(message) => bp.message = Some(message),
+                }
+
+                events.push(bp.get_event_body());
+            }
+        }
+
+        self.data_breakpoints = data_breakpoints;
+
+        let mut exception_breakpoints = std::mem::take(&mut self.exception_breakpoints);
+
+        for bp in exception_breakpoints.iter_mut() {
+            if !bp.addresses.is_empty() && !bp.verified {
+                match self.arm_all(core, &bp.addresses) {
+                    Ok(()) => bp.verified = true,
+                    Err(message) => warn!("{}", message),
+                }
+            }
+        }
+
+        self.exception_breakpoints = exception_breakpoints;
+
+        if self.vector_catch != 0 {
+            if let Err(e) = self.set_vector_catch(core) {
+                warn!("Unable to enable the vector catch: {}", e);
+            }
+        }
+
+        events
+    }
+
+    /// Remove all breakpoints from the target, and restore the code replaced by
+    /// software breakpoints.
+    pub fn clear_all(&mut self, core: &mut dyn CoreAccess) -> Result<(), probe_rs::Error> {
+        self.temporary = None;
+
+        for (address, armed) in self.armed.drain() {
+            match armed.kind {
+                BreakpointKind::Hardware => core.clear_hw_breakpoint(address)?,
+                BreakpointKind::Software { original } => core.write_8(address, &original)?,
+            }
+        }
+
+        if let Some(comparators) = &mut self.comparators {
+            for (index, user) in comparators.users.iter_mut().enumerate() {
+                if user.take().is_some() {
+                    dwt::clear_comparator(core, index as u32)?;
+                }
+            }
+        }
+
+        for bp in self
+            .source_breakpoints
+            .values_mut()
+            .flatten()
+            .chain(self.function_breakpoints.iter_mut())
+            .chain(self.data_breakpoints.iter_mut())
+            .chain(self.exception_breakpoints.iter_mut())
+        {
+            bp.verified = false;
+        }
+
+        if self.vector_catch != 0 {
+            let demcr = core.read_word_32(dwt::DEMCR)?;
+            core.write_word_32(dwt::DEMCR, demcr & !exceptions::VECTOR_CATCH_MASK)?;
+        }
+
+        Ok(())
+    }
+
+    /// If the core is halted at a software breakpoint, execute the original instruction
+    /// and put the breakpoint back in place.
+    ///
+    /// Returns `true` if an instruction was executed.
+    pub fn step_over_software_breakpoint(
+        &self,
+        core: &mut dyn CoreAccess,
+    ) -> Result<bool, probe_rs::Error> {
+        let pc = core.read_core_reg(core.program_counter())?;
+
+        match self.armed.get(&pc) {
+            Some(ArmedBreakpoint {
+                kind: BreakpointKind::Software { original },
+                ..
+            }) => {
+                debug!("Stepping over software breakpoint at {:#010x}", pc);
+
+                core.write_8(pc, original)?;
+                let result = core.step();
+                core.write_8(pc, &THUMB_BKPT)?;
+
+                result.map(|_| true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Set a breakpoint which is not visible to the client, replacing any previous
+    /// temporary breakpoint.
+    pub fn set_temporary(&mut self, core: &mut dyn CoreAccess, address: u32) -> Result<(), String> {
+        self.clear_temporary(core).map_err(|e| e.to_string())?;
+
+        self.arm(core, address)?;
+        self.temporary = Some(address);
+
+        Ok(())
+    }
+
+    pub fn clear_temporary(&mut self, core: &mut dyn CoreAccess) -> Result<(), probe_rs::Error> {
+        match self.temporary.take() {
+            Some(address) => self.disarm(core, address),
+            None => Ok(()),
+        }
+    }
+
+    /// Replace the `BKPT` instructions of software breakpoints in `data`, which was
+    /// read from `address`, with the original code.
+    pub fn restore_original_code(&self, address: u32, data: &mut [u8]) {
+        let end = u64::from(address) + data.len() as u64;
+
+        for (&breakpoint, armed) in &self.armed {
+            let original = match &armed.kind {
+                BreakpointKind::Software { original } => original,
+                BreakpointKind::Hardware => continue,
+            };
+
+            for (i, &byte) in original.iter().enumerate() {
+                let byte_address = u64::from(breakpoint) + i as u64;
+
+                if u64::from(address) <= byte_address && byte_address < end {
+                    data[(byte_address - u64::from(address)) as usize] = byte;
+                }
+            }
+        }
+    }
+
+    /// The breakpoints at `address`, for deciding if the core stays halted there.
+    pub fn hit_at(&self, address: u32) -> Vec<HitBreakpoint> {
+        self.source_breakpoints
+            .values()
+            .flatten()
+            .chain(&self.function_breakpoints)
+            .chain(&self.exception_breakpoints)
+            .filter(|bp| bp.verified && bp.addresses.contains(&address))
+            .map(BreakpointInfo::to_hit)
+            .collect()
+    }
+
+    /// The data breakpoints whose comparators matched since the core was resumed.
+    pub fn data_hit(&self, core: &mut dyn CoreAccess) -> Vec<HitBreakpoint> {
+        let comparators = match &self.comparators {
+            Some(comparators) => comparators,
+            None => return Vec::new(),
+        };
+
+        let mut ids = Vec::new();
+
+        for (index, user) in comparators.users.iter().enumerate() {
+            if let Some(id) = user {
+                // Reading the comparator clears its match, so all of them are read.
+                match dwt::matched(core, index as u32) {
+                    Ok(true) if !ids.contains(id) => ids.push(*id),
+                    Ok(_) => (),
+                    Err(e) => warn!("Failed to read DWT comparator {}: {}", index, e),
+                }
+            }
+        }
+
+        self.data_breakpoints
+            .iter()
+            .filter(|bp| ids.contains(&bp.id))
+            .map(BreakpointInfo::to_hit)
+            .collect()
+    }
+
+    /// Count a hit of the breakpoint `id`, and check its hit condition.
+    ///
+    /// A hit condition is a number `n`, which stops from the n-th hit on, or a number
+    /// with one of the operators `==`, `!=`, `<`, `<=`, `>`, `>=` and `%`, where `% n`
+    /// stops at every n-th hit.
+    pub fn count_hit(&mut self, id: i64) -> Result<bool, String> {
+        let bp = match self
+            .source_breakpoints
+            .values_mut()
+            .flatten()
+            .chain(self.function_breakpoints.iter_mut())
+            .chain(self.data_breakpoints.iter_mut())
+            .find(|bp| bp.id == id)
+        {
+            Some(bp) => bp,
+            None => return Ok(true),
+        };
+
+        bp.hits += 1;
+
+        match bp.request.hit_condition() {
+            Some(hit_condition) => hit_condition_met(hit_condition, bp.hits),
+            None => Ok(true),
+        }
+    }
+
+    /// Set a breakpoint at `address`. If this is not possible, the error contains
+    /// a message for the user.
+    fn arm(&mut self, core: &mut dyn CoreAccess, address: u32) -> Result<(), String> {
+        // Several breakpoints can resolve to the same address, but they only need to be set once.
+        if let Some(armed) = self.armed.get_mut(&address) {
+            armed.users += 1;
+            return Ok(());
+        }
+
+        let kind = match self.set_hardware_breakpoint(core, address) {
+            Ok(()) => BreakpointKind::Hardware,
+            Err(e) => {
+                info!(
+                    "Unable to set hardware breakpoint at {:#010x} ({}), trying software breakpoint",
+                    address, e
+                );
+
+                let original = set_software_breakpoint(core, address).map_err(|software| {
+                    format!(
+                        "Unable to set a hardware breakpoint ({}), nor a software breakpoint ({})",
+                        e, software
+                    )
+                })?;
+
+                BreakpointKind::Software { original }
+            }
+        };
+
+        self.armed
+            .insert(address, ArmedBreakpoint { kind, users: 1 });
+
+        Ok(())
+    }
+
+    /// Set breakpoints at all `addresses`. If one of them can not be set, the ones
+    /// which were already set are removed again.
+    fn arm_all(&mut self, core: &mut dyn CoreAccess, addresses: &[u32]) -> Result<(), String> {
+        for (index, &address) in addresses.iter().enumerate() {
+            if let Err(message) = self.arm(core, address) {
+                for &armed in &addresses[..index] {
+                    if let Err(e) = self.disarm(core, armed) {
+                        warn!("Failed to remove breakpoint at {:#010x}: {}", armed, e);
+                    }
+                }
+
+                return Err(message);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Program the DWT comparators for the data breakpoint `id`. If this is not
+    /// possible, the error contains a message for the user.
+    fn arm_data(
+        &mut self,
+        core: &mut dyn CoreAccess,
+        id: i64,
+        bp: &DataBreakpoint,
+    ) -> Result<(), String> {
+        let (address, size) = parse_data_id(&bp.data_id)?;
+
+        let access = match bp.access_type {
+            Some(DataBreakpointAccessType::Read) => Access::Read,
+            Some(DataBreakpointAccessType::ReadWrite) => Access::ReadWrite,
+            Some(DataBreakpointAccessType::Write) | None => Access::Write,
+        };
+
+        let comparators = self.comparators(core)?;
+
+        if comparators.users.is_empty() {
+            return Err("The core has no DWT comparators for data breakpoints".to_owned());
+        }
+
+        let ranges = dwt::ranges(address, size, comparators.max_mask);
+        let free: Vec<usize> = comparators
+            .users
+            .iter()
+            .enumerate()
+            .filter(|(_, user)| user.is_none())
+            .map(|(index, _)| index)
+            .collect();
+
+        if ranges.len() > free.len() {
+            return Err(format!(
+                "Watching {} bytes at {:#010x} needs {} DWT comparators, but only {} of {} are free",
+                size,
+                address,
+                ranges.len(),
+                free.len(),
+                comparators.users.len()
+            ));
+        }
+
+        for (&index, &(address, mask)) in free.iter().zip(&ranges) {
+            debug!(
+                "Watching {} bytes at {:#010x} with DWT comparator {}",
+                1u64 << mask,
+                address,
+                index
+            );
+
+            comparators.users[index] = Some(id);
+
+            let result = dwt::set_comparator(
+                core,
+                comparators.version,
+                index as u32,
+                address,
+                mask,
+                access,
+            );
+
+            if let Err(e) = result {
+                if let Err(e) = release(core, comparators, id) {
+                    warn!("Failed to remove data breakpoint {}: {}", id, e);
+                }
+
+                return Err(format!("Unable to set DWT comparator {}: {}", index, e));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn comparators(&mut self, core: &mut dyn CoreAccess) -> Result<&mut Comparators, String> {
+        if self.comparators.is_none() {
+            let count = dwt::enable(core).map_err(|e| e.to_string())?;
+
+            // Comparators might still be set by a previous session.
+            for index in 0..count {
+                dwt::clear_comparator(core, index).map_err(|e| e.to_string())?;
+            }
+
+            let (version, max_mask) = if count > 0 {
+                let version = dwt::version(core).map_err(|e| e.to_string())?;
+
+                (
+                    version,
+                    dwt::max_mask(core, version, 0).map_err(|e| e.to_string())?,
+                )
+            } else {
+                (dwt::Version::V1, 0)
+            };
+
+            debug!(
+                "Core has {} DWT comparators of {:?}, with masks up to {}",
+                count, version, max_mask
+            );
+
+            self.comparators = Some(Comparators {
+                users: vec![None; count as usize],
+                max_mask,
+                version,
+            });
+        }
+
+        Ok(self.comparators.as_mut().unwrap())
+    }
+
+    fn set_hardware_breakpoint(
+        &mut self,
+        core: &mut dyn CoreAccess,
+        address: u32,
+    ) -> Result<(), String> {
+        let units = match self.hardware_units {
+            Some(units) => units,
+            None => {
+                let units = core
+                    .available_breakpoint_units()
+                    .map_err(|e| e.to_string())?;
+                debug!("Core has {} hardware breakpoint units", units);
+
+                self.hardware_units = Some(units);
+                units
+            }
+        };
+
+        let used = self
+            .armed
+            .values()
+            .filter(|armed| matches!(armed.kind, BreakpointKind::Hardware))
+            .count();
+
+        if used >= units as usize {
+            return Err(format!(
+                "all {} hardware breakpoint units are in use",
+                units
+            ));
+        }
+
+        core.set_hw_breakpoint(address).map_err(|e| e.to_string())
+    }
+
+    fn disarm(&mut self, core: &mut dyn CoreAccess, address: u32) -> Result<(), probe_rs::Error> {
+        if let Some(armed) = self.armed.get_mut(&address) {
+            armed.users -= 1;
+
+            if armed.users == 0 {
+                match self.armed.remove(&address).unwrap().kind {
+                    BreakpointKind::Hardware => core.clear_hw_breakpoint(address)?,
+                    BreakpointKind::Software { original } => core.write_8(address, &original)?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The id of a data breakpoint on `size` bytes at `address`, for the
+/// `dataBreakpointInfo` request.
+pub fn data_id(address: u32, size: u64) -> String {
+    format!("{:#010x}/{}", address, size)
+}
+
+fn parse_data_id(data_id: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("Invalid data breakpoint '{}'", data_id);
+
+    let (address, size) = data_id.split_once('/').ok_or_else(invalid)?;
+
+    let address = address
+        .strip_prefix("0x")
+        .and_then(|address| u32::from_str_radix(address, 16).ok())
+        .ok_or_else(invalid)?;
+    let size: u32 = size.parse().map_err(|_| invalid())?;
+
+    if size == 0 || u64::from(address) + u64::from(size) > 1 << 32 {
+        return Err(invalid());
+    }
+
+    Ok((address, size))
+}
+
+/// Clear the comparators used by the data breakpoint `id`.
+fn release(
+    core: &mut dyn CoreAccess,
+    comparators: &mut Comparators,
+    id: i64,
+) -> Result<(), probe_rs::Error> {
+    for (index, user) in comparators.users.iter_mut().enumerate() {
+        if *user == Some(id) {
+            *user = None;
+            dwt::clear_comparator(core, index as u32)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn hit_condition_met(hit_condition: &str, hits: u64) -> Result<bool, String> {
+    let hit_condition = hit_condition.trim();
+
+    let operator_len = hit_condition
+        .find(|c: char| c.is_ascii_digit() || c.is_whitespace())
+        .unwrap_or(hit_condition.len());
+    let (operator, count) = hit_condition.split_at(operator_len);
+
+    let count: u64 = count
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid hit condition '{}'", hit_condition))?;
+
+    Ok(match operator {
+        "" | ">=" => hits >= count,
+        "==" => hits == count,
+        "!=" => hits != count,
+        "<" => hits < count,
+        "<=" => hits <= count,
+        ">" => hits > count,
+        "%" => hits.checked_rem(count) == Some(0),
+        _ => return Err(format!("Invalid hit condition '{}'", hit_condition)),
+    })
+}
+
+/// Replace the instruction at `address` with a `BKPT` instruction, and return the
+/// original code. This only works for code in RAM.
+fn set_software_breakpoint(core: &mut dyn CoreAccess, address: u32) -> Result<Vec<u8>, String> {
+    if core.architecture() != Architecture::Arm {
+        return Err("software breakpoints are only supported on ARM".to_owned());
+    }
+
+    let mut original = vec![0; THUMB_BKPT.len()];
+    core.read_8(address, &mut original)
+        .map_err(|e| e.to_string())?;
+
+    let written = core.write_8(address, &THUMB_BKPT).and_then(|_| {
+        let mut patched = [0; THUMB_BKPT.len()];
+        core.read_8(address, &mut patched)?;
+        Ok(patched)
+    });
+
+    match written {
+        Ok(patched) if patched == THUMB_BKPT => Ok(original),
+        _ => {
+            // The write might have partially succeeded.
+            let _ = core.write_8(address, &original);
+
+            Err(format!("the code at {:#010x} is not in RAM", address))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::hit_condition_met;
+
+    #[test]
+    fn hit_conditions() {
+        let met = |condition: &str| {
+            (1..=6)
+                .filter(|hits| hit_condition_met(condition, *hits).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(met("3"), vec![3, 4, 5, 6]);
+        assert_eq!(met(">= 3"), vec![3, 4, 5, 6]);
+        assert_eq!(met("== 3"), vec![3]);
+        assert_eq!(met("< 3"), vec![1, 2]);
+        assert_eq!(met("%3"), vec![3, 6]);
+        assert_eq!(met("% 2"), vec![2, 4, 6]);
+        assert_eq!(met("%0"), Vec::<u64>::new());
+
+        assert!(hit_condition_met("~3", 1).is_err());
+        assert!(hit_condition_met("%", 1).is_err());
+    }
+}
@@ -0,0 +1,254 @@
+//! Types of the debug adapter protocol which are missing in `debugserver_types`,
+//! because they were added in later versions of the protocol.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Debug, Clone)]
+pub struct StoppedEvent {
+    pub seq: i64,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub event: String,
+    pub body: StoppedEventBody,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct StoppedEventBody {
+    pub reason: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preserve_focus_hint: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub all_threads_stopped: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hit_breakpoint_ids: Option<Vec<i64>>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct InvalidatedEvent {
+    pub seq: i64,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub event: String,
+    pub body: InvalidatedEventBody,
+}
+
+/// Body of the `invalidated` event, which makes the client fetch the invalidated
+/// parts of its views again.
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct InvalidatedEventBody {
+    /// The invalidated areas: `all`, `stacks`, `threads` or `variables`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub areas: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stack_frame_id: Option<i64>,
+}
+
+/// Capabilities of the debug adapter, including the ones missing in
+/// `debugserver_types::Capabilities`.
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Capabilities {
+    #[serde(flatten)]
+    pub base: debugserver_types::Capabilities,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supports_stepping_granularity: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supports_read_memory_request: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supports_write_memory_request: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supports_disassemble_request: Option<bool>,
+}
+
+/// Arguments of the `next`, `stepIn` and `stepOut` requests.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StepArguments {
+    pub thread_id: i64,
+    /// Either `statement`, `line` or `instruction`.
+    pub granularity: Option<String>,
+}
+
+/// A variable, with the `memoryReference` which is missing in `debugserver_types::Variable`.
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Variable {
+    pub name: String,
+    pub value: String,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub type_: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presentation_hint: Option<debugserver_types::VariablePresentationHint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub evaluate_name: Option<String>,
+    pub variables_reference: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub named_variables: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub indexed_variables: Option<i64>,
+    /// Address of the memory the variable is stored in, or points to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_reference: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct VariablesResponseBody {
+    pub variables: Vec<Variable>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadMemoryArguments {
+    pub memory_reference: String,
+    pub offset: Option<i64>,
+    pub count: i64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadMemoryResponseBody {
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unreadable_bytes: Option<i64>,
+    /// The bytes read, encoded in base64.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WriteMemoryArguments {
+    pub memory_reference: String,
+    pub offset: Option<i64>,
+    pub allow_partial: Option<bool>,
+    /// The bytes to write, encoded in base64.
+    pub data: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WriteMemoryResponseBody {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes_written: Option<i64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DisassembleArguments {
+    pub memory_reference: String,
+    /// Offset in bytes, added to the memory reference.
+    pub offset: Option<i64>,
+    /// Offset in instructions, after the byte offset was applied.
+    pub instruction_offset: Option<i64>,
+    pub instruction_count: i64,
+    pub resolve_symbols: Option<bool>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DisassembledInstruction {
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instruction_bytes: Option<String>,
+    pub instruction: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<debugserver_types::Source>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_line: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_column: Option<i64>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DisassembleResponseBody {
+    pub instructions: Vec<DisassembledInstruction>,
+}
+
+/// A stack frame, with the `instructionPointerReference` which is missing in
+/// `debugserver_types::StackFrame`.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StackFrame {
+    pub id: i64,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<debugserver_types::Source>,
+    pub line: i64,
+    pub column: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_line: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_column: Option<i64>,
+    /// Address of the current instruction of the frame.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instruction_pointer_reference: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presentation_hint: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StackTraceResponseBody {
+    pub stack_frames: Vec<StackFrame>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_frames: Option<i64>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EvaluateResponseBody {
+    pub result: String,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub type_: Option<String>,
+    pub variables_reference: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub named_variables: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub indexed_variables: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_reference: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SetVariableResponseBody {
+    pub value: String,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub type_: Option<String>,
+    pub variables_reference: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub named_variables: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub indexed_variables: Option<i64>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SetExpressionResponseBody {
+    pub value: String,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub type_: Option<String>,
+    pub variables_reference: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub named_variables: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub indexed_variables: Option<i64>,
+}
@@ -1,5 +1,7 @@
 use debugserver_types::BreakpointEvent;
 use debugserver_types::BreakpointEventBody;
+use debugserver_types::ExitedEvent;
+use debugserver_types::ExitedEventBody;
 use debugserver_types::OutputEvent;
 use debugserver_types::OutputEventBody;
 use debugserver_types::ProcessEvent;
@@ -7,7 +9,6 @@ use debugserver_types::ProcessEventBody;
 use debugserver_types::ProtocolMessage;
 use debugserver_types::Request;
 use debugserver_types::Response;
-use debugserver_types::StoppedEvent;
 use debugserver_types::TerminatedEvent;
 use debugserver_types::TerminatedEventBody;
 use debugserver_types::ThreadEvent;
@@ -21,13 +22,15 @@ use std::io::{Read, Write};
 use std::str;
 use std::string::ToString;
 
-use debugserver_types::{InitializedEvent, StoppedEventBody};
+use debugserver_types::InitializedEvent;
+
+use crate::dap_types::{InvalidatedEvent, InvalidatedEventBody, StoppedEvent, StoppedEventBody};
 
 use anyhow::{anyhow, Result};
 use serde::Serialize;
-use thiserror;
 
 #[derive(Debug, thiserror::Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     #[error("Input error")]
     IoError(#[from] io::Error),
@@ -35,6 +38,8 @@ pub enum Error {
     SerdeError(#[from] serde_json::Error),
     #[error("Error in interaction with probe")]
     ProbeError(#[from] probe_rs::Error),
+    #[error("Failed to flash program")]
+    FlashingError(#[from] probe_rs::flashing::FileDownloadError),
     #[error("Missing session for interaction with probe")]
     MissingSession,
     #[error("Received an invalid requeset")]
@@ -45,9 +50,13 @@ pub enum Error {
     Other(#[from] anyhow::Error),
 }
 
-pub struct DebugAdapter<R: Read, W: Write> {
-    seq: i64,
+/// Reads messages sent by the debug client.
+pub struct MessageReader<R: Read> {
     input: BufReader<R>,
+}
+
+pub struct DebugAdapter<W: Write> {
+    seq: i64,
     output: W,
 }
 
@@ -58,19 +67,13 @@ pub enum DebugAdapterMessage {
     Event(debugserver_types::Event),
 }
 
-impl<R: Read, W: Write> DebugAdapter<R, W> {
-    pub fn new(input: R, output: W) -> DebugAdapter<R, W> {
-        DebugAdapter {
-            seq: 1,
+impl<R: Read> MessageReader<R> {
+    pub fn new(input: R) -> MessageReader<R> {
+        MessageReader {
             input: BufReader::new(input),
-            output,
         }
     }
 
-    pub fn peek_seq(&self) -> i64 {
-        self.seq
-    }
-
     pub fn receive_data(&mut self) -> Result<DebugAdapterMessage> {
         let mut header = String::new();
 
@@ -85,9 +88,7 @@ impl<R: Read, W: Write> DebugAdapter<R, W> {
             .ok_or_else(|| anyhow!("Failed to read content length from header '{}'", header))?;
 
         let mut content = vec![0u8; len];
-        let bytes_read = self.input.read(&mut content)?;
-
-        assert!(bytes_read == len);
+        self.input.read_exact(&mut content)?;
 
         // Extract protocol message
         let protocol_message: ProtocolMessage = serde_json::from_slice(&content)?;
@@ -105,6 +106,16 @@ impl<R: Read, W: Write> DebugAdapter<R, W> {
             other => Err(anyhow!("Unknown message type: {}", other)),
         }
     }
+}
+
+impl<W: Write> DebugAdapter<W> {
+    pub fn new(output: W) -> DebugAdapter<W> {
+        DebugAdapter { seq: 1, output }
+    }
+
+    pub fn peek_seq(&self) -> i64 {
+        self.seq
+    }
 
     pub fn send_response<S: Serialize>(
         &mut self,
@@ -132,7 +143,7 @@ impl<R: Read, W: Write> DebugAdapter<R, W> {
             }
             Err(e) => {
                 resp.success = false;
-                resp.message = Some(e.to_string());
+                resp.message = Some(error_message(&e));
             }
         };
 
@@ -149,8 +160,8 @@ impl<R: Read, W: Write> DebugAdapter<R, W> {
         trace!("> {}", response_header.trim_end());
         trace!("> {}", str::from_utf8(response_body).unwrap());
 
-        self.output.write(response_header.as_bytes())?;
-        self.output.write(response_body)?;
+        self.output.write_all(response_header.as_bytes())?;
+        self.output.write_all(response_body)?;
 
         self.output.flush()?;
 
@@ -173,6 +184,21 @@ impl<R: Read, W: Write> DebugAdapter<R, W> {
     }
 }
 
+/// Format an error together with all its causes, so that the client gets
+/// to see the actual reason for a failed request.
+fn error_message(error: &(dyn std::error::Error + 'static)) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+
+    message
+}
+
 fn get_content_len(header: &str) -> Option<usize> {
     let mut parts = header.trim_end().split_ascii_whitespace();
 
@@ -196,6 +222,7 @@ pub enum Event {
     Initialized,
     Capabilities,
     LoadedSource,
+    Invalidated(InvalidatedEventBody),
 }
 
 impl Event {
@@ -233,6 +260,14 @@ impl Event {
                 type_: "event".to_owned(),
                 event: "output".to_owned(),
             })?,
+            Exited(exit_code) => serde_json::to_vec(&ExitedEvent {
+                seq,
+                body: ExitedEventBody {
+                    exit_code: *exit_code,
+                },
+                type_: "event".to_owned(),
+                event: "exited".to_owned(),
+            })?,
             Terminated(restart_request) => {
                 let body = TerminatedEventBody {
                     restart: Some(serde_json::Value::Bool(
@@ -253,6 +288,12 @@ impl Event {
                 type_: "event".to_owned(),
                 event: "breakpoint".to_owned(),
             })?,
+            Invalidated(ref body) => serde_json::to_vec(&InvalidatedEvent {
+                seq,
+                body: body.clone(),
+                type_: "event".to_owned(),
+                event: "invalidated".to_owned(),
+            })?,
             _ => return Err(Error::Unimplemented),
         };
 
@@ -260,9 +301,15 @@ impl Event {
     }
 
     pub fn console_output(msg: String) -> Event {
+        Event::output("console", msg)
+    }
+
+    /// Output of the program or the debugger, shown in the debug console depending
+    /// on its `category`.
+    pub fn output(category: &str, msg: String) -> Event {
         Event::Output(OutputEventBody {
             output: msg,
-            category: Some("console".to_owned()),
+            category: Some(category.to_owned()),
             variables_reference: None,
             source: None,
             line: None,
@@ -286,6 +333,6 @@ mod test {
     fn parse_valid_header() {
         let header = "Content-Length: 234\r\n";
 
-        assert_eq!(234, get_content_len(&header).unwrap());
+        assert_eq!(234, get_content_len(header).unwrap());
     }
 }
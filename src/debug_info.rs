@@ -0,0 +1,2437 @@
+//! Debug information, parsed from the DWARF sections of the debugged program.
+//!
+//! All access to the target goes through [`CoreAccess`], so that stack frames and
+//! variables can also be inspected on a simulated target.
+
+use crate::{defmt, fault, target::CoreAccess};
+use gimli::{EvaluationResult, Location, Piece, UnwindSection};
+use log::{debug, info, warn};
+use object::{Object, ObjectSection, ObjectSymbol, SymbolKind};
+use probe_rs::{Architecture, CoreRegisterAddress};
+use std::{
+    borrow,
+    collections::{BTreeMap, HashMap},
+    io,
+    ops::Range,
+    path::{Path, PathBuf},
+    rc::Rc,
+    str::{from_utf8, Utf8Error},
+};
+
+type DwarfReader = gimli::EndianRcSlice<gimli::LittleEndian>;
+type Unit = gimli::Unit<DwarfReader>;
+type EntriesNode<'abbrev, 'unit, 'tree> =
+    gimli::EntriesTreeNode<'abbrev, 'unit, 'tree, DwarfReader>;
+
+/// DWARF register number of the stack pointer.
+const SP: usize = 13;
+/// DWARF register number of the link register.
+const LR: usize = 14;
+/// DWARF register number of the program counter.
+const PC: usize = 15;
+/// Number of the xPSR register, see [`Registers`].
+pub const XPSR: usize = 16;
+/// Number of the FPSCR register, see [`Registers`].
+pub const FPSCR: usize = 33;
+/// DWARF register number of the single precision register S0.
+pub const FPU_S0: usize = 64;
+/// DWARF register number of the double precision register D0.
+const FPU_D0: usize = 256;
+
+/// Address of the Media and VFP Feature Register 0.
+const MVFR0: u32 = 0xe000_ef40;
+/// Address of the Floating-Point Context Control Register.
+const FPCCR: u32 = 0xe000_ef34;
+/// Set in FPCCR while the space for the FPU registers in the exception frame is
+/// reserved, but the registers were not stacked yet.
+const FPCCR_LSPACT: u32 = 1;
+
+/// Upper limit for the number of unwound frames, in case the stack is corrupted.
+const MAX_FRAMES: usize = 128;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DebugError {
+    #[error("IO Error while accessing debug data")]
+    Io(#[from] io::Error),
+    #[error("Error accessing debug data")]
+    DebugData(#[from] object::read::Error),
+    #[error("Error parsing debug data")]
+    Parse(#[from] gimli::read::Error),
+    #[error("Non-UTF8 data found in debug data")]
+    NonUtf8(#[from] Utf8Error),
+    #[error("Error using the probe")]
+    Probe(#[from] probe_rs::Error),
+    #[error("Value of register {0} is not available")]
+    MissingRegister(u16),
+    #[error("Unsupported DWARF expression: {0}")]
+    UnsupportedExpression(String),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ColumnType {
+    LeftEdge,
+    Column(u64),
+}
+
+impl From<gimli::ColumnType> for ColumnType {
+    fn from(column: gimli::ColumnType) -> Self {
+        match column {
+            gimli::ColumnType::LeftEdge => ColumnType::LeftEdge,
+            gimli::ColumnType::Column(c) => ColumnType::Column(c),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLocation {
+    pub line: Option<u64>,
+    pub column: Option<ColumnType>,
+
+    pub file: Option<String>,
+    pub directory: Option<PathBuf>,
+}
+
+impl SourceLocation {
+    /// The full path of the source file.
+    pub fn path(&self) -> Option<PathBuf> {
+        let file = self.file.as_ref()?;
+
+        Some(match &self.directory {
+            Some(directory) => directory.join(file),
+            None => PathBuf::from(file),
+        })
+    }
+}
+
+/// A row of the line table, which is a (part of a) source statement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Statement {
+    /// Address of the first instruction of the row.
+    pub address: u64,
+    /// Index of the source file.
+    file: usize,
+    /// The source line, or 0 if the code doesn't belong to any line.
+    pub line: u64,
+    /// Set if this is a recommended location for a breakpoint.
+    pub is_stmt: bool,
+}
+
+impl Statement {
+    /// Check if both statements are on the same source line.
+    pub fn same_line(&self, other: &Statement) -> bool {
+        self.file == other.file && self.line == other.line
+    }
+}
+
+/// Values of the registers in a stack frame, indexed by their DWARF register number.
+///
+/// xPSR and FPSCR have no DWARF register number, they use their number in the
+/// register selector of the Cortex-M debug interface instead.
+#[derive(Debug, Clone, Default)]
+pub struct Registers(BTreeMap<usize, u32>);
+
+impl Registers {
+    /// Read the core registers R0 to R15.
+    pub fn from_core(core: &mut dyn CoreAccess) -> Self {
+        let mut registers = Registers::default();
+
+        for i in 0..16 {
+            registers.set(i, core.read_core_reg(CoreRegisterAddress(i as u16)).ok());
+        }
+
+        registers
+    }
+
+    /// Read xPSR, and the registers of the FPU if the core has one.
+    pub fn read_special_registers(&mut self, core: &mut dyn CoreAccess) {
+        if core.architecture() != Architecture::Arm {
+            return;
+        }
+
+        self.set(
+            XPSR,
+            core.read_core_reg(CoreRegisterAddress(XPSR as u16)).ok(),
+        );
+
+        // The Media and VFP Feature Register 0 is zero if there is no FPU.
+        match core.read_word_32(MVFR0) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => (),
+        }
+
+        self.set(
+            FPSCR,
+            core.read_core_reg(CoreRegisterAddress(FPSCR as u16)).ok(),
+        );
+
+        for i in FPU_S0..FPU_S0 + 32 {
+            self.set(i, core.read_core_reg(CoreRegisterAddress(i as u16)).ok());
+        }
+    }
+
+    pub fn get(&self, register: usize) -> Option<u32> {
+        self.0.get(&register).copied()
+    }
+
+    pub fn set(&mut self, register: usize, value: Option<u32>) {
+        match value {
+            Some(value) => self.0.insert(register, value),
+            None => self.0.remove(&register),
+        };
+    }
+
+    /// Check if the values of the FPU registers were read.
+    pub fn has_fpu(&self) -> bool {
+        self.get(FPSCR).is_some()
+    }
+}
+
+#[derive(Debug)]
+pub struct StackFrame {
+    pub id: u64,
+    pub function_name: String,
+    pub source_location: Option<SourceLocation>,
+    /// Register values, as they were in this frame.
+    pub registers: Registers,
+    pub pc: u32,
+    pub variables: Vec<Variable>,
+    /// Set for the frame between an exception handler and the code it interrupted,
+    /// which is not a function.
+    pub is_exception_entry: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub name: String,
+    /// Type of the variable, if it is known.
+    pub type_ref: Option<TypeRef>,
+    pub location: ValueLocation,
+}
+
+/// Where the value of a variable is stored.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueLocation {
+    Memory(u32),
+    /// The value is held in a register, which had `value` in the stack frame.
+    Register {
+        register: u16,
+        value: u32,
+    },
+    /// The value is not stored on the target, but computed by the debug information.
+    Value(Vec<u8>),
+    /// The variable has no value at the current address, e.g. because it was optimized out.
+    Unavailable,
+}
+
+impl ValueLocation {
+    /// The location of a part of the value, starting `offset` bytes into it.
+    pub fn offset(&self, offset: u64) -> ValueLocation {
+        match self {
+            ValueLocation::Memory(address) => ValueLocation::Memory(address + offset as u32),
+            ValueLocation::Register { value, .. } => ValueLocation::Value(
+                value
+                    .to_le_bytes()
+                    .iter()
+                    .skip(offset as usize)
+                    .copied()
+                    .collect(),
+            ),
+            ValueLocation::Value(bytes) => {
+                ValueLocation::Value(bytes.iter().skip(offset as usize).copied().collect())
+            }
+            ValueLocation::Unavailable => ValueLocation::Unavailable,
+        }
+    }
+}
+
+/// Reference to the DIE of a type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TypeRef {
+    /// Index into `DebugInfo::units`.
+    unit: usize,
+    offset: gimli::UnitOffset,
+}
+
+#[derive(Debug, Clone)]
+pub struct Type {
+    pub name: String,
+    /// Size of a value of the type in bytes, 0 if unknown.
+    pub size: u64,
+    pub kind: TypeKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum TypeKind {
+    Base(gimli::DwAte),
+    /// Pointers and references, with the type they point to.
+    Pointer(Option<TypeRef>),
+    /// Structures, unions and Rust enums, which have a variant part.
+    Structure {
+        members: Vec<Member>,
+        variants: Option<VariantPart>,
+    },
+    Array {
+        element: Option<TypeRef>,
+        count: u64,
+        /// Distance between two elements in bytes.
+        stride: u64,
+    },
+    /// A C-like enumeration, with the name and value of each enumerator.
+    Enumeration(Vec<(String, u64)>),
+    Unknown,
+}
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub name: String,
+    /// Offset from the start of the containing value in bytes.
+    pub offset: u64,
+    pub type_ref: Option<TypeRef>,
+}
+
+/// The variants of a Rust enum. The active variant is selected by the discriminant.
+#[derive(Debug, Clone)]
+pub struct VariantPart {
+    pub discriminant: Option<Member>,
+    pub variants: Vec<Variant>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Variant {
+    /// Value of the discriminant for this variant, or `None` for the default variant.
+    pub discriminant_value: Option<u64>,
+    pub member: Member,
+}
+
+/// A row of a line number program.
+#[derive(Debug)]
+struct LineRow {
+    address: u64,
+    /// Index into `DebugInfo::files`.
+    file: usize,
+    line: Option<u64>,
+    column: ColumnType,
+    is_stmt: bool,
+    /// Set for the first instruction after the prologue of a function.
+    prologue_end: bool,
+    end_sequence: bool,
+}
+
+/// A function in the debug information.
+struct Function {
+    /// Offset of the DIE of the function.
+    offset: gimli::UnitOffset,
+    /// Qualified name of the function.
+    name: Option<String>,
+    /// The address range which contains the address the function was searched for.
+    range: Range<u64>,
+}
+
+/// A function symbol from the symbol table of the ELF file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    /// Demangled name, without the hash.
+    pub name: String,
+    /// Name as it appears in the symbol table.
+    pub mangled_name: String,
+    /// Address of the first instruction, without the Thumb bit.
+    pub address: u64,
+    /// Size in bytes, 0 if unknown.
+    pub size: u64,
+}
+
+/// A function found for the name of a function breakpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionLocation {
+    /// Demangled name, without the hash.
+    pub name: String,
+    /// Address of the first instruction of the function.
+    pub address: u64,
+    /// Address of the first instruction after the prologue.
+    pub breakpoint_address: u64,
+}
+
+/// Values needed to evaluate DWARF expressions in the context of a stack frame.
+struct FrameContext<'a> {
+    registers: &'a Registers,
+    frame_base: Option<u64>,
+    cfa: Option<u64>,
+}
+
+/// Debug information which is parsed from DWARF debugging information.
+pub struct DebugInfo {
+    dwarf: gimli::Dwarf<DwarfReader>,
+    frame_section: gimli::DebugFrame<DwarfReader>,
+    units: Vec<Unit>,
+    /// Rows of all line programs, sorted by address.
+    lines: Vec<LineRow>,
+    /// Paths of all source files referenced in the line programs.
+    files: Vec<PathBuf>,
+    /// Function symbols of the ELF file, sorted by address.
+    symbols: Vec<Symbol>,
+    /// Addresses of the data symbols of the ELF file, by their mangled name.
+    data_symbols: HashMap<String, u64>,
+    /// Format strings of the log messages, if the program uses `defmt`.
+    defmt: Option<defmt::Table>,
+}
+
+impl DebugInfo {
+    /// Read debug info directly from a ELF file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<DebugInfo, DebugError> {
+        let data = std::fs::read(path)?;
+
+        DebugInfo::from_raw(&data)
+    }
+
+    /// Parse debug information directly from a buffer containing an ELF file.
+    pub fn from_raw(data: &[u8]) -> Result<Self, DebugError> {
+        let object = object::File::parse(data)?;
+
+        // Load a section and return as `Cow<[u8]>`.
+        let load_section = |id: gimli::SectionId| -> Result<DwarfReader, gimli::Error> {
+            let data = object
+                .section_by_name(id.name())
+                .and_then(|section| section.uncompressed_data().ok())
+                .unwrap_or_else(|| borrow::Cow::Borrowed(&[][..]));
+
+            Ok(gimli::EndianRcSlice::new(
+                Rc::from(&*data),
+                gimli::LittleEndian,
+            ))
+        };
+        // Load a supplementary section. We don't have a supplementary object file,
+        // so always return an empty slice.
+        let load_section_sup = |_| {
+            Ok(gimli::EndianRcSlice::new(
+                Rc::from(&[][..]),
+                gimli::LittleEndian,
+            ))
+        };
+
+        let dwarf = gimli::Dwarf::load(&load_section, &load_section_sup)?;
+
+        use gimli::Section;
+        let mut frame_section = gimli::DebugFrame::load(load_section)?;
+
+        // To support DWARF v2, where the address size is not encoded in the .debug_frame section,
+        // we have to set the address size here.
+        frame_section.set_address_size(4);
+
+        let mut units = Vec::new();
+        let mut headers = dwarf.units();
+        while let Some(header) = headers.next()? {
+            units.push(dwarf.unit(header)?);
+        }
+
+        let mut symbols: Vec<_> = object
+            .symbols()
+            .filter(|symbol| symbol.kind() == SymbolKind::Text && !symbol.is_undefined())
+            .filter_map(|symbol| {
+                let mangled_name = symbol.name().ok()?;
+
+                Some(Symbol {
+                    name: format!("{:#}", rustc_demangle::demangle(mangled_name)),
+                    mangled_name: mangled_name.to_owned(),
+                    address: symbol.address() & !1,
+                    size: symbol.size(),
+                })
+            })
+            .collect();
+        symbols.sort_by_key(|symbol| symbol.address);
+
+        let data_symbols = object
+            .symbols()
+            .filter(|symbol| symbol.kind() == SymbolKind::Data && !symbol.is_undefined())
+            .filter_map(|symbol| Some((symbol.name().ok()?.to_owned(), symbol.address())))
+            .collect();
+
+        let defmt = defmt::Table::parse(&object);
+
+        let mut debug_info = DebugInfo {
+            dwarf,
+            frame_section,
+            units,
+            lines: Vec::new(),
+            files: Vec::new(),
+            symbols,
+            data_symbols,
+            defmt,
+        };
+
+        debug_info.load_line_programs()?;
+
+        if debug_info.defmt.is_some() {
+            let locations = debug_info.defmt_locations()?;
+
+            if let Some(defmt) = debug_info.defmt.as_mut() {
+                defmt.set_locations(locations);
+            }
+        }
+
+        Ok(debug_info)
+    }
+
+    fn load_line_programs(&mut self) -> Result<(), DebugError> {
+        let units = std::mem::take(&mut self.units);
+
+        let mut file_indices: HashMap<PathBuf, usize> = HashMap::new();
+
+        for unit in &units {
+            let line_program = match unit.line_program.clone() {
+                Some(line_program) => line_program,
+                None => continue,
+            };
+
+            let comp_dir = unit
+                .comp_dir
+                .as_ref()
+                .map(|dir| from_utf8(dir).map(PathBuf::from))
+                .transpose()?;
+
+            let mut rows = line_program.rows();
+
+            // Paths for the file indices of this unit
+            let mut unit_files: HashMap<u64, usize> = HashMap::new();
+
+            while let Some((header, row)) = rows.next_row()? {
+                let file = match unit_files.get(&row.file_index()) {
+                    Some(file) => *file,
+                    None => {
+                        let path = row
+                            .file(header)
+                            .and_then(|entry| {
+                                self.file_path(unit, comp_dir.as_deref(), header, entry)
+                            })
+                            .unwrap_or_default();
+
+                        let next_index = self.files.len();
+                        let index = *file_indices.entry(path.clone()).or_insert(next_index);
+
+                        if index == next_index {
+                            self.files.push(path);
+                        }
+
+                        unit_files.insert(row.file_index(), index);
+                        index
+                    }
+                };
+
+                self.lines.push(LineRow {
+                    address: row.address(),
+                    file,
+                    line: row.line(),
+                    column: row.column().into(),
+                    is_stmt: row.is_stmt(),
+                    prologue_end: row.prologue_end(),
+                    end_sequence: row.end_sequence(),
+                });
+            }
+        }
+
+        self.units = units;
+
+        // Keep the order of rows with the same address, so that an end of sequence
+        // comes before the start of the following sequence.
+        self.lines
+            .sort_by_key(|row| (row.address, !row.end_sequence));
+
+        Ok(())
+    }
+
+    /// Find the source locations of the `defmt` log statements. Each statement has a
+    /// static `DEFMT_LOG_STATEMENT`, whose address is the index of its format string.
+    fn defmt_locations(&self) -> Result<HashMap<u16, SourceLocation>, DebugError> {
+        let mut locations = HashMap::new();
+
+        for unit in &self.units {
+            let mut entries = unit.entries();
+
+            while let Some((_, entry)) = entries.next_dfs()? {
+                if entry.tag() != gimli::DW_TAG_variable
+                    || self.entry_name(unit, entry)?.as_deref() != Some("DEFMT_LOG_STATEMENT")
+                {
+                    continue;
+                }
+
+                let index = match entry.attr_value(gimli::DW_AT_location)? {
+                    Some(gimli::AttributeValue::Exprloc(expression)) => {
+                        match expression.operations(unit.encoding()).next()? {
+                            Some(gimli::Operation::Address { address }) => address as u16,
+                            _ => continue,
+                        }
+                    }
+                    _ => continue,
+                };
+
+                let file = match entry.attr_value(gimli::DW_AT_decl_file)? {
+                    Some(gimli::AttributeValue::FileIndex(file)) => Some(file),
+                    Some(value) => value.udata_value(),
+                    None => None,
+                };
+
+                let comp_dir = unit
+                    .comp_dir
+                    .as_ref()
+                    .and_then(|dir| from_utf8(dir).ok().map(PathBuf::from));
+
+                let path = match (file, &unit.line_program) {
+                    (Some(file), Some(line_program)) => {
+                        let header = line_program.header();
+                        header.file(file).and_then(|entry| {
+                            self.file_path(unit, comp_dir.as_deref(), header, entry)
+                        })
+                    }
+                    _ => None,
+                };
+
+                let line = entry
+                    .attr_value(gimli::DW_AT_decl_line)?
+                    .and_then(|line| line.udata_value());
+
+                locations.insert(
+                    index,
+                    SourceLocation {
+                        line,
+                        column: None,
+                        file: path
+                            .as_ref()
+                            .and_then(|path| path.file_name())
+                            .map(|name| name.to_string_lossy().into_owned()),
+                        directory: path
+                            .as_ref()
+                            .and_then(|path| path.parent())
+                            .map(Path::to_owned),
+                    },
+                );
+            }
+        }
+
+        Ok(locations)
+    }
+
+    /// Get the absolute path for an entry in a line program header
+    fn file_path(
+        &self,
+        unit: &Unit,
+        comp_dir: Option<&Path>,
+        header: &gimli::LineProgramHeader<DwarfReader>,
+        file_entry: &gimli::FileEntry<DwarfReader>,
+    ) -> Option<PathBuf> {
+        let file_name = self.dwarf.attr_string(unit, file_entry.path_name()).ok()?;
+        let file_name = PathBuf::from(from_utf8(&file_name).ok()?);
+
+        let dir_path = file_entry
+            .directory(header)
+            .and_then(|dir| self.dwarf.attr_string(unit, dir).ok())
+            .and_then(|dir| from_utf8(&dir).ok().map(PathBuf::from));
+
+        let mut path = match dir_path {
+            Some(dir_path) => dir_path.join(file_name),
+            None => file_name,
+        };
+
+        if path.is_relative() {
+            if let Some(comp_dir) = comp_dir {
+                path = comp_dir.join(path);
+            }
+        }
+
+        Some(path)
+    }
+
+    /// Index of the line row which covers `address`.
+    fn line_row_index(&self, address: u64) -> Option<usize> {
+        let index = match self.lines.binary_search_by_key(&address, |row| row.address) {
+            Ok(mut index) => {
+                // Several rows can start at the same address, the last one is the relevant one.
+                while index + 1 < self.lines.len() && self.lines[index + 1].address == address {
+                    index += 1;
+                }
+                index
+            }
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+
+        if self.lines[index].end_sequence {
+            None
+        } else {
+            Some(index)
+        }
+    }
+
+    pub fn get_source_location(&self, address: u64) -> Option<SourceLocation> {
+        let row = &self.lines[self.line_row_index(address)?];
+
+        let path = &self.files[row.file];
+
+        Some(SourceLocation {
+            line: row.line,
+            column: Some(row.column),
+            file: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+            directory: path.parent().map(Path::to_owned),
+        })
+    }
+
+    /// Get the line table row which covers `address`.
+    pub fn statement_at(&self, address: u64) -> Option<Statement> {
+        let row = &self.lines[self.line_row_index(address)?];
+
+        Some(Statement {
+            address: row.address,
+            file: row.file,
+            line: row.line.unwrap_or(0),
+            is_stmt: row.is_stmt,
+        })
+    }
+
+    /// Get the function symbol which contains `address`. Symbols without a size
+    /// extend up to the next symbol.
+    pub fn symbol_at(&self, address: u64) -> Option<&Symbol> {
+        let index = match self
+            .symbols
+            .binary_search_by_key(&address, |symbol| symbol.address)
+        {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+
+        let symbol = &self.symbols[index];
+
+        if symbol.size == 0 || address < symbol.address + symbol.size {
+            Some(symbol)
+        } else {
+            None
+        }
+    }
+
+    /// Get the address of the data symbol `name`, as it is given in the symbol table.
+    pub fn data_symbol(&self, name: &str) -> Option<u64> {
+        self.data_symbols.get(name).copied()
+    }
+
+    /// Get the format strings of the `defmt` log messages, if the program uses `defmt`.
+    pub fn defmt_table(&self) -> Option<&defmt::Table> {
+        self.defmt.as_ref()
+    }
+
+    /// Get the address range of the function which contains `address`.
+    pub fn function_range(&self, address: u64) -> Option<Range<u64>> {
+        match self.function_at(address) {
+            Ok(function) => function.map(|(_, f)| f.range),
+            Err(e) => {
+                warn!("Unable to get function at {:#010x}: {}", address, e);
+                None
+            }
+        }
+    }
+
+    /// Get the return address of the function the core is halted in, and the
+    /// value of the stack pointer after it returned.
+    pub fn return_address(
+        &self,
+        core: &mut dyn CoreAccess,
+        pc: u32,
+    ) -> Result<Option<(u32, u32)>, DebugError> {
+        let registers = Registers::from_core(core);
+
+        let (cfa, caller_registers) = self.unwind_registers(core, u64::from(pc), &registers)?;
+
+        Ok(caller_registers.get(PC).map(|address| (address, cfa)))
+    }
+
+    /// Find the program counter where a breakpoint should be set,
+    /// given a source file, a line and optionally a column.
+    pub fn get_breakpoint_location(
+        &self,
+        path: &Path,
+        line: u64,
+        column: Option<u64>,
+    ) -> Result<Option<u64>, DebugError> {
+        debug!(
+            "Looking for breakpoint location for {}:{}:{}",
+            path.display(),
+            line,
+            column
+                .map(|c| c.to_string())
+                .unwrap_or_else(|| "-".to_owned())
+        );
+
+        let mut locations: Vec<(u64, ColumnType)> = self
+            .lines
+            .iter()
+            .filter(|row| {
+                !row.end_sequence
+                    && row.is_stmt
+                    && row.line == Some(line)
+                    && self.files[row.file] == path
+            })
+            .map(|row| (row.address, row.column))
+            .collect();
+
+        // Look for the break point location for the best match based on the column specified.
+        locations.sort_by(|a, b| column_order(a.1, b.1).then(a.0.cmp(&b.0)));
+
+        let search_col = match column {
+            Some(0) => ColumnType::LeftEdge,
+            Some(c) => ColumnType::Column(c),
+            None => return Ok(locations.first().map(|loc| loc.0)),
+        };
+
+        let mut best_location = match locations.first() {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+
+        for loc in &locations[1..] {
+            if column_order(loc.1, search_col) == std::cmp::Ordering::Greater {
+                break;
+            }
+
+            if column_order(best_location.1, loc.1) == std::cmp::Ordering::Less {
+                best_location = loc;
+            }
+        }
+
+        Ok(Some(best_location.0))
+    }
+
+    /// Find the functions matching `pattern`, from the symbol table and the debug
+    /// information. The pattern is a mangled name, or a demangled path which can be
+    /// shortened from the front and contain the wildcards `*` and `?`, like
+    /// `driver::*` or `rust_begin_unwind`.
+    pub fn find_functions(&self, pattern: &str) -> Result<Vec<FunctionLocation>, DebugError> {
+        let pattern = pattern.trim();
+
+        let mut functions: Vec<(String, u64)> = self
+            .symbols
+            .iter()
+            .filter(|symbol| {
+                symbol.mangled_name == pattern || function_name_matches(pattern, &symbol.name)
+            })
+            .map(|symbol| (symbol.name.clone(), symbol.address))
+            .collect();
+
+        let mut subprograms = Vec::new();
+
+        for unit in &self.units {
+            let mut tree = unit.entries_tree(None)?;
+            self.collect_subprograms(unit, tree.root()?, &mut Vec::new(), &mut subprograms)?;
+        }
+
+        functions.extend(
+            subprograms
+                .into_iter()
+                .filter(|(name, linkage_name, _)| {
+                    linkage_name.as_deref() == Some(pattern) || function_name_matches(pattern, name)
+                })
+                .map(|(name, _, address)| (name, address)),
+        );
+
+        functions.sort_by_key(|(_, address)| *address);
+        functions.dedup_by_key(|(_, address)| *address);
+
+        Ok(functions
+            .into_iter()
+            .map(|(name, address)| FunctionLocation {
+                name,
+                address,
+                breakpoint_address: self.after_prologue(address),
+            })
+            .collect())
+    }
+
+    /// Collect the qualified names, linkage names and start addresses of all
+    /// functions with code.
+    fn collect_subprograms(
+        &self,
+        unit: &Unit,
+        node: EntriesNode,
+        namespace: &mut Vec<String>,
+        subprograms: &mut Vec<(String, Option<String>, u64)>,
+    ) -> Result<(), DebugError> {
+        let mut children = node.children();
+
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+
+            match entry.tag() {
+                gimli::DW_TAG_namespace => {
+                    namespace.push(self.entry_name(unit, entry)?.unwrap_or_default());
+                    self.collect_subprograms(unit, child, namespace, subprograms)?;
+                    namespace.pop();
+                }
+                gimli::DW_TAG_subprogram => {
+                    let name = match self.entry_name(unit, entry)? {
+                        Some(name) if namespace.is_empty() => name,
+                        Some(name) => format!("{}::{}", namespace.join("::"), name),
+                        None => continue,
+                    };
+
+                    let linkage_name = match entry.attr_value(gimli::DW_AT_linkage_name)? {
+                        Some(value) => {
+                            Some(from_utf8(&self.dwarf.attr_string(unit, value)?)?.to_owned())
+                        }
+                        None => None,
+                    };
+
+                    // Declarations and functions which are only inlined have no ranges.
+                    let mut ranges = self.dwarf.die_ranges(unit, entry)?;
+                    let mut start = None;
+                    while let Some(range) = ranges.next()? {
+                        start =
+                            Some(start.map_or(range.begin, |start: u64| start.min(range.begin)));
+                    }
+
+                    if let Some(start) = start {
+                        subprograms.push((name, linkage_name, start));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the address of the first statement after the prologue of the function
+    /// starting at `address`. Without line information, this is the start of the function.
+    fn after_prologue(&self, address: u64) -> u64 {
+        let end = match self.function_range(address) {
+            Some(range) => range.end,
+            None => match self.symbol_at(address) {
+                Some(symbol) if symbol.size > 0 => symbol.address + symbol.size,
+                _ => return address,
+            },
+        };
+
+        let first = match self.line_row_index(address) {
+            Some(index) if self.lines[index].address == address => index,
+            _ => return address,
+        };
+
+        let rows = self.lines[first..]
+            .iter()
+            .take_while(|row| row.address < end && !row.end_sequence)
+            .filter(|row| row.is_stmt);
+
+        // Without a marked end of the prologue, the prologue is assumed to be the
+        // code of the first line, the function signature.
+        let first_line = self.lines[first].line;
+        let mut next_line = None;
+
+        for row in rows {
+            if row.prologue_end {
+                return row.address;
+            }
+
+            if next_line.is_none() && row.address > address && row.line != first_line {
+                next_line = Some(row.address);
+            }
+        }
+
+        next_line.unwrap_or(address)
+    }
+
+    /// Unwind the stack of the halted core, starting at `pc`.
+    pub fn unwind(&self, core: &mut dyn CoreAccess, pc: u32) -> Vec<StackFrame> {
+        let mut frames: Vec<StackFrame> = Vec::new();
+
+        let mut registers = Registers::from_core(core);
+        registers.read_special_registers(core);
+        registers.set(PC, Some(pc));
+
+        while let Some(pc) = registers.get(PC) {
+            // For all frames except the first one, the program counter is the return address,
+            // which might already belong to the next function. The calling instruction is
+            // right before it. Code interrupted by an exception continues at the stacked
+            // address instead, which is the interrupted instruction itself.
+            let interrupted = matches!(frames.last(), Some(f) if f.is_exception_entry);
+            let lookup_address = if frames.is_empty() || interrupted {
+                u64::from(pc)
+            } else {
+                u64::from(pc) - 1
+            };
+
+            let unwound = match self.unwind_registers(core, lookup_address, &registers) {
+                Ok(unwound) => Some(unwound),
+                Err(e) => {
+                    info!(
+                        "Failed to unwind the stack at address {:#010x}: {:?}",
+                        lookup_address, e
+                    );
+
+                    if frames.is_empty() {
+                        self.unwind_handler_entry(pc, &registers)
+                    } else {
+                        None
+                    }
+                }
+            };
+            let (cfa, caller_registers) = match unwound {
+                Some((cfa, caller_registers)) => (Some(cfa), Some(caller_registers)),
+                None => (None, None),
+            };
+
+            let frame = self.stackframe_info(
+                core,
+                lookup_address,
+                frames.len() as u64,
+                registers,
+                cfa.map(u64::from),
+            );
+
+            debug!("Unwound frame {}: {}", frame.id, frame.function_name);
+
+            frames.push(frame);
+
+            registers = match caller_registers {
+                Some(caller_registers) => caller_registers,
+                None => break,
+            };
+
+            // A handler returns to the interrupted code with an `EXC_RETURN` value, and
+            // the registers of that code are in the exception frame.
+            if let Some(exc_return) = registers.get(LR).filter(|lr| fault::is_exc_return(*lr)) {
+                let interrupted = match unwind_exception(core, exc_return, &registers) {
+                    Ok(interrupted) => interrupted,
+                    Err(e) => {
+                        info!("Failed to unwind the exception frame: {:?}", e);
+                        break;
+                    }
+                };
+
+                frames.push(StackFrame {
+                    id: frames.len() as u64,
+                    function_name: "<exception entry>".to_owned(),
+                    source_location: None,
+                    registers,
+                    pc: exc_return,
+                    variables: Vec::new(),
+                    is_exception_entry: true,
+                });
+
+                registers = interrupted;
+            }
+
+            // The reset value of the link register ends the stack.
+            if let Some(0) | Some(0xffff_ffe0..=0xffff_ffff) = registers.get(PC) {
+                break;
+            }
+
+            if frames.len() >= MAX_FRAMES {
+                warn!("Stopped unwinding after {} frames", MAX_FRAMES);
+                break;
+            }
+
+            // Without any progress, we would unwind the same frame forever.
+            let previous = frames
+                .last()
+                .map(|f| (f.registers.get(PC), f.registers.get(SP)));
+            if previous == Some((registers.get(PC), registers.get(SP))) {
+                break;
+            }
+        }
+
+        frames
+    }
+
+    /// Unwind a handler without unwind information, which is halted at its first
+    /// instruction, e.g. by the vector catch. It hasn't changed the stack yet, and
+    /// returns with the `EXC_RETURN` value in the link register.
+    fn unwind_handler_entry(&self, pc: u32, registers: &Registers) -> Option<(u32, Registers)> {
+        let lr = registers.get(LR).filter(|lr| fault::is_exc_return(*lr))?;
+        let sp = registers.get(SP)?;
+
+        match self.symbol_at(u64::from(pc)) {
+            Some(symbol) if symbol.address == u64::from(pc) => (),
+            _ => return None,
+        }
+
+        let mut caller_registers = registers.clone();
+        caller_registers.set(PC, Some(lr & !1));
+
+        Some((sp, caller_registers))
+    }
+
+    /// Calculate the canonical frame address of the frame at `address`, and the
+    /// register values of its caller.
+    fn unwind_registers(
+        &self,
+        core: &mut dyn CoreAccess,
+        address: u64,
+        registers: &Registers,
+    ) -> Result<(u32, Registers), DebugError> {
+        let mut ctx = gimli::UninitializedUnwindContext::new();
+        let bases = gimli::BaseAddresses::default();
+
+        let unwind_info = self.frame_section.unwind_info_for_address(
+            &bases,
+            &mut ctx,
+            address,
+            gimli::DebugFrame::cie_from_offset,
+        )?;
+
+        let cfa = match unwind_info.cfa() {
+            gimli::CfaRule::RegisterAndOffset { register, offset } => {
+                let value = registers
+                    .get(register.0 as usize)
+                    .ok_or(DebugError::MissingRegister(register.0))?;
+
+                (i64::from(value) + offset) as u32
+            }
+            gimli::CfaRule::Expression(_) => {
+                return Err(DebugError::UnsupportedExpression(
+                    "CFA expressions are not supported".to_owned(),
+                ))
+            }
+        };
+
+        debug!("CFA at {:#010x}: {:#010x}", address, cfa);
+
+        let mut caller_registers = Registers::default();
+
+        for i in 0..16 {
+            use gimli::RegisterRule::*;
+
+            let value = match unwind_info.register(gimli::Register(i as u16)) {
+                // If we get undefined for the LR register (register 14) or any callee saved register,
+                // we assume that it is unchanged. Gimli doesn't allow us
+                // to distinguish if  a rule is not present or actually set to Undefined
+                // in the call frame information.
+                Undefined => match i {
+                    4..=11 | LR => registers.get(i),
+                    _ => None,
+                },
+                SameValue => registers.get(i),
+                Offset(offset) => {
+                    let address = (i64::from(cfa) + offset) as u32;
+
+                    Some(core.read_word_32(address)?)
+                }
+                ValOffset(offset) => Some((i64::from(cfa) + offset) as u32),
+                Register(register) => registers.get(register.0 as usize),
+                rule => {
+                    warn!("Unsupported unwind rule for register {}: {:?}", i, rule);
+                    None
+                }
+            };
+
+            caller_registers.set(i, value);
+        }
+
+        if registers.has_fpu() {
+            self.unwind_fpu_registers(core, &unwind_info, cfa, registers, &mut caller_registers)?;
+        }
+
+        // The stack pointer of the caller is the CFA.
+        caller_registers.set(SP, Some(cfa));
+
+        // The caller continues at the return address. We just have to remove the
+        // lowest bit (indicator for Thumb mode).
+        caller_registers.set(PC, caller_registers.get(LR).map(|lr| lr & !1));
+
+        Ok((cfa, caller_registers))
+    }
+
+    /// Unwind the registers of the FPU. S16 to S31 are callee saved, and usually saved
+    /// as the double precision registers D8 to D15.
+    fn unwind_fpu_registers(
+        &self,
+        core: &mut dyn CoreAccess,
+        unwind_info: &gimli::UnwindTableRow<DwarfReader>,
+        cfa: u32,
+        registers: &Registers,
+        caller_registers: &mut Registers,
+    ) -> Result<(), DebugError> {
+        caller_registers.set(FPSCR, registers.get(FPSCR));
+
+        for i in 16..32 {
+            caller_registers.set(FPU_S0 + i, registers.get(FPU_S0 + i));
+        }
+
+        for d in 8..16 {
+            if let gimli::RegisterRule::Offset(offset) =
+                unwind_info.register(gimli::Register((FPU_D0 + d) as u16))
+            {
+                let address = (i64::from(cfa) + offset) as u32;
+
+                caller_registers.set(FPU_S0 + 2 * d, Some(core.read_word_32(address)?));
+                caller_registers.set(FPU_S0 + 2 * d + 1, Some(core.read_word_32(address + 4)?));
+            }
+        }
+
+        for s in 16..32 {
+            if let gimli::RegisterRule::Offset(offset) =
+                unwind_info.register(gimli::Register((FPU_S0 + s) as u16))
+            {
+                let address = (i64::from(cfa) + offset) as u32;
+
+                caller_registers.set(FPU_S0 + s, Some(core.read_word_32(address)?));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn stackframe_info(
+        &self,
+        core: &mut dyn CoreAccess,
+        address: u64,
+        frame_count: u64,
+        registers: Registers,
+        cfa: Option<u64>,
+    ) -> StackFrame {
+        let mut function_name = format!("<unknown_function_{}>", frame_count);
+        let mut variables = vec![];
+
+        match self.function_at(address) {
+            Ok(Some((unit, function))) => {
+                if let Some(name) = function.name {
+                    function_name = name;
+                }
+
+                match self.function_variables(core, unit, function.offset, address, &registers, cfa)
+                {
+                    Ok(vars) => variables = vars,
+                    Err(e) => warn!("Unable to read variables of {}: {}", function_name, e),
+                }
+            }
+            // Handlers are often written in assembly, and only have a symbol.
+            Ok(None) => {
+                if let Some(symbol) = self.symbol_at(address) {
+                    function_name = symbol.name.clone();
+                }
+            }
+            Err(e) => warn!("Unable to get function at {:#010x}: {}", address, e),
+        }
+
+        StackFrame {
+            id: frame_count,
+            function_name,
+            source_location: self.get_source_location(address),
+            pc: registers.get(PC).unwrap_or(address as u32),
+            registers,
+            variables,
+            is_exception_entry: false,
+        }
+    }
+
+    /// Find the function containing `address`.
+    fn function_at(&self, address: u64) -> Result<Option<(usize, Function)>, DebugError> {
+        for (index, unit) in self.units.iter().enumerate() {
+            // Units without any address ranges are searched as well, to be safe.
+            let mut has_ranges = false;
+            let mut in_unit = false;
+            let mut ranges = self.dwarf.unit_ranges(unit)?;
+            while let Some(range) = ranges.next()? {
+                has_ranges = true;
+
+                if range.begin <= address && address < range.end {
+                    in_unit = true;
+                    break;
+                }
+            }
+
+            if has_ranges && !in_unit {
+                continue;
+            }
+
+            let found = {
+                let mut tree = unit.entries_tree(None)?;
+                let root = tree.root()?;
+
+                self.find_function(unit, root, address, &mut Vec::new())?
+            };
+
+            if let Some(function) = found {
+                return Ok(Some((index, function)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn find_function(
+        &self,
+        unit: &Unit,
+        node: EntriesNode,
+        address: u64,
+        namespace: &mut Vec<String>,
+    ) -> Result<Option<Function>, DebugError> {
+        let mut children = node.children();
+
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+
+            match entry.tag() {
+                gimli::DW_TAG_namespace => {
+                    namespace.push(self.entry_name(unit, entry)?.unwrap_or_default());
+                    let found = self.find_function(unit, child, address, namespace)?;
+                    namespace.pop();
+
+                    if found.is_some() {
+                        return Ok(found);
+                    }
+                }
+                gimli::DW_TAG_subprogram => {
+                    let mut ranges = self.dwarf.die_ranges(unit, entry)?;
+
+                    while let Some(range) = ranges.next()? {
+                        if range.begin <= address && address < range.end {
+                            let name = self.entry_name(unit, entry)?.map(|name| {
+                                if namespace.is_empty() {
+                                    name
+                                } else {
+                                    format!("{}::{}", namespace.join("::"), name)
+                                }
+                            });
+
+                            return Ok(Some(Function {
+                                offset: entry.offset(),
+                                name,
+                                range: range.begin..range.end,
+                            }));
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Get the static variables of the program, with their names qualified by their
+    /// module.
+    pub fn static_variables(&self, core: &mut dyn CoreAccess) -> Result<Vec<Variable>, DebugError> {
+        let mut variables = Vec::new();
+
+        for unit_index in 0..self.units.len() {
+            let mut tree = self.units[unit_index].entries_tree(None)?;
+
+            self.collect_statics(
+                core,
+                unit_index,
+                tree.root()?,
+                &mut Vec::new(),
+                &mut variables,
+            )?;
+        }
+
+        variables.sort_by(|a, b| a.name.cmp(&b.name));
+
+        // Variables can be declared in several compile units.
+        variables.dedup_by(|a, b| a.name == b.name && a.location == b.location);
+
+        Ok(variables)
+    }
+
+    /// Find a type by its name, optionally qualified with its namespaces, like `app::Point`.
+    pub fn find_type(&self, name: &str) -> Result<Option<TypeRef>, DebugError> {
+        for unit_index in 0..self.units.len() {
+            let mut tree = self.units[unit_index].entries_tree(None)?;
+
+            if let Some(type_ref) =
+                self.find_type_in(unit_index, tree.root()?, &mut Vec::new(), name)?
+            {
+                return Ok(Some(type_ref));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn find_type_in(
+        &self,
+        unit_index: usize,
+        node: EntriesNode,
+        namespace: &mut Vec<String>,
+        name: &str,
+    ) -> Result<Option<TypeRef>, DebugError> {
+        let unit = &self.units[unit_index];
+        let mut children = node.children();
+
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+
+            match entry.tag() {
+                gimli::DW_TAG_namespace => {
+                    namespace.push(self.entry_name(unit, entry)?.unwrap_or_default());
+                    let found = self.find_type_in(unit_index, child, namespace, name)?;
+                    namespace.pop();
+
+                    if found.is_some() {
+                        return Ok(found);
+                    }
+                }
+                gimli::DW_TAG_base_type
+                | gimli::DW_TAG_structure_type
+                | gimli::DW_TAG_union_type
+                | gimli::DW_TAG_enumeration_type
+                | gimli::DW_TAG_typedef => {
+                    if let Some(gimli::AttributeValue::Flag(true)) =
+                        entry.attr_value(gimli::DW_AT_declaration)?
+                    {
+                        continue;
+                    }
+
+                    let entry_name = match self.entry_name(unit, entry)? {
+                        Some(entry_name) => entry_name,
+                        None => continue,
+                    };
+
+                    let matches = entry_name == name
+                        || (!namespace.is_empty()
+                            && format!("{}::{}", namespace.join("::"), entry_name) == name);
+
+                    if matches {
+                        return Ok(Some(TypeRef {
+                            unit: unit_index,
+                            offset: entry.offset(),
+                        }));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn collect_statics(
+        &self,
+        core: &mut dyn CoreAccess,
+        unit_index: usize,
+        node: EntriesNode,
+        namespace: &mut Vec<String>,
+        variables: &mut Vec<Variable>,
+    ) -> Result<(), DebugError> {
+        let unit = &self.units[unit_index];
+        let mut children = node.children();
+
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+
+            match entry.tag() {
+                gimli::DW_TAG_namespace => {
+                    namespace.push(self.entry_name(unit, entry)?.unwrap_or_default());
+                    self.collect_statics(core, unit_index, child, namespace, variables)?;
+                    namespace.pop();
+                }
+                gimli::DW_TAG_variable => {
+                    let expression = match entry.attr_value(gimli::DW_AT_location)? {
+                        Some(gimli::AttributeValue::Exprloc(expression)) => expression,
+                        // Declarations of variables which are defined somewhere else
+                        _ => continue,
+                    };
+
+                    let name = match self.entry_name(unit, entry)? {
+                        // Only used to find the source location of defmt log messages.
+                        Some(name) if name == "DEFMT_LOG_STATEMENT" => continue,
+                        Some(name) if namespace.is_empty() => name,
+                        Some(name) => format!("{}::{}", namespace.join("::"), name),
+                        None => continue,
+                    };
+
+                    let frame_context = FrameContext {
+                        registers: &Registers::default(),
+                        frame_base: None,
+                        cfa: None,
+                    };
+
+                    let location =
+                        match self.evaluate_expression(core, unit, expression, &frame_context) {
+                            Ok(pieces) => match pieces.first().map(|p| &p.location) {
+                                Some(Location::Address { address }) => {
+                                    ValueLocation::Memory(*address as u32)
+                                }
+                                _ => ValueLocation::Unavailable,
+                            },
+                            Err(e) => {
+                                warn!("Unable to get location of static {}: {}", name, e);
+                                ValueLocation::Unavailable
+                            }
+                        };
+
+                    // The type might only be given in the declaration.
+                    let type_ref = match self.type_ref(unit_index, entry)? {
+                        Some(type_ref) => Some(type_ref),
+                        None => match entry.attr_value(gimli::DW_AT_specification)? {
+                            Some(gimli::AttributeValue::UnitRef(offset)) => {
+                                self.type_ref(unit_index, &unit.entry(offset)?)?
+                            }
+                            _ => None,
+                        },
+                    };
+
+                    variables.push(Variable {
+                        name,
+                        type_ref,
+                        location,
+                    });
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the name of an entry, following references to its declaration or abstract origin.
+    fn entry_name(
+        &self,
+        unit: &Unit,
+        entry: &gimli::DebuggingInformationEntry<DwarfReader>,
+    ) -> Result<Option<String>, DebugError> {
+        if let Some(name) = entry.attr_value(gimli::DW_AT_name)? {
+            let name = self.dwarf.attr_string(unit, name)?;
+            return Ok(Some(String::from_utf8_lossy(&name).into_owned()));
+        }
+
+        for attr in &[gimli::DW_AT_specification, gimli::DW_AT_abstract_origin] {
+            if let Some(gimli::AttributeValue::UnitRef(offset)) = entry.attr_value(*attr)? {
+                let origin = unit.entry(offset)?;
+                return self.entry_name(unit, &origin);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Read the variables of the function at `offset`, which are visible at `address`.
+    fn function_variables(
+        &self,
+        core: &mut dyn CoreAccess,
+        unit_index: usize,
+        offset: gimli::UnitOffset,
+        address: u64,
+        registers: &Registers,
+        cfa: Option<u64>,
+    ) -> Result<Vec<Variable>, DebugError> {
+        let unit = &self.units[unit_index];
+        let function = unit.entry(offset)?;
+
+        let mut frame_context = FrameContext {
+            registers,
+            frame_base: None,
+            cfa,
+        };
+
+        if let Some(gimli::AttributeValue::Exprloc(expression)) =
+            function.attr_value(gimli::DW_AT_frame_base)?
+        {
+            let pieces = self.evaluate_expression(core, unit, expression, &frame_context)?;
+
+            frame_context.frame_base = match pieces.first().map(|p| &p.location) {
+                Some(Location::Address { address }) => Some(*address),
+                Some(Location::Register { register }) => {
+                    registers.get(register.0 as usize).map(u64::from)
+                }
+                _ => None,
+            };
+        }
+
+        let mut variables = Vec::new();
+
+        let mut tree = unit.entries_tree(Some(offset))?;
+        self.collect_variables(
+            core,
+            unit_index,
+            tree.root()?,
+            address,
+            &frame_context,
+            &mut variables,
+        )?;
+
+        Ok(variables)
+    }
+
+    fn collect_variables(
+        &self,
+        core: &mut dyn CoreAccess,
+        unit_index: usize,
+        node: EntriesNode,
+        address: u64,
+        frame_context: &FrameContext,
+        variables: &mut Vec<Variable>,
+    ) -> Result<(), DebugError> {
+        let unit = &self.units[unit_index];
+        let mut children = node.children();
+
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+
+            match entry.tag() {
+                gimli::DW_TAG_variable | gimli::DW_TAG_formal_parameter => {
+                    let name = self
+                        .entry_name(unit, entry)?
+                        .unwrap_or_else(|| "<unnamed>".to_owned());
+
+                    let location =
+                        match self.variable_location(core, unit, entry, address, frame_context) {
+                            Ok(location) => location,
+                            Err(e) => {
+                                warn!("Unable to get location of variable {}: {}", name, e);
+                                ValueLocation::Unavailable
+                            }
+                        };
+
+                    variables.push(Variable {
+                        name,
+                        type_ref: self.type_ref(unit_index, entry)?,
+                        location,
+                    });
+                }
+                gimli::DW_TAG_lexical_block => {
+                    let mut in_block = false;
+                    let mut ranges = self.dwarf.die_ranges(unit, entry)?;
+
+                    while let Some(range) = ranges.next()? {
+                        if range.begin <= address && address < range.end {
+                            in_block = true;
+                            break;
+                        }
+                    }
+
+                    if in_block {
+                        self.collect_variables(
+                            core,
+                            unit_index,
+                            child,
+                            address,
+                            frame_context,
+                            variables,
+                        )?;
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Find out where the value of a variable is stored, when the core is at `address`.
+    fn variable_location(
+        &self,
+        core: &mut dyn CoreAccess,
+        unit: &Unit,
+        entry: &gimli::DebuggingInformationEntry<DwarfReader>,
+        address: u64,
+        frame_context: &FrameContext,
+    ) -> Result<ValueLocation, DebugError> {
+        let expression = match self.location_expression(unit, entry, address)? {
+            Some(expression) => expression,
+            None => return Ok(ValueLocation::Unavailable),
+        };
+
+        let pieces = self.evaluate_expression(core, unit, expression, frame_context)?;
+
+        if let [piece] = &pieces[..] {
+            if piece.size_in_bits.is_none() {
+                return Ok(match &piece.location {
+                    Location::Address { address } => ValueLocation::Memory(*address as u32),
+                    Location::Register { register } => {
+                        match frame_context.registers.get(register.0 as usize) {
+                            Some(value) => ValueLocation::Register {
+                                register: register.0,
+                                value,
+                            },
+                            None => ValueLocation::Unavailable,
+                        }
+                    }
+                    Location::Value { value } => {
+                        ValueLocation::Value(value.to_u64(u64::MAX)?.to_le_bytes().to_vec())
+                    }
+                    Location::Bytes { value } => ValueLocation::Value(value.to_vec()),
+                    _ => ValueLocation::Unavailable,
+                });
+            }
+        }
+
+        // A value which is split into several pieces is put together by the debugger.
+        let mut data = Vec::new();
+
+        for piece in &pieces {
+            let size = piece
+                .size_in_bits
+                .map(|bits| (bits / 8) as usize)
+                .unwrap_or(4);
+
+            let mut bytes = match &piece.location {
+                Location::Address { address } => {
+                    let mut bytes = vec![0; size];
+                    core.read_8(*address as u32, &mut bytes)?;
+                    bytes
+                }
+                Location::Register { register } => frame_context
+                    .registers
+                    .get(register.0 as usize)
+                    .ok_or(DebugError::MissingRegister(register.0))?
+                    .to_le_bytes()
+                    .to_vec(),
+                Location::Value { value } => value.to_u64(u64::MAX)?.to_le_bytes().to_vec(),
+                Location::Bytes { value } => value.to_vec(),
+                _ => return Ok(ValueLocation::Unavailable),
+            };
+
+            bytes.resize(size, 0);
+            data.extend(bytes);
+        }
+
+        Ok(ValueLocation::Value(data))
+    }
+
+    /// Get the type referenced by the `DW_AT_type` attribute of an entry.
+    fn type_ref(
+        &self,
+        unit_index: usize,
+        entry: &gimli::DebuggingInformationEntry<DwarfReader>,
+    ) -> Result<Option<TypeRef>, DebugError> {
+        Ok(match entry.attr_value(gimli::DW_AT_type)? {
+            Some(gimli::AttributeValue::UnitRef(offset)) => Some(TypeRef {
+                unit: unit_index,
+                offset,
+            }),
+            Some(gimli::AttributeValue::DebugInfoRef(offset)) => {
+                let offset = gimli::UnitSectionOffset::DebugInfoOffset(offset);
+
+                self.units.iter().enumerate().find_map(|(unit, u)| {
+                    offset
+                        .to_unit_offset(u)
+                        .map(|offset| TypeRef { unit, offset })
+                })
+            }
+            _ => None,
+        })
+    }
+
+    /// Get the name of a type, as it would be written in the source code.
+    pub fn type_name(&self, type_ref: TypeRef) -> Result<String, DebugError> {
+        let unit = &self.units[type_ref.unit];
+        let entry = unit.entry(type_ref.offset)?;
+
+        if let Some(name) = self.entry_name(unit, &entry)? {
+            return Ok(name);
+        }
+
+        let target = match self.type_ref(type_ref.unit, &entry)? {
+            Some(target) => self.type_name(target)?,
+            None => "()".to_owned(),
+        };
+
+        Ok(match entry.tag() {
+            gimli::DW_TAG_pointer_type => format!("*{}", target),
+            gimli::DW_TAG_reference_type | gimli::DW_TAG_rvalue_reference_type => {
+                format!("&{}", target)
+            }
+            gimli::DW_TAG_const_type => format!("const {}", target),
+            gimli::DW_TAG_volatile_type => format!("volatile {}", target),
+            gimli::DW_TAG_array_type => {
+                format!("[{}; {}]", target, self.array_count(unit, type_ref.offset)?)
+            }
+            gimli::DW_TAG_subroutine_type => "fn()".to_owned(),
+            _ => target,
+        })
+    }
+
+    /// Get the layout of a type. Typedefs and type qualifiers are resolved to the
+    /// type they refer to, but keep their name.
+    pub fn type_info(&self, type_ref: TypeRef) -> Result<Type, DebugError> {
+        let unit = &self.units[type_ref.unit];
+        let entry = unit.entry(type_ref.offset)?;
+
+        let name = self.type_name(type_ref)?;
+        let target = self.type_ref(type_ref.unit, &entry)?;
+        let byte_size = entry
+            .attr_value(gimli::DW_AT_byte_size)?
+            .and_then(|size| size.udata_value());
+
+        let kind = match entry.tag() {
+            gimli::DW_TAG_typedef
+            | gimli::DW_TAG_const_type
+            | gimli::DW_TAG_volatile_type
+            | gimli::DW_TAG_restrict_type
+            | gimli::DW_TAG_atomic_type => {
+                let target = match target {
+                    Some(target) => self.type_info(target)?,
+                    None => Type {
+                        name: String::new(),
+                        size: 0,
+                        kind: TypeKind::Unknown,
+                    },
+                };
+
+                return Ok(Type { name, ..target });
+            }
+            gimli::DW_TAG_base_type => match entry.attr_value(gimli::DW_AT_encoding)? {
+                Some(gimli::AttributeValue::Encoding(encoding)) => TypeKind::Base(encoding),
+                _ => TypeKind::Unknown,
+            },
+            gimli::DW_TAG_pointer_type
+            | gimli::DW_TAG_reference_type
+            | gimli::DW_TAG_rvalue_reference_type => {
+                return Ok(Type {
+                    name,
+                    size: byte_size.unwrap_or(4),
+                    kind: TypeKind::Pointer(target),
+                })
+            }
+            gimli::DW_TAG_structure_type | gimli::DW_TAG_class_type | gimli::DW_TAG_union_type => {
+                self.structure_members(type_ref)?
+            }
+            gimli::DW_TAG_array_type => {
+                let count = self.array_count(unit, type_ref.offset)?;
+                let element_size = match target {
+                    Some(element) => self.type_info(element)?.size,
+                    None => 0,
+                };
+                let stride = entry
+                    .attr_value(gimli::DW_AT_byte_stride)?
+                    .and_then(|stride| stride.udata_value())
+                    .unwrap_or(element_size);
+
+                return Ok(Type {
+                    name,
+                    size: byte_size.unwrap_or(count * stride),
+                    kind: TypeKind::Array {
+                        element: target,
+                        count,
+                        stride,
+                    },
+                });
+            }
+            gimli::DW_TAG_enumeration_type => {
+                let mut enumerators = Vec::new();
+
+                let mut tree = unit.entries_tree(Some(type_ref.offset))?;
+                let mut children = tree.root()?.children();
+                while let Some(child) = children.next()? {
+                    let entry = child.entry();
+
+                    if entry.tag() == gimli::DW_TAG_enumerator {
+                        let name = self.entry_name(unit, entry)?.unwrap_or_default();
+                        let value = entry
+                            .attr_value(gimli::DW_AT_const_value)?
+                            .and_then(|value| constant_value(&value));
+
+                        if let Some(value) = value {
+                            enumerators.push((name, value));
+                        }
+                    }
+                }
+
+                TypeKind::Enumeration(enumerators)
+            }
+            _ => TypeKind::Unknown,
+        };
+
+        let size = match (byte_size, target) {
+            (Some(size), _) => size,
+            // The size of an enumeration can also be given by its underlying type.
+            (None, Some(target)) => self.type_info(target)?.size,
+            (None, None) => 0,
+        };
+
+        Ok(Type { name, size, kind })
+    }
+
+    /// Number of elements of the array type at `offset`. The dimensions of
+    /// multi-dimensional arrays are multiplied.
+    fn array_count(&self, unit: &Unit, offset: gimli::UnitOffset) -> Result<u64, DebugError> {
+        let mut count = 1;
+
+        let mut tree = unit.entries_tree(Some(offset))?;
+        let mut children = tree.root()?.children();
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+
+            if entry.tag() != gimli::DW_TAG_subrange_type {
+                continue;
+            }
+
+            let lower_bound = entry
+                .attr_value(gimli::DW_AT_lower_bound)?
+                .and_then(|bound| bound.udata_value())
+                .unwrap_or(0);
+
+            let dimension = match entry.attr_value(gimli::DW_AT_count)? {
+                Some(value) => value.udata_value().unwrap_or(0),
+                None => entry
+                    .attr_value(gimli::DW_AT_upper_bound)?
+                    .and_then(|bound| bound.udata_value())
+                    .map(|bound| bound + 1 - lower_bound)
+                    .unwrap_or(0),
+            };
+
+            count *= dimension;
+        }
+
+        Ok(count)
+    }
+
+    fn structure_members(&self, type_ref: TypeRef) -> Result<TypeKind, DebugError> {
+        let unit = &self.units[type_ref.unit];
+
+        let mut members = Vec::new();
+        let mut variants = None;
+
+        let mut tree = unit.entries_tree(Some(type_ref.offset))?;
+        let mut children = tree.root()?.children();
+        while let Some(child) = children.next()? {
+            match child.entry().tag() {
+                gimli::DW_TAG_member => {
+                    if let Some(member) = self.member(type_ref.unit, child.entry())? {
+                        members.push(member);
+                    }
+                }
+                gimli::DW_TAG_variant_part => {
+                    variants = Some(self.variant_part(type_ref.unit, child)?);
+                }
+                _ => (),
+            }
+        }
+
+        Ok(TypeKind::Structure { members, variants })
+    }
+
+    fn variant_part(
+        &self,
+        unit_index: usize,
+        node: EntriesNode,
+    ) -> Result<VariantPart, DebugError> {
+        let unit = &self.units[unit_index];
+
+        let discriminant_offset = match node.entry().attr_value(gimli::DW_AT_discr)? {
+            Some(gimli::AttributeValue::UnitRef(offset)) => Some(offset),
+            _ => None,
+        };
+
+        let mut discriminant = None;
+        let mut variants = Vec::new();
+
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+
+            match entry.tag() {
+                gimli::DW_TAG_member if Some(entry.offset()) == discriminant_offset => {
+                    discriminant = self.member(unit_index, entry)?;
+                }
+                gimli::DW_TAG_variant => {
+                    let discriminant_value = entry
+                        .attr_value(gimli::DW_AT_discr_value)?
+                        .and_then(|value| constant_value(&value));
+
+                    let mut members = child.children();
+                    while let Some(member) = members.next()? {
+                        if member.entry().tag() != gimli::DW_TAG_member {
+                            continue;
+                        }
+
+                        if let Some(member) = self.member(unit_index, member.entry())? {
+                            variants.push(Variant {
+                                discriminant_value,
+                                member,
+                            });
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        // The discriminant can also be declared outside of the variant part.
+        if discriminant.is_none() {
+            if let Some(offset) = discriminant_offset {
+                discriminant = self.member(unit_index, &unit.entry(offset)?)?;
+            }
+        }
+
+        Ok(VariantPart {
+            discriminant,
+            variants,
+        })
+    }
+
+    /// Read a data member. Static members are skipped.
+    fn member(
+        &self,
+        unit_index: usize,
+        entry: &gimli::DebuggingInformationEntry<DwarfReader>,
+    ) -> Result<Option<Member>, DebugError> {
+        if entry.attr_value(gimli::DW_AT_external)?.is_some()
+            || entry.attr_value(gimli::DW_AT_declaration)?.is_some()
+        {
+            return Ok(None);
+        }
+
+        let unit = &self.units[unit_index];
+
+        let offset = match entry.attr_value(gimli::DW_AT_data_member_location)? {
+            Some(gimli::AttributeValue::Exprloc(expression)) => {
+                let mut operations = expression.operations(unit.encoding());
+
+                match operations.next()? {
+                    Some(gimli::Operation::PlusConstant { value }) => value,
+                    operation => {
+                        return Err(DebugError::UnsupportedExpression(format!(
+                            "Member location {:?}",
+                            operation
+                        )))
+                    }
+                }
+            }
+            Some(value) => value.udata_value().unwrap_or(0),
+            None => 0,
+        };
+
+        Ok(Some(Member {
+            name: self.entry_name(unit, entry)?.unwrap_or_default(),
+            offset,
+            type_ref: self.type_ref(unit_index, entry)?,
+        }))
+    }
+
+    /// Get the location expression of a variable, which is valid at `address`.
+    fn location_expression(
+        &self,
+        unit: &Unit,
+        entry: &gimli::DebuggingInformationEntry<DwarfReader>,
+        address: u64,
+    ) -> Result<Option<gimli::Expression<DwarfReader>>, DebugError> {
+        match entry.attr_value(gimli::DW_AT_location)? {
+            Some(gimli::AttributeValue::Exprloc(expression)) => Ok(Some(expression)),
+            Some(gimli::AttributeValue::LocationListsRef(offset)) => {
+                let mut locations = self.dwarf.locations(unit, offset)?;
+
+                while let Some(location) = locations.next()? {
+                    if location.range.begin <= address && address < location.range.end {
+                        return Ok(Some(location.data));
+                    }
+                }
+
+                Ok(None)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn evaluate_expression(
+        &self,
+        core: &mut dyn CoreAccess,
+        unit: &Unit,
+        expression: gimli::Expression<DwarfReader>,
+        frame_context: &FrameContext,
+    ) -> Result<Vec<Piece<DwarfReader>>, DebugError> {
+        let mut evaluation = expression.evaluation(unit.encoding());
+
+        let mut result = evaluation.evaluate()?;
+
+        loop {
+            result = match result {
+                EvaluationResult::Complete => break,
+                EvaluationResult::RequiresMemory { address, size, .. } => {
+                    let mut data = [0u8; 8];
+                    core.read_8(address as u32, &mut data[..size as usize])?;
+
+                    evaluation
+                        .resume_with_memory(gimli::Value::Generic(u64::from_le_bytes(data)))?
+                }
+                EvaluationResult::RequiresRegister { register, .. } => {
+                    let value = frame_context
+                        .registers
+                        .get(register.0 as usize)
+                        .ok_or(DebugError::MissingRegister(register.0))?;
+
+                    evaluation.resume_with_register(gimli::Value::Generic(u64::from(value)))?
+                }
+                EvaluationResult::RequiresFrameBase => {
+                    let frame_base = frame_context.frame_base.ok_or_else(|| {
+                        DebugError::UnsupportedExpression("Frame base is unknown".to_owned())
+                    })?;
+
+                    evaluation.resume_with_frame_base(frame_base)?
+                }
+                EvaluationResult::RequiresCallFrameCfa => {
+                    let cfa = frame_context.cfa.ok_or_else(|| {
+                        DebugError::UnsupportedExpression("CFA is unknown".to_owned())
+                    })?;
+
+                    evaluation.resume_with_call_frame_cfa(cfa)?
+                }
+                EvaluationResult::RequiresRelocatedAddress(address) => {
+                    evaluation.resume_with_relocated_address(address)?
+                }
+                other => {
+                    return Err(DebugError::UnsupportedExpression(format!("{:?}", other)));
+                }
+            }
+        }
+
+        Ok(evaluation.result())
+    }
+}
+
+/// Get the value of a constant attribute, e.g. the value of an enumerator.
+fn constant_value(value: &gimli::AttributeValue<DwarfReader>) -> Option<u64> {
+    match value {
+        gimli::AttributeValue::Sdata(value) => Some(*value as u64),
+        value => value.udata_value(),
+    }
+}
+
+/// Order columns, so that the left edge comes before all other columns.
+fn column_order(a: ColumnType, b: ColumnType) -> std::cmp::Ordering {
+    let value = |c| match c {
+        ColumnType::LeftEdge => 0,
+        ColumnType::Column(c) => c,
+    };
+
+    value(a).cmp(&value(b))
+}
+
+/// Check if a demangled function name matches the name of a function breakpoint.
+/// The pattern can leave out leading path segments, `*` matches any sequence of
+/// characters and `?` a single character.
+fn function_name_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+
+    std::iter::once(name)
+        .chain(
+            name.match_indices("::")
+                .map(|(index, _)| &name[index + 2..]),
+        )
+        .any(|candidate| glob_matches(&pattern, &candidate.chars().collect::<Vec<_>>()))
+}
+
+fn glob_matches(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Position after the last `*`, and the text position it currently matches up to.
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_t)) => {
+                    p = star_p;
+                    t = star_t + 1;
+                    backtrack = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Recover the registers of the code interrupted by an exception, from the frame the
+/// core stacked on exception entry. `registers` are the values when the handler
+/// returns, with the `EXC_RETURN` value `exc_return` in the link register.
+fn unwind_exception(
+    core: &mut dyn CoreAccess,
+    exc_return: u32,
+    registers: &Registers,
+) -> Result<Registers, DebugError> {
+    let address = if fault::uses_psp(exc_return) {
+        core.read_core_reg(CoreRegisterAddress(fault::PSP))?
+    } else {
+        registers
+            .get(SP)
+            .ok_or(DebugError::MissingRegister(SP as u16))?
+    };
+
+    let frame = fault::ExceptionFrame::read(core, address)?;
+
+    debug!(
+        "Exception frame at {:#010x}: {:x?}",
+        address, frame.registers
+    );
+
+    // R4 to R11 are callee saved, so the handler restores them like any other function.
+    let mut interrupted = registers.clone();
+    for (register, value) in [0, 1, 2, 3, 12, LR, PC, XPSR]
+        .iter()
+        .zip(frame.registers.iter())
+    {
+        interrupted.set(*register, Some(*value));
+    }
+
+    let mut size = 0x20;
+
+    if fault::is_extended_frame(exc_return) {
+        size = fault::EXTENDED_FRAME_SIZE;
+
+        // With lazy stacking, the registers are still in the FPU until the handler uses it.
+        let lazy = matches!(core.read_word_32(FPCCR), Ok(fpccr) if fpccr & FPCCR_LSPACT != 0);
+
+        if registers.has_fpu() && !lazy {
+            // S0 to S15 and FPSCR follow the basic frame.
+            let mut data = [0u8; 17 * 4];
+            core.read_8(address + 0x20, &mut data)?;
+
+            for (i, bytes) in data.chunks_exact(4).enumerate() {
+                let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                let register = if i < 16 { FPU_S0 + i } else { FPSCR };
+
+                interrupted.set(register, Some(value));
+            }
+        }
+    }
+
+    // The core aligns the frame to 8 bytes, and sets bit 9 of the stacked xPSR if it
+    // had to skip a word for that.
+    if frame.xpsr() & (1 << 9) != 0 {
+        size += 4;
+    }
+
+    interrupted.set(SP, Some(address + size));
+
+    Ok(interrupted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{function_name_matches, DebugInfo, PC, SP};
+    use crate::simulator::SimulatedTarget;
+    use gimli::{
+        write::{
+            Address, AttributeValue, CallFrameInstruction, CommonInformationEntry, DwarfUnit,
+            EndianVec, FrameDescriptionEntry, FrameTable, LineProgram, LineString, Sections,
+        },
+        Encoding, Format, LineEncoding, LittleEndian, Register,
+    };
+    use object::{
+        write::{Object, StandardSegment},
+        Architecture, BinaryFormat, Endianness, SectionKind,
+    };
+
+    const MAIN: u64 = 0x0800_0100;
+    const ADD_ONE: u64 = 0x0800_0140;
+    const RAM_START: u32 = 0x2000_0000;
+
+    /// Build an ELF file with the functions `app::main`, lines 10 to 14, and
+    /// `app::add_one`, lines 20 to 22. Both start with `push {r7, lr}`.
+    fn program() -> DebugInfo {
+        let encoding = Encoding {
+            format: Format::Dwarf32,
+            version: 4,
+            address_size: 4,
+        };
+
+        let mut dwarf = DwarfUnit::new(encoding);
+
+        let mut line_program = LineProgram::new(
+            encoding,
+            LineEncoding::default(),
+            LineString::String(b"/work/app".to_vec()),
+            LineString::String(b"src/main.rs".to_vec()),
+            None,
+        );
+        let directory = line_program.add_directory(LineString::String(b"src".to_vec()));
+        let file = line_program.add_file(LineString::String(b"main.rs".to_vec()), directory, None);
+
+        line_program.begin_sequence(Some(Address::Constant(MAIN)));
+        for (address, line) in &[
+            (0x100, 10),
+            (0x104, 11),
+            (0x10a, 12),
+            (0x110, 13),
+            (0x114, 14),
+            (0x140, 20),
+            (0x146, 21),
+            (0x14c, 22),
+        ] {
+            let row = line_program.row();
+            row.address_offset = address - 0x100;
+            row.file = file;
+            row.line = *line;
+            line_program.generate_row();
+        }
+        line_program.end_sequence(0x60);
+
+        dwarf.unit.line_program = line_program;
+
+        let root = dwarf.unit.root();
+        let cu = dwarf.unit.get_mut(root);
+        cu.set(
+            gimli::DW_AT_name,
+            AttributeValue::String(b"src/main.rs".to_vec()),
+        );
+        cu.set(
+            gimli::DW_AT_comp_dir,
+            AttributeValue::String(b"/work/app".to_vec()),
+        );
+        cu.set(
+            gimli::DW_AT_low_pc,
+            AttributeValue::Address(Address::Constant(MAIN)),
+        );
+        cu.set(gimli::DW_AT_high_pc, AttributeValue::Udata(0x60));
+
+        let namespace = dwarf.unit.add(root, gimli::DW_TAG_namespace);
+        dwarf
+            .unit
+            .get_mut(namespace)
+            .set(gimli::DW_AT_name, AttributeValue::String(b"app".to_vec()));
+
+        for (name, address, size) in &[(&b"main"[..], MAIN, 0x40), (b"add_one", ADD_ONE, 0x20)] {
+            let function = dwarf.unit.add(namespace, gimli::DW_TAG_subprogram);
+            let entry = dwarf.unit.get_mut(function);
+            entry.set(gimli::DW_AT_name, AttributeValue::String(name.to_vec()));
+            entry.set(
+                gimli::DW_AT_low_pc,
+                AttributeValue::Address(Address::Constant(*address)),
+            );
+            entry.set(gimli::DW_AT_high_pc, AttributeValue::Udata(*size));
+        }
+
+        let mut sections = Sections::new(EndianVec::new(LittleEndian));
+        dwarf.write(&mut sections).unwrap();
+
+        let mut frames = FrameTable::default();
+        let mut cie = CommonInformationEntry::new(encoding, 2, -4, Register(14));
+        cie.add_instruction(CallFrameInstruction::Cfa(Register(13), 0));
+        let cie = frames.add_cie(cie);
+
+        for (address, size) in &[(MAIN, 0x40), (ADD_ONE, 0x20)] {
+            let mut fde = FrameDescriptionEntry::new(Address::Constant(*address), *size);
+            fde.add_instruction(2, CallFrameInstruction::CfaOffset(8));
+            fde.add_instruction(2, CallFrameInstruction::Offset(Register(14), -4));
+            fde.add_instruction(2, CallFrameInstruction::Offset(Register(7), -8));
+            frames.add_fde(cie, fde);
+        }
+
+        let mut debug_frame = gimli::write::DebugFrame(EndianVec::new(LittleEndian));
+        frames.write_debug_frame(&mut debug_frame).unwrap();
+
+        let mut elf = Object::new(BinaryFormat::Elf, Architecture::Arm, Endianness::Little);
+
+        let debug_segment = elf.segment_name(StandardSegment::Debug).to_vec();
+        let mut add_section = |name: &str, data: &[u8]| {
+            let id = elf.add_section(
+                debug_segment.clone(),
+                name.as_bytes().to_vec(),
+                SectionKind::Debug,
+            );
+            elf.append_section_data(id, data, 1);
+        };
+
+        sections
+            .for_each(|id, data| {
+                if !data.slice().is_empty() {
+                    add_section(id.name(), data.slice());
+                }
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        add_section(".debug_frame", debug_frame.slice());
+
+        DebugInfo::from_raw(&elf.write().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn statement_at() {
+        let debug_info = program();
+
+        let statement = debug_info.statement_at(ADD_ONE + 8).unwrap();
+        assert_eq!(statement.address, ADD_ONE + 6);
+        assert_eq!(statement.line, 21);
+        assert!(statement.is_stmt);
+
+        let next = debug_info.statement_at(ADD_ONE + 0xc).unwrap();
+        assert_eq!(next.line, 22);
+        assert!(!statement.same_line(&next));
+        assert!(statement.same_line(&debug_info.statement_at(ADD_ONE + 6).unwrap()));
+
+        let location = debug_info.get_source_location(ADD_ONE + 8).unwrap();
+        assert_eq!(
+            location.path(),
+            Some(std::path::PathBuf::from("/work/app/src/main.rs"))
+        );
+
+        // Outside of the line table, and after the end of the sequence.
+        assert_eq!(debug_info.statement_at(MAIN - 2), None);
+        assert_eq!(debug_info.statement_at(MAIN + 0x60), None);
+    }
+
+    #[test]
+    fn function_at() {
+        let debug_info = program();
+
+        let (_, function) = debug_info.function_at(ADD_ONE + 8).unwrap().unwrap();
+        assert_eq!(function.name.as_deref(), Some("app::add_one"));
+        assert_eq!(function.range, ADD_ONE..ADD_ONE + 0x20);
+
+        let (_, function) = debug_info.function_at(MAIN).unwrap().unwrap();
+        assert_eq!(function.name.as_deref(), Some("app::main"));
+
+        assert!(debug_info.function_at(MAIN - 2).unwrap().is_none());
+        assert!(debug_info.function_at(ADD_ONE + 0x20).unwrap().is_none());
+    }
+
+    #[test]
+    fn unwind() {
+        let debug_info = program();
+
+        let mut target = SimulatedTarget::new();
+        target.add_memory(RAM_START, vec![0; 0x100]);
+
+        // Frame of `add_one`, called from line 13 of `main`: saved r7 and lr.
+        let sp = RAM_START + 0xe0;
+        target.write_memory(sp, &0x2000_00f0u32.to_le_bytes());
+        target.write_memory(sp + 4, &0x0800_0115u32.to_le_bytes());
+
+        // Frame of `main`, with the reset value of the link register.
+        target.write_memory(sp + 8, &0u32.to_le_bytes());
+        target.write_memory(sp + 0xc, &0xffff_ffffu32.to_le_bytes());
+
+        target.set_register(SP as u16, sp);
+
+        let frames = debug_info.unwind(&mut target, ADD_ONE as u32 + 8);
+        assert_eq!(frames.len(), 2);
+
+        assert_eq!(frames[0].function_name, "app::add_one");
+        assert_eq!(frames[0].pc, ADD_ONE as u32 + 8);
+        assert_eq!(frames[0].source_location.as_ref().unwrap().line, Some(21));
+
+        assert_eq!(frames[1].function_name, "app::main");
+        assert_eq!(frames[1].registers.get(PC), Some(0x0800_0114));
+        assert_eq!(frames[1].registers.get(SP), Some(sp + 8));
+        assert_eq!(frames[1].registers.get(7), Some(0x2000_00f0));
+        assert_eq!(frames[1].source_location.as_ref().unwrap().line, Some(13));
+    }
+
+    #[test]
+    fn function_names() {
+        let name = "my_crate::driver::Uart::write";
+
+        for pattern in &[
+            "my_crate::driver::Uart::write",
+            "Uart::write",
+            "write",
+            "my_crate::driver::*",
+            "driver::*::write",
+            "*::wr?te",
+            "*",
+        ] {
+            assert!(function_name_matches(pattern, name), "{}", pattern);
+        }
+
+        for pattern in &["rite", "driver::Uart", "other::*", "Uart::write::*", "wr?e"] {
+            assert!(!function_name_matches(pattern, name), "{}", pattern);
+        }
+    }
+}
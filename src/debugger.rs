@@ -1,7 +1,27 @@
+use crate::breakpoints::{self, Breakpoints, HitBreakpoint};
+use crate::dap_types::{
+    DisassembleArguments, DisassembleResponseBody, DisassembledInstruction, EvaluateResponseBody,
+    InvalidatedEventBody, ReadMemoryArguments, ReadMemoryResponseBody, SetExpressionResponseBody,
+    SetVariableResponseBody, StackFrame, StackTraceResponseBody, StepArguments, StoppedEventBody,
+    Variable, VariablesResponseBody, WriteMemoryArguments, WriteMemoryResponseBody,
+};
 use crate::debug_adapter::{self, DebugAdapter};
-use probe_rs::{debug::DebugInfo, Probe, Session};
+use crate::debug_info::{self, DebugInfo};
+use crate::disassembly::Disassembler;
+use crate::evaluate::{self, Place};
+use crate::exceptions::{self, ExceptionFilter};
+use crate::fault::{self, FaultStatus};
+use crate::itm::{self, Itm, SwoConfig};
+use crate::memory;
+use crate::rtt::{self, Rtt, RttConfig, RttWriteArguments, RttWriteResponseBody};
+use crate::semihosting::{self, Effect, Semihosting};
+use crate::stepping::{self, Step, StepKind, StepResult};
+use crate::svd;
+use crate::target::{CoreAccess, TargetAccess};
+use crate::variables::{self, ChildFilter, VariableContainer, VariableRegistry};
+use probe_rs::{architecture::arm, CoreRegisterAddress, CoreStatus, HaltReason, Probe, Session};
 use std::{
-    io::{Read, Write},
+    io::Write,
     path::{Path, PathBuf},
     time::Duration,
 };
@@ -11,19 +31,90 @@ use log::{debug, error, info, trace, warn};
 
 use debugserver_types::*;
 
-use debug_adapter::{DebugAdapterMessage, Event};
+use debug_adapter::{DebugAdapterMessage, Event, RestartRequest};
 use serde::{de::DeserializeOwned, Deserialize};
 
+/// The `invalidated` event after a value was written to `place`. Changed registers
+/// can change the whole stack trace.
+fn invalidated(place: Place) -> InvalidatedEventBody {
+    let area = match place {
+        Place::Memory(_) => "variables",
+        Place::Register(_) => "all",
+    };
+
+    InvalidatedEventBody {
+        areas: Some(vec![area.to_owned()]),
+        ..InvalidatedEventBody::default()
+    }
+}
+
+/// An exception the core stopped at because of an exception filter, for the
+/// `exceptionInfo` request.
+#[derive(Debug, Clone)]
+struct StoppedException {
+    filter: &'static ExceptionFilter,
+    /// Number of the active exception, from the IPSR.
+    number: u32,
+}
+
+impl StoppedException {
+    fn id(&self) -> String {
+        if self.filter.filter == "rust_panic" {
+            "panic".to_owned()
+        } else {
+            exceptions::exception_name(self.number)
+        }
+    }
+
+    fn description(&self) -> String {
+        match self.filter.filter {
+            "rust_panic" => "The program panicked".to_owned(),
+            "default_handler" => format!(
+                "{} is handled by the default handler",
+                exceptions::exception_name(self.number)
+            ),
+            _ => format!("{} exception", exceptions::exception_name(self.number)),
+        }
+    }
+
+    /// Faults are caught by the vector catch, so the core is halted at the entry of
+    /// the fault handler.
+    fn is_fault(&self) -> bool {
+        self.filter.vector_catch != 0
+    }
+}
+
+/// Creates the target for the given chip, when the debugger launches or attaches.
+pub type TargetConnector = Box<dyn Fn(&str) -> Result<Box<dyn TargetAccess>, anyhow::Error>>;
+
 #[derive(Default)]
 pub struct Debugger {
     location: PathBuf,
     arguments: AttachRequestArguments,
     program: Option<PathBuf>,
-    session: Option<Session>,
+    /// Used instead of a debug probe to connect to the target, if set.
+    connector: Option<TargetConnector>,
+    target: Option<Box<dyn TargetAccess>>,
     debug_info: Option<DebugInfo>,
-    breakpoints: Vec<BreakpointInfo>,
-    bp_id: u32,
-    current_stackframes: Vec<probe_rs::debug::StackFrame>,
+    /// Peripherals of the device, from the SVD file given in the arguments.
+    svd: Option<svd::Device>,
+    breakpoints: Breakpoints,
+    current_stackframes: Vec<debug_info::StackFrame>,
+    /// References to the variables shown to the client.
+    variables: VariableRegistry,
+    /// Set while the core is running, so that we can notify the client once it halts.
+    core_running: bool,
+    /// A step which waits for the core to reach a return address.
+    step: Option<Step>,
+    /// The exception the core stopped at, if it was stopped by an exception filter.
+    exception: Option<StoppedException>,
+    /// RTT channels, which are read while the core is running.
+    rtt: Option<Rtt>,
+    semihosting: Semihosting,
+    /// Decoder of the SWO output, once SWO is set up.
+    itm: Option<Itm>,
+    /// Set once the `terminated` event was sent, which ends the session.
+    terminated: bool,
 }
 
 impl Debugger {
@@ -34,36 +125,250 @@ impl Debugger {
         }
     }
 
-    fn add_breakpoint(&mut self, bp: &SourceBreakpoint, verified: bool, location: Option<u64>) {
-        let id = self.bp_id;
-        self.bp_id += 1;
+    /// Create a debugger, which uses `connector` instead of a debug probe to connect to the target.
+    pub fn with_connector(location: impl Into<PathBuf>, connector: TargetConnector) -> Debugger {
+        Debugger {
+            location: location.into(),
+            connector: Some(connector),
+            ..Default::default()
+        }
+    }
 
-        self.breakpoints.push(BreakpointInfo {
-            id,
-            verified,
-            info: bp.to_owned(),
-            address: location,
-        });
+    fn connect(&self, chip: &str) -> Result<Box<dyn TargetAccess>, anyhow::Error> {
+        match &self.connector {
+            Some(connector) => connector(chip),
+            None => Ok(Box::new(connect_to_probe(chip)?)),
+        }
+    }
+
+    /// Resolve the path to the program to be debugged, and try to load its debug information.
+    fn load_program(&mut self, program: &str, cwd: Option<&str>) -> PathBuf {
+        let mut program_path = PathBuf::from(program);
+
+        if let Some(cwd) = cwd {
+            debug!("Current working directory: '{}'", cwd);
+            self.location = PathBuf::from(cwd);
+
+            // If the path to the programm to be debugged is relative, we join if with the
+            if program_path.is_relative() {
+                program_path = self.location.clone().join(&program_path);
+            }
+        }
+
+        self.debug_info = match DebugInfo::from_file(&program_path) {
+            Ok(di) => Some(di),
+            Err(e) => {
+                // Just log this, debugging without debug info should be possible.
+                // Showing a warning to the user would be optimal, but not clear how
+                // this can be done with vs code.
+                warn!(
+                    "Unable to read debug information from file '{}': {}",
+                    program_path.display(),
+                    e
+                );
+                None
+            }
+        };
+
+        self.program = Some(program_path.clone());
+
+        program_path
+    }
+
+    /// Load the peripherals from the SVD file at `path`, which is relative to the working directory.
+    fn load_svd<W: Write>(
+        &mut self,
+        adapter: &mut DebugAdapter<W>,
+        path: Option<&str>,
+    ) -> Result<(), debug_adapter::Error> {
+        let path = match path {
+            Some(path) => self.location.join(path),
+            None => {
+                self.svd = None;
+                return Ok(());
+            }
+        };
+
+        self.svd = match svd::Device::from_file(&path) {
+            Ok(device) => Some(device),
+            Err(e) => {
+                warn!("Unable to read SVD file '{}': {}", path.display(), e);
+                adapter.log_to_console(format!(
+                    "Unable to read SVD file '{}': {}\n",
+                    path.display(),
+                    e
+                ))?;
+                None
+            }
+        };
+
+        Ok(())
+    }
+
+    /// Read the RTT channels given in `config` while the core is running. Without an
+    /// address or scan range, the control block is found by its symbol.
+    fn setup_rtt<W: Write>(
+        &mut self,
+        adapter: &mut DebugAdapter<W>,
+        config: Option<&RttConfig>,
+    ) -> Result<(), debug_adapter::Error> {
+        let config = match config {
+            Some(config) => config.clone(),
+            None => {
+                self.rtt = None;
+                return Ok(());
+            }
+        };
+
+        let symbol = self
+            .debug_info
+            .as_ref()
+            .and_then(|debug_info| debug_info.data_symbol(rtt::CONTROL_BLOCK_SYMBOL))
+            .map(|address| address as u32);
+
+        let defmt = self
+            .debug_info
+            .as_ref()
+            .and_then(|debug_info| debug_info.defmt_table());
+
+        let rtt = Rtt::new(config, symbol, defmt.cloned());
+
+        if rtt.uses_defmt() {
+            match defmt {
+                None => adapter.log_to_console(
+                    "defmt frames can't be decoded: the program has no .defmt section\n",
+                )?,
+                Some(table) => {
+                    if let Some(encoding) = table.unsupported_encoding() {
+                        adapter.log_to_console(format!(
+                            "defmt frames can't be decoded: the encoding '{}' is not \
+                             supported, enable the `encoding-raw` feature of defmt\n",
+                            encoding
+                        ))?;
+                    }
+                }
+            }
+        }
+
+        self.rtt = if rtt.has_location() {
+            Some(rtt)
+        } else {
+            adapter.log_to_console(format!(
+                "RTT is not available: the program has no symbol {}, and neither an \
+                 address nor a scan range for the control block is given\n",
+                rtt::CONTROL_BLOCK_SYMBOL
+            ))?;
+            None
+        };
+
+        Ok(())
+    }
+
+    /// Set up SWO as given in the arguments, after the target is connected.
+    fn setup_swo<W: Write>(
+        &mut self,
+        adapter: &mut DebugAdapter<W>,
+    ) -> Result<(), debug_adapter::Error> {
+        let config = match self.arguments.swo.clone() {
+            Some(config) => config,
+            None => {
+                self.itm = None;
+                return Ok(());
+            }
+        };
+
+        self.itm = match self.enable_swo(&config) {
+            Ok(()) => Some(Itm::new(&config)),
+            Err(e) => {
+                warn!("Failed to set up SWO: {:?}", e);
+                adapter.log_to_console(format!("SWO is not available: {}\n", e))?;
+                None
+            }
+        };
+
+        Ok(())
+    }
+
+    /// Configure the TPIU and the probe for SWO, and enable the stimulus ports.
+    fn enable_swo(&mut self, config: &SwoConfig) -> Result<(), anyhow::Error> {
+        if config.baud_rate == 0 {
+            return Err(anyhow!("The baud rate must not be 0"));
+        }
+
+        let ports = config.enabled_ports().map_err(|e| anyhow!(e))?;
+
+        let target = self
+            .target
+            .as_mut()
+            .ok_or_else(|| anyhow!("Not connected to a target"))?;
+
+        let swo = arm::SwoConfig::new(config.tpiu_clock()).set_baud(config.baud_rate);
+        target.setup_swo(&swo)?;
+
+        // All ports are enabled by the setup.
+        target.core()?.write_word_32(itm::ITM_TER, ports)?;
+
+        Ok(())
+    }
+
+    /// Set breakpoints which were requested before a session to the probe was available.
+    fn set_pending_breakpoints<W: Write>(
+        &mut self,
+        adapter: &mut DebugAdapter<W>,
+    ) -> Result<(), debug_adapter::Error> {
+        let mut core = self
+            .target
+            .as_mut()
+            .ok_or(debug_adapter::Error::MissingSession)?
+            .core()?;
+
+        for event_body in self.breakpoints.set_pending(&mut *core) {
+            adapter.send_event(&Event::Breakpoint(event_body))?;
+        }
+
+        Ok(())
     }
 
-    pub fn handle<R: Read, W: Write>(
+    pub fn handle<W: Write>(
         &mut self,
-        adapter: &mut DebugAdapter<R, W>,
+        adapter: &mut DebugAdapter<W>,
         req: &DebugAdapterMessage,
     ) -> Result<HandleResult, crate::debug_adapter::Error> {
-        match req {
-            DebugAdapterMessage::Request(req) => self.handle_request(req, adapter),
+        let result = match req {
+            DebugAdapterMessage::Request(req) => self.handle_request(req, adapter)?,
             other => {
                 log::warn!("Unexpected message: {:?}", other);
-                Ok(HandleResult::Continue)
+                HandleResult::Continue
             }
+        };
+
+        // The program might have exited while it was stepped.
+        if self.terminated {
+            return Ok(HandleResult::Stop);
+        }
+
+        Ok(result)
+    }
+
+    /// Notify the client that the session ended. The `terminated` event is only sent
+    /// once, also if the session ends because the program exited.
+    pub fn terminate<W: Write>(
+        &mut self,
+        adapter: &mut DebugAdapter<W>,
+    ) -> Result<(), debug_adapter::Error> {
+        if self.terminated {
+            return Ok(());
         }
+
+        self.terminated = true;
+
+        adapter.send_event(&Event::Terminated(RestartRequest::No))
     }
 
-    fn handle_request<R: Read, W: Write>(
+    fn handle_request<W: Write>(
         &mut self,
         req: &Request,
-        adapter: &mut DebugAdapter<R, W>,
+        adapter: &mut DebugAdapter<W>,
     ) -> Result<HandleResult, crate::debug_adapter::Error> {
         debug!("Handling request {}", req.command);
 
@@ -72,78 +377,94 @@ impl Debugger {
                 let args: LaunchRequestArguments = get_arguments(req)?;
                 trace!("Arguments: {:?}", args);
 
-                adapter.send_response::<()>(req, Err(debug_adapter::Error::Unimplemented))?;
-            }
-            "attach" => {
-                let args: AttachRequestArguments = get_arguments(req)?;
-                trace!("Arguments: {:?}", args);
-
-                let mut program_path = PathBuf::from(args.program.clone());
+                let program_path = self.load_program(&args.program, args.cwd.as_deref());
+                self.load_svd(adapter, args.svd_file.as_deref())?;
+                self.setup_rtt(adapter, args.rtt.as_ref())?;
+                self.semihosting = Semihosting::new();
 
-                if let Some(ref cwd) = args.cwd {
-                    debug!("Current working directory: '{}'", cwd);
-                    self.location = PathBuf::from(cwd);
+                let mut target = match self.connect(&args.chip) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        warn!("Failed to attach to probe: {:?}", e);
 
-                    // If the path to the programm to be debugged is relative, we join if with the
-                    if program_path.is_relative() {
-                        program_path = self.location.clone().join(&program_path);
+                        adapter.send_response::<()>(req, Err(e.into()))?;
+                        return Ok(HandleResult::Continue);
                     }
+                };
+
+                info!("Attached to probe");
+
+                adapter.log_to_console(format!("Flashing {}\n", program_path.display()))?;
+
+                if let Err(e) = target.download(&program_path) {
+                    warn!("Failed to flash program: {:?}", e);
+
+                    adapter.send_response::<()>(req, Err(e.into()))?;
+                    return Ok(HandleResult::Continue);
                 }
 
-                self.debug_info = match DebugInfo::from_file(&program_path) {
-                    Ok(di) => Some(di),
-                    Err(e) => {
-                        // Just log this, debugging without debug info should be possible.
-                        // Showing a warning to the user would be optimal, but not clear how
-                        // this can be done with vs code.
-                        warn!(
-                            "Unable to read debug information from file '{}': {}",
-                            program_path.display(),
-                            e
-                        );
-                        None
-                    }
+                adapter.log_to_console("Flashing finished\n")?;
+
+                self.target = Some(target);
+
+                // The core is reset when the configuration is done, so that all breakpoints
+                // are in place before the program starts running.
+                self.arguments = AttachRequestArguments {
+                    program: args.program,
+                    chip: args.chip,
+                    cwd: args.cwd,
+                    reset: Some(true),
+                    halt_after_reset: args.halt_after_reset,
+                    svd_file: args.svd_file,
+                    rtt: args.rtt,
+                    swo: args.swo,
                 };
 
-                self.program = Some(program_path);
+                self.setup_swo(adapter)?;
 
-                let session = connect_to_probe(&args.chip);
+                adapter.send_response::<()>(req, Ok(None))?;
 
-                self.arguments = args;
+                self.set_pending_breakpoints(adapter)?;
+            }
+            "attach" => {
+                let args: AttachRequestArguments = get_arguments(req)?;
+                trace!("Arguments: {:?}", args);
 
-                match session {
-                    Ok(s) => {
-                        self.session = Some(s);
+                self.load_program(&args.program, args.cwd.as_deref());
+                self.load_svd(adapter, args.svd_file.as_deref())?;
+                self.setup_rtt(adapter, args.rtt.as_ref())?;
+                self.semihosting = Semihosting::new();
 
-                        info!("Attached to probe");
+                let target = self.connect(&args.chip);
 
-                        adapter.log_to_console("Attached to probe")?;
+                self.arguments = args;
 
-                        adapter.send_response::<()>(&req, Ok(None))?;
+                match target {
+                    Ok(mut t) => {
+                        // The core might already be running, in which case we have to
+                        // watch for it to halt.
+                        self.core_running = t
+                            .core()
+                            .and_then(|mut core| core.status())
+                            .map(|status| !status.is_halted())
+                            .unwrap_or(false);
 
-                        if self.breakpoints.len() > 0 {
-                            let mut core = self
-                                .session
-                                .as_mut()
-                                .unwrap()
-                                .core(0)
-                                .expect("Failed to get core");
+                        self.target = Some(t);
 
-                            for bp in self.breakpoints.iter_mut() {
-                                if let Some(location) = bp.address {
-                                    core.set_hw_breakpoint(location as u32)?;
+                        info!("Attached to probe");
 
-                                    bp.verified = true;
+                        self.setup_swo(adapter)?;
 
-                                    adapter.send_event(&Event::Breakpoint(bp.get_event_body()))?;
-                                }
-                            }
-                        }
+                        adapter.log_to_console("Attached to probe")?;
+
+                        adapter.send_response::<()>(req, Ok(None))?;
+
+                        self.set_pending_breakpoints(adapter)?;
                     }
                     Err(e) => {
                         warn!("Failed to attacht to probe: {:?}", e);
 
-                        adapter.send_response::<()>(&req, Err(e.into()))?;
+                        adapter.send_response::<()>(req, Err(e.into()))?;
                     }
                 }
             }
@@ -151,7 +472,15 @@ impl Debugger {
                 let args: DisconnectArguments = get_arguments(req)?;
                 trace!("Arguments: {:?}", args);
 
-                adapter.send_response::<()>(&req, Ok(None))?;
+                // Software breakpoints have to be removed, otherwise the program would
+                // halt at them without a debugger.
+                if let Some(mut core) = target_core(&mut self.target) {
+                    if let Err(e) = self.breakpoints.clear_all(&mut *core) {
+                        warn!("Failed to remove breakpoints: {:?}", e);
+                    }
+                }
+
+                adapter.send_response::<()>(req, Ok(None))?;
 
                 return Ok(HandleResult::Stop);
             }
@@ -160,58 +489,25 @@ impl Debugger {
 
                 trace!("Arguments: {:?}", args);
 
-                let mut create_breakpoints = Vec::new();
-
-                let source_path = args.source.path.as_ref().map(Path::new);
-
-                debug!("Source path: {:?}", source_path);
-
-                if let Some(breakpoints) = args.breakpoints.as_ref() {
-                    for bp in breakpoints {
-                        // Try to find source code location
-                        debug!(
-                            "Trying to set breakpoint {:?}, source_file {:?}",
-                            bp, source_path
-                        );
-
-                        let source_location: Option<u64> =
-                            self.debug_info.as_ref().and_then(|di| {
-                                di.get_breakpoint_location(
-                                    dbg!(source_path.unwrap()),
-                                    dbg!(bp.line as u64),
-                                    bp.column.map(|c| c as u64),
-                                )
-                                .unwrap_or(None)
-                            });
-
-                        if let Some(location) = source_location {
-                            debug!("Found source location: {:#08x}!", location);
-
-                            let verified = if let Some(mut core) =
-                                self.session.as_mut().and_then(|s| s.core(0).ok())
-                            {
-                                core.set_hw_breakpoint(location as u32)?;
-                                true
-                            } else {
-                                false
-                            };
-
-                            self.add_breakpoint(bp, verified, source_location);
-
-                            create_breakpoints.push(Breakpoint {
-                                column: bp.column,
-                                end_column: None,
-                                end_line: None,
-                                id: None,
-                                line: Some(bp.line),
-                                message: None,
-                                source: None,
-                                verified,
-                            });
-                        } else {
-                            warn!("Failed to find location for breakpoint {:?}", bp);
+                let breakpoints = match args.source.path.as_ref() {
+                    Some(path) => {
+                        let mut core = target_core(&mut self.target);
+
+                        self.breakpoints.set_source_breakpoints(
+                            core.as_mut().map(|core| &mut **core as &mut dyn CoreAccess),
+                            self.debug_info.as_ref(),
+                            Path::new(path),
+                            &args.source,
+                            args.breakpoints.as_deref().unwrap_or_default(),
+                        )
+                    }
+                    None => {
+                        warn!("Breakpoints are only supported for sources with a path");
 
-                            create_breakpoints.push(Breakpoint {
+                        args.breakpoints
+                            .unwrap_or_default()
+                            .iter()
+                            .map(|bp| Breakpoint {
                                 column: bp.column,
                                 end_column: None,
                                 end_line: None,
@@ -220,41 +516,121 @@ impl Debugger {
                                 message: None,
                                 source: None,
                                 verified: false,
-                            });
-                        }
+                            })
+                            .collect()
                     }
-                } else {
-                    warn!("No breakpoints in request!");
-                }
-
-                let breakpoint_body = SetBreakpointsResponseBody {
-                    breakpoints: create_breakpoints,
                 };
 
-                adapter.send_response(&req, Ok(Some(breakpoint_body)))?;
+                adapter.send_response(req, Ok(Some(SetBreakpointsResponseBody { breakpoints })))?;
+            }
+            "setFunctionBreakpoints" => {
+                let args: SetFunctionBreakpointsArguments = get_arguments(req)?;
+                debug!("Arguments: {:?}", args);
+
+                let mut core = target_core(&mut self.target);
+
+                let breakpoints = self.breakpoints.set_function_breakpoints(
+                    core.as_mut().map(|core| &mut **core as &mut dyn CoreAccess),
+                    self.debug_info.as_ref(),
+                    &args.breakpoints,
+                );
+
+                adapter.send_response(
+                    req,
+                    Ok(Some(SetFunctionBreakpointsResponseBody { breakpoints })),
+                )?;
+            }
+            "dataBreakpointInfo" => {
+                let args: DataBreakpointInfoArguments = get_arguments(req)?;
+                debug!("Arguments: {:?}", args);
+
+                let body = self.data_breakpoint_info(&args);
+
+                adapter.send_response(req, Ok(Some(body)))?;
+            }
+            "setDataBreakpoints" => {
+                let args: SetDataBreakpointsArguments = get_arguments(req)?;
+                debug!("Arguments: {:?}", args);
+
+                let mut core = target_core(&mut self.target);
+
+                let breakpoints = self.breakpoints.set_data_breakpoints(
+                    core.as_mut().map(|core| &mut **core as &mut dyn CoreAccess),
+                    &args.breakpoints,
+                );
+
+                adapter.send_response(
+                    req,
+                    Ok(Some(SetDataBreakpointsResponseBody { breakpoints })),
+                )?;
             }
             "setExceptionBreakpoints" => {
                 let args: SetExceptionBreakpointsArguments = get_arguments(req)?;
                 debug!("Arguments: {:?}", args);
 
-                adapter.send_response::<()>(&req, Ok(None))?;
+                let filters: Vec<_> = args
+                    .filters
+                    .iter()
+                    .filter_map(|name| {
+                        let filter = exceptions::by_name(name);
+                        if filter.is_none() {
+                            warn!("Unknown exception filter '{}'", name);
+                        }
+                        filter
+                    })
+                    .collect();
+
+                let mut core = target_core(&mut self.target);
+
+                let messages = self.breakpoints.set_exception_breakpoints(
+                    core.as_mut().map(|core| &mut **core as &mut dyn CoreAccess),
+                    self.debug_info.as_ref(),
+                    &filters,
+                );
+                drop(core);
+
+                for message in messages {
+                    adapter.log_to_console(format!("{}\n", message))?;
+                }
+
+                adapter.send_response::<()>(req, Ok(None))?;
+            }
+            "exceptionInfo" => {
+                let args: ExceptionInfoArguments = get_arguments(req)?;
+                debug!("Arguments: {:?}", args);
+
+                let result = self.exception_info();
+
+                adapter.send_response(req, result.map(Some))?;
             }
             "configurationDone" => {
                 //let args: ConfigurationDoneArguments = get_arguments(req)?;
                 //debug!("Arguments: {:?}", args);
 
-                if self.arguments.reset.unwrap_or(false) {
-                    if let Some(mut core) = self.session.as_mut().and_then(|s| s.core(0).ok()) {
-                        debug!("Resetting target");
-                        core.reset_and_halt(Duration::from_millis(10))?;
+                let halted_at_entry = match self.reset() {
+                    Ok(halted_at_entry) => halted_at_entry,
+                    Err(e) => {
+                        warn!("Failed to reset the target: {:?}", e);
 
-                        if !self.arguments.halt_after_reset.unwrap_or(true) {
-                            core.run()?;
-                        }
+                        adapter.send_response::<()>(req, Err(e))?;
+                        return Ok(HandleResult::Continue);
                     }
-                }
+                };
 
-                adapter.send_response::<()>(&req, Ok(None))?;
+                adapter.send_response::<()>(req, Ok(None))?;
+
+                if halted_at_entry {
+                    let event_body = StoppedEventBody {
+                        reason: "entry".to_owned(),
+                        description: Some("Target halted after reset.".to_owned()),
+                        thread_id: Some(0),
+                        preserve_focus_hint: None,
+                        text: None,
+                        all_threads_stopped: None,
+                        hit_breakpoint_ids: None,
+                    };
+                    adapter.send_event(&Event::Stopped(event_body))?;
+                }
             }
             "threads" => {
                 //let args: ThreadsArguments = serde_json::from_value(req.arguments.as_ref().unwrap().clone()).unwrap();
@@ -267,16 +643,19 @@ impl Debugger {
 
                 let threads = vec![single_thread];
 
-                adapter.send_response(&req, Ok(Some(ThreadsResponseBody { threads })))?;
+                adapter.send_response(req, Ok(Some(ThreadsResponseBody { threads })))?;
             }
             "pause" => {
                 let args: PauseArguments = get_arguments(req)?;
                 debug!("Arguments: {:?}", args);
 
-                adapter.send_response::<()>(&req, Ok(None))?;
+                adapter.send_response::<()>(req, Ok(None))?;
 
                 match self.pause() {
                     Ok(_) => {
+                        self.core_running = false;
+                        self.cancel_step()?;
+
                         debug!("Stopped, sending pause event");
 
                         let event_body = StoppedEventBody {
@@ -286,6 +665,7 @@ impl Debugger {
                             preserve_focus_hint: None,
                             text: None,
                             all_threads_stopped: None,
+                            hit_breakpoint_ids: None,
                         };
                         adapter.send_event(&Event::Stopped(event_body))?;
 
@@ -300,94 +680,81 @@ impl Debugger {
                 let args: StackTraceArguments = get_arguments(req)?;
                 debug!("Arguments: {:?}", args);
 
-                let mut core = self.session.as_mut().and_then(|s| s.core(0).ok()).unwrap();
+                let debug_info = match self.debug_info.as_ref() {
+                    Some(debug_info) => debug_info,
+                    None => {
+                        // No debug information, so we cannot send stack trace information
+                        adapter.send_response::<()>(
+                            req,
+                            Err(anyhow!("No debug information found!").into()),
+                        )?;
+                        return Ok(HandleResult::Continue);
+                    }
+                };
 
-                let regs = core.registers();
+                let mut core = self
+                    .target
+                    .as_mut()
+                    .ok_or(debug_adapter::Error::MissingSession)?
+                    .core()?;
 
-                let pc = core.read_core_reg(regs.program_counter()).unwrap();
+                let pc = core.read_core_reg(core.program_counter())?;
                 debug!("Stopped at address 0x{:08x}", pc);
 
-                if let Some(debug_info) = self.debug_info.as_ref() {
-                    self.current_stackframes =
-                        debug_info.try_unwind(&mut core, pc as u64).collect();
+                self.current_stackframes = debug_info.unwind(&mut *core, pc);
+                self.variables.clear();
 
-                    let frame_list: Vec<StackFrame> = self
-                        .current_stackframes
-                        .iter()
-                        .map(|f| {
-                            use probe_rs::debug::ColumnType::*;
+                let frame_list: Vec<StackFrame> = self
+                    .current_stackframes
+                    .iter()
+                    .map(|f| {
+                        let (line, column) = source_position(f.source_location.as_ref());
 
-                            let column = f
-                                .source_location
+                        debug!(
+                            "  Frame {: <2} - {}:{}:{}",
+                            f.id,
+                            f.source_location
                                 .as_ref()
-                                .and_then(|sl| {
-                                    sl.column.map(|col| match col {
-                                        LeftEdge => 0,
-                                        Column(c) => c,
-                                    })
-                                })
-                                .unwrap_or(0);
-
-                            let sl = f.source_location.as_ref().unwrap();
-
-                            let mut path: PathBuf = sl.directory.as_ref().unwrap().into();
-
-                            path.push(sl.file.as_ref().unwrap());
-
-                            let source = Some(Source {
-                                name: Some(sl.file.clone().unwrap()),
-                                path: path.to_str().map(|s| s.to_owned()),
-                                source_reference: None,
-                                presentation_hint: None,
-                                origin: None,
-                                sources: None,
-                                adapter_data: None,
-                                checksums: None,
-                            });
+                                .and_then(|sl| sl.path())
+                                .unwrap_or_default()
+                                .display(),
+                            line,
+                            column
+                        );
 
-                            let line = f
-                                .source_location
-                                .as_ref()
-                                .and_then(|sl| sl.line)
-                                .unwrap_or(0) as i64;
-
-                            debug!(
-                                "  Frame {: <2} - {}:{}:{}",
-                                f.id,
-                                path.display(),
-                                line,
-                                column
-                            );
-
-                            StackFrame {
-                                id: f.id as i64,
-                                name: f.function_name.clone(),
-                                source: source,
-                                line: line,
-                                column: column as i64,
-                                end_column: None,
-                                end_line: None,
-                                module_id: None,
-                                presentation_hint: Some("normal".to_owned()),
-                            }
-                        })
-                        .collect();
+                        StackFrame {
+                            id: f.id as i64,
+                            name: f.function_name.clone(),
+                            source: f.source_location.as_ref().map(source),
+                            line,
+                            column,
+                            end_column: None,
+                            end_line: None,
+                            instruction_pointer_reference: if f.is_exception_entry {
+                                None
+                            } else {
+                                Some(format!("{:#010x}", f.pc))
+                            },
+                            presentation_hint: Some(
+                                if f.is_exception_entry {
+                                    "label"
+                                } else {
+                                    "normal"
+                                }
+                                .to_owned(),
+                            ),
+                        }
+                    })
+                    .collect();
 
-                    let frame_len = frame_list.len();
+                let frame_len = frame_list.len();
 
-                    let body = StackTraceResponseBody {
-                        stack_frames: frame_list,
-                        total_frames: Some(frame_len as i64),
-                    };
+                let body = StackTraceResponseBody {
+                    stack_frames: frame_list,
+                    total_frames: Some(frame_len as i64),
+                };
 
-                    adapter.send_response(&req, Ok(Some(body)))?;
-                } else {
-                    // No debug information, so we cannot send stack trace information
-                    adapter.send_response::<()>(
-                        &req,
-                        Err(anyhow!("No debug information found!").into()),
-                    )?;
-                }
+                adapter.send_response(req, Ok(Some(body)))?;
             }
             "scopes" => {
                 let args: ScopesArguments = get_arguments(req)?;
@@ -400,47 +767,73 @@ impl Debugger {
                     .iter()
                     .find(|sf| sf.id == args.frame_id as u64)
                 {
-                    use probe_rs::debug::ColumnType::*;
-
-                    let sl = frame.source_location.as_ref().unwrap();
-                    let path: PathBuf = sl.directory.as_ref().unwrap().into();
-
-                    let source = Some(Source {
-                        name: Some(sl.file.clone().unwrap()),
-                        path: path.to_str().map(|s| s.to_owned()),
-                        source_reference: None,
-                        presentation_hint: None,
-                        origin: None,
-                        sources: None,
-                        adapter_data: None,
-                        checksums: None,
-                    });
+                    let (line, column) = source_position(frame.source_location.as_ref());
 
                     let scope = Scope {
-                        line: frame
-                            .source_location
-                            .as_ref()
-                            .and_then(|l| l.line.map(|l| l as i64)),
-                        column: frame.source_location.as_ref().and_then(|l| {
-                            l.column.map(|c| match c {
-                                LeftEdge => 0,
-                                Column(c) => c as i64,
-                            })
-                        }),
+                        line: Some(line),
+                        column: Some(column),
                         end_column: None,
                         end_line: None,
                         expensive: false,
                         indexed_variables: None,
                         name: "Locals".to_string(),
                         named_variables: None,
-                        source: source,
-                        variables_reference: frame.id as i64,
+                        source: frame.source_location.as_ref().map(source),
+                        variables_reference: self
+                            .variables
+                            .insert(VariableContainer::Locals { frame_id: frame.id }),
                     };
 
                     scopes.push(scope);
+
+                    scopes.push(Scope {
+                        line: None,
+                        column: None,
+                        end_column: None,
+                        end_line: None,
+                        // Reading all statics takes a while, so the client only does it on request.
+                        expensive: true,
+                        indexed_variables: None,
+                        name: "Statics".to_string(),
+                        named_variables: None,
+                        source: None,
+                        variables_reference: self.variables.insert(VariableContainer::Statics),
+                    });
+
+                    if self.svd.is_some() {
+                        scopes.push(Scope {
+                            line: None,
+                            column: None,
+                            end_column: None,
+                            end_line: None,
+                            expensive: true,
+                            indexed_variables: None,
+                            name: "Peripherals".to_string(),
+                            named_variables: None,
+                            source: None,
+                            variables_reference: self
+                                .variables
+                                .insert(VariableContainer::Peripherals),
+                        });
+                    }
+
+                    scopes.push(Scope {
+                        line: None,
+                        column: None,
+                        end_column: None,
+                        end_line: None,
+                        expensive: false,
+                        indexed_variables: None,
+                        name: "Registers".to_string(),
+                        named_variables: None,
+                        source: None,
+                        variables_reference: self
+                            .variables
+                            .insert(VariableContainer::Registers { frame_id: frame.id }),
+                    });
                 }
 
-                adapter.send_response(&req, Ok(Some(ScopesResponseBody { scopes })))?;
+                adapter.send_response(req, Ok(Some(ScopesResponseBody { scopes })))?;
             }
             "source" => {
                 let args: SourceArguments = get_arguments(req)?;
@@ -471,78 +864,151 @@ impl Debugger {
                     Err(anyhow!("Unable to open resource").into())
                 };
 
-                adapter.send_response(&req, result)?;
+                adapter.send_response(req, result)?;
             }
             "variables" => {
                 let args: VariablesArguments = get_arguments(req)?;
                 debug!("Arguments: {:?}", args);
 
-                let mut variables = vec![];
+                let result = self.variables(&args);
+                debug!("{:?}", &result);
 
-                if let Some(frame) = self
-                    .current_stackframes
-                    .iter()
-                    .find(|sf| sf.id == args.variables_reference as u64)
+                adapter.send_response(
+                    req,
+                    result.map(|variables| Some(VariablesResponseBody { variables })),
+                )?;
+            }
+            "evaluate" => {
+                let args: EvaluateArguments = get_arguments(req)?;
+                debug!("Arguments: {:?}", args);
+
+                // While the core is running, the debug console is the input of RTT.
+                let down_channel = self.rtt.as_ref().and_then(Rtt::down_channel);
+                if let (Some("repl"), true, Some(channel)) =
+                    (args.context.as_deref(), self.core_running, down_channel)
                 {
-                    variables = frame
-                        .variables
-                        .iter()
-                        .map(|variable| Variable {
-                            name: variable.name.clone(),
-                            value: variable.value.to_string(),
-                            type_: None,
-                            presentation_hint: None,
-                            evaluate_name: None,
-                            variables_reference: -1,
-                            named_variables: None,
-                            indexed_variables: None,
-                        })
-                        .collect();
-                    debug!("{:?}", &variables);
+                    let input = format!("{}\n", args.expression);
+                    let result =
+                        self.rtt_write(channel, input.as_bytes())
+                            .map(|_| EvaluateResponseBody {
+                                result: String::new(),
+                                type_: None,
+                                variables_reference: 0,
+                                named_variables: None,
+                                indexed_variables: None,
+                                memory_reference: None,
+                            });
+
+                    adapter.send_response(req, result.map(Some))?;
+                    return Ok(HandleResult::Continue);
                 }
 
-                adapter.send_response(&req, Ok(Some(VariablesResponseBody { variables })))?;
+                let result = self.evaluate(&args);
+
+                adapter.send_response(req, result.map(Some))?;
             }
-            "continue" => {
-                let args: ContinueArguments = get_arguments(req)?;
+            "setVariable" => {
+                let args: SetVariableArguments = get_arguments(req)?;
                 debug!("Arguments: {:?}", args);
 
-                if let Some(ref mut core) = self.session.as_mut().and_then(|s| s.core(0).ok()) {
-                    core.run().expect("Failed to continue running target.");
+                match self.set_variable(&args) {
+                    Ok((body, invalidated)) => {
+                        adapter.send_response(req, Ok(Some(body)))?;
+                        adapter.send_event(&Event::Invalidated(invalidated))?;
+                    }
+                    Err(e) => adapter.send_response::<()>(req, Err(e))?,
                 }
+            }
+            "setExpression" => {
+                let args: SetExpressionArguments = get_arguments(req)?;
+                debug!("Arguments: {:?}", args);
 
-                adapter.send_response(
-                    &req,
-                    Ok(Some(ContinueResponseBody {
-                        all_threads_continued: Some(true),
-                    })),
-                )?;
+                match self.set_expression(&args) {
+                    Ok((body, invalidated)) => {
+                        adapter.send_response(req, Ok(Some(body)))?;
+                        adapter.send_event(&Event::Invalidated(invalidated))?;
+                    }
+                    Err(e) => adapter.send_response::<()>(req, Err(e))?,
+                }
             }
-            "next" => {
-                let args: NextArguments = get_arguments(req)?;
+            "probe-rs/rttWrite" => {
+                let args: RttWriteArguments = get_arguments(req)?;
                 debug!("Arguments: {:?}", args);
 
-                adapter.send_response::<()>(&req, Ok(None))?;
+                let channel = args
+                    .channel
+                    .or_else(|| self.rtt.as_ref().and_then(Rtt::down_channel))
+                    .unwrap_or(0);
 
-                if let Some(ref mut core) = self.session.as_mut().and_then(|s| s.core(0).ok()) {
-                    let _cpu_info = core.step().expect("Failed to continue running target.");
+                let result = self.rtt_write(channel, args.data.as_bytes());
 
-                    debug!("Stopped, sending pause event");
+                adapter.send_response(req, result.map(Some))?;
+            }
+            "readMemory" => {
+                let args: ReadMemoryArguments = get_arguments(req)?;
+                debug!("Arguments: {:?}", args);
 
-                    let event_body = StoppedEventBody {
-                        reason: "step".to_owned(),
-                        description: Some("Target paused after step.".to_owned()),
-                        thread_id: Some(0),
-                        preserve_focus_hint: None,
-                        text: None,
-                        all_threads_stopped: None,
-                    };
-                    adapter.send_event(&Event::Stopped(event_body))?;
+                let result = self.read_memory(&args);
 
-                    debug!("Sended pause event");
-                }
+                adapter.send_response(req, result.map(Some))?;
             }
-            cmd => {
+            "writeMemory" => {
+                let args: WriteMemoryArguments = get_arguments(req)?;
+                debug!("Arguments: {:?}", args);
+
+                let result = self.write_memory(&args);
+
+                adapter.send_response(req, result.map(Some))?;
+            }
+            "disassemble" => {
+                let args: DisassembleArguments = get_arguments(req)?;
+                debug!("Arguments: {:?}", args);
+
+                let result = self.disassemble(&args);
+
+                adapter.send_response(req, result.map(Some))?;
+            }
+            "continue" => {
+                let args: ContinueArguments = get_arguments(req)?;
+                debug!("Arguments: {:?}", args);
+
+                let result = self.resume().map(|()| {
+                    Some(ContinueResponseBody {
+                        all_threads_continued: Some(true),
+                    })
+                });
+
+                adapter.send_response(req, result)?;
+            }
+            "next" | "stepIn" | "stepOut" => {
+                let args: StepArguments = get_arguments(req)?;
+                debug!("Arguments: {:?}", args);
+
+                self.exception = None;
+
+                let kind = match req.command.as_ref() {
+                    "stepIn" => StepKind::Into,
+                    "stepOut" => StepKind::Out,
+                    _ => StepKind::Over,
+                };
+
+                let result = self.start_step(kind, args.granularity.as_deref());
+                forward_semihosting(adapter, &mut self.semihosting)?;
+
+                match result {
+                    Ok(result) => {
+                        adapter.send_response::<()>(req, Ok(None))?;
+
+                        self.step_finished(adapter, result)?;
+                    }
+                    Err(e) => {
+                        warn!("Failed to step: {:?}", e);
+
+                        adapter.send_response::<()>(req, Err(e))?;
+                    }
+                }
+            }
+            cmd => {
                 error!(
                     "Received request {}, which is not supported / implemented yet",
                     cmd
@@ -573,7 +1039,7 @@ impl Debugger {
 
                 */
 
-                adapter.send_response::<()>(&req, Err(debug_adapter::Error::Unimplemented))?;
+                adapter.send_response::<()>(req, Err(debug_adapter::Error::Unimplemented))?;
 
                 adapter.log_to_console(format!("Received unsupported request '{}'\n", cmd))?;
             }
@@ -582,9 +1048,1176 @@ impl Debugger {
         Ok(HandleResult::Continue)
     }
 
+    /// Check if the running core has halted, and send a `stopped` event to the client if it has.
+    /// Returns [`HandleResult::Stop`] once the program exited.
+    ///
+    /// Errors while accessing the target are shown in the debug console, and don't end
+    /// the session. Only an error in the communication with the client is returned.
+    pub fn poll_core<W: Write>(
+        &mut self,
+        adapter: &mut DebugAdapter<W>,
+    ) -> Result<HandleResult, debug_adapter::Error> {
+        if self.terminated {
+            return Ok(HandleResult::Stop);
+        }
+
+        let was_running = self.core_running;
+
+        if let Err(e) = self.check_core(adapter) {
+            warn!("Failed to check the status of the core: {:?}", e);
+
+            adapter.log_to_console(format!("Unable to check the status of the core: {}\n", e))?;
+
+            // The core halted, but the client would still show it as running.
+            if was_running && !self.core_running {
+                adapter.send_event(&Event::Stopped(
+                    self.stopped_event_body(HaltReason::Unknown, Vec::new()),
+                ))?;
+            }
+        }
+
+        if self.terminated {
+            return Ok(HandleResult::Stop);
+        }
+
+        Ok(HandleResult::Continue)
+    }
+
+    fn check_core<W: Write>(
+        &mut self,
+        adapter: &mut DebugAdapter<W>,
+    ) -> Result<(), debug_adapter::Error> {
+        if !self.core_running {
+            return Ok(());
+        }
+
+        if let (Some(itm), Some(target)) = (self.itm.as_mut(), self.target.as_mut()) {
+            forward_swo(adapter, itm, &mut **target)?;
+        }
+
+        let mut core = match target_core(&mut self.target) {
+            Some(core) => core,
+            None => return Ok(()),
+        };
+
+        let status = match core.status() {
+            Ok(status) => status,
+            Err(e) => {
+                // Stop polling, otherwise we would just repeat the error.
+                self.core_running = false;
+
+                return Err(e.into());
+            }
+        };
+
+        // The status is read first, so that all data written before a halt is read.
+        if let Some(rtt) = self.rtt.as_mut() {
+            forward_rtt(adapter, rtt, &mut *core)?;
+        }
+
+        let reason = match status {
+            CoreStatus::Halted(reason) => reason,
+            _ => return Ok(()),
+        };
+
+        self.core_running = false;
+
+        let pc = core.read_core_reg(core.program_counter())?;
+        let sp = core.read_core_reg(core.stack_pointer())?;
+        let ipsr = core.read_core_reg(CoreRegisterAddress(debug_info::XPSR as u16))? & 0x1ff;
+
+        if matches!(reason, HaltReason::Breakpoint)
+            && semihosting::is_semihosting_call(&mut *core, pc)
+        {
+            let effect = self.semihosting.service(&mut *core, pc);
+            forward_semihosting(adapter, &mut self.semihosting)?;
+
+            match effect {
+                Ok(Effect::Exit(code)) => {
+                    drop(core);
+                    return self.program_exited(adapter, code);
+                }
+                Ok(Effect::None) => {
+                    core.run()?;
+                    self.core_running = true;
+                    return Ok(());
+                }
+                // Stop, so that the program can be inspected.
+                Err(e) => adapter.log_to_console(format!("{}\n", e))?,
+            }
+        }
+
+        drop(core);
+
+        debug!("Core halted at pc={:#010x}, reason: {:?}", pc, reason);
+
+        if let Some(step) = self.step.take() {
+            if step.is_return_point(pc, sp) {
+                return self.resume_step(adapter, step);
+            }
+
+            if step.is_return_address(pc) {
+                // A recursive call returned, but not the one we are waiting for.
+                let mut core =
+                    target_core(&mut self.target).ok_or(debug_adapter::Error::MissingSession)?;
+                self.breakpoints.step_over_software_breakpoint(&mut *core)?;
+                core.run()?;
+
+                self.core_running = true;
+                self.step = Some(step);
+                return Ok(());
+            }
+
+            self.step = Some(step);
+        }
+
+        let hit = match reason {
+            HaltReason::Breakpoint => Some(self.breakpoints.hit_at(pc)),
+            HaltReason::Watchpoint => {
+                let mut core =
+                    target_core(&mut self.target).ok_or(debug_adapter::Error::MissingSession)?;
+                Some(self.breakpoints.data_hit(&mut *core))
+            }
+            _ => None,
+        };
+
+        let hit_breakpoint_ids = match hit {
+            Some(hit) => match self.breakpoints_hit(adapter, pc, hit)? {
+                Some(ids) => ids,
+                // The core was resumed, a step continues as well.
+                None => return Ok(()),
+            },
+            None => Vec::new(),
+        };
+
+        // The core halted somewhere else, e.g. at a breakpoint.
+        self.cancel_step()?;
+
+        let filter = match reason {
+            HaltReason::Exception => exceptions::by_exception(ipsr),
+            HaltReason::Breakpoint => self.breakpoints.exception_filter(&hit_breakpoint_ids),
+            _ => None,
+        };
+
+        self.exception = filter.map(|filter| StoppedException {
+            filter,
+            number: ipsr,
+        });
+
+        let event_body = match &self.exception {
+            Some(exception) => StoppedEventBody {
+                description: Some(format!("Paused on {}", exception.description())),
+                text: Some(exception.id()),
+                ..self.stopped_event_body(HaltReason::Exception, Vec::new())
+            },
+            None => self.stopped_event_body(reason, hit_breakpoint_ids),
+        };
+
+        adapter.send_event(&Event::Stopped(event_body))
+    }
+
+    /// Notify the client that the program exited with `code`, which ends the session.
+    fn program_exited<W: Write>(
+        &mut self,
+        adapter: &mut DebugAdapter<W>,
+        code: i64,
+    ) -> Result<(), debug_adapter::Error> {
+        info!("Program exited with code {}", code);
+
+        self.cancel_step()?;
+
+        adapter.log_to_console(format!("Program exited with code {}\n", code))?;
+        adapter.send_event(&Event::Exited(code))?;
+        self.terminate(adapter)
+    }
+
+    /// Check the conditions and hit counts of the breakpoints `hit` when the core halted
+    /// at `pc`, and log the messages of logpoints. If none of the breakpoints stops the
+    /// core, it is resumed and `None` is returned, otherwise the ids of the breakpoints
+    /// which stopped it.
+    fn breakpoints_hit<W: Write>(
+        &mut self,
+        adapter: &mut DebugAdapter<W>,
+        pc: u32,
+        hit: Vec<HitBreakpoint>,
+    ) -> Result<Option<Vec<i64>>, debug_adapter::Error> {
+        if hit.is_empty() {
+            return Ok(Some(Vec::new()));
+        }
+
+        let mut core = target_core(&mut self.target).ok_or(debug_adapter::Error::MissingSession)?;
+
+        // Conditions and messages are evaluated in the frame of the breakpoint.
+        let needs_frame = hit
+            .iter()
+            .any(|bp| bp.condition.is_some() || bp.log_message.is_some());
+        let frames = match (self.debug_info.as_ref(), needs_frame) {
+            (Some(debug_info), true) => debug_info.unwind(&mut *core, pc),
+            _ => Vec::new(),
+        };
+        let frame = frames.first();
+
+        let mut stopped = Vec::new();
+
+        for bp in hit {
+            if let Some(condition) = &bp.condition {
+                let result = match self.debug_info.as_ref() {
+                    Some(debug_info) => {
+                        evaluate::evaluate_condition(debug_info, &mut *core, frame, condition)
+                            .map_err(|e| e.to_string())
+                    }
+                    None => Err("No debug information found".to_owned()),
+                };
+
+                match result {
+                    Ok(true) => (),
+                    Ok(false) => continue,
+                    Err(e) => {
+                        // Stop, so that the condition can be fixed.
+                        adapter.log_to_console(format!(
+                            "Unable to evaluate the condition '{}' of breakpoint {}: {}\n",
+                            condition, bp.id, e
+                        ))?;
+                        stopped.push(bp.id);
+                        continue;
+                    }
+                }
+            }
+
+            match self.breakpoints.count_hit(bp.id) {
+                Ok(true) => (),
+                Ok(false) => continue,
+                Err(message) => {
+                    adapter.log_to_console(format!("{} of breakpoint {}\n", message, bp.id))?;
+                    stopped.push(bp.id);
+                    continue;
+                }
+            }
+
+            if let Some(message) = &bp.log_message {
+                let output = match self.debug_info.as_ref() {
+                    Some(debug_info) => {
+                        evaluate::format_message(debug_info, &mut *core, frame, message)
+                    }
+                    None => message.clone(),
+                };
+
+                adapter.send_event(&Event::console_output(format!("{}\n", output)))?;
+                continue;
+            }
+
+            stopped.push(bp.id);
+        }
+
+        if stopped.is_empty() {
+            debug!("No breakpoint at {:#010x} stops, resuming", pc);
+
+            self.breakpoints.step_over_software_breakpoint(&mut *core)?;
+            core.run()?;
+            self.core_running = true;
+
+            return Ok(None);
+        }
+
+        Ok(Some(stopped))
+    }
+
+    /// Step the core by source lines, or by a single instruction if requested or if
+    /// there is no debug information. The client is notified about the result with
+    /// [`Debugger::step_finished`], after the response to the request was sent.
+    fn start_step(
+        &mut self,
+        kind: StepKind,
+        granularity: Option<&str>,
+    ) -> Result<StepResult, debug_adapter::Error> {
+        self.cancel_step()?;
+
+        let mut core = target_core(&mut self.target).ok_or(debug_adapter::Error::MissingSession)?;
+
+        let debug_info = match self.debug_info.as_ref() {
+            Some(debug_info) if granularity != Some("instruction") => debug_info,
+            _ => {
+                let effect = stepping::step_instruction(
+                    &mut *core,
+                    &self.breakpoints,
+                    &mut self.semihosting,
+                )?;
+
+                return Ok(match effect {
+                    Effect::Exit(code) => StepResult::Exited(code),
+                    Effect::None => StepResult::Done,
+                });
+            }
+        };
+
+        let pc = core.read_core_reg(core.program_counter())?;
+
+        let mut step = Step::new(kind, debug_info, pc);
+        let result = step.resume(
+            debug_info,
+            &mut *core,
+            &mut self.breakpoints,
+            &mut self.semihosting,
+        )?;
+        drop(core);
+
+        if result == StepResult::Running {
+            self.step = Some(step);
+            self.core_running = true;
+        }
+
+        Ok(result)
+    }
+
+    /// Continue a step, after the core reached the return address it was running to.
+    fn resume_step<W: Write>(
+        &mut self,
+        adapter: &mut DebugAdapter<W>,
+        mut step: Step,
+    ) -> Result<(), debug_adapter::Error> {
+        let result = match (target_core(&mut self.target), self.debug_info.as_ref()) {
+            (Some(mut core), Some(debug_info)) => step.resume(
+                debug_info,
+                &mut *core,
+                &mut self.breakpoints,
+                &mut self.semihosting,
+            ),
+            (None, _) => Err(anyhow!("Not connected to a target")),
+            (_, None) => Err(anyhow!("No debug information found!")),
+        };
+
+        forward_semihosting(adapter, &mut self.semihosting)?;
+
+        let result = match result {
+            Ok(StepResult::Running) => {
+                self.step = Some(step);
+                self.core_running = true;
+                StepResult::Running
+            }
+            Ok(result) => result,
+            Err(e) => {
+                warn!("Failed to step: {:?}", e);
+
+                adapter.log_to_console(format!("Unable to step: {}\n", e))?;
+                StepResult::Done
+            }
+        };
+
+        self.step_finished(adapter, result)
+    }
+
+    /// Notify the client that a step is done, or that the program exited during it.
+    fn step_finished<W: Write>(
+        &mut self,
+        adapter: &mut DebugAdapter<W>,
+        result: StepResult,
+    ) -> Result<(), debug_adapter::Error> {
+        match result {
+            StepResult::Running => Ok(()),
+            StepResult::Done => adapter.send_event(&Event::Stopped(
+                self.stopped_event_body(HaltReason::Step, Vec::new()),
+            )),
+            StepResult::Exited(code) => self.program_exited(adapter, code),
+        }
+    }
+
+    /// Reset the core after the configuration is done, if the client asked for it.
+    /// Returns true if the core is halted at the entry of the program.
+    fn reset(&mut self) -> Result<bool, debug_adapter::Error> {
+        if !self.arguments.reset.unwrap_or(false) {
+            return Ok(false);
+        }
+
+        let mut core = match target_core(&mut self.target) {
+            Some(core) => core,
+            None => return Ok(false),
+        };
+
+        debug!("Resetting target");
+        core.reset_and_halt(Duration::from_millis(10))?;
+
+        if self.arguments.halt_after_reset.unwrap_or(true) {
+            return Ok(true);
+        }
+
+        core.run()?;
+        self.core_running = true;
+
+        Ok(false)
+    }
+
+    /// Let the core run, for a `continue` request.
+    fn resume(&mut self) -> Result<(), debug_adapter::Error> {
+        self.cancel_step()?;
+        self.exception = None;
+
+        if let Some(mut core) = target_core(&mut self.target) {
+            self.breakpoints.step_over_software_breakpoint(&mut *core)?;
+
+            core.run()?;
+            self.core_running = true;
+        }
+
+        Ok(())
+    }
+
+    /// Stop waiting for a step to reach a return address.
+    fn cancel_step(&mut self) -> Result<(), debug_adapter::Error> {
+        if self.step.take().is_some() {
+            if let Some(mut core) = target_core(&mut self.target) {
+                self.breakpoints.clear_temporary(&mut *core)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Evaluate an expression for an `evaluate` request, in the context of the
+    /// selected stack frame, or the top frame if none is selected.
+    fn evaluate(
+        &mut self,
+        args: &EvaluateArguments,
+    ) -> Result<EvaluateResponseBody, debug_adapter::Error> {
+        let debug_info = self
+            .debug_info
+            .as_ref()
+            .ok_or_else(|| anyhow!("No debug information found!"))?;
+        let mut core = target_core(&mut self.target).ok_or(debug_adapter::Error::MissingSession)?;
+
+        let frame = match args.frame_id {
+            Some(frame_id) => self
+                .current_stackframes
+                .iter()
+                .find(|frame| frame.id as i64 == frame_id),
+            None => self.current_stackframes.first(),
+        };
+
+        let variable = evaluate::evaluate(
+            debug_info,
+            &mut *core,
+            frame,
+            &mut self.variables,
+            &args.expression,
+        )
+        .map_err(|e| anyhow!("{}", e))?;
+
+        Ok(EvaluateResponseBody {
+            result: variable.value,
+            type_: variable.type_,
+            variables_reference: variable.variables_reference,
+            named_variables: variable.named_variables,
+            indexed_variables: variable.indexed_variables,
+            memory_reference: variable.memory_reference,
+        })
+    }
+
+    /// Write `data` to the RTT down channel `channel`. The part which doesn't fit into
+    /// the buffer is written later, while the target reads from it.
+    fn rtt_write(
+        &mut self,
+        channel: u32,
+        data: &[u8],
+    ) -> Result<RttWriteResponseBody, debug_adapter::Error> {
+        let rtt = self
+            .rtt
+            .as_mut()
+            .ok_or_else(|| anyhow!("RTT is not configured"))?;
+        let mut core = target_core(&mut self.target).ok_or(debug_adapter::Error::MissingSession)?;
+
+        let bytes_written = rtt
+            .write(&mut *core, channel, data)
+            .map_err(|e| anyhow!("{}", e))?;
+
+        Ok(RttWriteResponseBody {
+            bytes_written,
+            bytes_pending: rtt.pending_input(channel),
+        })
+    }
+
+    /// Describe the exception the core stopped at, for an `exceptionInfo` request.
+    /// Faults are diagnosed from the fault status registers, and the faulting
+    /// instruction is found in the stacked exception frame.
+    fn exception_info(&mut self) -> Result<ExceptionInfoResponseBody, debug_adapter::Error> {
+        let exception = self
+            .exception
+            .clone()
+            .ok_or_else(|| anyhow!("The core is not stopped at an exception"))?;
+
+        let mut body = ExceptionInfoResponseBody {
+            exception_id: exception.id(),
+            description: Some(exception.description()),
+            break_mode: ExceptionBreakMode::Always,
+            details: None,
+        };
+
+        if !exception.is_fault() {
+            return Ok(body);
+        }
+
+        let mut core = target_core(&mut self.target).ok_or(debug_adapter::Error::MissingSession)?;
+
+        let causes = match FaultStatus::read(&mut *core) {
+            Ok(status) => status.causes(),
+            Err(e) => {
+                warn!("Failed to read the fault status registers: {}", e);
+                Vec::new()
+            }
+        };
+
+        let frame = match fault::stacked_frame(&mut *core) {
+            Ok(frame) => frame,
+            Err(e) => {
+                warn!("Failed to read the exception frame: {}", e);
+                None
+            }
+        };
+
+        drop(core);
+
+        let debug_info = self.debug_info.as_ref();
+        let location = frame.map(|frame| {
+            let pc = frame.pc();
+
+            let function = debug_info
+                .and_then(|debug_info| debug_info.symbol_at(u64::from(pc)))
+                .map(|symbol| format!(" in {}", symbol.name))
+                .unwrap_or_default();
+
+            let source = debug_info
+                .and_then(|debug_info| debug_info.get_source_location(u64::from(pc)))
+                .and_then(|location| Some((location.path()?, location.line?)))
+                .map(|(path, line)| format!(" at {}:{}", path.display(), line))
+                .unwrap_or_default();
+
+            format!("{:#010x}{}{}", pc, function, source)
+        });
+
+        let mut description = exception.description();
+        if !causes.is_empty() {
+            description = format!("{}: {}", description, causes.join(", "));
+        }
+        if let Some(location) = &location {
+            description = format!("{}\nFaulting instruction: {}", description, location);
+        }
+
+        body.description = Some(description);
+        body.details = Some(ExceptionDetails {
+            message: Some(causes.join("\n")),
+            type_name: Some(exception.id()),
+            stack_trace: location,
+            ..ExceptionDetails::default()
+        });
+
+        Ok(body)
+    }
+
+    /// Describe the data a data breakpoint can be set on, for a `dataBreakpointInfo`
+    /// request. `name` is either a variable in the container `variables_reference`, or
+    /// an expression which is evaluated in the top stack frame.
+    fn data_breakpoint_info(
+        &mut self,
+        args: &DataBreakpointInfoArguments,
+    ) -> DataBreakpointInfoResponseBody {
+        let unavailable = |description: String| DataBreakpointInfoResponseBody {
+            data_id: None,
+            description,
+            access_types: None,
+            can_persist: None,
+        };
+
+        let value = match args.variables_reference {
+            Some(reference) => self
+                .find_variable(reference, &args.name)
+                .map(|(value, _, _)| value),
+            None => self.evaluate_value(&args.name),
+        };
+
+        let value = match value {
+            Ok(value) => value,
+            Err(e) => return unavailable(e.to_string()),
+        };
+
+        let size = self
+            .debug_info
+            .as_ref()
+            .and_then(|debug_info| value.size(debug_info));
+
+        match (value.place(), size) {
+            (Some(Place::Memory(address)), Some(size)) if size > 0 => {
+                DataBreakpointInfoResponseBody {
+                    data_id: Some(breakpoints::data_id(address, size)),
+                    description: format!("{} ({} bytes at {:#010x})", args.name, size, address),
+                    access_types: Some(vec![
+                        DataBreakpointAccessType::Read,
+                        DataBreakpointAccessType::Write,
+                        DataBreakpointAccessType::ReadWrite,
+                    ]),
+                    can_persist: Some(false),
+                }
+            }
+            (Some(Place::Register(_)), _) => {
+                unavailable(format!("'{}' is stored in a register", args.name))
+            }
+            _ => unavailable(format!("'{}' is not stored in memory", args.name)),
+        }
+    }
+
+    /// Evaluate `expression` in the top stack frame.
+    fn evaluate_value(
+        &mut self,
+        expression: &str,
+    ) -> Result<evaluate::Value, debug_adapter::Error> {
+        let debug_info = self
+            .debug_info
+            .as_ref()
+            .ok_or_else(|| anyhow!("No debug information found!"))?;
+        let mut core = target_core(&mut self.target).ok_or(debug_adapter::Error::MissingSession)?;
+
+        evaluate::evaluate_value(
+            debug_info,
+            &mut *core,
+            self.current_stackframes.first(),
+            expression,
+        )
+        .map_err(|e| anyhow!("{}", e).into())
+    }
+
+    /// Change a variable for a `setVariable` request. Variables of the program,
+    /// registers and the fields of both can be changed.
+    fn set_variable(
+        &mut self,
+        args: &SetVariableArguments,
+    ) -> Result<(SetVariableResponseBody, InvalidatedEventBody), debug_adapter::Error> {
+        let (target, frame_id, _) = self.find_variable(args.variables_reference, &args.name)?;
+
+        let place = self.assign(&target, frame_id, &args.value)?;
+
+        // Read the new value, registers are taken from the unwound frames again.
+        let (target, _, evaluate_name) =
+            self.find_variable(args.variables_reference, &args.name)?;
+
+        let debug_info = self
+            .debug_info
+            .as_ref()
+            .ok_or_else(|| anyhow!("No debug information found!"))?;
+        let mut core = target_core(&mut self.target).ok_or(debug_adapter::Error::MissingSession)?;
+
+        let variable = evaluate::to_dap_variable(
+            debug_info,
+            &mut *core,
+            &mut self.variables,
+            target,
+            &args.name,
+            evaluate_name,
+        );
+
+        Ok((
+            SetVariableResponseBody {
+                value: variable.value,
+                type_: variable.type_,
+                variables_reference: variable.variables_reference,
+                named_variables: variable.named_variables,
+                indexed_variables: variable.indexed_variables,
+            },
+            invalidated(place),
+        ))
+    }
+
+    /// Change the value of an expression for a `setExpression` request.
+    fn set_expression(
+        &mut self,
+        args: &SetExpressionArguments,
+    ) -> Result<(SetExpressionResponseBody, InvalidatedEventBody), debug_adapter::Error> {
+        let frame_id = args
+            .frame_id
+            .map(|frame_id| frame_id as u64)
+            .or_else(|| self.current_stackframes.first().map(|frame| frame.id));
+
+        let target = {
+            let debug_info = self
+                .debug_info
+                .as_ref()
+                .ok_or_else(|| anyhow!("No debug information found!"))?;
+            let mut core =
+                target_core(&mut self.target).ok_or(debug_adapter::Error::MissingSession)?;
+
+            let frame = self
+                .current_stackframes
+                .iter()
+                .find(|frame| Some(frame.id) == frame_id);
+
+            evaluate::evaluate_value(debug_info, &mut *core, frame, &args.expression)
+                .map_err(|e| anyhow!("{}", e))?
+        };
+
+        let place = self.assign(&target, frame_id, &args.value)?;
+
+        let result = self.evaluate(&EvaluateArguments {
+            expression: args.expression.clone(),
+            frame_id: frame_id.map(|frame_id| frame_id as i64),
+            context: Some("watch".to_owned()),
+            format: None,
+        })?;
+
+        Ok((
+            SetExpressionResponseBody {
+                value: result.result,
+                type_: result.type_,
+                variables_reference: result.variables_reference,
+                named_variables: result.named_variables,
+                indexed_variables: result.indexed_variables,
+            },
+            invalidated(place),
+        ))
+    }
+
+    /// Find the variable `name` in the container `reference`. Returns the variable,
+    /// the frame it belongs to and its evaluate name.
+    fn find_variable(
+        &mut self,
+        reference: i64,
+        name: &str,
+    ) -> Result<(evaluate::Value, Option<u64>, String), debug_adapter::Error> {
+        let container = self
+            .variables
+            .get(reference)
+            .cloned()
+            .ok_or_else(|| anyhow!("Unknown variables reference {}", reference))?;
+
+        let debug_info = self
+            .debug_info
+            .as_ref()
+            .ok_or_else(|| anyhow!("No debug information found!"))?;
+        let mut core = target_core(&mut self.target).ok_or(debug_adapter::Error::MissingSession)?;
+
+        let (evaluate_name, frame_id) = match container {
+            VariableContainer::Locals { frame_id } => (name.to_owned(), Some(frame_id)),
+            VariableContainer::Statics => (name.to_owned(), None),
+            VariableContainer::Registers { frame_id }
+            | VariableContainer::FpuRegisters { frame_id } => {
+                (format!("${}", name.to_lowercase()), Some(frame_id))
+            }
+            VariableContainer::Children {
+                variable,
+                evaluate_name,
+            } => {
+                let index = name
+                    .strip_prefix('[')
+                    .and_then(|index| index.strip_suffix(']'))
+                    .and_then(|index| index.parse().ok());
+
+                let filter = match index {
+                    Some(index) => ChildFilter {
+                        filter: Some("indexed"),
+                        start: Some(index),
+                        count: Some(1),
+                    },
+                    None => ChildFilter {
+                        filter: Some("named"),
+                        ..ChildFilter::default()
+                    },
+                };
+
+                return variables::children(
+                    debug_info,
+                    &mut *core,
+                    &variable,
+                    &evaluate_name,
+                    filter,
+                )
+                .map_err(|e| anyhow!("Unable to read variable: {}", e))?
+                .into_iter()
+                .find(|(child, _)| child.name == name)
+                .map(|(child, evaluate_name)| {
+                    (evaluate::Value::Variable(child), None, evaluate_name)
+                })
+                .ok_or_else(|| anyhow!("Unknown variable '{}'", name).into());
+            }
+            VariableContainer::Variable {
+                variable,
+                evaluate_name,
+            } if variable.name == name => {
+                return Ok((evaluate::Value::Variable(variable), None, evaluate_name))
+            }
+            _ => return Err(anyhow!("'{}' can not be changed", name).into()),
+        };
+
+        let stackframes = &self.current_stackframes;
+        let frame =
+            frame_id.and_then(|frame_id| stackframes.iter().find(|frame| frame.id == frame_id));
+
+        let value = evaluate::evaluate_value(debug_info, &mut *core, frame, &evaluate_name)
+            .map_err(|e| anyhow!("{}", e))?;
+
+        Ok((value, frame_id, evaluate_name))
+    }
+
+    /// Write the value given by `text` to the place where `target` is stored.
+    fn assign(
+        &mut self,
+        target: &evaluate::Value,
+        frame_id: Option<u64>,
+        text: &str,
+    ) -> Result<Place, debug_adapter::Error> {
+        let place = target.place().ok_or_else(|| {
+            anyhow!("The value is not stored on the target and can not be changed")
+        })?;
+
+        let debug_info = self
+            .debug_info
+            .as_ref()
+            .ok_or_else(|| anyhow!("No debug information found!"))?;
+        let mut core = target_core(&mut self.target).ok_or(debug_adapter::Error::MissingSession)?;
+
+        let top_frame_id = self.current_stackframes.first().map(|frame| frame.id);
+        let frame = self
+            .current_stackframes
+            .iter()
+            .find(|frame| Some(frame.id) == frame_id.or(top_frame_id));
+
+        let data = evaluate::encode(debug_info, &mut *core, frame, target, text)
+            .map_err(|e| anyhow!("{}", e))?;
+
+        match place {
+            Place::Memory(address) => core
+                .write_8(address, &data)
+                .map_err(|e| anyhow!("Unable to write to {:#010x}: {}", address, e))?,
+            Place::Register(register) => {
+                // The registers of the callers are only known from the unwinding, and
+                // are restored by the code when the functions return.
+                if frame_id.is_some() && frame_id != top_frame_id {
+                    return Err(
+                        anyhow!("Registers can only be changed in the top stack frame").into(),
+                    );
+                }
+
+                let mut bytes = [0u8; 4];
+                let len = data.len().min(4);
+                bytes[..len].copy_from_slice(&data[..len]);
+
+                core.write_core_reg(
+                    CoreRegisterAddress(register as u16),
+                    u32::from_le_bytes(bytes),
+                )?;
+
+                let pc = core.read_core_reg(core.program_counter())?;
+                self.current_stackframes = debug_info.unwind(&mut *core, pc);
+            }
+        }
+
+        Ok(place)
+    }
+
+    /// Read memory for a `readMemory` request. Bytes after the first unreadable
+    /// byte are reported as unreadable.
+    fn read_memory(
+        &mut self,
+        args: &ReadMemoryArguments,
+    ) -> Result<ReadMemoryResponseBody, debug_adapter::Error> {
+        let address = memory::parse_memory_reference(&args.memory_reference, args.offset)?;
+        // Larger reads are cut short, which the client handles like any partial read.
+        let count = (args.count.max(0) as u64).min(memory::MAX_READ_SIZE);
+
+        let mut core =
+            target_core(&mut self.target).ok_or_else(|| anyhow!("Not connected to a target"))?;
+
+        let data = memory::read_readable(&mut *core, address, count);
+        let unreadable_bytes = count - data.len() as u64;
+
+        Ok(ReadMemoryResponseBody {
+            address: format!("{:#010x}", address),
+            unreadable_bytes: if unreadable_bytes > 0 {
+                Some(unreadable_bytes as i64)
+            } else {
+                None
+            },
+            data: Some(base64::encode(&data)),
+        })
+    }
+
+    /// Write memory for a `writeMemory` request.
+    fn write_memory(
+        &mut self,
+        args: &WriteMemoryArguments,
+    ) -> Result<WriteMemoryResponseBody, debug_adapter::Error> {
+        let address = memory::parse_memory_reference(&args.memory_reference, args.offset)?;
+        let data = base64::decode(&args.data)
+            .map_err(|e| anyhow!("Invalid data for writeMemory request: {}", e))?;
+
+        let mut core =
+            target_core(&mut self.target).ok_or_else(|| anyhow!("Not connected to a target"))?;
+
+        let written = memory::write(
+            &mut *core,
+            address,
+            &data,
+            args.allow_partial.unwrap_or(false),
+        )?;
+
+        Ok(WriteMemoryResponseBody {
+            offset: None,
+            bytes_written: Some(written as i64),
+        })
+    }
+
+    /// Disassemble the code on the target for a `disassemble` request, annotated
+    /// with source locations and symbols from the debug information.
+    fn disassemble(
+        &mut self,
+        args: &DisassembleArguments,
+    ) -> Result<DisassembleResponseBody, debug_adapter::Error> {
+        let address = memory::parse_memory_reference(&args.memory_reference, args.offset)?;
+        let count = args.instruction_count.max(0) as usize;
+
+        let mut core =
+            target_core(&mut self.target).ok_or_else(|| anyhow!("Not connected to a target"))?;
+
+        let disassembler = Disassembler::new(core.architecture())?;
+        let instructions = disassembler.disassemble(
+            &mut *core,
+            &self.breakpoints,
+            address,
+            args.instruction_offset.unwrap_or(0),
+            count,
+        );
+
+        let debug_info = self.debug_info.as_ref();
+        let resolve_symbols = args.resolve_symbols.unwrap_or(true);
+
+        let mut previous_path = None;
+
+        let instructions = instructions
+            .into_iter()
+            .map(|instruction| {
+                let address = u64::from(instruction.address);
+
+                let source_location = if instruction.bytes.is_empty() {
+                    None
+                } else {
+                    debug_info.and_then(|debug_info| debug_info.get_source_location(address))
+                };
+                let (line, column) = source_position(source_location.as_ref());
+
+                // The source only has to be repeated when it changes.
+                let path = source_location
+                    .as_ref()
+                    .and_then(|location| location.path());
+                let location = if path.is_some() && path != previous_path {
+                    source_location.as_ref().map(source)
+                } else {
+                    None
+                };
+                previous_path = path;
+
+                let symbol = debug_info
+                    .filter(|_| resolve_symbols)
+                    .and_then(|debug_info| debug_info.symbol_at(address))
+                    .map(|symbol| match address - symbol.address {
+                        0 => symbol.name.clone(),
+                        offset => format!("{}+{:#x}", symbol.name, offset),
+                    });
+
+                DisassembledInstruction {
+                    address: format!("{:#010x}", instruction.address),
+                    instruction_bytes: if instruction.bytes.is_empty() {
+                        None
+                    } else {
+                        Some(
+                            instruction
+                                .bytes
+                                .iter()
+                                .map(|byte| format!("{:02x}", byte))
+                                .collect::<Vec<_>>()
+                                .join(" "),
+                        )
+                    },
+                    instruction: instruction.text,
+                    symbol,
+                    location,
+                    line: source_location
+                        .as_ref()
+                        .and_then(|location| location.line)
+                        .map(|_| line),
+                    column: source_location.as_ref().map(|_| column),
+                    end_line: None,
+                    end_column: None,
+                }
+            })
+            .collect();
+
+        Ok(DisassembleResponseBody { instructions })
+    }
+
+    /// Get the variables for a `variables` request.
+    fn variables(
+        &mut self,
+        args: &VariablesArguments,
+    ) -> Result<Vec<Variable>, debug_adapter::Error> {
+        let container = self
+            .variables
+            .get(args.variables_reference)
+            .cloned()
+            .ok_or_else(|| anyhow!("Unknown variables reference {}", args.variables_reference))?;
+
+        let frame_registers = |frame_id: u64| {
+            self.current_stackframes
+                .iter()
+                .find(|frame| frame.id == frame_id)
+                .map(|frame| frame.registers.clone())
+                .unwrap_or_default()
+        };
+
+        let children: Vec<(debug_info::Variable, String)> = match container {
+            VariableContainer::Registers { frame_id } => {
+                let registers = frame_registers(frame_id);
+
+                return Ok(variables::core_registers(
+                    &registers,
+                    &mut self.variables,
+                    frame_id,
+                ));
+            }
+            VariableContainer::FpuRegisters { frame_id } => {
+                return Ok(variables::fpu_registers(&frame_registers(frame_id)));
+            }
+            VariableContainer::Peripherals
+            | VariableContainer::Peripheral { .. }
+            | VariableContainer::PeripheralRegister { .. } => {
+                let device = self
+                    .svd
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("No SVD file loaded"))?;
+                let mut core =
+                    target_core(&mut self.target).ok_or(debug_adapter::Error::MissingSession)?;
+
+                return Ok(match container {
+                    VariableContainer::Peripheral { peripheral } => {
+                        variables::peripheral_registers(
+                            device,
+                            &mut *core,
+                            &mut self.variables,
+                            peripheral,
+                        )
+                    }
+                    VariableContainer::PeripheralRegister {
+                        peripheral,
+                        register,
+                    } => variables::register_fields(device, &mut *core, peripheral, register)
+                        .map_err(|e| anyhow!("Unable to read register: {}", e))?,
+                    _ => variables::peripherals(device, &mut self.variables),
+                });
+            }
+            VariableContainer::Statics => {
+                let debug_info = self
+                    .debug_info
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("No debug information found!"))?;
+                let mut core =
+                    target_core(&mut self.target).ok_or(debug_adapter::Error::MissingSession)?;
+
+                debug_info
+                    .static_variables(&mut *core)
+                    .map_err(|e| anyhow!("Unable to read static variables: {}", e))?
+                    .into_iter()
+                    .map(|variable| {
+                        let evaluate_name = variable.name.clone();
+                        (variable, evaluate_name)
+                    })
+                    .collect()
+            }
+            VariableContainer::Locals { frame_id } => self
+                .current_stackframes
+                .iter()
+                .find(|frame| frame.id == frame_id)
+                .map(|frame| {
+                    frame
+                        .variables
+                        .iter()
+                        .map(|variable| (variable.clone(), variable.name.clone()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            VariableContainer::Children {
+                variable,
+                evaluate_name,
+            } => {
+                let debug_info = self
+                    .debug_info
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("No debug information found!"))?;
+                let mut core =
+                    target_core(&mut self.target).ok_or(debug_adapter::Error::MissingSession)?;
+
+                let filter = ChildFilter {
+                    filter: args.filter.as_deref(),
+                    start: args.start,
+                    count: args.count,
+                };
+
+                variables::children(debug_info, &mut *core, &variable, &evaluate_name, filter)
+                    .map_err(|e| anyhow!("Unable to read variable: {}", e))?
+            }
+            VariableContainer::Variable {
+                variable,
+                evaluate_name,
+            } => vec![(variable, evaluate_name)],
+        };
+
+        let debug_info = self
+            .debug_info
+            .as_ref()
+            .ok_or_else(|| anyhow!("No debug information found!"))?;
+        let mut core = target_core(&mut self.target).ok_or(debug_adapter::Error::MissingSession)?;
+        let registry = &mut self.variables;
+
+        Ok(children
+            .into_iter()
+            .map(|(variable, evaluate_name)| {
+                variables::to_dap_variable(
+                    debug_info,
+                    &mut *core,
+                    registry,
+                    &variable,
+                    evaluate_name,
+                )
+            })
+            .collect())
+    }
+
+    /// Body of the `stopped` event, for a core halted at `pc`.
+    fn stopped_event_body(
+        &self,
+        reason: HaltReason,
+        hit_breakpoint_ids: Vec<i64>,
+    ) -> StoppedEventBody {
+        let (reason, description) = match reason {
+            HaltReason::Breakpoint => ("breakpoint", "Target halted at breakpoint."),
+            HaltReason::Exception => ("exception", "Target halted due to an exception."),
+            HaltReason::Watchpoint => ("data breakpoint", "Target halted at watchpoint."),
+            HaltReason::Step => ("step", "Target paused after step."),
+            HaltReason::Request | HaltReason::External | HaltReason::Unknown => {
+                ("pause", "Target halted.")
+            }
+        };
+
+        StoppedEventBody {
+            reason: reason.to_owned(),
+            description: Some(description.to_owned()),
+            thread_id: Some(0),
+            preserve_focus_hint: None,
+            text: None,
+            all_threads_stopped: Some(true),
+            hit_breakpoint_ids: if hit_breakpoint_ids.is_empty() {
+                None
+            } else {
+                Some(hit_breakpoint_ids)
+            },
+        }
+    }
+
     fn pause(&mut self) -> Result<bool, probe_rs::Error> {
-        match self.session.as_mut().and_then(|s| s.core(0).ok()) {
-            Some(ref mut core) => {
+        match target_core(&mut self.target) {
+            Some(mut core) => {
                 debug!("Trying to pause target");
                 let cpi = core.halt(Duration::from_millis(10))?;
                 debug!("Paused target at pc=0x{:08x}", cpi.pc);
@@ -596,6 +2229,20 @@ impl Debugger {
     }
 }
 
+#[derive(Deserialize, Debug, Default)]
+struct LaunchRequestArguments {
+    program: String,
+    chip: String,
+    cwd: Option<String>,
+    halt_after_reset: Option<bool>,
+    /// CMSIS-SVD file describing the peripherals of the device.
+    svd_file: Option<String>,
+    /// Location of the RTT control block, and settings of the channels.
+    rtt: Option<RttConfig>,
+    /// Baud rate, TPIU prescaler and stimulus ports of the SWO output.
+    swo: Option<SwoConfig>,
+}
+
 #[derive(Deserialize, Debug, Default)]
 struct AttachRequestArguments {
     program: String,
@@ -603,6 +2250,12 @@ struct AttachRequestArguments {
     cwd: Option<String>,
     reset: Option<bool>,
     halt_after_reset: Option<bool>,
+    /// CMSIS-SVD file describing the peripherals of the device.
+    svd_file: Option<String>,
+    /// Location of the RTT control block, and settings of the channels.
+    rtt: Option<RttConfig>,
+    /// Baud rate, TPIU prescaler and stimulus ports of the SWO output.
+    swo: Option<SwoConfig>,
 }
 
 pub fn get_arguments<T: DeserializeOwned>(req: &Request) -> Result<T, debug_adapter::Error> {
@@ -626,33 +2279,118 @@ fn connect_to_probe(target: &str) -> Result<Session, anyhow::Error> {
     Ok(session)
 }
 
-pub enum HandleResult {
-    Continue,
-    Stop,
+/// Send the output of semihosting operations to the debug console.
+fn forward_semihosting<W: Write>(
+    adapter: &mut DebugAdapter<W>,
+    semihosting: &mut Semihosting,
+) -> Result<(), debug_adapter::Error> {
+    for (category, text) in semihosting.take_output() {
+        adapter.send_event(&Event::output(category, text))?;
+    }
+
+    Ok(())
 }
 
-#[derive(Debug)]
-struct BreakpointInfo {
-    id: u32,
-    verified: bool,
-    info: SourceBreakpoint,
-    address: Option<u64>,
+/// Send the new data of the RTT channels to the client.
+fn forward_rtt<W: Write>(
+    adapter: &mut DebugAdapter<W>,
+    rtt: &mut Rtt,
+    core: &mut dyn CoreAccess,
+) -> Result<(), debug_adapter::Error> {
+    let output = match rtt.poll(core) {
+        Ok(output) => output,
+        Err(e) => {
+            // The target might just be busy, so try again at the next poll.
+            warn!("Failed to read RTT channels: {:?}", e);
+            return Ok(());
+        }
+    };
+
+    for output in output {
+        let mut event = Event::output(&output.category, output.text);
+
+        // Link log messages to their source, so the client can show where they come from.
+        if let (Event::Output(body), Some(location)) = (&mut event, &output.location) {
+            let (line, column) = source_position(Some(location));
+
+            body.source = Some(source(location));
+            body.line = Some(line);
+            body.column = location.column.map(|_| column);
+        }
+
+        adapter.send_event(&event)?;
+    }
+
+    Ok(())
 }
 
-impl BreakpointInfo {
-    fn get_event_body(&self) -> BreakpointEventBody {
-        BreakpointEventBody {
-            reason: "changed".to_owned(),
-            breakpoint: Breakpoint {
-                id: Some(self.id as i64),
-                column: self.info.column,
-                end_column: None,
-                line: Some(self.info.line),
-                end_line: None,
-                message: None,
-                verified: self.verified,
-                source: None,
-            },
+/// Send the output decoded from the SWO data to the client.
+fn forward_swo<W: Write>(
+    adapter: &mut DebugAdapter<W>,
+    itm: &mut Itm,
+    target: &mut dyn TargetAccess,
+) -> Result<(), debug_adapter::Error> {
+    let data = match target.read_swo() {
+        Ok(data) => data,
+        Err(e) => {
+            warn!("Failed to read SWO data: {:?}", e);
+            return Ok(());
         }
+    };
+
+    for output in itm.process(&data) {
+        adapter.send_event(&Event::output(&output.category, output.text))?;
     }
+
+    Ok(())
+}
+
+/// Access the core of the target, if we are connected to one.
+fn target_core(target: &mut Option<Box<dyn TargetAccess>>) -> Option<Box<dyn CoreAccess + '_>> {
+    match target.as_mut()?.core() {
+        Ok(core) => Some(core),
+        Err(e) => {
+            warn!("Failed to access the core: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Line and column of a source location, as sent to the client.
+fn source_position(location: Option<&debug_info::SourceLocation>) -> (i64, i64) {
+    use debug_info::ColumnType::*;
+
+    let line = location.and_then(|sl| sl.line).unwrap_or(0) as i64;
+
+    let column = location
+        .and_then(|sl| {
+            sl.column.map(|col| match col {
+                LeftEdge => 0,
+                Column(c) => c as i64,
+            })
+        })
+        .unwrap_or(0);
+
+    (line, column)
+}
+
+fn source(location: &debug_info::SourceLocation) -> Source {
+    Source {
+        name: location.file.clone(),
+        path: location
+            .path()
+            .and_then(|path| path.to_str().map(|s| s.to_owned())),
+        source_reference: None,
+        presentation_hint: None,
+        origin: None,
+        sources: None,
+        adapter_data: None,
+        checksums: None,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum HandleResult {
+    Continue,
+    Stop,
 }
@@ -0,0 +1,562 @@
+//! Decoding of the log frames of `defmt`, which the target sends over RTT.
+//!
+//! The frames use the raw encoding of defmt 0.3: the index of the format string as a
+//! 16-bit number, followed by the arguments of the timestamp and the arguments of the
+//! format string. The format strings are the names of the symbols in the `.defmt`
+//! section of the program, and the addresses of the symbols are their indices.
+
+use crate::debug_info::SourceLocation;
+use object::{Object, ObjectSection, ObjectSymbol, SymbolSection};
+use serde::Deserialize;
+use std::{collections::HashMap, fmt};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Level::Trace => "TRACE",
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        };
+
+        // Padded, so that the messages of all levels are aligned.
+        f.pad(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum DecodeError {
+    #[error("The frame is incomplete")]
+    UnexpectedEof,
+    #[error("Unknown format string index {0}")]
+    UnknownIndex(u16),
+    #[error("Format string {0} is not a log message")]
+    NotALogMessage(u16),
+    #[error("Unsupported format parameter '{0}'")]
+    Unsupported(String),
+    #[error("Invalid data for the parameter '{0}'")]
+    InvalidData(String),
+}
+
+/// A symbol of the `.defmt` section. Its name is a JSON object like
+/// `{"package":"app","tag":"defmt_info","data":"x = {=u32}","disambiguator":"..."}`.
+#[derive(Deserialize, Debug, Clone)]
+struct Entry {
+    tag: String,
+    data: String,
+}
+
+/// The format strings of the program, and the source locations of its log statements.
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    entries: HashMap<u16, Entry>,
+    /// Format string of the timestamp, which is sent with every frame.
+    timestamp: Option<String>,
+    /// Encoding of the frames, as given by the program.
+    encoding: Option<String>,
+    locations: HashMap<u16, SourceLocation>,
+}
+
+/// A decoded log frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// The level, or `None` for `defmt::println!`.
+    pub level: Option<Level>,
+    pub timestamp: Option<String>,
+    pub message: String,
+    /// Source location of the log statement.
+    pub location: Option<SourceLocation>,
+}
+
+impl Frame {
+    /// The frame as a line of the debug console.
+    pub fn display(&self) -> String {
+        let mut line = String::new();
+
+        if let Some(timestamp) = &self.timestamp {
+            line.push_str(timestamp);
+            line.push(' ');
+        }
+
+        if let Some(level) = self.level {
+            line.push_str(&format!("{:<5} ", level));
+        }
+
+        line.push_str(&self.message);
+        line.push('\n');
+
+        line
+    }
+}
+
+impl Table {
+    /// Read the format strings from the `.defmt` section of the program, or return
+    /// `None` if it doesn't use defmt.
+    pub fn parse(object: &object::File) -> Option<Table> {
+        let section = object.section_by_name(".defmt")?.index();
+
+        let mut table = Table::default();
+
+        for symbol in object.symbols() {
+            if symbol.section() != SymbolSection::Section(section) {
+                continue;
+            }
+
+            let entry: Entry = match symbol.name().map(serde_json::from_str) {
+                Ok(Ok(entry)) => entry,
+                // Other symbols in the section, like its start and end.
+                _ => continue,
+            };
+
+            match entry.tag.as_str() {
+                "defmt_timestamp" => table.timestamp = Some(entry.data),
+                "defmt_encoding" => table.encoding = Some(entry.data),
+                _ => {
+                    table.entries.insert(symbol.address() as u16, entry);
+                }
+            }
+        }
+
+        Some(table)
+    }
+
+    /// Set the source locations of the log statements, by the index of their format string.
+    pub fn set_locations(&mut self, locations: HashMap<u16, SourceLocation>) {
+        self.locations = locations;
+    }
+
+    /// Check if the frames use an encoding which can't be decoded.
+    pub fn unsupported_encoding(&self) -> Option<&str> {
+        match self.encoding.as_deref() {
+            None | Some("raw") => None,
+            Some(encoding) => Some(encoding),
+        }
+    }
+
+    /// Decode the frame at the start of `data`, and return it with its size.
+    pub fn decode(&self, data: &[u8]) -> Result<(Frame, usize), DecodeError> {
+        let mut reader = Reader { data, position: 0 };
+
+        let index = reader.u16()?;
+        let entry = self
+            .entries
+            .get(&index)
+            .ok_or(DecodeError::UnknownIndex(index))?;
+
+        let level = match entry.tag.as_str() {
+            "defmt_trace" => Some(Level::Trace),
+            "defmt_debug" => Some(Level::Debug),
+            "defmt_info" => Some(Level::Info),
+            "defmt_warn" => Some(Level::Warn),
+            "defmt_error" => Some(Level::Error),
+            "defmt_println" => None,
+            _ => return Err(DecodeError::NotALogMessage(index)),
+        };
+
+        let timestamp = match &self.timestamp {
+            Some(format) => Some(self.format(format, &mut reader)?),
+            None => None,
+        };
+
+        let message = self.format(&entry.data, &mut reader)?;
+
+        let frame = Frame {
+            level,
+            timestamp,
+            message,
+            location: self.locations.get(&index).cloned(),
+        };
+
+        Ok((frame, reader.position))
+    }
+
+    /// Format the arguments for `format`, which are read from `reader`.
+    fn format(&self, format: &str, reader: &mut Reader) -> Result<String, DecodeError> {
+        // The format strings of derived enums contain the variants, separated by `|`.
+        let variants = split_variants(format);
+        let format = match variants.len() {
+            1 => format,
+            _ => {
+                let discriminant = reader.u8()? as usize;
+                variants
+                    .get(discriminant)
+                    .ok_or_else(|| DecodeError::InvalidData(format.to_owned()))?
+            }
+        };
+
+        let mut output = String::new();
+
+        for segment in parse(format)? {
+            match segment {
+                Segment::Literal(text) => output.push_str(&text),
+                Segment::Parameter { ty, hint } => {
+                    output.push_str(&self.argument(&ty, hint.as_deref(), reader)?)
+                }
+            }
+        }
+
+        Ok(output)
+    }
+
+    fn argument(
+        &self,
+        ty: &str,
+        hint: Option<&str>,
+        reader: &mut Reader,
+    ) -> Result<String, DecodeError> {
+        Ok(match ty {
+            "u8" => integer(u128::from(reader.u8()?), hint),
+            "u16" => integer(u128::from(reader.u16()?), hint),
+            "u32" => integer(u128::from(reader.u32()?), hint),
+            "u64" => integer(u128::from(reader.u64()?), hint),
+            "u128" => integer(
+                u128::from(reader.u64()?) | u128::from(reader.u64()?) << 64,
+                hint,
+            ),
+            "usize" => integer(u128::from(reader.leb128()?), hint),
+            "i8" => signed(i128::from(reader.u8()? as i8), hint),
+            "i16" => signed(i128::from(reader.u16()? as i16), hint),
+            "i32" => signed(i128::from(reader.u32()? as i32), hint),
+            "i64" => signed(i128::from(reader.u64()? as i64), hint),
+            "i128" => signed(
+                (u128::from(reader.u64()?) | u128::from(reader.u64()?) << 64) as i128,
+                hint,
+            ),
+            "isize" => {
+                // Zigzag encoded, so that small negative numbers are short as well.
+                let value = reader.leb128()?;
+                signed(
+                    i128::from((value >> 1) as i64 ^ -((value & 1) as i64)),
+                    hint,
+                )
+            }
+            "f32" => f32::from_bits(reader.u32()?).to_string(),
+            "f64" => f64::from_bits(reader.u64()?).to_string(),
+            "bool" => (reader.u8()? != 0).to_string(),
+            "char" => std::char::from_u32(reader.u32()?)
+                .ok_or_else(|| DecodeError::InvalidData(ty.to_owned()))?
+                .to_string(),
+            "str" => {
+                let len = reader.leb128()? as usize;
+                let text = String::from_utf8_lossy(reader.bytes(len)?).into_owned();
+                match hint {
+                    Some("?") => format!("{:?}", text),
+                    _ => text,
+                }
+            }
+            "istr" => {
+                let index = reader.u16()?;
+                self.entries
+                    .get(&index)
+                    .ok_or(DecodeError::UnknownIndex(index))?
+                    .data
+                    .clone()
+            }
+            "[u8]" => {
+                let len = reader.leb128()? as usize;
+                bytes(reader.bytes(len)?, hint)
+            }
+            "?" => self.nested(reader)?,
+            "[?]" => {
+                let len = reader.leb128()? as usize;
+                let elements = (0..len)
+                    .map(|_| self.nested(reader))
+                    .collect::<Result<Vec<_>, _>>()?;
+                format!("[{}]", elements.join(", "))
+            }
+            _ => match array_len(ty) {
+                Some(len) => bytes(reader.bytes(len)?, hint),
+                None => return Err(DecodeError::Unsupported(ty.to_owned())),
+            },
+        })
+    }
+
+    /// Format a value which implements `Format`, with its own format string.
+    fn nested(&self, reader: &mut Reader) -> Result<String, DecodeError> {
+        let index = reader.u16()?;
+        let entry = self
+            .entries
+            .get(&index)
+            .ok_or(DecodeError::UnknownIndex(index))?;
+
+        self.format(&entry.data, reader)
+    }
+}
+
+/// A part of a format string.
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    /// A parameter like `{=u8:x}`, with its type and display hint.
+    Parameter {
+        ty: String,
+        hint: Option<String>,
+    },
+}
+
+fn parse(format: &str) -> Result<Vec<Segment>, DecodeError> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = format.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let parameter: String = chars.by_ref().take_while(|c| *c != '}').collect();
+
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+
+                segments.push(parse_parameter(&parameter)?);
+            }
+            c => literal.push(c),
+        }
+    }
+
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+
+    Ok(segments)
+}
+
+/// Parse the inside of a parameter, like `=u8:x`. `{}` is the same as `{=?}`.
+fn parse_parameter(parameter: &str) -> Result<Segment, DecodeError> {
+    if parameter.is_empty() {
+        return Ok(Segment::Parameter {
+            ty: "?".to_owned(),
+            hint: None,
+        });
+    }
+
+    // Explicit positions of the arguments are not supported.
+    let spec = parameter
+        .strip_prefix('=')
+        .ok_or_else(|| DecodeError::Unsupported(parameter.to_owned()))?;
+
+    let (ty, hint) = match spec.find(':') {
+        Some(colon) => (&spec[..colon], Some(spec[colon + 1..].to_owned())),
+        None => (spec, None),
+    };
+
+    Ok(Segment::Parameter {
+        ty: ty.to_owned(),
+        hint,
+    })
+}
+
+/// Split the format string of a derived enum into its variants, at the `|` which
+/// are not inside of a parameter.
+fn split_variants(format: &str) -> Vec<&str> {
+    let mut variants = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, c) in format.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            '|' if depth == 0 => {
+                variants.push(&format[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+
+    variants.push(&format[start..]);
+    variants
+}
+
+/// The length of an array type like `[u8; 4]`.
+fn array_len(ty: &str) -> Option<usize> {
+    ty.strip_prefix("[u8;")?
+        .strip_suffix(']')?
+        .trim()
+        .parse()
+        .ok()
+}
+
+fn integer(value: u128, hint: Option<&str>) -> String {
+    match hint {
+        Some("x") => format!("{:x}", value),
+        Some("#x") => format!("{:#x}", value),
+        Some("X") => format!("{:X}", value),
+        Some("#X") => format!("{:#X}", value),
+        Some("b") => format!("{:b}", value),
+        Some("#b") => format!("{:#b}", value),
+        Some("o") => format!("{:o}", value),
+        Some("#o") => format!("{:#o}", value),
+        // Timestamps in microseconds or milliseconds, shown in seconds.
+        Some("us") => format!("{}.{:06}", value / 1_000_000, value % 1_000_000),
+        Some("ms") => format!("{}.{:03}", value / 1_000, value % 1_000),
+        _ => value.to_string(),
+    }
+}
+
+fn signed(value: i128, hint: Option<&str>) -> String {
+    match hint {
+        Some("x") | Some("#x") | Some("X") | Some("#X") | Some("b") | Some("#b") | Some("o")
+        | Some("#o")
+            if value >= 0 =>
+        {
+            integer(value as u128, hint)
+        }
+        _ => value.to_string(),
+    }
+}
+
+fn bytes(data: &[u8], hint: Option<&str>) -> String {
+    match hint {
+        Some("a") => format!("b\"{}\"", data.escape_ascii()),
+        _ => {
+            let elements: Vec<_> = data
+                .iter()
+                .map(|byte| integer(u128::from(*byte), hint))
+                .collect();
+            format!("[{}]", elements.join(", "))
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or(DecodeError::UnexpectedEof)?;
+
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, DecodeError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, DecodeError> {
+        Ok(u64::from(self.u32()?) | u64::from(self.u32()?) << 32)
+    }
+
+    fn leb128(&mut self) -> Result<u64, DecodeError> {
+        let mut value = 0u64;
+
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= u64::from(byte & 0x7f) << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(DecodeError::InvalidData("leb128".to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Entry, Level, Table};
+
+    fn table() -> Table {
+        let mut table = Table::default();
+
+        for (index, tag, data) in &[
+            (1, "defmt_info", "x = {=u8}, y = {=i16:#x}"),
+            (2, "defmt_derived", "None|Some({=u32})"),
+            (3, "defmt_error", "{} {=[u8]:x} {=str:?} {{literal}}"),
+            (4, "defmt_str", "interned"),
+            (5, "defmt_println", "{=istr} {=[?]} {=usize} {=isize}"),
+        ] {
+            table.entries.insert(
+                *index,
+                Entry {
+                    tag: tag.to_string(),
+                    data: data.to_string(),
+                },
+            );
+        }
+
+        table
+    }
+
+    #[test]
+    fn decode_frames() {
+        let mut table = table();
+
+        let (frame, size) = table.decode(&[1, 0, 7, 0x34, 0x12, 0xff]).unwrap();
+        assert_eq!(size, 5);
+        assert_eq!(frame.level, Some(Level::Info));
+        assert_eq!(frame.message, "x = 7, y = 0x1234");
+        assert_eq!(frame.display(), "INFO  x = 7, y = 0x1234\n");
+
+        let frame = [3, 0, 2, 0, 1, 5, 0, 0, 0, 2, 0xab, 1, 2, b'h', b'i'];
+        let (frame, _) = table.decode(&frame).unwrap();
+        assert_eq!(frame.message, "Some(5) [ab, 1] \"hi\" {literal}");
+
+        // Slices of `Format` values, and zigzag encoded `isize`.
+        let frame = [5, 0, 4, 0, 2, 2, 0, 0, 2, 0, 1, 3, 0, 0, 0, 0x80, 0x01, 3];
+        let (frame, _) = table.decode(&frame).unwrap();
+        assert_eq!(frame.level, None);
+        assert_eq!(frame.message, "interned [None, Some(3)] 128 -2");
+
+        table.timestamp = Some("{=u32:us}".to_owned());
+        let (frame, _) = table.decode(&[1, 0, 0x40, 0x42, 0x0f, 0, 7, 0, 0]).unwrap();
+        assert_eq!(frame.display(), "1.000000 INFO  x = 7, y = 0x0\n");
+    }
+
+    #[test]
+    fn decode_errors() {
+        let table = table();
+
+        assert_eq!(
+            table.decode(&[1, 0, 7]),
+            Err(super::DecodeError::UnexpectedEof)
+        );
+        assert_eq!(
+            table.decode(&[9, 0]),
+            Err(super::DecodeError::UnknownIndex(9))
+        );
+        assert_eq!(
+            table.decode(&[4, 0]),
+            Err(super::DecodeError::NotALogMessage(4))
+        );
+    }
+}
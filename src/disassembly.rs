@@ -0,0 +1,244 @@
+//! Disassembly of the code on the target, for the `disassemble` request.
+//!
+//! Code is read from the target, with software breakpoints replaced by the original
+//! instructions. Thumb instructions are either 2 or 4 bytes long, so disassembling
+//! backwards from an address is a guess which is validated by checking that the
+//! decoded instructions line up with the address again.
+
+use crate::breakpoints::Breakpoints;
+use crate::memory;
+use crate::target::CoreAccess;
+use anyhow::anyhow;
+use capstone::arch::{arm, BuildsCapstone, BuildsCapstoneExtraMode};
+use capstone::Capstone;
+use probe_rs::Architecture;
+
+/// Maximum size of a single instruction in bytes.
+const MAX_INSTRUCTION_SIZE: u32 = 4;
+
+/// Alignment of instructions in bytes.
+const INSTRUCTION_ALIGNMENT: u32 = 2;
+
+/// A disassembled instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub address: u32,
+    /// The encoded instruction, empty if the memory could not be read.
+    pub bytes: Vec<u8>,
+    /// The instruction in assembly syntax.
+    pub text: String,
+}
+
+impl Instruction {
+    fn unreadable(address: u32) -> Instruction {
+        Instruction {
+            address,
+            bytes: Vec::new(),
+            text: "<unreadable>".to_owned(),
+        }
+    }
+
+    /// Size of the instruction, unreadable memory is treated like 2 byte instructions.
+    fn size(&self) -> u32 {
+        if self.bytes.is_empty() {
+            INSTRUCTION_ALIGNMENT
+        } else {
+            self.bytes.len() as u32
+        }
+    }
+}
+
+pub struct Disassembler {
+    capstone: Capstone,
+}
+
+impl Disassembler {
+    /// Create a disassembler for the instruction set of a core.
+    pub fn new(architecture: Architecture) -> Result<Disassembler, anyhow::Error> {
+        let capstone = match architecture {
+            Architecture::Arm => Capstone::new()
+                .arm()
+                .mode(arm::ArchMode::Thumb)
+                .extra_mode(
+                    [arm::ArchExtraMode::MClass, arm::ArchExtraMode::V8]
+                        .iter()
+                        .copied(),
+                )
+                .build(),
+            Architecture::Riscv => {
+                return Err(anyhow!(
+                    "Disassembly is not supported for RISC-V targets yet"
+                ))
+            }
+        }
+        .map_err(|e| anyhow!("Unable to create disassembler: {}", e))?;
+
+        Ok(Disassembler { capstone })
+    }
+
+    /// Disassemble `count` instructions, starting `instruction_offset` instructions
+    /// away from the instruction at `address`. If the code can not be read, the
+    /// missing instructions are filled with placeholders.
+    pub fn disassemble(
+        &self,
+        core: &mut dyn CoreAccess,
+        breakpoints: &Breakpoints,
+        address: u32,
+        instruction_offset: i64,
+        count: usize,
+    ) -> Vec<Instruction> {
+        let address = address & !(INSTRUCTION_ALIGNMENT - 1);
+
+        let mut instructions = Vec::with_capacity(count);
+
+        if instruction_offset < 0 {
+            let before = ((-instruction_offset) as usize).min(count);
+            let skip = (-instruction_offset) as usize - before;
+
+            instructions = self.before(core, breakpoints, address, before + skip);
+            instructions.truncate(before);
+        }
+
+        if instructions.len() < count {
+            let skip = instruction_offset.max(0) as usize;
+            let after = self.after(
+                core,
+                breakpoints,
+                address,
+                skip + count - instructions.len(),
+            );
+
+            instructions.extend(after.into_iter().skip(skip));
+        }
+
+        instructions
+    }
+
+    /// Disassemble `count` instructions starting at `address`.
+    fn after(
+        &self,
+        core: &mut dyn CoreAccess,
+        breakpoints: &Breakpoints,
+        address: u32,
+        count: usize,
+    ) -> Vec<Instruction> {
+        let len =
+            (count as u64 * u64::from(MAX_INSTRUCTION_SIZE)).min((1u64 << 32) - u64::from(address));
+
+        let mut instructions = self.decode(core, breakpoints, address, len);
+        instructions.truncate(count);
+
+        instructions
+    }
+
+    /// Disassemble the `count` instructions in front of `address`, in order of their
+    /// addresses. If there are fewer instructions, the list is filled up with
+    /// placeholders at the start.
+    fn before(
+        &self,
+        core: &mut dyn CoreAccess,
+        breakpoints: &Breakpoints,
+        address: u32,
+        count: usize,
+    ) -> Vec<Instruction> {
+        let len = (count as u64 * u64::from(MAX_INSTRUCTION_SIZE)).min(u64::from(address));
+        let start = address - len as u32;
+
+        // Try the start of the window, and the next possible instruction after it,
+        // and take the first one where the instructions end exactly at `address`.
+        let mut instructions = (0..MAX_INSTRUCTION_SIZE / INSTRUCTION_ALIGNMENT)
+            .map(|i| i * INSTRUCTION_ALIGNMENT)
+            .filter(|offset| u64::from(*offset) <= len)
+            .map(|offset| self.decode(core, breakpoints, start + offset, len - u64::from(offset)))
+            .find(|instructions| {
+                instructions
+                    .last()
+                    .map(|last| last.address + last.size() == address)
+                    .unwrap_or(true)
+            })
+            .unwrap_or_else(|| {
+                let mut instructions = self.decode(core, breakpoints, start, len);
+                instructions
+                    .retain(|instruction| instruction.address + instruction.size() <= address);
+                instructions
+            });
+
+        if instructions.len() > count {
+            instructions.drain(..instructions.len() - count);
+        }
+
+        let mut first = instructions.first().map(|i| i.address).unwrap_or(address);
+        let mut padding = Vec::new();
+        while padding.len() + instructions.len() < count {
+            first = first.wrapping_sub(INSTRUCTION_ALIGNMENT);
+            padding.push(Instruction::unreadable(first));
+        }
+        padding.reverse();
+        padding.extend(instructions);
+
+        padding
+    }
+
+    /// Decode all instructions which start in the `len` bytes at `address`.
+    fn decode(
+        &self,
+        core: &mut dyn CoreAccess,
+        breakpoints: &Breakpoints,
+        address: u32,
+        len: u64,
+    ) -> Vec<Instruction> {
+        // The last instruction can extend past the end of the range.
+        let read_len = (len + u64::from(MAX_INSTRUCTION_SIZE - INSTRUCTION_ALIGNMENT))
+            .min((1u64 << 32) - u64::from(address));
+
+        let mut code = memory::read_readable(core, address, read_len);
+        breakpoints.restore_original_code(address, &mut code);
+
+        let mut instructions = Vec::new();
+        let mut offset = 0;
+
+        while (offset as u64) < len {
+            let instruction_address = address + offset as u32;
+
+            let decoded = self
+                .capstone
+                .disasm_count(
+                    &code[offset.min(code.len())..],
+                    instruction_address.into(),
+                    1,
+                )
+                .ok()
+                .and_then(|decoded| {
+                    decoded.iter().next().map(|instruction| Instruction {
+                        address: instruction_address,
+                        bytes: instruction.bytes().to_vec(),
+                        text: match (instruction.mnemonic(), instruction.op_str()) {
+                            (Some(mnemonic), Some(op_str)) if !op_str.is_empty() => {
+                                format!("{} {}", mnemonic, op_str)
+                            }
+                            (mnemonic, _) => mnemonic.unwrap_or("<unknown>").to_owned(),
+                        },
+                    })
+                });
+
+            let instruction = match decoded {
+                Some(instruction) => instruction,
+                // Data in the code, or an encoding the disassembler doesn't know.
+                None if offset + 2 <= code.len() => Instruction {
+                    address: instruction_address,
+                    bytes: code[offset..offset + 2].to_vec(),
+                    text: format!(
+                        ".short {:#06x}",
+                        u16::from_le_bytes([code[offset], code[offset + 1]])
+                    ),
+                },
+                None => Instruction::unreadable(instruction_address),
+            };
+
+            offset += instruction.size() as usize;
+            instructions.push(instruction);
+        }
+
+        instructions
+    }
+}
@@ -0,0 +1,225 @@
+//! The Data Watchpoint and Trace (DWT) unit of Cortex-M cores, which is used for
+//! data breakpoints.
+//!
+//! On ARMv6-M and ARMv7-M cores, each comparator matches an address range of a power
+//! of two bytes, which is aligned to its size and given by the `MASK` register. The DWT
+//! of ARMv8-M cores has no `MASK` register, a comparator matches up to a word given by
+//! the `DATAVSIZE` field of its `FUNCTION` register.
+
+use crate::target::CoreAccess;
+use anyhow::anyhow;
+use probe_rs::{Architecture, Error};
+
+/// Debug Exception and Monitor Control Register, with the `TRCENA` bit which enables the DWT.
+pub const DEMCR: u32 = 0xe000_edfc;
+pub const DEMCR_TRCENA: u32 = 1 << 24;
+
+/// DWT Control Register, with the number of comparators in `NUMCOMP`.
+pub const DWT_CTRL: u32 = 0xe000_1000;
+
+/// Device Architecture Register of the DWT, which only ARMv8-M cores implement.
+pub const DWT_DEVARCH: u32 = 0xe000_1fbc;
+
+/// Architecture ID of the DWT of ARMv8-M, and the `PRESENT` bit, in `DWT_DEVARCH`.
+const DEVARCH_ARCHID_MASK: u32 = 0x0010_ffff;
+const DEVARCH_DWT_V2: u32 = 0x0010_1a02;
+const DEVARCH_PRESENT: u32 = 1 << 20;
+
+/// Address of the first comparator. Each comparator has a `COMP`, `MASK` and `FUNCTION`
+/// register, and the registers of the next comparator follow 16 bytes later.
+pub const DWT_COMP0: u32 = 0xe000_1020;
+
+const MASK_OFFSET: u32 = 4;
+const FUNCTION_OFFSET: u32 = 8;
+
+/// `ACTION` of an ARMv8-M comparator, which halts the core when it matches.
+const FUNCTION_ACTION_DEBUG_EVENT: u32 = 0b01 << 4;
+const FUNCTION_DATAVSIZE_SHIFT: u32 = 10;
+
+/// Set in the `FUNCTION` register when the comparator matched, cleared when it is read.
+pub const FUNCTION_MATCHED: u32 = 1 << 24;
+
+/// The version of the DWT, which determines how the comparators are programmed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Version {
+    /// The DWT of ARMv6-M and ARMv7-M cores.
+    V1,
+    /// The DWT of ARMv8-M cores, like the Cortex-M23 and Cortex-M33.
+    V2,
+}
+
+/// The accesses a data breakpoint halts the core for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    /// Value of the `FUNCTION` register for a watchpoint on this kind of access, of
+    /// `2^mask` bytes.
+    pub fn function(self, version: Version, mask: u32) -> u32 {
+        match version {
+            Version::V1 => match self {
+                Access::Read => 0b0101,
+                Access::Write => 0b0110,
+                Access::ReadWrite => 0b0111,
+            },
+            // `MATCH` of a data address, and the size of the watched data.
+            Version::V2 => {
+                let data_address = match self {
+                    Access::Read => 0b0110,
+                    Access::Write => 0b0101,
+                    Access::ReadWrite => 0b0100,
+                };
+
+                data_address | FUNCTION_ACTION_DEBUG_EVENT | mask << FUNCTION_DATAVSIZE_SHIFT
+            }
+        }
+    }
+}
+
+/// Enable the DWT, and read the number of comparators it has.
+pub fn enable(core: &mut dyn CoreAccess) -> Result<u32, Error> {
+    if core.architecture() != Architecture::Arm {
+        return Ok(0);
+    }
+
+    let demcr = core.read_word_32(DEMCR)?;
+    core.write_word_32(DEMCR, demcr | DEMCR_TRCENA)?;
+
+    Ok(core.read_word_32(DWT_CTRL)? >> 28)
+}
+
+/// Read the version of the DWT from its `DEVARCH` register. ARMv6-M and ARMv7-M cores
+/// don't implement the register, so it reads as zero or the read fails.
+pub fn version(core: &mut dyn CoreAccess) -> Result<Version, Error> {
+    let devarch = core.read_word_32(DWT_DEVARCH).unwrap_or(0);
+
+    if devarch & DEVARCH_PRESENT == 0 {
+        Ok(Version::V1)
+    } else if devarch & DEVARCH_ARCHID_MASK == DEVARCH_DWT_V2 {
+        Ok(Version::V2)
+    } else {
+        Err(Error::Other(anyhow!(
+            "Unsupported DWT architecture {:#010x}",
+            devarch
+        )))
+    }
+}
+
+/// The largest mask the comparators support, as a number of ignored address bits.
+///
+/// On ARMv6-M and ARMv7-M cores the maximum is implementation defined, it is found by
+/// writing the largest possible mask to the unused comparator `index` and reading it back.
+/// On ARMv8-M cores a comparator watches at most a word.
+pub fn max_mask(core: &mut dyn CoreAccess, version: Version, index: u32) -> Result<u32, Error> {
+    if version == Version::V2 {
+        return Ok(2);
+    }
+
+    let mask = comparator(index) + MASK_OFFSET;
+
+    core.write_word_32(mask, 0x1f)?;
+    let max = core.read_word_32(mask)? & 0x1f;
+    core.write_word_32(mask, 0)?;
+
+    Ok(max)
+}
+
+/// Watch `2^mask` bytes at `address`, which has to be aligned to their size.
+pub fn set_comparator(
+    core: &mut dyn CoreAccess,
+    version: Version,
+    index: u32,
+    address: u32,
+    mask: u32,
+    access: Access,
+) -> Result<(), Error> {
+    let base = comparator(index);
+
+    // Disable the comparator while it is changed.
+    core.write_word_32(base + FUNCTION_OFFSET, 0)?;
+    core.write_word_32(base, address)?;
+    if version == Version::V1 {
+        core.write_word_32(base + MASK_OFFSET, mask)?;
+    }
+    core.write_word_32(base + FUNCTION_OFFSET, access.function(version, mask))
+}
+
+pub fn clear_comparator(core: &mut dyn CoreAccess, index: u32) -> Result<(), Error> {
+    core.write_word_32(comparator(index) + FUNCTION_OFFSET, 0)
+}
+
+/// Check if the comparator `index` matched since it was last checked.
+pub fn matched(core: &mut dyn CoreAccess, index: u32) -> Result<bool, Error> {
+    Ok(core.read_word_32(comparator(index) + FUNCTION_OFFSET)? & FUNCTION_MATCHED != 0)
+}
+
+fn comparator(index: u32) -> u32 {
+    DWT_COMP0 + 16 * index
+}
+
+/// Split the `size` bytes at `address` into ranges a comparator can watch, each
+/// given as its address and mask.
+///
+/// The ranges cover exactly the requested bytes, so that accesses to neighbouring
+/// data don't halt the core.
+pub fn ranges(address: u32, size: u32, max_mask: u32) -> Vec<(u32, u32)> {
+    let mut ranges = Vec::new();
+
+    let mut address = u64::from(address);
+    let end = address + u64::from(size);
+
+    while address < end {
+        let alignment = address.trailing_zeros().min(max_mask);
+        let remaining = 63 - (end - address).leading_zeros();
+        let mask = alignment.min(remaining);
+
+        ranges.push((address as u32, mask));
+        address += 1 << mask;
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ranges;
+
+    #[test]
+    fn aligned_ranges() {
+        assert_eq!(ranges(0x2000_0000, 4, 31), vec![(0x2000_0000, 2)]);
+        assert_eq!(ranges(0x2000_0002, 1, 31), vec![(0x2000_0002, 0)]);
+
+        // A 12 byte struct needs two comparators.
+        assert_eq!(
+            ranges(0x2000_0100, 12, 31),
+            vec![(0x2000_0100, 3), (0x2000_0108, 2)]
+        );
+
+        // Unaligned data is split at the alignment boundaries.
+        assert_eq!(
+            ranges(0x2000_0002, 4, 31),
+            vec![(0x2000_0002, 1), (0x2000_0004, 1)]
+        );
+        assert_eq!(
+            ranges(0x2000_0003, 2, 31),
+            vec![(0x2000_0003, 0), (0x2000_0004, 0)]
+        );
+
+        // The range of a comparator is limited by the largest mask.
+        assert_eq!(
+            ranges(0x2000_0000, 64, 4),
+            vec![
+                (0x2000_0000, 4),
+                (0x2000_0010, 4),
+                (0x2000_0020, 4),
+                (0x2000_0030, 4)
+            ]
+        );
+
+        assert_eq!(ranges(0x2000_0000, 0, 31), vec![]);
+    }
+}
@@ -0,0 +1,1448 @@
+//! Evaluation of expressions, for watches, hovers and the debug console, and
+//! encoding of the new values for `setVariable` and `setExpression`.
+//!
+//! The expressions are a small subset of Rust and C, which covers the `evaluateName`
+//! of every variable sent to the client:
+//!
+//! - variables of the selected frame and statics: `counter`, `app::ORIGIN`
+//! - fields, elements and dereferences: `point.x`, `buffer[3]`, `*pointer`, `(*pointer).y`
+//! - registers of the selected frame: `$r0`, `$sp`, `$pc`, `$s0`
+//! - integers and casts: `*(u32*)0x2000_0000`, `(app::Point*)$r0`, `(*const u8)$sp`
+//! - comparisons and logic, for breakpoint conditions: `counter == 5 && !done`
+//!
+//! Fields of Rust enums are resolved through the active variant, like `state.__0`.
+
+use crate::dap_types;
+use crate::debug_info::{
+    DebugError, DebugInfo, StackFrame, TypeKind, TypeRef, ValueLocation, Variable,
+};
+use crate::target::CoreAccess;
+use crate::variables::{self, ChildFilter, VariableContainer, VariableRegistry};
+use std::{convert::TryFrom, iter::Peekable, str::Chars};
+
+#[derive(Debug, thiserror::Error)]
+pub enum EvaluateError {
+    #[error("Syntax error: {0}")]
+    Syntax(String),
+    #[error("Unknown variable '{0}'")]
+    UnknownVariable(String),
+    #[error("Unknown register '${0}'")]
+    UnknownRegister(String),
+    #[error("Register ${0} is not available in this frame")]
+    UnavailableRegister(String),
+    #[error("Unknown type '{0}'")]
+    UnknownType(String),
+    #[error("{0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Debug(#[from] DebugError),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    Register(String),
+    Integer(u64),
+    PathSeparator,
+    Dot,
+    Star,
+    Ampersand,
+    Minus,
+    OpenParen,
+    CloseParen,
+    OpenBracket,
+    CloseBracket,
+    Comparison(Comparison),
+    Not,
+    And,
+    Or,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, EvaluateError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        let token = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '$' => {
+                chars.next();
+                let name = take_identifier(&mut chars);
+                if name.is_empty() {
+                    return Err(EvaluateError::Syntax("Missing register name".to_owned()));
+                }
+                Token::Register(name)
+            }
+            c if c.is_ascii_digit() => parse_integer(&take_identifier(&mut chars))?,
+            c if c.is_alphabetic() || c == '_' => Token::Identifier(take_identifier(&mut chars)),
+            ':' => {
+                chars.next();
+                if chars.next() != Some(':') {
+                    return Err(EvaluateError::Syntax("Expected '::'".to_owned()));
+                }
+                Token::PathSeparator
+            }
+            '=' | '!' | '<' | '>' | '&' | '|' => {
+                chars.next();
+                let next = chars.peek().copied();
+
+                let (token, length) = match (c, next) {
+                    ('=', Some('=')) => (Token::Comparison(Comparison::Equal), 2),
+                    ('!', Some('=')) => (Token::Comparison(Comparison::NotEqual), 2),
+                    ('<', Some('=')) => (Token::Comparison(Comparison::LessEqual), 2),
+                    ('>', Some('=')) => (Token::Comparison(Comparison::GreaterEqual), 2),
+                    ('&', Some('&')) => (Token::And, 2),
+                    ('|', Some('|')) => (Token::Or, 2),
+                    ('<', _) => (Token::Comparison(Comparison::Less), 1),
+                    ('>', _) => (Token::Comparison(Comparison::Greater), 1),
+                    ('!', _) => (Token::Not, 1),
+                    ('&', _) => (Token::Ampersand, 1),
+                    _ => {
+                        return Err(EvaluateError::Syntax(format!(
+                            "Unexpected character '{}'",
+                            c
+                        )))
+                    }
+                };
+
+                if length == 2 {
+                    chars.next();
+                }
+                token
+            }
+            _ => {
+                chars.next();
+                match c {
+                    '.' => Token::Dot,
+                    '*' => Token::Star,
+                    '-' => Token::Minus,
+                    '(' => Token::OpenParen,
+                    ')' => Token::CloseParen,
+                    '[' => Token::OpenBracket,
+                    ']' => Token::CloseBracket,
+                    _ => {
+                        return Err(EvaluateError::Syntax(format!(
+                            "Unexpected character '{}'",
+                            c
+                        )))
+                    }
+                }
+            }
+        };
+
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+fn take_identifier(chars: &mut Peekable<Chars>) -> String {
+    let mut identifier = String::new();
+
+    while let Some(&c) = chars.peek() {
+        if !(c.is_alphanumeric() || c == '_') {
+            break;
+        }
+        identifier.push(c);
+        chars.next();
+    }
+
+    identifier
+}
+
+/// Parse a decimal or hexadecimal integer, which may contain `_` separators.
+fn parse_integer(text: &str) -> Result<Token, EvaluateError> {
+    let digits = text.replace('_', "");
+
+    let value = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => digits.parse(),
+    };
+
+    value
+        .map(Token::Integer)
+        .map_err(|_| EvaluateError::Syntax(format!("Invalid number '{}'", text)))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expression {
+    /// A local or static variable, with an optional path like `app::ORIGIN`.
+    Name(String),
+    Register(String),
+    Integer(u64),
+    Negate(Box<Expression>),
+    Field(Box<Expression>, String),
+    Index(Box<Expression>, Box<Expression>),
+    Deref(Box<Expression>),
+    Cast(TypeName, Box<Expression>),
+    Boolean(bool),
+    Compare(Comparison, Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl Comparison {
+    fn apply(self, left: i64, right: i64) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterEqual => left >= right,
+        }
+    }
+}
+
+/// The target type of a cast, like `u32*` or `*const app::Point`.
+#[derive(Debug, Clone, PartialEq)]
+struct TypeName {
+    name: String,
+    /// Number of pointer levels.
+    pointers: usize,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), EvaluateError> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(EvaluateError::Syntax(format!(
+                "Expected {:?}, found {:?}",
+                expected, token
+            ))),
+            None => Err(EvaluateError::Syntax(format!(
+                "Expected {:?} at the end",
+                expected
+            ))),
+        }
+    }
+
+    /// Parse a full expression, with `||` binding weakest.
+    fn expression(&mut self) -> Result<Expression, EvaluateError> {
+        let mut expression = self.and()?;
+
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            expression = Expression::Or(Box::new(expression), Box::new(self.and()?));
+        }
+
+        Ok(expression)
+    }
+
+    fn and(&mut self) -> Result<Expression, EvaluateError> {
+        let mut expression = self.comparison()?;
+
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            expression = Expression::And(Box::new(expression), Box::new(self.comparison()?));
+        }
+
+        Ok(expression)
+    }
+
+    /// Comparisons can't be chained, like in Rust.
+    fn comparison(&mut self) -> Result<Expression, EvaluateError> {
+        let left = self.unary()?;
+
+        match self.peek() {
+            Some(Token::Comparison(comparison)) => {
+                let comparison = *comparison;
+                self.next();
+
+                Ok(Expression::Compare(
+                    comparison,
+                    Box::new(left),
+                    Box::new(self.unary()?),
+                ))
+            }
+            _ => Ok(left),
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expression, EvaluateError> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.next();
+                Ok(Expression::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::Star) => {
+                self.next();
+                Ok(Expression::Deref(Box::new(self.unary()?)))
+            }
+            Some(Token::Minus) => {
+                self.next();
+                Ok(Expression::Negate(Box::new(self.unary()?)))
+            }
+            Some(Token::OpenParen) => match self.cast()? {
+                Some(cast) => Ok(cast),
+                None => self.postfix(),
+            },
+            _ => self.postfix(),
+        }
+    }
+
+    /// Parse a cast, if the parenthesis at the current position starts one.
+    fn cast(&mut self) -> Result<Option<Expression>, EvaluateError> {
+        let start = self.position;
+        self.next();
+
+        if let Some(type_name) = self.type_name() {
+            if self.peek() == Some(&Token::CloseParen) {
+                self.next();
+
+                if self.starts_operand() {
+                    return Ok(Some(Expression::Cast(type_name, Box::new(self.unary()?))));
+                }
+            }
+        }
+
+        self.position = start;
+        Ok(None)
+    }
+
+    fn type_name(&mut self) -> Option<TypeName> {
+        let mut pointers = 0;
+
+        // Rust pointers and references: `*const T`, `*mut T`, `&T`, `&mut T`
+        loop {
+            match (self.peek(), self.tokens.get(self.position + 1)) {
+                (Some(Token::Star), Some(Token::Identifier(qualifier)))
+                    if qualifier == "const" || qualifier == "mut" =>
+                {
+                    self.position += 2;
+                }
+                (Some(Token::Ampersand), Some(Token::Identifier(qualifier)))
+                    if qualifier == "mut" =>
+                {
+                    self.position += 2;
+                }
+                (Some(Token::Ampersand), _) => self.position += 1,
+                _ => break,
+            }
+            pointers += 1;
+        }
+
+        let name = self.path()?;
+
+        // C pointers: `T*`
+        while self.peek() == Some(&Token::Star) {
+            self.next();
+            pointers += 1;
+        }
+
+        Some(TypeName { name, pointers })
+    }
+
+    /// Parse a path like `app::ORIGIN`.
+    fn path(&mut self) -> Option<String> {
+        let mut path = match self.peek() {
+            Some(Token::Identifier(name)) => name.clone(),
+            _ => return None,
+        };
+        self.next();
+
+        while let (Some(Token::PathSeparator), Some(Token::Identifier(name))) =
+            (self.peek(), self.tokens.get(self.position + 1))
+        {
+            path.push_str("::");
+            path.push_str(name);
+            self.position += 2;
+        }
+
+        Some(path)
+    }
+
+    fn starts_operand(&self) -> bool {
+        matches!(
+            self.peek(),
+            Some(Token::Identifier(_))
+                | Some(Token::Register(_))
+                | Some(Token::Integer(_))
+                | Some(Token::OpenParen)
+                | Some(Token::Star)
+                | Some(Token::Minus)
+                | Some(Token::Not)
+        )
+    }
+
+    fn postfix(&mut self) -> Result<Expression, EvaluateError> {
+        let mut expression = self.primary()?;
+
+        loop {
+            match self.peek() {
+                Some(Token::Dot) => {
+                    self.next();
+
+                    let field = match self.next() {
+                        Some(Token::Identifier(field)) => field,
+                        // Fields of tuples
+                        Some(Token::Integer(index)) => index.to_string(),
+                        _ => return Err(EvaluateError::Syntax("Expected a field name".to_owned())),
+                    };
+
+                    expression = Expression::Field(Box::new(expression), field);
+                }
+                Some(Token::OpenBracket) => {
+                    self.next();
+                    let index = self.expression()?;
+                    self.expect(Token::CloseBracket)?;
+
+                    expression = Expression::Index(Box::new(expression), Box::new(index));
+                }
+                _ => return Ok(expression),
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expression, EvaluateError> {
+        if let Some(path) = self.path() {
+            return Ok(match path.as_str() {
+                "true" => Expression::Boolean(true),
+                "false" => Expression::Boolean(false),
+                _ => Expression::Name(path),
+            });
+        }
+
+        match self.next() {
+            Some(Token::Register(name)) => Ok(Expression::Register(name)),
+            Some(Token::Integer(value)) => Ok(Expression::Integer(value)),
+            Some(Token::OpenParen) => {
+                let expression = self.expression()?;
+                self.expect(Token::CloseParen)?;
+                Ok(expression)
+            }
+            Some(token) => Err(EvaluateError::Syntax(format!("Unexpected {:?}", token))),
+            None => Err(EvaluateError::Syntax("Unexpected end".to_owned())),
+        }
+    }
+}
+
+fn parse(text: &str) -> Result<Expression, EvaluateError> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        position: 0,
+    };
+
+    let expression = parser.expression()?;
+
+    match parser.peek() {
+        None => Ok(expression),
+        Some(token) => Err(EvaluateError::Syntax(format!("Unexpected {:?}", token))),
+    }
+}
+
+/// A primitive type, which can be used in casts even if the program doesn't use it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Primitive {
+    pub name: &'static str,
+    pub encoding: gimli::DwAte,
+    pub size: u64,
+}
+
+const PRIMITIVES: [Primitive; 14] = [
+    primitive("u8", gimli::DW_ATE_unsigned, 1),
+    primitive("u16", gimli::DW_ATE_unsigned, 2),
+    primitive("u32", gimli::DW_ATE_unsigned, 4),
+    primitive("u64", gimli::DW_ATE_unsigned, 8),
+    primitive("usize", gimli::DW_ATE_unsigned, 4),
+    primitive("i8", gimli::DW_ATE_signed, 1),
+    primitive("i16", gimli::DW_ATE_signed, 2),
+    primitive("i32", gimli::DW_ATE_signed, 4),
+    primitive("i64", gimli::DW_ATE_signed, 8),
+    primitive("isize", gimli::DW_ATE_signed, 4),
+    primitive("f32", gimli::DW_ATE_float, 4),
+    primitive("f64", gimli::DW_ATE_float, 8),
+    primitive("bool", gimli::DW_ATE_boolean, 1),
+    primitive("char", gimli::DW_ATE_UTF, 4),
+];
+
+const fn primitive(name: &'static str, encoding: gimli::DwAte, size: u64) -> Primitive {
+    Primitive {
+        name,
+        encoding,
+        size,
+    }
+}
+
+/// The type a pointer created in an expression points to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pointee {
+    Type(TypeRef),
+    Primitive(Primitive),
+}
+
+impl Pointee {
+    fn size(&self, debug_info: &DebugInfo) -> Result<u64, DebugError> {
+        match self {
+            Pointee::Type(type_ref) => debug_info.type_info(*type_ref).map(|ty| ty.size),
+            Pointee::Primitive(primitive) => Ok(primitive.size),
+        }
+    }
+
+    fn name(&self, debug_info: &DebugInfo) -> String {
+        match self {
+            Pointee::Type(type_ref) => debug_info
+                .type_name(*type_ref)
+                .unwrap_or_else(|_| "<unknown>".to_owned()),
+            Pointee::Primitive(primitive) => primitive.name.to_owned(),
+        }
+    }
+
+    fn is_integer(&self, debug_info: &DebugInfo) -> bool {
+        match self {
+            Pointee::Type(type_ref) => match debug_info.type_info(*type_ref).map(|ty| ty.kind) {
+                Ok(TypeKind::Base(encoding)) => encoding != gimli::DW_ATE_float,
+                Ok(TypeKind::Enumeration(_)) | Ok(TypeKind::Pointer(_)) => true,
+                _ => false,
+            },
+            Pointee::Primitive(primitive) => primitive.encoding != gimli::DW_ATE_float,
+        }
+    }
+}
+
+/// The result of an expression.
+#[derive(Debug, Clone)]
+pub enum Value {
+    /// A value with a type from the debug information.
+    Variable(Variable),
+    /// A value with a primitive type which is not in the debug information.
+    Primitive {
+        primitive: Primitive,
+        location: ValueLocation,
+    },
+    /// A pointer created by a cast.
+    Pointer {
+        pointee: Pointee,
+        /// Number of pointer levels, at least 1.
+        depth: usize,
+        address: u32,
+    },
+    /// An integer which isn't stored anywhere, like a literal.
+    Integer(i64),
+    /// The result of a comparison or logical operation.
+    Boolean(bool),
+    /// The value of a register in the frame, by its number in [`crate::debug_info::Registers`].
+    Register { register: usize, value: u32 },
+}
+
+/// Where a value is stored on the target, for values which can be changed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Place {
+    Memory(u32),
+    /// A register, by its number in [`crate::debug_info::Registers`].
+    Register(usize),
+}
+
+impl Value {
+    /// Where the value is stored, or `None` if it can not be changed.
+    pub fn place(&self) -> Option<Place> {
+        let location = match self {
+            Value::Variable(variable) => &variable.location,
+            Value::Primitive { location, .. } => location,
+            Value::Register { register, .. } => return Some(Place::Register(*register)),
+            Value::Pointer { .. } | Value::Integer(_) | Value::Boolean(_) => return None,
+        };
+
+        match location {
+            ValueLocation::Memory(address) => Some(Place::Memory(*address)),
+            ValueLocation::Register { register, .. } => Some(Place::Register(*register as usize)),
+            ValueLocation::Value(_) | ValueLocation::Unavailable => None,
+        }
+    }
+
+    /// The size of the value in bytes, if it has a type.
+    pub fn size(&self, debug_info: &DebugInfo) -> Option<u64> {
+        match self {
+            Value::Variable(variable) => {
+                let ty = debug_info.type_info(variable.type_ref?).ok()?;
+
+                // Pointers without a size are 32 bits, see `variables::to_dap_variable`.
+                match ty.kind {
+                    TypeKind::Pointer(_) if ty.size == 0 => Some(4),
+                    _ => Some(ty.size),
+                }
+            }
+            Value::Primitive { primitive, .. } => Some(primitive.size),
+            Value::Pointer { .. } | Value::Register { .. } => Some(4),
+            Value::Integer(_) | Value::Boolean(_) => None,
+        }
+    }
+}
+
+struct Evaluator<'a> {
+    debug_info: &'a DebugInfo,
+    core: &'a mut dyn CoreAccess,
+    frame: Option<&'a StackFrame>,
+}
+
+impl Evaluator<'_> {
+    fn evaluate(&mut self, expression: &Expression) -> Result<Value, EvaluateError> {
+        match expression {
+            Expression::Name(name) => self.variable(name).map(Value::Variable),
+            Expression::Register(name) => {
+                let register = variables::register_by_name(name)
+                    .ok_or_else(|| EvaluateError::UnknownRegister(name.clone()))?;
+
+                self.frame
+                    .and_then(|frame| frame.registers.get(register))
+                    .map(|value| Value::Register { register, value })
+                    .ok_or_else(|| EvaluateError::UnavailableRegister(name.clone()))
+            }
+            Expression::Integer(value) => Ok(Value::Integer(*value as i64)),
+            Expression::Negate(operand) => {
+                let value = self.evaluate(operand)?;
+                Ok(Value::Integer(self.integer(&value)?.wrapping_neg()))
+            }
+            Expression::Field(operand, field) => {
+                let value = self.evaluate(operand)?;
+                self.field(value, field)
+            }
+            Expression::Index(operand, index) => {
+                let value = self.evaluate(operand)?;
+                let index = self.evaluate(index)?;
+
+                match self.integer(&index)? {
+                    index if index < 0 => {
+                        Err(EvaluateError::Invalid(format!("Negative index {}", index)))
+                    }
+                    index => self.index(value, index as u64),
+                }
+            }
+            Expression::Deref(operand) => {
+                let value = self.evaluate(operand)?;
+                self.deref(value)
+            }
+            Expression::Cast(type_name, operand) => {
+                let value = self.evaluate(operand)?;
+                self.cast(value, type_name)
+            }
+            Expression::Boolean(value) => Ok(Value::Boolean(*value)),
+            Expression::Compare(comparison, left, right) => {
+                let left = self.evaluate(left)?;
+                let left = self.integer(&left)?;
+                let right = self.evaluate(right)?;
+                let right = self.integer(&right)?;
+
+                Ok(Value::Boolean(comparison.apply(left, right)))
+            }
+            Expression::Not(operand) => Ok(Value::Boolean(!self.truth(operand)?)),
+            Expression::And(left, right) => {
+                Ok(Value::Boolean(self.truth(left)? && self.truth(right)?))
+            }
+            Expression::Or(left, right) => {
+                Ok(Value::Boolean(self.truth(left)? || self.truth(right)?))
+            }
+        }
+    }
+
+    /// Evaluate an expression as a condition, where every value except 0 is true.
+    fn truth(&mut self, expression: &Expression) -> Result<bool, EvaluateError> {
+        let value = self.evaluate(expression)?;
+
+        Ok(self.integer(&value)? != 0)
+    }
+
+    /// Find a variable of the frame, or a static variable. Statics can be given
+    /// without their namespace, if the name is unique.
+    fn variable(&mut self, name: &str) -> Result<Variable, EvaluateError> {
+        if let Some(variable) = self
+            .frame
+            .and_then(|frame| frame.variables.iter().find(|v| v.name == name))
+        {
+            return Ok(variable.clone());
+        }
+
+        let statics = self.debug_info.static_variables(self.core)?;
+
+        if let Some(variable) = statics.iter().find(|v| v.name == name) {
+            return Ok(variable.clone());
+        }
+
+        let suffix = format!("::{}", name);
+        let mut matching = statics.iter().filter(|v| v.name.ends_with(&suffix));
+
+        match (matching.next(), matching.next()) {
+            (Some(variable), None) => Ok(variable.clone()),
+            _ => Err(EvaluateError::UnknownVariable(name.to_owned())),
+        }
+    }
+
+    /// The value as an integer, e.g. for an index or the address of a cast to a pointer.
+    fn integer(&mut self, value: &Value) -> Result<i64, EvaluateError> {
+        let (size, signed, location) = match value {
+            Value::Integer(value) => return Ok(*value),
+            Value::Boolean(value) => return Ok(i64::from(*value)),
+            Value::Register { value, .. } => return Ok(i64::from(*value)),
+            Value::Pointer { address, .. } => return Ok(i64::from(*address)),
+            Value::Primitive {
+                primitive,
+                location,
+            } if primitive.encoding != gimli::DW_ATE_float => (
+                primitive.size,
+                primitive.encoding == gimli::DW_ATE_signed,
+                location,
+            ),
+            Value::Variable(variable) => {
+                let ty = match variable.type_ref {
+                    Some(type_ref) => self.debug_info.type_info(type_ref)?,
+                    None => return Err(EvaluateError::Invalid("Unknown type".to_owned())),
+                };
+
+                match ty.kind {
+                    TypeKind::Base(encoding) if encoding != gimli::DW_ATE_float => (
+                        ty.size,
+                        encoding == gimli::DW_ATE_signed || encoding == gimli::DW_ATE_signed_char,
+                        &variable.location,
+                    ),
+                    TypeKind::Pointer(_) | TypeKind::Enumeration(_) => {
+                        (ty.size, false, &variable.location)
+                    }
+                    _ => {
+                        return Err(EvaluateError::Invalid(format!(
+                            "A value of type {} is not an integer",
+                            ty.name
+                        )))
+                    }
+                }
+            }
+            Value::Primitive { primitive, .. } => {
+                return Err(EvaluateError::Invalid(format!(
+                    "A value of type {} is not an integer",
+                    primitive.name
+                )))
+            }
+        };
+
+        let value = variables::read_unsigned(self.core, location, size)?;
+
+        // Sign extend the value from its actual size.
+        Ok(if signed && size > 0 && size < 8 {
+            let shift = 64 - size * 8;
+            ((value << shift) as i64) >> shift
+        } else {
+            value as i64
+        })
+    }
+
+    fn deref(&mut self, value: Value) -> Result<Value, EvaluateError> {
+        match value {
+            Value::Pointer {
+                pointee,
+                depth,
+                address,
+            } => self.pointee(pointee, depth, address),
+            Value::Variable(variable) => {
+                let ty = match variable.type_ref {
+                    Some(type_ref) => self.debug_info.type_info(type_ref)?,
+                    None => return Err(EvaluateError::Invalid("Unknown type".to_owned())),
+                };
+
+                match ty.kind {
+                    TypeKind::Pointer(Some(pointee)) => {
+                        let address =
+                            variables::read_unsigned(self.core, &variable.location, ty.size)?;
+
+                        Ok(Value::Variable(Variable {
+                            name: format!("*{}", variable.name),
+                            type_ref: Some(pointee),
+                            location: ValueLocation::Memory(address as u32),
+                        }))
+                    }
+                    TypeKind::Pointer(None) => Err(EvaluateError::Invalid(format!(
+                        "Cannot dereference {}, the type it points to is unknown",
+                        ty.name
+                    ))),
+                    _ => Err(EvaluateError::Invalid(format!(
+                        "Cannot dereference a value of type {}",
+                        ty.name
+                    ))),
+                }
+            }
+            _ => Err(EvaluateError::Invalid(
+                "Only pointers can be dereferenced, cast the value to a pointer first".to_owned(),
+            )),
+        }
+    }
+
+    /// The value `address` points to, through `depth` levels of pointers.
+    fn pointee(
+        &mut self,
+        pointee: Pointee,
+        depth: usize,
+        address: u32,
+    ) -> Result<Value, EvaluateError> {
+        if depth > 1 {
+            let address = variables::read_unsigned(self.core, &ValueLocation::Memory(address), 4)?;
+
+            return Ok(Value::Pointer {
+                pointee,
+                depth: depth - 1,
+                address: address as u32,
+            });
+        }
+
+        let location = ValueLocation::Memory(address);
+
+        Ok(match pointee {
+            Pointee::Type(type_ref) => Value::Variable(Variable {
+                name: String::new(),
+                type_ref: Some(type_ref),
+                location,
+            }),
+            Pointee::Primitive(primitive) => Value::Primitive {
+                primitive,
+                location,
+            },
+        })
+    }
+
+    /// Dereference pointers, like Rust does for field accesses.
+    fn auto_deref(&mut self, mut value: Value) -> Result<Value, EvaluateError> {
+        loop {
+            let is_pointer = match &value {
+                Value::Pointer { .. } => true,
+                Value::Variable(Variable {
+                    type_ref: Some(type_ref),
+                    ..
+                }) => matches!(
+                    self.debug_info.type_info(*type_ref)?.kind,
+                    TypeKind::Pointer(Some(_))
+                ),
+                _ => false,
+            };
+
+            if !is_pointer {
+                return Ok(value);
+            }
+
+            value = self.deref(value)?;
+        }
+    }
+
+    fn field(&mut self, value: Value, field: &str) -> Result<Value, EvaluateError> {
+        let variable = match self.auto_deref(value)? {
+            Value::Variable(variable) => variable,
+            _ => {
+                return Err(EvaluateError::Invalid(format!(
+                    "Cannot access field {} of a value without fields",
+                    field
+                )))
+            }
+        };
+
+        let filter = ChildFilter {
+            filter: Some("named"),
+            ..ChildFilter::default()
+        };
+
+        // Fields of tuples and tuple variants are called `__0`, `__1`, ... in the debug information.
+        let tuple_field = format!("__{}", field);
+
+        variables::children(self.debug_info, self.core, &variable, "", filter)?
+            .into_iter()
+            .map(|(child, _)| child)
+            .find(|child| child.name == field || child.name == tuple_field)
+            .map(Value::Variable)
+            .ok_or_else(|| {
+                let type_name = variable
+                    .type_ref
+                    .and_then(|type_ref| self.debug_info.type_name(type_ref).ok())
+                    .unwrap_or_default();
+
+                EvaluateError::Invalid(format!("No field {} in {}", field, type_name))
+            })
+    }
+
+    fn index(&mut self, value: Value, index: u64) -> Result<Value, EvaluateError> {
+        match value {
+            Value::Pointer {
+                pointee,
+                depth,
+                address,
+            } => {
+                let size = if depth > 1 {
+                    4
+                } else {
+                    pointee.size(self.debug_info)?
+                };
+
+                let offset = index
+                    .checked_mul(size)
+                    .and_then(|offset| u32::try_from(offset).ok())
+                    .ok_or_else(|| {
+                        EvaluateError::Invalid(format!("Index {} is out of range", index))
+                    })?;
+
+                self.pointee(pointee, depth, address.wrapping_add(offset))
+            }
+            Value::Variable(variable) => {
+                let ty = match variable.type_ref {
+                    Some(type_ref) => self.debug_info.type_info(type_ref)?,
+                    None => return Err(EvaluateError::Invalid("Unknown type".to_owned())),
+                };
+
+                if let TypeKind::Pointer(Some(pointee)) = ty.kind {
+                    let address = variables::read_unsigned(self.core, &variable.location, ty.size)?;
+
+                    return self.index(
+                        Value::Pointer {
+                            pointee: Pointee::Type(pointee),
+                            depth: 1,
+                            address: address as u32,
+                        },
+                        index,
+                    );
+                }
+
+                let filter = ChildFilter {
+                    filter: Some("indexed"),
+                    start: Some(index as i64),
+                    count: Some(1),
+                };
+
+                variables::children(self.debug_info, self.core, &variable, "", filter)?
+                    .into_iter()
+                    .next()
+                    .map(|(child, _)| Value::Variable(child))
+                    .ok_or_else(|| {
+                        EvaluateError::Invalid(format!(
+                            "Index {} is out of bounds for {}",
+                            index, ty.name
+                        ))
+                    })
+            }
+            _ => Err(EvaluateError::Invalid(
+                "Only arrays, slices and pointers can be indexed".to_owned(),
+            )),
+        }
+    }
+
+    fn cast(&mut self, value: Value, type_name: &TypeName) -> Result<Value, EvaluateError> {
+        let pointee = match self.debug_info.find_type(&type_name.name)? {
+            Some(type_ref) => Pointee::Type(type_ref),
+            None => PRIMITIVES
+                .iter()
+                .find(|primitive| primitive.name == type_name.name)
+                .map(|primitive| Pointee::Primitive(*primitive))
+                .ok_or_else(|| EvaluateError::UnknownType(type_name.name.clone()))?,
+        };
+
+        if type_name.pointers > 0 {
+            return Ok(Value::Pointer {
+                pointee,
+                depth: type_name.pointers,
+                address: self.integer(&value)? as u32,
+            });
+        }
+
+        // Integers are converted, everything else is reinterpreted in place.
+        let integer = if pointee.is_integer(self.debug_info) {
+            self.integer(&value).ok()
+        } else {
+            None
+        };
+
+        let location = match (integer, value) {
+            (Some(integer), _) => ValueLocation::Value(integer.to_le_bytes().to_vec()),
+            (None, Value::Variable(variable)) => variable.location,
+            (None, Value::Primitive { location, .. }) => location,
+            (None, value) => ValueLocation::Value(self.integer(&value)?.to_le_bytes().to_vec()),
+        };
+
+        Ok(match pointee {
+            Pointee::Type(type_ref) => Value::Variable(Variable {
+                name: String::new(),
+                type_ref: Some(type_ref),
+                location,
+            }),
+            Pointee::Primitive(primitive) => Value::Primitive {
+                primitive,
+                location,
+            },
+        })
+    }
+}
+
+/// Evaluate an expression in the context of a stack frame.
+pub fn evaluate_value(
+    debug_info: &DebugInfo,
+    core: &mut dyn CoreAccess,
+    frame: Option<&StackFrame>,
+    text: &str,
+) -> Result<Value, EvaluateError> {
+    let expression = parse(text)?;
+
+    Evaluator {
+        debug_info,
+        core,
+        frame,
+    }
+    .evaluate(&expression)
+}
+
+/// Evaluate the condition of a breakpoint. Every value except 0 is true.
+pub fn evaluate_condition(
+    debug_info: &DebugInfo,
+    core: &mut dyn CoreAccess,
+    frame: Option<&StackFrame>,
+    text: &str,
+) -> Result<bool, EvaluateError> {
+    let expression = parse(text)?;
+
+    Evaluator {
+        debug_info,
+        core,
+        frame,
+    }
+    .truth(&expression)
+}
+
+/// Format the message of a logpoint, replacing each `{expression}` with the value of
+/// the expression. Braces are escaped by doubling them, like `{{`.
+pub fn format_message(
+    debug_info: &DebugInfo,
+    core: &mut dyn CoreAccess,
+    frame: Option<&StackFrame>,
+    message: &str,
+) -> String {
+    let mut output = String::new();
+    let mut chars = message.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                output.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                output.push('}');
+            }
+            '{' => {
+                let expression: String = chars.by_ref().take_while(|c| *c != '}').collect();
+
+                // References for composite values are not needed, the client can't expand them.
+                let mut registry = VariableRegistry::default();
+
+                match evaluate(debug_info, core, frame, &mut registry, &expression) {
+                    Ok(variable) => output.push_str(&variable.value),
+                    Err(e) => output.push_str(&format!("<{}>", e)),
+                }
+            }
+            c => output.push(c),
+        }
+    }
+
+    output
+}
+
+/// Evaluate an expression, and convert the result into the form it is sent to the
+/// client. Composite results get a reference from `registry`, so that they can be expanded.
+pub fn evaluate(
+    debug_info: &DebugInfo,
+    core: &mut dyn CoreAccess,
+    frame: Option<&StackFrame>,
+    registry: &mut VariableRegistry,
+    text: &str,
+) -> Result<dap_types::Variable, EvaluateError> {
+    let expression = parse(text)?;
+
+    let value = Evaluator {
+        debug_info,
+        core,
+        frame,
+    }
+    .evaluate(&expression)?;
+
+    let text = text.trim();
+
+    // The evaluate names of fields and elements are appended to this one.
+    let evaluate_name = match expression {
+        Expression::Cast(..)
+        | Expression::Negate(_)
+        | Expression::Compare(..)
+        | Expression::Not(_)
+        | Expression::And(..)
+        | Expression::Or(..) => format!("({})", text),
+        _ => text.to_owned(),
+    };
+
+    Ok(to_dap_variable(
+        debug_info,
+        core,
+        registry,
+        value,
+        text,
+        evaluate_name,
+    ))
+}
+
+/// Convert a value into the form it is sent to the client, like
+/// [`variables::to_dap_variable`] does for the variables of the program.
+pub fn to_dap_variable(
+    debug_info: &DebugInfo,
+    core: &mut dyn CoreAccess,
+    registry: &mut VariableRegistry,
+    value: Value,
+    name: &str,
+    evaluate_name: String,
+) -> dap_types::Variable {
+    let memory_reference = |location: &ValueLocation| match location {
+        ValueLocation::Memory(address) => Some(format!("{:#010x}", address)),
+        _ => None,
+    };
+
+    let mut result = dap_types::Variable {
+        name: name.to_owned(),
+        evaluate_name: Some(name.to_owned()),
+        ..Default::default()
+    };
+
+    match value {
+        Value::Variable(mut variable) => {
+            variable.name = name.to_owned();
+            return variables::to_dap_variable(
+                debug_info,
+                core,
+                registry,
+                &variable,
+                evaluate_name,
+            );
+        }
+        Value::Primitive {
+            primitive,
+            location,
+        } => {
+            result.value = match variables::read_value(core, &location, primitive.size) {
+                Ok(bytes) => variables::format_base(primitive.encoding, &bytes),
+                Err(e) => format!("<{}>", e),
+            };
+            result.type_ = Some(primitive.name.to_owned());
+            result.memory_reference = memory_reference(&location);
+        }
+        Value::Pointer {
+            pointee,
+            depth,
+            address,
+        } => {
+            result.value = format!("{:#010x}", address);
+            result.type_ = Some(format!("{}{}", "*".repeat(depth), pointee.name(debug_info)));
+            result.memory_reference = Some(format!("{:#010x}", address));
+
+            // The target of the pointer can be expanded, like for pointers in the program.
+            if let (Pointee::Type(type_ref), 1, true) = (pointee, depth, address != 0) {
+                result.variables_reference = registry.insert(VariableContainer::Variable {
+                    variable: Variable {
+                        name: format!("*{}", name),
+                        type_ref: Some(type_ref),
+                        location: ValueLocation::Memory(address),
+                    },
+                    evaluate_name: format!("*{}", name),
+                });
+                result.named_variables = Some(1);
+            }
+        }
+        Value::Integer(value) => result.value = value.to_string(),
+        Value::Boolean(value) => {
+            result.value = value.to_string();
+            result.type_ = Some("bool".to_owned());
+        }
+        Value::Register { value, .. } => {
+            result.value = format!("{:#010x}", value);
+            result.evaluate_name = Some(evaluate_name);
+        }
+    }
+
+    result
+}
+
+/// Encode `text` as a new value for `target`, in the representation of its type.
+///
+/// Integers are given as expressions, so other variables and registers can be used,
+/// and have to fit into the type. Booleans are `true` or `false`, floats are decimal
+/// numbers, and enumerations can be given by the name of the variant.
+pub fn encode(
+    debug_info: &DebugInfo,
+    core: &mut dyn CoreAccess,
+    frame: Option<&StackFrame>,
+    target: &Value,
+    text: &str,
+) -> Result<Vec<u8>, EvaluateError> {
+    let mut encoder = Encoder {
+        evaluator: Evaluator {
+            debug_info,
+            core,
+            frame,
+        },
+        text: text.trim(),
+    };
+
+    match target {
+        Value::Variable(variable) => {
+            let ty = match variable.type_ref {
+                Some(type_ref) => debug_info.type_info(type_ref)?,
+                None => return Err(EvaluateError::Invalid("Unknown type".to_owned())),
+            };
+
+            match ty.kind {
+                TypeKind::Base(encoding) => encoder.base(encoding, ty.size, &ty.name),
+                TypeKind::Enumeration(enumerators) => {
+                    let name = encoder.text.rsplit("::").next().unwrap_or_default();
+
+                    match enumerators.iter().find(|(variant, _)| variant == name) {
+                        Some((_, value)) => {
+                            Ok(value.to_le_bytes()[..ty.size.min(8) as usize].to_vec())
+                        }
+                        None => encoder.integer(ty.size, false, &ty.name),
+                    }
+                }
+                // Pointers without a size are 32 bits, see `variables::to_dap_variable`.
+                TypeKind::Pointer(_) => {
+                    let size = if ty.size == 0 { 4 } else { ty.size };
+                    encoder.integer(size, false, &ty.name)
+                }
+                _ => Err(EvaluateError::Invalid(format!(
+                    "Values of type {} can not be changed",
+                    ty.name
+                ))),
+            }
+        }
+        Value::Primitive { primitive, .. } => {
+            encoder.base(primitive.encoding, primitive.size, primitive.name)
+        }
+        Value::Register { .. } => encoder.integer(4, false, "a register"),
+        Value::Pointer { .. } | Value::Integer(_) | Value::Boolean(_) => {
+            Err(EvaluateError::Invalid(
+                "The value is not stored on the target and can not be changed".to_owned(),
+            ))
+        }
+    }
+}
+
+struct Encoder<'a> {
+    evaluator: Evaluator<'a>,
+    text: &'a str,
+}
+
+impl Encoder<'_> {
+    /// Encode a value with a base type, e.g. `u16` or `f32`.
+    fn base(
+        &mut self,
+        encoding: gimli::DwAte,
+        size: u64,
+        type_name: &str,
+    ) -> Result<Vec<u8>, EvaluateError> {
+        match encoding {
+            gimli::DW_ATE_boolean => match self.text {
+                "true" => Ok(pad(&[1], size)),
+                "false" => Ok(pad(&[0], size)),
+                _ => Err(self.invalid(type_name)),
+            },
+            gimli::DW_ATE_float => {
+                let value: f64 = self.text.parse().map_err(|_| self.invalid(type_name))?;
+
+                match size {
+                    4 => Ok((value as f32).to_le_bytes().to_vec()),
+                    8 => Ok(value.to_le_bytes().to_vec()),
+                    _ => Err(EvaluateError::Invalid(format!(
+                        "Floats with {} bytes are not supported",
+                        size
+                    ))),
+                }
+            }
+            gimli::DW_ATE_UTF => {
+                let mut chars = self.text.chars();
+
+                let value = match (chars.next(), chars.next(), chars.next_back()) {
+                    (Some('\''), Some(c), Some('\'')) if chars.next().is_none() => u32::from(c),
+                    _ => {
+                        let value = self.value()?;
+
+                        Some(value)
+                            .filter(|value| *value >= 0 && *value <= i64::from(u32::MAX))
+                            .and_then(|value| std::char::from_u32(value as u32))
+                            .ok_or_else(|| self.invalid(type_name))
+                            .map(u32::from)?
+                    }
+                };
+
+                Ok(pad(&value.to_le_bytes(), size))
+            }
+            gimli::DW_ATE_signed | gimli::DW_ATE_signed_char => self.integer(size, true, type_name),
+            _ => self.integer(size, false, type_name),
+        }
+    }
+
+    /// Encode an integer with `size` bytes, which has to fit into the signed or
+    /// unsigned range of the type.
+    fn integer(
+        &mut self,
+        size: u64,
+        signed: bool,
+        type_name: &str,
+    ) -> Result<Vec<u8>, EvaluateError> {
+        let value = self.value()?;
+
+        let bits = (size * 8).min(64) as u32;
+
+        let fits = match (signed, bits) {
+            (_, 64) => true,
+            (true, bits) => value >= -(1 << (bits - 1)) && value < (1 << (bits - 1)),
+            (false, bits) => value >= 0 && value < (1 << bits),
+        };
+
+        if !fits {
+            return Err(EvaluateError::Invalid(format!(
+                "{} is out of range for {}",
+                self.text, type_name
+            )));
+        }
+
+        Ok(value.to_le_bytes()[..(bits / 8) as usize].to_vec())
+    }
+
+    /// Evaluate the text as an integer expression.
+    fn value(&mut self) -> Result<i64, EvaluateError> {
+        let expression = parse(self.text)?;
+        let value = self.evaluator.evaluate(&expression)?;
+
+        self.evaluator.integer(&value)
+    }
+
+    fn invalid(&self, type_name: &str) -> EvaluateError {
+        EvaluateError::Invalid(format!("'{}' is not a valid {}", self.text, type_name))
+    }
+}
+
+/// Zero extend `bytes` to `size` bytes.
+fn pad(bytes: &[u8], size: u64) -> Vec<u8> {
+    let mut data = bytes.to_vec();
+    data.resize(size as usize, 0);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(name: &str) -> Box<Expression> {
+        Box::new(Expression::Name(name.to_owned()))
+    }
+
+    #[test]
+    fn parse_evaluate_names() {
+        assert_eq!(
+            parse("app::ORIGIN.y").unwrap(),
+            Expression::Field(name("app::ORIGIN"), "y".to_owned())
+        );
+        assert_eq!(
+            parse("(*pointer).y").unwrap(),
+            Expression::Field(Box::new(Expression::Deref(name("pointer"))), "y".to_owned())
+        );
+        assert_eq!(
+            parse("buffer[0x1_0]").unwrap(),
+            Expression::Index(name("buffer"), Box::new(Expression::Integer(16)))
+        );
+        assert_eq!(
+            parse("state.0").unwrap(),
+            Expression::Field(name("state"), "0".to_owned())
+        );
+        assert_eq!(parse("$sp").unwrap(), Expression::Register("sp".to_owned()));
+    }
+
+    #[test]
+    fn parse_casts() {
+        let pointer = |name: &str, pointers| TypeName {
+            name: name.to_owned(),
+            pointers,
+        };
+
+        assert_eq!(
+            parse("*(u16*)0x2000_0808").unwrap(),
+            Expression::Deref(Box::new(Expression::Cast(
+                pointer("u16", 1),
+                Box::new(Expression::Integer(0x2000_0808))
+            )))
+        );
+        assert_eq!(
+            parse("(*const app::Point)$r0").unwrap(),
+            Expression::Cast(
+                pointer("app::Point", 1),
+                Box::new(Expression::Register("r0".to_owned()))
+            )
+        );
+        // Not a cast, only a variable in parentheses.
+        assert_eq!(
+            parse("(point).x").unwrap(),
+            Expression::Field(name("point"), "x".to_owned())
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert!(matches!(parse("point."), Err(EvaluateError::Syntax(_))));
+        assert!(matches!(parse("buffer[1"), Err(EvaluateError::Syntax(_))));
+        assert!(matches!(parse("a b"), Err(EvaluateError::Syntax(_))));
+        assert!(matches!(parse("0xzz"), Err(EvaluateError::Syntax(_))));
+        assert!(matches!(
+            parse("a == b == c"),
+            Err(EvaluateError::Syntax(_))
+        ));
+        assert!(matches!(parse("a |b"), Err(EvaluateError::Syntax(_))));
+    }
+
+    #[test]
+    fn parse_conditions() {
+        assert_eq!(
+            parse("counter >= 5 && !done || point.x != -1").unwrap(),
+            Expression::Or(
+                Box::new(Expression::And(
+                    Box::new(Expression::Compare(
+                        Comparison::GreaterEqual,
+                        name("counter"),
+                        Box::new(Expression::Integer(5))
+                    )),
+                    Box::new(Expression::Not(name("done")))
+                )),
+                Box::new(Expression::Compare(
+                    Comparison::NotEqual,
+                    Box::new(Expression::Field(name("point"), "x".to_owned())),
+                    Box::new(Expression::Negate(Box::new(Expression::Integer(1))))
+                ))
+            )
+        );
+        assert_eq!(parse("true").unwrap(), Expression::Boolean(true));
+    }
+}
@@ -0,0 +1,108 @@
+//! Exception breakpoints, which stop the core when a Rust panic or a Cortex-M fault
+//! occurs.
+//!
+//! Faults are caught with the vector catch of the core, which halts it when the
+//! exception is taken. Panics and the default handler of `cortex-m-rt` have no vector
+//! catch, so breakpoints are placed on their functions instead.
+
+use debugserver_types::ExceptionBreakpointsFilter;
+
+// Vector catch bits of DEMCR, see `dwt::DEMCR`.
+pub const VC_HARDERR: u32 = 1 << 10;
+pub const VC_BUSERR: u32 = 1 << 8;
+pub const VC_STATERR: u32 = 1 << 7;
+pub const VC_CHKERR: u32 = 1 << 6;
+pub const VC_NOCPERR: u32 = 1 << 5;
+pub const VC_MMERR: u32 = 1 << 4;
+
+/// All vector catch bits used by the filters.
+pub const VECTOR_CATCH_MASK: u32 =
+    VC_HARDERR | VC_BUSERR | VC_STATERR | VC_CHKERR | VC_NOCPERR | VC_MMERR;
+
+/// An exception filter, which the client can enable with the `setExceptionBreakpoints`
+/// request.
+#[derive(Debug, PartialEq)]
+pub struct ExceptionFilter {
+    pub filter: &'static str,
+    pub label: &'static str,
+    /// Functions which get a breakpoint when the filter is enabled.
+    pub functions: &'static [&'static str],
+    /// Bits of DEMCR which are set when the filter is enabled.
+    pub vector_catch: u32,
+    /// Numbers of the exceptions caught by the vector catch.
+    pub exceptions: &'static [u32],
+}
+
+pub const FILTERS: [ExceptionFilter; 4] = [
+    ExceptionFilter {
+        filter: "rust_panic",
+        label: "Rust panic",
+        functions: &["rust_begin_unwind"],
+        vector_catch: 0,
+        exceptions: &[],
+    },
+    ExceptionFilter {
+        filter: "hard_fault",
+        label: "HardFault",
+        functions: &[],
+        vector_catch: VC_HARDERR,
+        exceptions: &[3],
+    },
+    ExceptionFilter {
+        filter: "faults",
+        label: "MemManage/BusFault/UsageFault",
+        functions: &[],
+        vector_catch: VC_MMERR | VC_BUSERR | VC_STATERR | VC_CHKERR | VC_NOCPERR,
+        exceptions: &[4, 5, 6],
+    },
+    ExceptionFilter {
+        filter: "default_handler",
+        label: "DefaultHandler",
+        functions: &["DefaultHandler", "DefaultHandler_"],
+        vector_catch: 0,
+        exceptions: &[],
+    },
+];
+
+/// The filters for the `exceptionBreakpointFilters` capability.
+pub fn breakpoint_filters() -> Vec<ExceptionBreakpointsFilter> {
+    FILTERS
+        .iter()
+        .map(|filter| ExceptionBreakpointsFilter {
+            filter: filter.filter.to_owned(),
+            label: filter.label.to_owned(),
+            default: Some(false),
+        })
+        .collect()
+}
+
+pub fn by_name(name: &str) -> Option<&'static ExceptionFilter> {
+    FILTERS.iter().find(|filter| filter.filter == name)
+}
+
+/// The filter whose vector catch halts the core for the exception `number`.
+pub fn by_exception(number: u32) -> Option<&'static ExceptionFilter> {
+    FILTERS
+        .iter()
+        .find(|filter| filter.exceptions.contains(&number))
+}
+
+/// The name of the exception `number`, as found in the IPSR.
+pub fn exception_name(number: u32) -> String {
+    match number {
+        0 => "Thread mode".to_owned(),
+        1 => "Reset".to_owned(),
+        2 => "NMI".to_owned(),
+        3 => "HardFault".to_owned(),
+        4 => "MemManage".to_owned(),
+        5 => "BusFault".to_owned(),
+        6 => "UsageFault".to_owned(),
+        7 => "SecureFault".to_owned(),
+        11 => "SVCall".to_owned(),
+        12 => "DebugMonitor".to_owned(),
+        14 => "PendSV".to_owned(),
+        15 => "SysTick".to_owned(),
+        number if number >= 16 => format!("Interrupt {}", number - 16),
+        number => format!("Exception {}", number),
+    }
+}
@@ -0,0 +1,260 @@
+//! Analysis of Cortex-M faults, from the fault status registers of the System Control
+//! Block and the exception frame stacked by the core.
+
+use crate::target::CoreAccess;
+use probe_rs::{CoreRegisterAddress, Error};
+
+/// Configurable Fault Status Register, with the MemManage, BusFault and UsageFault
+/// status in its three parts.
+pub const CFSR: u32 = 0xe000_ed28;
+/// HardFault Status Register.
+pub const HFSR: u32 = 0xe000_ed2c;
+/// MemManage Fault Address Register.
+pub const MMFAR: u32 = 0xe000_ed34;
+/// BusFault Address Register.
+pub const BFAR: u32 = 0xe000_ed38;
+
+/// Register selector of the process stack pointer.
+pub const PSP: u16 = 18;
+const LR: u16 = 14;
+
+/// Set in CFSR if MMFAR holds the address of the MemManage fault.
+const MMARVALID: u32 = 1 << 7;
+/// Set in CFSR if BFAR holds the address of the BusFault.
+const BFARVALID: u32 = 1 << 15;
+
+/// Bits of CFSR, and what they mean.
+const CFSR_BITS: [(u32, &str); 18] = [
+    (0, "instruction access violation"),
+    (1, "data access violation"),
+    (3, "MemManage fault on unstacking for an exception return"),
+    (
+        4,
+        "stack overflow on exception entry (MemManage fault while stacking)",
+    ),
+    (
+        5,
+        "MemManage fault during lazy floating-point state preservation",
+    ),
+    (8, "instruction bus error"),
+    (9, "precise data bus error"),
+    (10, "imprecise data bus error"),
+    (11, "bus error on unstacking for an exception return"),
+    (
+        12,
+        "stack overflow on exception entry (bus error while stacking)",
+    ),
+    (
+        13,
+        "bus error during lazy floating-point state preservation",
+    ),
+    (16, "undefined instruction"),
+    (
+        17,
+        "invalid state, e.g. a branch to an address without the Thumb bit",
+    ),
+    (18, "invalid PC load by an exception return"),
+    (
+        19,
+        "access to a coprocessor which is disabled or not present",
+    ),
+    (20, "stack overflow, the stack pointer crossed its limit"),
+    (24, "unaligned access"),
+    (25, "divide by zero"),
+];
+
+/// Bits of HFSR, and what they mean.
+const HFSR_BITS: [(u32, &str); 3] = [
+    (1, "bus error on a vector table read"),
+    (
+        30,
+        "forced, a configurable fault was escalated to a HardFault",
+    ),
+    (31, "debug event"),
+];
+
+/// The fault status registers of the core.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FaultStatus {
+    pub cfsr: u32,
+    pub hfsr: u32,
+    pub mmfar: u32,
+    pub bfar: u32,
+}
+
+impl FaultStatus {
+    pub fn read(core: &mut dyn CoreAccess) -> Result<Self, Error> {
+        Ok(FaultStatus {
+            cfsr: core.read_word_32(CFSR)?,
+            hfsr: core.read_word_32(HFSR)?,
+            mmfar: core.read_word_32(MMFAR)?,
+            bfar: core.read_word_32(BFAR)?,
+        })
+    }
+
+    /// Describe the causes of the fault, in the order of HFSR and CFSR.
+    pub fn causes(&self) -> Vec<String> {
+        let mut causes: Vec<String> = HFSR_BITS
+            .iter()
+            .filter(|(bit, _)| self.hfsr & (1 << bit) != 0)
+            .map(|(_, cause)| cause.to_string())
+            .collect();
+
+        for (bit, cause) in CFSR_BITS.iter() {
+            if self.cfsr & (1 << bit) == 0 {
+                continue;
+            }
+
+            let address = match bit {
+                1 if self.cfsr & MMARVALID != 0 => Some(self.mmfar),
+                9 if self.cfsr & BFARVALID != 0 => Some(self.bfar),
+                _ => None,
+            };
+
+            causes.push(match address {
+                Some(address) => format!("{} at {:#010x}", cause, address),
+                None => cause.to_string(),
+            });
+        }
+
+        causes
+    }
+}
+
+/// The registers stacked by the core on exception entry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExceptionFrame {
+    /// Address of the frame on the stack.
+    pub address: u32,
+    /// R0 to R3, R12, LR, PC and xPSR.
+    pub registers: [u32; 8],
+}
+
+impl ExceptionFrame {
+    /// The address of the instruction which was interrupted, or which caused the fault.
+    pub fn pc(&self) -> u32 {
+        self.registers[6]
+    }
+
+    pub fn lr(&self) -> u32 {
+        self.registers[5]
+    }
+
+    pub fn xpsr(&self) -> u32 {
+        self.registers[7]
+    }
+
+    /// Read the frame at `address`.
+    pub fn read(core: &mut dyn CoreAccess, address: u32) -> Result<Self, Error> {
+        let mut data = [0u8; 32];
+        core.read_8(address, &mut data)?;
+
+        let mut registers = [0u32; 8];
+        for (register, bytes) in registers.iter_mut().zip(data.chunks_exact(4)) {
+            *register = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+
+        Ok(ExceptionFrame { address, registers })
+    }
+}
+
+/// Size of an extended exception frame, which also contains S0 to S15 and FPSCR.
+pub const EXTENDED_FRAME_SIZE: u32 = 0x68;
+
+/// Check if `value` is an `EXC_RETURN` value, which returns from an exception.
+///
+/// The reset value of the link register, `0xffff_ffff`, is not.
+pub fn is_exc_return(value: u32) -> bool {
+    value & 0xffff_ffe0 == 0xffff_ffe0 && matches!(value & 0xf, 0x1 | 0x9 | 0xd)
+}
+
+/// Check if the frame of the `EXC_RETURN` value is on the process stack.
+pub fn uses_psp(exc_return: u32) -> bool {
+    exc_return & (1 << 2) != 0
+}
+
+/// Check if the frame of the `EXC_RETURN` value is an extended frame, with the
+/// registers of the FPU.
+pub fn is_extended_frame(exc_return: u32) -> bool {
+    exc_return & (1 << 4) == 0
+}
+
+/// Read the exception frame of the handler the core is halted at, while the link
+/// register still contains the `EXC_RETURN` value of the exception entry.
+pub fn stacked_frame(core: &mut dyn CoreAccess) -> Result<Option<ExceptionFrame>, Error> {
+    let exc_return = core.read_core_reg(CoreRegisterAddress(LR))?;
+
+    if !is_exc_return(exc_return) {
+        return Ok(None);
+    }
+
+    let stack_pointer = if uses_psp(exc_return) {
+        CoreRegisterAddress(PSP)
+    } else {
+        core.stack_pointer()
+    };
+
+    let address = core.read_core_reg(stack_pointer)?;
+
+    ExceptionFrame::read(core, address).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_exc_return, FaultStatus, MMARVALID};
+
+    #[test]
+    fn fault_causes() {
+        let status = FaultStatus {
+            cfsr: (1 << 9) | (1 << 15),
+            hfsr: 1 << 30,
+            mmfar: 0,
+            bfar: 0x4000_1234,
+        };
+        assert_eq!(
+            status.causes(),
+            vec![
+                "forced, a configurable fault was escalated to a HardFault",
+                "precise data bus error at 0x40001234",
+            ]
+        );
+
+        // Addresses are only shown if they are valid.
+        let status = FaultStatus {
+            cfsr: (1 << 1) | (1 << 25),
+            mmfar: 0x2000_0000,
+            ..FaultStatus::default()
+        };
+        assert_eq!(
+            status.causes(),
+            vec!["data access violation", "divide by zero"]
+        );
+
+        let status = FaultStatus {
+            cfsr: (1 << 1) | MMARVALID,
+            mmfar: 0x2000_0000,
+            ..FaultStatus::default()
+        };
+        assert_eq!(status.causes(), vec!["data access violation at 0x20000000"]);
+
+        assert!(FaultStatus::default().causes().is_empty());
+    }
+
+    #[test]
+    fn exc_return_values() {
+        for value in &[
+            0xffff_ffe1,
+            0xffff_ffe9,
+            0xffff_ffed,
+            0xffff_fff1,
+            0xffff_fff9,
+            0xffff_fffd,
+        ] {
+            assert!(is_exc_return(*value), "{:#010x}", value);
+        }
+
+        for value in &[0, 0xffff_ffff, 0xffff_fff8, 0x0800_0101] {
+            assert!(!is_exc_return(*value), "{:#010x}", value);
+        }
+    }
+}
@@ -0,0 +1,472 @@
+//! Decoding of the Instrumentation Trace Macrocell (ITM) packets, which the target
+//! sends over the Serial Wire Output (SWO) pin.
+//!
+//! The program writes to the stimulus ports of the ITM, which turns the writes into
+//! instrumentation packets. The DWT adds hardware source packets to the same stream,
+//! like samples of the program counter and the entry and exit of exceptions.
+
+use crate::{exceptions, rtt};
+use serde::Deserialize;
+use std::{collections::HashMap, fmt};
+
+/// Trace Enable Register of the ITM, with one bit per stimulus port.
+pub const ITM_TER: u32 = 0xe000_0e00;
+
+/// Category of the output of hardware source packets.
+pub const HARDWARE_CATEGORY: &str = "trace";
+
+/// The `swo` section of the launch and attach arguments.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SwoConfig {
+    /// Baud rate of the SWO pin, which has to be supported by the probe.
+    pub baud_rate: u32,
+    /// Prescaler of the TPIU, the TPIU clock is `baud_rate * (prescaler + 1)`.
+    #[serde(default)]
+    pub prescaler: u32,
+    /// Stimulus ports whose output is shown, only port 0 if none are given.
+    #[serde(default = "default_stimulus_ports")]
+    pub stimulus_ports: Vec<StimulusPortConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct StimulusPortConfig {
+    /// Number of the stimulus port.
+    pub port: u8,
+    /// Category of the output events, `stdout` if it is not given.
+    pub category: Option<String>,
+}
+
+fn default_stimulus_ports() -> Vec<StimulusPortConfig> {
+    vec![StimulusPortConfig {
+        port: 0,
+        category: None,
+    }]
+}
+
+impl SwoConfig {
+    /// The clock of the TPIU, from which the baud rate is derived.
+    pub fn tpiu_clock(&self) -> u32 {
+        self.baud_rate
+            .saturating_mul(self.prescaler.saturating_add(1))
+    }
+
+    /// The value of `ITM_TER` which enables the stimulus ports, or an error if one
+    /// of the ports doesn't exist.
+    pub fn enabled_ports(&self) -> Result<u32, String> {
+        self.stimulus_ports.iter().try_fold(0, |mask, config| {
+            if config.port < 32 {
+                Ok(mask | 1 << config.port)
+            } else {
+                Err(format!("There is no stimulus port {}", config.port))
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExceptionAction {
+    Entered,
+    Exited,
+    Returned,
+}
+
+/// A packet of the ITM stream.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Packet {
+    /// Synchronization, or padding between the packets.
+    Sync,
+    /// The ITM had to drop packets, because its FIFO was full.
+    Overflow,
+    /// Data written to a stimulus port.
+    Instrumentation { port: u8, payload: Vec<u8> },
+    /// A local or global timestamp.
+    Timestamp,
+    /// A stimulus port page, or another extension of the protocol.
+    Extension,
+    /// Counters of the DWT which wrapped around, as a bit mask.
+    EventCounter(u8),
+    ExceptionTrace {
+        exception: u16,
+        action: ExceptionAction,
+    },
+    /// A periodic sample of the program counter, or `None` if the core was sleeping.
+    PcSample(Option<u32>),
+    /// The program counter of an access which matched the DWT comparator.
+    DataTracePc { comparator: u8, pc: u32 },
+    /// The lower bits of the address of an access which matched the DWT comparator.
+    DataTraceAddress { comparator: u8, offset: u16 },
+    DataTraceValue {
+        comparator: u8,
+        write: bool,
+        value: u32,
+    },
+    /// A hardware source packet which is not known.
+    Unknown { header: u8, payload: Vec<u8> },
+}
+
+/// Bits of the event counter packet, and the counters they stand for.
+const EVENT_COUNTERS: [(u8, &str); 6] = [
+    (0, "CPI"),
+    (1, "EXC"),
+    (2, "SLEEP"),
+    (3, "LSU"),
+    (4, "FOLD"),
+    (5, "CYC"),
+];
+
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Packet::Overflow => write!(f, "ITM overflow, packets were lost"),
+            Packet::EventCounter(counters) => {
+                let names: Vec<_> = EVENT_COUNTERS
+                    .iter()
+                    .filter(|(bit, _)| counters & (1 << bit) != 0)
+                    .map(|(_, name)| *name)
+                    .collect();
+                write!(f, "Event counter wrapped: {}", names.join(", "))
+            }
+            Packet::ExceptionTrace { exception, action } => {
+                let action = match action {
+                    ExceptionAction::Entered => "entered",
+                    ExceptionAction::Exited => "exited",
+                    ExceptionAction::Returned => "returned to",
+                };
+                write!(
+                    f,
+                    "Exception {} {}",
+                    action,
+                    exceptions::exception_name(u32::from(*exception))
+                )
+            }
+            Packet::PcSample(Some(pc)) => write!(f, "PC sample {:#010x}", pc),
+            Packet::PcSample(None) => write!(f, "PC sample: sleeping"),
+            Packet::DataTracePc { comparator, pc } => {
+                write!(f, "Data trace {}: PC {:#010x}", comparator, pc)
+            }
+            Packet::DataTraceAddress { comparator, offset } => {
+                write!(
+                    f,
+                    "Data trace {}: address offset {:#06x}",
+                    comparator, offset
+                )
+            }
+            Packet::DataTraceValue {
+                comparator,
+                write,
+                value,
+            } => write!(
+                f,
+                "Data trace {}: {} {:#x}",
+                comparator,
+                if *write { "write" } else { "read" },
+                value
+            ),
+            Packet::Unknown { header, payload } => {
+                write!(f, "Unknown packet {:#04x} {:02x?}", header, payload)
+            }
+            Packet::Instrumentation { port, payload } => {
+                write!(f, "Stimulus port {}: {:02x?}", port, payload)
+            }
+            Packet::Sync | Packet::Timestamp | Packet::Extension => Ok(()),
+        }
+    }
+}
+
+/// Splits the byte stream into packets. Packets can be split across the data of
+/// several reads.
+#[derive(Debug, Default)]
+pub struct Decoder {
+    /// Data of a packet which is not complete yet.
+    pending: Vec<u8>,
+}
+
+impl Decoder {
+    /// Decode the packets in `data`, following the data of the previous calls.
+    pub fn decode(&mut self, data: &[u8]) -> Vec<Packet> {
+        self.pending.extend_from_slice(data);
+
+        let mut packets = Vec::new();
+        let mut used = 0;
+
+        while let Some((packet, size)) = decode_packet(&self.pending[used..]) {
+            packets.push(packet);
+            used += size;
+        }
+
+        self.pending.drain(..used);
+
+        packets
+    }
+}
+
+/// Decode the packet at the start of `data`, and return it with its size. Returns
+/// `None` if the packet is not complete.
+fn decode_packet(data: &[u8]) -> Option<(Packet, usize)> {
+    let header = *data.first()?;
+
+    // Source packets, with a payload of 1, 2 or 4 bytes.
+    if header & 0x03 != 0 {
+        let size = match header & 0x03 {
+            1 => 1,
+            2 => 2,
+            _ => 4,
+        };
+        let payload = data.get(1..=size)?;
+
+        let packet = if header & 0x04 == 0 {
+            Packet::Instrumentation {
+                port: header >> 3,
+                payload: payload.to_vec(),
+            }
+        } else {
+            hardware_packet(header, payload)
+        };
+
+        return Some((packet, size + 1));
+    }
+
+    match header {
+        // The synchronization packet is a sequence of zeros, followed by 0x80.
+        0x00 | 0x80 => Some((Packet::Sync, 1)),
+        0x70 => Some((Packet::Overflow, 1)),
+        // Global timestamps.
+        0x94 | 0xb4 => continued(data, Packet::Timestamp),
+        // Local timestamp with continuation bytes.
+        header if header & 0xcf == 0xc0 => continued(data, Packet::Timestamp),
+        // Local timestamp in the header alone.
+        header if header & 0x8f == 0x00 => Some((Packet::Timestamp, 1)),
+        header if header & 0x0b == 0x08 => {
+            if header & 0x80 != 0 {
+                continued(data, Packet::Extension)
+            } else {
+                Some((Packet::Extension, 1))
+            }
+        }
+        // Reserved headers, which are skipped.
+        header => Some((
+            Packet::Unknown {
+                header,
+                payload: Vec::new(),
+            },
+            1,
+        )),
+    }
+}
+
+/// A packet whose payload continues as long as bit 7 of its bytes is set.
+fn continued(data: &[u8], packet: Packet) -> Option<(Packet, usize)> {
+    let end = data[1..].iter().position(|byte| byte & 0x80 == 0)?;
+
+    Some((packet, end + 2))
+}
+
+fn hardware_packet(header: u8, payload: &[u8]) -> Packet {
+    let mut bytes = [0u8; 4];
+    bytes[..payload.len()].copy_from_slice(payload);
+    let value = u32::from_le_bytes(bytes);
+
+    let id = header >> 3;
+
+    match id {
+        0 => Packet::EventCounter(payload[0]),
+        1 if payload.len() == 2 => {
+            let action = match (value >> 12) & 0x3 {
+                1 => ExceptionAction::Entered,
+                2 => ExceptionAction::Exited,
+                _ => ExceptionAction::Returned,
+            };
+
+            Packet::ExceptionTrace {
+                exception: (value & 0x1ff) as u16,
+                action,
+            }
+        }
+        2 if payload.len() == 4 => Packet::PcSample(Some(value)),
+        2 if payload.len() == 1 && value == 0 => Packet::PcSample(None),
+        8..=15 if id & 1 == 0 && payload.len() == 4 => Packet::DataTracePc {
+            comparator: (id >> 1) & 0x3,
+            pc: value,
+        },
+        8..=15 if id & 1 == 1 && payload.len() == 2 => Packet::DataTraceAddress {
+            comparator: (id >> 1) & 0x3,
+            offset: value as u16,
+        },
+        16..=23 => Packet::DataTraceValue {
+            comparator: (id >> 1) & 0x3,
+            write: id & 1 == 1,
+            value,
+        },
+        _ => Packet::Unknown {
+            header,
+            payload: payload.to_vec(),
+        },
+    }
+}
+
+/// Output of the ITM, which is forwarded to the client.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItmOutput {
+    pub category: String,
+    pub text: String,
+}
+
+/// Turns the SWO data into output for the client.
+pub struct Itm {
+    /// Category of the output of each enabled stimulus port.
+    categories: HashMap<u8, String>,
+    decoder: Decoder,
+    /// Data of each stimulus port, which doesn't end with a complete UTF-8 character yet.
+    pending: HashMap<u8, Vec<u8>>,
+}
+
+impl Itm {
+    pub fn new(config: &SwoConfig) -> Self {
+        Itm {
+            categories: config
+                .stimulus_ports
+                .iter()
+                .map(|config| {
+                    let category = config
+                        .category
+                        .clone()
+                        .unwrap_or_else(|| "stdout".to_owned());
+
+                    (config.port, category)
+                })
+                .collect(),
+            decoder: Decoder::default(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Decode the SWO data in `data`. The text written to a stimulus port is shown in
+    /// the category of the port, the hardware source packets in `HARDWARE_CATEGORY`.
+    pub fn process(&mut self, data: &[u8]) -> Vec<ItmOutput> {
+        let mut output = Vec::new();
+
+        for packet in self.decoder.decode(data) {
+            match packet {
+                Packet::Instrumentation { port, payload } => {
+                    let category = match self.categories.get(&port) {
+                        Some(category) => category,
+                        None => continue,
+                    };
+
+                    let pending = self.pending.entry(port).or_default();
+                    pending.extend_from_slice(&payload);
+
+                    let text = rtt::take_text(pending);
+                    if !text.is_empty() {
+                        output.push(ItmOutput {
+                            category: category.clone(),
+                            text,
+                        });
+                    }
+                }
+                Packet::Sync | Packet::Timestamp | Packet::Extension => (),
+                packet => output.push(ItmOutput {
+                    category: HARDWARE_CATEGORY.to_owned(),
+                    text: format!("{}\n", packet),
+                }),
+            }
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decoder, ExceptionAction, Itm, Packet, SwoConfig};
+
+    /// Synthetic SWO data, written by hand after the ITM packet format of the ARMv7-M
+    /// reference manual. It prints `Hi!\n` to port 0 with a word write, traces the entry
+    /// and exit of SysTick, and ends in the middle of a PC sample.
+    const STREAM: [u8; 24] = [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x80, // synchronization
+        0x03, b'H', b'i', b'!', b'\n', // port 0, 4 bytes
+        0xc0, 0x85, 0x02, // local timestamp
+        0x0e, 0x0f, 0x10, // SysTick entered
+        0x0e, 0x0f, 0x20, // SysTick exited
+        0x70, // overflow
+        0x17, 0x00, 0x01, // PC sample, truncated
+    ];
+
+    #[test]
+    fn decode_stream() {
+        let mut decoder = Decoder::default();
+
+        let packets = decoder.decode(&STREAM);
+        assert_eq!(
+            packets,
+            vec![
+                Packet::Sync,
+                Packet::Sync,
+                Packet::Sync,
+                Packet::Sync,
+                Packet::Sync,
+                Packet::Sync,
+                Packet::Instrumentation {
+                    port: 0,
+                    payload: b"Hi!\n".to_vec(),
+                },
+                Packet::Timestamp,
+                Packet::ExceptionTrace {
+                    exception: 15,
+                    action: ExceptionAction::Entered,
+                },
+                Packet::ExceptionTrace {
+                    exception: 15,
+                    action: ExceptionAction::Exited,
+                },
+                Packet::Overflow,
+            ]
+        );
+
+        // The rest of the PC sample arrives with the next read.
+        assert_eq!(
+            decoder.decode(&[0x00, 0x08, 0x09, 0x01]),
+            vec![
+                Packet::PcSample(Some(0x0800_0100)),
+                Packet::Instrumentation {
+                    port: 1,
+                    payload: vec![0x01],
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn output_per_port() {
+        let config: SwoConfig = serde_json::from_str(
+            r#"{
+                "baud_rate": 2000000,
+                "stimulus_ports": [ { "port": 0 }, { "port": 2, "category": "log" } ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.tpiu_clock(), 2_000_000);
+        assert_eq!(config.enabled_ports(), Ok(0b101));
+
+        let config = SwoConfig {
+            prescaler: u32::MAX,
+            ..config
+        };
+        assert_eq!(config.tpiu_clock(), u32::MAX);
+
+        let mut itm = Itm::new(&config);
+
+        // Port 1 is not enabled, and a character is split across two packets.
+        let output = itm.process(&[0x01, b'a', 0x09, b'b', 0x11, 0xc3, 0x11, 0xa9]);
+        let output: Vec<_> = output
+            .iter()
+            .map(|output| (output.category.as_str(), output.text.as_str()))
+            .collect();
+        assert_eq!(output, vec![("stdout", "a"), ("log", "é")]);
+
+        let output = itm.process(&[0x0e, 0x13, 0x10]);
+        assert_eq!(output[0].category, "trace");
+        assert_eq!(output[0].text, "Exception entered Interrupt 3\n");
+    }
+}
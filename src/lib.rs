@@ -0,0 +1,20 @@
+pub mod breakpoints;
+pub mod dap_types;
+pub mod debug_adapter;
+pub mod debug_info;
+pub mod debugger;
+pub mod defmt;
+pub mod disassembly;
+pub mod dwt;
+pub mod evaluate;
+pub mod exceptions;
+pub mod fault;
+pub mod itm;
+pub mod memory;
+pub mod rtt;
+pub mod semihosting;
+pub mod simulator;
+pub mod stepping;
+pub mod svd;
+pub mod target;
+pub mod variables;
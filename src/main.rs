@@ -1,7 +1,6 @@
-mod debug_adapter;
-mod debugger;
-
-use debug_adapter::{DebugAdapter, DebugAdapterMessage, Event};
+use probe_rs_debugadapter::debug_adapter::{
+    DebugAdapter, DebugAdapterMessage, Event, MessageReader,
+};
 
 use debugserver_types::InitializeRequestArguments;
 
@@ -12,16 +11,20 @@ use std::{
     io::{Read, Write},
     net::{SocketAddr, TcpListener},
     path::PathBuf,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
 };
 
-use debugserver_types::Capabilities;
 use log::{debug, error, info, trace};
+use probe_rs_debugadapter::dap_types::Capabilities;
+use probe_rs_debugadapter::exceptions;
 
 use simplelog::*;
 
 use clap::{App, Arg};
 
-use debugger::{get_arguments, Debugger, HandleResult};
+use probe_rs_debugadapter::debugger::{get_arguments, Debugger, HandleResult};
 
 use anyhow::anyhow;
 
@@ -51,7 +54,7 @@ fn main() -> Result<(), anyhow::Error> {
 
         let port: u16 = matches
             .value_of("port")
-            .map(|s| u16::from_str_radix(s, 10).unwrap())
+            .map(|s| s.parse().unwrap())
             .unwrap_or(8000);
         info!("Starting in server mode on port {}", port);
 
@@ -63,12 +66,10 @@ fn main() -> Result<(), anyhow::Error> {
 
         info!("Accepted connection from {}", addr);
 
-        let reader = socket.try_clone()?;
-        let writer = socket;
+        let reader = MessageReader::new(socket.try_clone()?);
+        let adapter = DebugAdapter::new(socket);
 
-        let adapter = DebugAdapter::new(reader, writer);
-
-        run(adapter, &current_dir)
+        run(reader, adapter, &current_dir)
     } else {
         if let Ok(path) = env::var("PROBE_RS_LOGFILE") {
             let file = File::create(path)?;
@@ -78,17 +79,22 @@ fn main() -> Result<(), anyhow::Error> {
         }
         debug!("Debugger started in directory {}", current_dir.display());
 
-        let adapter = DebugAdapter::new(io::stdin(), io::stdout());
+        let reader = MessageReader::new(io::stdin());
+        let adapter = DebugAdapter::new(io::stdout());
 
-        run(adapter, &current_dir)
+        run(reader, adapter, &current_dir)
     }
 }
 
-fn run<R: Read, W: Write>(
-    mut adapter: DebugAdapter<R, W>,
+/// Interval in which the status of a running core is checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+fn run<R: Read + Send + 'static, W: Write>(
+    mut reader: MessageReader<R>,
+    mut adapter: DebugAdapter<W>,
     cwd: &PathBuf,
 ) -> Result<(), anyhow::Error> {
-    let data = adapter.receive_data()?;
+    let data = reader.receive_data()?;
 
     let request = match data {
         DebugAdapterMessage::Request(request) => request,
@@ -110,9 +116,24 @@ fn run<R: Read, W: Write>(
     );
 
     let capabilities = Capabilities {
-        supports_configuration_done_request: Some(true),
-        //supports_function_breakpoints: Some(true),
-        ..Default::default()
+        base: debugserver_types::Capabilities {
+            supports_configuration_done_request: Some(true),
+            supports_evaluate_for_hovers: Some(true),
+            supports_set_variable: Some(true),
+            supports_set_expression: Some(true),
+            supports_function_breakpoints: Some(true),
+            supports_conditional_breakpoints: Some(true),
+            supports_hit_conditional_breakpoints: Some(true),
+            supports_log_points: Some(true),
+            supports_data_breakpoints: Some(true),
+            supports_exception_info_request: Some(true),
+            exception_breakpoint_filters: Some(exceptions::breakpoint_filters()),
+            ..Default::default()
+        },
+        supports_stepping_granularity: Some(true),
+        supports_read_memory_request: Some(true),
+        supports_write_memory_request: Some(true),
+        supports_disassemble_request: Some(true),
     };
 
     adapter.send_response(&request, Ok(Some(capabilities)))?;
@@ -121,9 +142,48 @@ fn run<R: Read, W: Write>(
 
     let mut dbg = Debugger::new(cwd);
 
+    // Messages are read in a separate thread, so that the core can be polled
+    // while we wait for the next request.
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || loop {
+        let message = reader.receive_data();
+        let failed = message.is_err();
+
+        if sender.send(message).is_err() || failed {
+            break;
+        }
+    });
+
+    // The core is polled at a fixed interval, also while requests keep arriving.
+    let mut next_poll = Instant::now() + POLL_INTERVAL;
+
     // look for other request
     loop {
-        let message = adapter.receive_data()?;
+        let timeout = next_poll.saturating_duration_since(Instant::now());
+
+        let message = match receiver.recv_timeout(timeout) {
+            Ok(message) => Some(message?),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        if Instant::now() >= next_poll {
+            match dbg.poll_core(&mut adapter) {
+                Ok(HandleResult::Continue) => (),
+                Ok(HandleResult::Stop) => break,
+                Err(e) => {
+                    error!("Failed to send the status of the core: {:?}", e);
+                    break;
+                }
+            }
+            next_poll = Instant::now() + POLL_INTERVAL;
+        }
+
+        let message = match message {
+            Some(message) => message,
+            None => continue,
+        };
         trace!("< {:?}", message);
 
         match dbg.handle(&mut adapter, &message) {
@@ -140,7 +200,7 @@ fn run<R: Read, W: Write>(
         }
     }
 
-    adapter.send_event(&Event::Terminated(debug_adapter::RestartRequest::No))?;
+    dbg.terminate(&mut adapter)?;
 
     debug!("Stopping debugger");
 
@@ -0,0 +1,113 @@
+//! Raw memory access for the `readMemory` and `writeMemory` requests.
+//!
+//! Memory references which are sent to the client are addresses formatted as
+//! hexadecimal numbers, see for example [`crate::variables::to_dap_variable`].
+
+use crate::target::CoreAccess;
+use anyhow::anyhow;
+use std::convert::TryFrom;
+
+/// Chunk sizes used to find the readable part of a memory range. Reads are aligned
+/// to the chunk size, so that a read never crosses into the next memory region
+/// unless the regions are smaller than the chunk.
+const CHUNK_SIZES: [u64; 3] = [256, 4, 1];
+
+/// Maximum number of bytes read for a single `readMemory` request. The client
+/// requests the rest of a larger range separately.
+pub const MAX_READ_SIZE: u64 = 0x10000;
+
+/// Parse a memory reference, optionally with an offset, into an address.
+pub fn parse_memory_reference(reference: &str, offset: Option<i64>) -> Result<u32, anyhow::Error> {
+    let reference = reference.trim();
+
+    let address = match reference
+        .strip_prefix("0x")
+        .or_else(|| reference.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(&hex.replace('_', ""), 16),
+        None => reference.parse(),
+    }
+    .map_err(|_| anyhow!("Invalid memory reference '{}'", reference))?;
+
+    let address = i64::try_from(address)
+        .ok()
+        .and_then(|address| address.checked_add(offset.unwrap_or(0)))
+        .ok_or_else(|| {
+            anyhow!(
+                "Memory reference '{}' is outside of the address space",
+                reference
+            )
+        })?;
+
+    if address < 0 || address > i64::from(u32::MAX) {
+        return Err(anyhow!(
+            "Address {:#x} is outside of the address space",
+            address
+        ));
+    }
+
+    Ok(address as u32)
+}
+
+/// Read up to `count` bytes starting at `address`. Reading stops at the first byte
+/// which can not be read, so the returned data may be shorter than requested.
+pub fn read_readable(core: &mut dyn CoreAccess, address: u32, count: u64) -> Vec<u8> {
+    // Don't wrap around at the end of the address space.
+    let count = count.min((1u64 << 32) - u64::from(address));
+
+    let mut data = vec![0; count as usize];
+
+    if core.read_8(address, &mut data).is_ok() {
+        return data;
+    }
+
+    data.clear();
+
+    for &chunk in &CHUNK_SIZES {
+        while (data.len() as u64) < count {
+            let start = u64::from(address) + data.len() as u64;
+            let len = (chunk - start % chunk).min(count - data.len() as u64);
+
+            let mut buffer = vec![0; len as usize];
+
+            if core.read_8(start as u32, &mut buffer).is_err() {
+                break;
+            }
+
+            data.extend_from_slice(&buffer);
+        }
+    }
+
+    data
+}
+
+/// Write `data` to `address`. If `allow_partial` is set, as much of the data as
+/// possible is written, otherwise the write either succeeds completely or fails.
+///
+/// Returns the number of bytes which were written.
+pub fn write(
+    core: &mut dyn CoreAccess,
+    address: u32,
+    data: &[u8],
+    allow_partial: bool,
+) -> Result<usize, probe_rs::Error> {
+    match core.write_8(address, data) {
+        Ok(()) => Ok(data.len()),
+        Err(e) if !allow_partial => Err(e),
+        Err(_) => {
+            let mut written = 0;
+
+            while written < data.len() {
+                let start = address.wrapping_add(written as u32);
+
+                if core.write_8(start, &data[written..written + 1]).is_err() {
+                    break;
+                }
+
+                written += 1;
+            }
+
+            Ok(written)
+        }
+    }
+}
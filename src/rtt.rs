@@ -0,0 +1,594 @@
+//! Real-Time Transfer (RTT), which exchanges data with the running target through
+//! ring buffers in its RAM.
+//!
+//! The target describes its buffers in a control block, which starts with the ID
+//! `SEGGER RTT`. The up channels carry data from the target to the host, and the down
+//! channels from the host to the target. Only the writer moves the write offset of a
+//! buffer, and only the reader moves the read offset, so both can access the buffer
+//! at the same time.
+//!
+//! Channels can also carry the log frames of `defmt`, which are decoded with the
+//! format strings of the program.
+
+use crate::{
+    debug_info::SourceLocation,
+    defmt::{self, DecodeError},
+    target::CoreAccess,
+};
+use anyhow::anyhow;
+use probe_rs::Error;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+};
+
+/// ID at the start of the control block.
+const ID: &[u8; 16] = b"SEGGER RTT\0\0\0\0\0\0";
+/// Size of the control block header, with the ID and the numbers of up and down channels.
+const HEADER_SIZE: u32 = 24;
+/// Size of a channel descriptor: name, buffer, size, write offset, read offset and flags.
+const CHANNEL_SIZE: u32 = 24;
+const WRITE_OFFSET: u32 = 12;
+const READ_OFFSET: u32 = 16;
+
+/// Upper limit for the number of channels, in case a corrupted control block is read.
+const MAX_CHANNELS: u32 = 16;
+
+/// Size of the reads while the memory is scanned for the control block.
+const SCAN_CHUNK_SIZE: u32 = 0x400;
+
+/// Symbol of the control block, in the SEGGER implementation and in `rtt-target`.
+pub const CONTROL_BLOCK_SYMBOL: &str = "_SEGGER_RTT";
+
+/// An address in the arguments, given as a number or as a string like `"0x2000_0000"`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "AddressArgument")]
+pub struct Address(pub u32);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AddressArgument {
+    Number(u32),
+    Text(String),
+}
+
+impl TryFrom<AddressArgument> for Address {
+    type Error = String;
+
+    fn try_from(argument: AddressArgument) -> Result<Self, Self::Error> {
+        match argument {
+            AddressArgument::Number(address) => Ok(Address(address)),
+            AddressArgument::Text(text) => {
+                let digits = text.trim().replace('_', "");
+                let parsed = match digits.strip_prefix("0x") {
+                    Some(hex) => u32::from_str_radix(hex, 16),
+                    None => digits.parse(),
+                };
+
+                parsed
+                    .map(Address)
+                    .map_err(|_| format!("Invalid address '{}'", text))
+            }
+        }
+    }
+}
+
+/// The `rtt` section of the launch and attach arguments.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct RttConfig {
+    /// Address of the control block, the `_SEGGER_RTT` symbol is used if it is not given.
+    pub address: Option<Address>,
+    /// Memory which is searched for the control block, if its address is unknown.
+    pub scan_range: Option<ScanRange>,
+    /// Settings of the up channels. If none are given, all up channels are shown.
+    #[serde(default)]
+    pub channels: Vec<ChannelConfig>,
+    /// Down channel which receives the input of the debug console.
+    pub down_channel: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct ScanRange {
+    pub start: Address,
+    /// End of the range, which is not included.
+    pub end: Address,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ChannelConfig {
+    /// Number of the up channel.
+    pub channel: u32,
+    /// Category of the output events, `stdout` if it is not given.
+    pub category: Option<String>,
+    /// Format of the data, `string` if it is not given.
+    #[serde(default)]
+    pub format: ChannelFormat,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelFormat {
+    /// UTF-8 text.
+    #[default]
+    String,
+    /// Log frames of `defmt`.
+    Defmt,
+}
+
+/// Arguments of the custom `probe-rs/rttWrite` request.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RttWriteArguments {
+    /// The down channel, the `down_channel` of the arguments or 0 if it is not given.
+    pub channel: Option<u32>,
+    pub data: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RttWriteResponseBody {
+    /// Bytes which were written to the buffer right away.
+    pub bytes_written: usize,
+    /// Bytes which are written once the target made room for them in the buffer.
+    pub bytes_pending: usize,
+}
+
+/// Data of an up channel, which is forwarded to the client.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RttOutput {
+    pub channel: u32,
+    pub category: String,
+    pub text: String,
+    /// Source location of a `defmt` log message.
+    pub location: Option<SourceLocation>,
+}
+
+pub struct Rtt {
+    config: RttConfig,
+    /// Address of the control block, once it was found.
+    control_block: Option<u32>,
+    /// Data of each up channel, which doesn't end with a complete UTF-8 character or
+    /// `defmt` frame yet.
+    pending: HashMap<u32, Vec<u8>>,
+    /// Format strings for the channels with `defmt` frames.
+    defmt: Option<defmt::Table>,
+    /// Data for each down channel, which didn't fit into its buffer yet.
+    input: BTreeMap<u32, Vec<u8>>,
+}
+
+impl Rtt {
+    /// Use the settings in `config`, with `symbol` as the address of the control block
+    /// if neither its address nor a scan range are given. `defmt` is used to decode
+    /// the channels with the `defmt` format.
+    pub fn new(mut config: RttConfig, symbol: Option<u32>, defmt: Option<defmt::Table>) -> Self {
+        if config.address.is_none() && config.scan_range.is_none() {
+            config.address = symbol.map(Address);
+        }
+
+        Rtt {
+            config,
+            control_block: None,
+            pending: HashMap::new(),
+            defmt,
+            input: BTreeMap::new(),
+        }
+    }
+
+    /// Check if any channel is configured to carry `defmt` frames.
+    pub fn uses_defmt(&self) -> bool {
+        self.config
+            .channels
+            .iter()
+            .any(|channel| channel.format == ChannelFormat::Defmt)
+    }
+
+    /// The down channel for the input of the debug console, if one is configured.
+    pub fn down_channel(&self) -> Option<u32> {
+        self.config.down_channel
+    }
+
+    /// Check if the control block can be found at all.
+    pub fn has_location(&self) -> bool {
+        self.config.address.is_some() || self.config.scan_range.is_some()
+    }
+
+    /// Read the new data of the up channels, and write pending input to the down channels.
+    ///
+    /// The control block is only valid once the firmware initialized it, so it is
+    /// checked on every call. This also keeps RTT working after a reset.
+    pub fn poll(&mut self, core: &mut dyn CoreAccess) -> Result<Vec<RttOutput>, Error> {
+        let control_block = match self.control_block(core)? {
+            Some(control_block) => control_block,
+            None => return Ok(Vec::new()),
+        };
+
+        self.write_input(core, &control_block)?;
+
+        let mut output = Vec::new();
+
+        for channel in 0..control_block.up_channels {
+            let (category, format) = match self.channel_config(channel) {
+                Some(config) => config,
+                None => continue,
+            };
+
+            let data = read_channel(core, control_block.up_channel(channel))?;
+
+            if data.is_empty() {
+                continue;
+            }
+
+            let pending = self.pending.entry(channel).or_default();
+            pending.extend_from_slice(&data);
+
+            match format {
+                ChannelFormat::String => {
+                    let text = take_text(pending);
+                    if !text.is_empty() {
+                        output.push(RttOutput {
+                            channel,
+                            category,
+                            text,
+                            location: None,
+                        });
+                    }
+                }
+                ChannelFormat::Defmt => match &self.defmt {
+                    Some(table) => take_frames(table, channel, &category, pending, &mut output),
+                    // Without the format strings, the frames can't be decoded.
+                    None => pending.clear(),
+                },
+            }
+        }
+
+        Ok(output)
+    }
+
+    /// Write `data` to the down channel `channel`. The data which doesn't fit into the
+    /// buffer is written at the next polls, once the target has read enough of it.
+    ///
+    /// Returns the number of bytes of `data` which were written right away.
+    pub fn write(
+        &mut self,
+        core: &mut dyn CoreAccess,
+        channel: u32,
+        data: &[u8],
+    ) -> Result<usize, Error> {
+        let control_block = self.control_block(core)?;
+
+        if let Some(control_block) = &control_block {
+            if channel >= control_block.down_channels {
+                return Err(Error::Other(anyhow!(
+                    "The target has no RTT down channel {}",
+                    channel
+                )));
+            }
+        }
+
+        let input = self.input.entry(channel).or_default();
+        let queued = input.len();
+        input.extend_from_slice(data);
+
+        if let Some(control_block) = control_block {
+            self.write_input(core, &control_block)?;
+        }
+
+        let remaining = self.input.get(&channel).map_or(0, |input| input.len());
+
+        Ok((queued + data.len() - remaining).saturating_sub(queued))
+    }
+
+    /// The number of bytes for the down channel `channel`, which were not written yet.
+    pub fn pending_input(&self, channel: u32) -> usize {
+        self.input.get(&channel).map_or(0, |input| input.len())
+    }
+
+    fn write_input(
+        &mut self,
+        core: &mut dyn CoreAccess,
+        control_block: &ControlBlock,
+    ) -> Result<(), Error> {
+        for (channel, input) in self.input.iter_mut() {
+            if *channel >= control_block.down_channels || input.is_empty() {
+                continue;
+            }
+
+            let written = write_channel(core, control_block.down_channel(*channel), input)?;
+            input.drain(..written);
+        }
+
+        self.input.retain(|_, input| !input.is_empty());
+
+        Ok(())
+    }
+
+    /// The category and format of the output of `channel`, or `None` if it isn't shown.
+    fn channel_config(&self, channel: u32) -> Option<(String, ChannelFormat)> {
+        if self.config.channels.is_empty() {
+            return Some(("stdout".to_owned(), ChannelFormat::String));
+        }
+
+        self.config
+            .channels
+            .iter()
+            .find(|config| config.channel == channel)
+            .map(|config| {
+                let category = config
+                    .category
+                    .clone()
+                    .unwrap_or_else(|| "stdout".to_owned());
+
+                (category, config.format)
+            })
+    }
+
+    /// Find the control block, and read its header.
+    fn control_block(&mut self, core: &mut dyn CoreAccess) -> Result<Option<ControlBlock>, Error> {
+        if self.control_block.is_none() {
+            self.control_block = match (self.config.address, self.config.scan_range) {
+                (Some(address), _) => Some(address.0),
+                (None, Some(range)) => scan(core, range)?,
+                (None, None) => None,
+            };
+        }
+
+        let address = match self.control_block {
+            Some(address) => address,
+            None => return Ok(None),
+        };
+
+        let mut header = [0u8; HEADER_SIZE as usize];
+        core.read_8(address, &mut header)?;
+
+        let up_channels = word(&header, 16);
+        let down_channels = word(&header, 20);
+
+        let valid = &header[..16] == ID
+            && up_channels <= MAX_CHANNELS
+            && down_channels <= MAX_CHANNELS
+            // The descriptors of all channels have to fit into the address space.
+            && address
+                .checked_add(HEADER_SIZE + (up_channels + down_channels) * CHANNEL_SIZE)
+                .is_some();
+
+        if !valid {
+            // A control block found by the scan might have been moved by a new program.
+            if self.config.address.is_none() {
+                self.control_block = None;
+            }
+            return Ok(None);
+        }
+
+        Ok(Some(ControlBlock {
+            address,
+            up_channels,
+            down_channels,
+        }))
+    }
+}
+
+/// The header of a control block.
+struct ControlBlock {
+    address: u32,
+    up_channels: u32,
+    down_channels: u32,
+}
+
+/// The descriptors of all channels are known to fit into the address space, once the
+/// header was checked in [`Rtt::control_block`].
+impl ControlBlock {
+    /// Address of the descriptor of the up channel `channel`.
+    fn up_channel(&self, channel: u32) -> u32 {
+        self.address + HEADER_SIZE + channel * CHANNEL_SIZE
+    }
+
+    /// Address of the descriptor of the down channel `channel`, which follow the
+    /// descriptors of the up channels.
+    fn down_channel(&self, channel: u32) -> u32 {
+        self.up_channel(self.up_channels + channel)
+    }
+}
+
+/// Decode the complete `defmt` frames at the start of `pending`, and keep the rest.
+fn take_frames(
+    table: &defmt::Table,
+    channel: u32,
+    category: &str,
+    pending: &mut Vec<u8>,
+    output: &mut Vec<RttOutput>,
+) {
+    let mut used = 0;
+
+    while used < pending.len() {
+        match table.decode(&pending[used..]) {
+            Ok((frame, size)) => {
+                output.push(RttOutput {
+                    channel,
+                    category: category.to_owned(),
+                    text: frame.display(),
+                    location: frame.location,
+                });
+                used += size;
+            }
+            // The rest of the frame is read at the next poll.
+            Err(DecodeError::UnexpectedEof) => break,
+            Err(e) => {
+                // The start of the next frame can't be found in the raw encoding,
+                // so the data up to now is dropped.
+                output.push(RttOutput {
+                    channel,
+                    category: "stderr".to_owned(),
+                    text: format!("Invalid defmt data on RTT channel {}: {}\n", channel, e),
+                    location: None,
+                });
+                used = pending.len();
+            }
+        }
+    }
+
+    pending.drain(..used);
+}
+
+/// Search the memory in `range` for the ID of the control block.
+fn scan(core: &mut dyn CoreAccess, range: ScanRange) -> Result<Option<u32>, Error> {
+    let mut address = range.start.0;
+    // The end of the previous chunk, in case the ID crosses the chunk boundary.
+    let mut memory = Vec::new();
+
+    while address < range.end.0 {
+        let size = (range.end.0 - address).min(SCAN_CHUNK_SIZE);
+        let start = address - memory.len() as u32;
+
+        let mut chunk = vec![0u8; size as usize];
+        core.read_8(address, &mut chunk)?;
+        memory.extend_from_slice(&chunk);
+
+        if let Some(offset) = memory.windows(ID.len()).position(|window| window == ID) {
+            return Ok(Some(start + offset as u32));
+        }
+
+        memory.drain(..memory.len().saturating_sub(ID.len() - 1));
+        address += size;
+    }
+
+    Ok(None)
+}
+
+/// Read the new data of the up channel with the descriptor at `descriptor`, and
+/// release it in the buffer.
+fn read_channel(core: &mut dyn CoreAccess, descriptor: u32) -> Result<Vec<u8>, Error> {
+    let mut data = [0u8; CHANNEL_SIZE as usize];
+    core.read_8(descriptor, &mut data)?;
+
+    let buffer = word(&data, 4);
+    let size = word(&data, 8);
+    let write = word(&data, WRITE_OFFSET as usize);
+    let read = word(&data, READ_OFFSET as usize);
+
+    // The channel is not initialized, or the descriptor is corrupted.
+    if buffer == 0 || write >= size || read >= size || buffer.checked_add(size).is_none() {
+        return Ok(Vec::new());
+    }
+
+    // The data wraps around at the end of the buffer.
+    let ranges = if write >= read {
+        [(read, write), (0, 0)]
+    } else {
+        [(read, size), (0, write)]
+    };
+
+    let mut output = Vec::new();
+    for (start, end) in ranges.iter().filter(|(start, end)| start < end) {
+        let mut chunk = vec![0u8; (end - start) as usize];
+        core.read_8(buffer + start, &mut chunk)?;
+        output.extend_from_slice(&chunk);
+    }
+
+    if write != read {
+        core.write_word_32(descriptor + READ_OFFSET, write)?;
+    }
+
+    Ok(output)
+}
+
+/// Write as much of `data` as fits into the buffer of the down channel with the
+/// descriptor at `descriptor`, and return the number of bytes written.
+fn write_channel(core: &mut dyn CoreAccess, descriptor: u32, data: &[u8]) -> Result<usize, Error> {
+    let mut header = [0u8; CHANNEL_SIZE as usize];
+    core.read_8(descriptor, &mut header)?;
+
+    let buffer = word(&header, 4);
+    let size = word(&header, 8);
+    let write = word(&header, WRITE_OFFSET as usize);
+    let read = word(&header, READ_OFFSET as usize);
+
+    if buffer == 0 || write >= size || read >= size || buffer.checked_add(size).is_none() {
+        return Ok(0);
+    }
+
+    // One byte always stays free, otherwise a full buffer would look empty.
+    let free = if read > write {
+        read - write - 1
+    } else {
+        size - (write - read) - 1
+    };
+    let count = data.len().min(free as usize);
+
+    if count == 0 {
+        return Ok(0);
+    }
+
+    // The free space wraps around at the end of the buffer.
+    let first = count.min((size - write) as usize);
+    core.write_8(buffer + write, &data[..first])?;
+    if count > first {
+        core.write_8(buffer, &data[first..count])?;
+    }
+
+    let write = if count > first {
+        (count - first) as u32
+    } else {
+        (write + count as u32) % size
+    };
+
+    // The write offset is moved last, so the target never reads incomplete data.
+    core.write_word_32(descriptor + WRITE_OFFSET, write)?;
+
+    Ok(count)
+}
+
+/// Take the text from `pending`, but keep an incomplete UTF-8 character at the end
+/// for the next data of the channel. Invalid data is replaced.
+pub(crate) fn take_text(pending: &mut Vec<u8>) -> String {
+    let mut complete = 0;
+
+    while let Err(e) = std::str::from_utf8(&pending[complete..]) {
+        match e.error_len() {
+            Some(invalid) => complete += e.valid_up_to() + invalid,
+            None => break,
+        }
+    }
+
+    // Without an error, the loop left `complete` at the start of the valid rest.
+    let complete = match std::str::from_utf8(&pending[complete..]) {
+        Ok(_) => pending.len(),
+        Err(e) => complete + e.valid_up_to(),
+    };
+
+    let rest = pending.split_off(complete);
+    let text = String::from_utf8_lossy(pending).into_owned();
+    *pending = rest;
+
+    text
+}
+
+fn word(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::take_text;
+
+    #[test]
+    fn incomplete_characters() {
+        let mut pending = b"temp: 21\xc2".to_vec();
+        assert_eq!(take_text(&mut pending), "temp: 21");
+        assert_eq!(pending, b"\xc2");
+
+        pending.extend_from_slice(b"\xb0C\n");
+        assert_eq!(take_text(&mut pending), "\u{b0}C\n");
+        assert!(pending.is_empty());
+
+        // Invalid data is replaced, and not kept back.
+        let mut pending = b"\xff\xfeok\xe2\x82".to_vec();
+        assert_eq!(take_text(&mut pending), "\u{fffd}\u{fffd}ok");
+        assert_eq!(pending, b"\xe2\x82");
+    }
+}
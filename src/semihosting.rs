@@ -0,0 +1,277 @@
+//! ARM semihosting, which lets the program use the console and clock of the host.
+//!
+//! The program requests an operation with the `BKPT 0xAB` instruction, with the number
+//! of the operation in R0 and its parameter in R1. The debugger performs the operation
+//! while the core is halted, writes the result to R0 and resumes the core after the
+//! breakpoint instruction.
+
+use crate::{rtt, target::CoreAccess};
+use anyhow::anyhow;
+use probe_rs::{CoreRegisterAddress, Error};
+use std::{
+    collections::HashMap,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// The `BKPT 0xAB` instruction in Thumb mode.
+const SEMIHOSTING_BKPT: u16 = 0xbeab;
+
+const SYS_OPEN: u32 = 0x01;
+const SYS_CLOSE: u32 = 0x02;
+const SYS_WRITEC: u32 = 0x03;
+const SYS_WRITE0: u32 = 0x04;
+const SYS_WRITE: u32 = 0x05;
+const SYS_ISTTY: u32 = 0x09;
+const SYS_CLOCK: u32 = 0x10;
+const SYS_TIME: u32 = 0x11;
+const SYS_EXIT: u32 = 0x18;
+const SYS_EXIT_EXTENDED: u32 = 0x20;
+
+/// Reason of `SYS_EXIT` for a normal exit of the program.
+const ADP_STOPPED_APPLICATION_EXIT: u32 = 0x2_0026;
+
+/// Handles of the console, which is opened with the special file name `:tt`.
+const STDIN: u32 = 1;
+const STDOUT: u32 = 2;
+const STDERR: u32 = 3;
+
+/// Upper limit for the length of the data read for a single operation, in case the
+/// parameter block is corrupted.
+const MAX_STRING_LEN: u32 = 0x1000;
+
+/// What the debugger has to do after an operation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    /// Resume the core.
+    None,
+    /// The program exited with `code`.
+    Exit(i64),
+}
+
+#[derive(Debug, Default)]
+pub struct Semihosting {
+    /// Start of the execution, for `SYS_CLOCK`.
+    start: Option<Instant>,
+    /// Output of each category, which doesn't end with a complete UTF-8 character yet.
+    pending: HashMap<&'static str, Vec<u8>>,
+    /// Output for the debug console, as category and text.
+    output: Vec<(&'static str, String)>,
+}
+
+impl Semihosting {
+    pub fn new() -> Self {
+        Semihosting {
+            start: Some(Instant::now()),
+            pending: HashMap::new(),
+            output: Vec::new(),
+        }
+    }
+
+    /// Perform the operation instead of stepping, if the core is halted at a semihosting
+    /// call. Executing the breakpoint instruction would only halt the core again.
+    ///
+    /// Returns `None` if the core is not halted at a semihosting call.
+    pub fn step_over_call(&mut self, core: &mut dyn CoreAccess) -> Result<Option<Effect>, Error> {
+        let pc = core.read_core_reg(core.program_counter())?;
+
+        if !is_semihosting_call(core, pc) {
+            return Ok(None);
+        }
+
+        self.service(core, pc).map(Some)
+    }
+
+    /// Take the output of the operations since the last call, as category and text.
+    pub fn take_output(&mut self) -> Vec<(&'static str, String)> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Perform the operation the core is halted for at `pc`, and advance the program
+    /// counter past the breakpoint instruction.
+    pub fn service(&mut self, core: &mut dyn CoreAccess, pc: u32) -> Result<Effect, Error> {
+        let operation = core.read_core_reg(CoreRegisterAddress(0))?;
+        let parameter = core.read_core_reg(CoreRegisterAddress(1))?;
+
+        let (result, effect) = match operation {
+            SYS_OPEN => {
+                let [name, mode, len] = read_words(core, parameter)?;
+
+                // Only the console can be opened, the mode selects the stream.
+                let mut path = vec![0u8; len.min(MAX_STRING_LEN) as usize];
+                core.read_8(name, &mut path)?;
+
+                let handle = match (&path[..], mode) {
+                    (b":tt", 0..=3) => STDIN,
+                    (b":tt", 4..=7) => STDOUT,
+                    (b":tt", 8..=11) => STDERR,
+                    _ => u32::MAX,
+                };
+
+                (handle, Effect::None)
+            }
+            SYS_CLOSE | SYS_ISTTY => {
+                let handle = core.read_word_32(parameter)?;
+                let is_console = (STDIN..=STDERR).contains(&handle);
+
+                match (operation, is_console) {
+                    (SYS_CLOSE, true) => (0, Effect::None),
+                    (SYS_ISTTY, true) => (1, Effect::None),
+                    _ => (u32::MAX, Effect::None),
+                }
+            }
+            SYS_WRITEC => {
+                let mut data = [0u8];
+                core.read_8(parameter, &mut data)?;
+
+                self.output("stdout", &data);
+
+                // R0 is not changed.
+                (operation, Effect::None)
+            }
+            SYS_WRITE0 => {
+                let data = read_string(core, parameter)?;
+                self.output("stdout", &data);
+
+                (operation, Effect::None)
+            }
+            SYS_WRITE => {
+                let [handle, buffer, len] = read_words(core, parameter)?;
+
+                let category = match handle {
+                    STDOUT => "stdout",
+                    STDERR => "stderr",
+                    // Nothing was written.
+                    _ => return self.finish(core, pc, len, Effect::None),
+                };
+
+                // Longer data is written partially, the program writes the rest with
+                // the next operation.
+                let count = len.min(MAX_STRING_LEN);
+
+                let mut data = vec![0u8; count as usize];
+                core.read_8(buffer, &mut data)?;
+                self.output(category, &data);
+
+                // The number of bytes which were not written.
+                (len - count, Effect::None)
+            }
+            SYS_CLOCK => {
+                let start = *self.start.get_or_insert_with(Instant::now);
+
+                ((start.elapsed().as_millis() / 10) as u32, Effect::None)
+            }
+            SYS_TIME => {
+                let time = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |time| time.as_secs());
+
+                (time as u32, Effect::None)
+            }
+            SYS_EXIT => {
+                // The reason is passed directly on 32-bit targets.
+                let code = if parameter == ADP_STOPPED_APPLICATION_EXIT {
+                    0
+                } else {
+                    1
+                };
+
+                return Ok(Effect::Exit(code));
+            }
+            SYS_EXIT_EXTENDED => {
+                let [reason, code] = read_words(core, parameter)?;
+
+                let code = if reason == ADP_STOPPED_APPLICATION_EXIT {
+                    i64::from(code as i32)
+                } else {
+                    1
+                };
+
+                return Ok(Effect::Exit(code));
+            }
+            operation => {
+                return Err(Error::Other(anyhow!(
+                    "Unsupported semihosting operation {:#x}",
+                    operation
+                )))
+            }
+        };
+
+        self.finish(core, pc, result, effect)
+    }
+
+    /// Write the result of an operation, and continue after the breakpoint instruction.
+    fn finish(
+        &mut self,
+        core: &mut dyn CoreAccess,
+        pc: u32,
+        result: u32,
+        effect: Effect,
+    ) -> Result<Effect, Error> {
+        core.write_core_reg(CoreRegisterAddress(0), result)?;
+        core.write_core_reg(core.program_counter(), pc + 2)?;
+
+        Ok(effect)
+    }
+
+    fn output(&mut self, category: &'static str, data: &[u8]) {
+        let pending = self.pending.entry(category).or_default();
+        pending.extend_from_slice(data);
+
+        let text = rtt::take_text(pending);
+        if !text.is_empty() {
+            self.output.push((category, text));
+        }
+    }
+}
+
+/// Check if the core is halted at `pc` for a semihosting operation.
+pub fn is_semihosting_call(core: &mut dyn CoreAccess, pc: u32) -> bool {
+    let mut instruction = [0u8; 2];
+
+    core.read_8(pc, &mut instruction).is_ok() && u16::from_le_bytes(instruction) == SEMIHOSTING_BKPT
+}
+
+/// Read the parameter block of an operation.
+fn read_words<const N: usize>(core: &mut dyn CoreAccess, address: u32) -> Result<[u32; N], Error> {
+    let mut words = [0u32; N];
+
+    for (i, word) in words.iter_mut().enumerate() {
+        let address = address.checked_add(4 * i as u32).ok_or_else(|| {
+            Error::Other(anyhow!(
+                "Invalid semihosting parameter block at {:#010x}",
+                address
+            ))
+        })?;
+
+        *word = core.read_word_32(address)?;
+    }
+
+    Ok(words)
+}
+
+/// Read a string which ends with a null byte.
+fn read_string(core: &mut dyn CoreAccess, address: u32) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+
+    while (data.len() as u32) < MAX_STRING_LEN {
+        // Aligned chunks, so that the reads don't cross the end of a memory region.
+        let start = address.checked_add(data.len() as u32).ok_or_else(|| {
+            Error::Other(anyhow!(
+                "Unterminated semihosting string at {:#010x}",
+                address
+            ))
+        })?;
+        let mut chunk = vec![0u8; (32 - start % 32) as usize];
+        core.read_8(start, &mut chunk)?;
+
+        match chunk.iter().position(|byte| *byte == 0) {
+            Some(end) => {
+                data.extend_from_slice(&chunk[..end]);
+                break;
+            }
+            None => data.extend_from_slice(&chunk),
+        }
+    }
+
+    Ok(data)
+}
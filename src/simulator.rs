@@ -0,0 +1,750 @@
+//! A simulated target, which keeps registers, memory and breakpoints in memory.
+//!
+//! The simulated core doesn't execute any instructions. Instead, tests give it a trace
+//! of program counter values, which it follows when it is stepped or running. This is
+//! enough to exercise the debugger without any hardware attached.
+
+use crate::dwt;
+use crate::exceptions;
+use crate::itm;
+use crate::target::{CoreAccess, TargetAccess};
+use anyhow::anyhow;
+use object::{Object, ObjectSegment};
+use probe_rs::{
+    architecture::arm::SwoConfig, flashing::FileDownloadError, Architecture, CoreInformation,
+    CoreRegisterAddress, CoreStatus, DebugProbeError, Error, HaltReason,
+};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    path::Path,
+    rc::Rc,
+    time::Duration,
+};
+
+/// The `BKPT #0` instruction in Thumb mode.
+const THUMB_BKPT: [u8; 2] = [0x00, 0xbe];
+
+/// Check if `instruction` is a Thumb `BKPT` instruction, with any immediate.
+fn is_bkpt(instruction: [u8; 2]) -> bool {
+    instruction[1] == THUMB_BKPT[1]
+}
+
+const SP: u16 = 13;
+const LR: u16 = 14;
+const PC: u16 = 15;
+const XPSR: u16 = 16;
+
+/// A simulated Cortex-M target.
+///
+/// The target can be cloned, all clones share the same state. This way a test can keep
+/// a handle to the target, while the debugger uses another one.
+#[derive(Clone, Debug)]
+pub struct SimulatedTarget {
+    state: Rc<RefCell<SimulatedState>>,
+}
+
+#[derive(Debug)]
+struct SimulatedState {
+    status: CoreStatus,
+    registers: HashMap<u16, u32>,
+    memory: Vec<MemoryRegion>,
+    breakpoint_units: u32,
+    breakpoints: Vec<u32>,
+    /// Program counter values the core passes through when it is stepped or running.
+    trace: VecDeque<TraceStep>,
+    entry_point: Option<u32>,
+    /// Baud rate and TPIU clock, once SWO is configured.
+    swo_config: Option<(u32, u32)>,
+    /// SWO data which was not read yet.
+    swo: Vec<u8>,
+    /// Cleared to let all operations which control the core fail.
+    responsive: bool,
+}
+
+/// An instruction executed by the simulated core.
+#[derive(Debug)]
+struct TraceStep {
+    /// Program counter after the instruction.
+    pc: u32,
+    /// Registers changed by the instruction.
+    registers: Vec<(u16, u32)>,
+    /// Memory accessed by the instruction.
+    access: Option<MemoryAccess>,
+    /// Number of the exception which is taken after the instruction.
+    exception: Option<u32>,
+}
+
+#[derive(Debug)]
+struct MemoryAccess {
+    address: u32,
+    len: usize,
+    /// The data written, or `None` for a read.
+    data: Option<Vec<u8>>,
+}
+
+#[derive(Debug)]
+struct MemoryRegion {
+    start: u32,
+    data: Vec<u8>,
+    /// Flash can't be written directly, just like on a real target.
+    writable: bool,
+}
+
+impl MemoryRegion {
+    fn contains(&self, address: u32, len: usize) -> bool {
+        let start = u64::from(self.start);
+        let address = u64::from(address);
+
+        address >= start && address + len as u64 <= start + self.data.len() as u64
+    }
+}
+
+impl Default for SimulatedTarget {
+    fn default() -> Self {
+        SimulatedTarget::new()
+    }
+}
+
+impl SimulatedTarget {
+    /// Create a halted target without any memory except for the DEMCR and ITM_TER
+    /// registers, and with six breakpoint units.
+    pub fn new() -> Self {
+        let target = SimulatedTarget {
+            state: Rc::new(RefCell::new(SimulatedState {
+                status: CoreStatus::Halted(HaltReason::Request),
+                registers: HashMap::new(),
+                memory: Vec::new(),
+                breakpoint_units: 6,
+                breakpoints: Vec::new(),
+                trace: VecDeque::new(),
+                entry_point: None,
+                swo_config: None,
+                swo: Vec::new(),
+                responsive: true,
+            })),
+        };
+
+        target.add_memory(dwt::DEMCR, vec![0; 4]);
+        target.add_memory(itm::ITM_TER, vec![0; 4]);
+
+        target
+    }
+
+    /// Add a memory region, initialized with `data`.
+    pub fn add_memory(&self, start: u32, data: Vec<u8>) {
+        self.state.borrow_mut().memory.push(MemoryRegion {
+            start,
+            data,
+            writable: true,
+        });
+    }
+
+    /// Add a memory region which can't be written by the debugger, like flash.
+    pub fn add_read_only_memory(&self, start: u32, data: Vec<u8>) {
+        self.state.borrow_mut().memory.push(MemoryRegion {
+            start,
+            data,
+            writable: false,
+        });
+    }
+
+    pub fn set_breakpoint_units(&self, units: u32) {
+        self.state.borrow_mut().breakpoint_units = units;
+    }
+
+    /// Addresses of the currently set hardware breakpoints.
+    pub fn breakpoints(&self) -> Vec<u32> {
+        self.state.borrow().breakpoints.clone()
+    }
+
+    pub fn register(&self, register: u16) -> u32 {
+        self.state
+            .borrow()
+            .registers
+            .get(&register)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn set_register(&self, register: u16, value: u32) {
+        self.state.borrow_mut().registers.insert(register, value);
+    }
+
+    pub fn read_memory(&self, address: u32, len: usize) -> Option<Vec<u8>> {
+        let mut data = vec![0; len];
+        self.state.borrow().read(address, &mut data).ok()?;
+        Some(data)
+    }
+
+    /// Write to memory, even if it's read-only for the debugger.
+    pub fn write_memory(&self, address: u32, data: &[u8]) {
+        self.state
+            .borrow_mut()
+            .program(address, data)
+            .expect("Write to unmapped memory of simulated target");
+    }
+
+    /// Let reading the status of the core, halting, running, stepping and resetting it
+    /// fail with a timeout, like for a core which doesn't respond to the debugger.
+    pub fn set_responsive(&self, responsive: bool) {
+        self.state.borrow_mut().responsive = responsive;
+    }
+
+    pub fn status(&self) -> CoreStatus {
+        self.state.borrow().status
+    }
+
+    /// Set the program counter values the core passes through when it is stepped or running.
+    pub fn set_trace(&self, trace: impl IntoIterator<Item = u32>) {
+        self.state.borrow_mut().trace = trace
+            .into_iter()
+            .map(|pc| TraceStep {
+                pc,
+                registers: Vec::new(),
+                access: None,
+                exception: None,
+            })
+            .collect();
+    }
+
+    /// Add an instruction to the trace, which changes registers besides the program counter.
+    pub fn add_trace_step(&self, pc: u32, registers: &[(u16, u32)]) {
+        self.state.borrow_mut().trace.push_back(TraceStep {
+            pc,
+            registers: registers.to_vec(),
+            access: None,
+            exception: None,
+        });
+    }
+
+    /// Add an instruction to the trace, which reads `len` bytes at `address`.
+    pub fn add_trace_read(&self, pc: u32, address: u32, len: usize) {
+        self.state.borrow_mut().trace.push_back(TraceStep {
+            pc,
+            registers: Vec::new(),
+            access: Some(MemoryAccess {
+                address,
+                len,
+                data: None,
+            }),
+            exception: None,
+        });
+    }
+
+    /// Add an instruction to the trace, which writes `data` to `address`.
+    pub fn add_trace_write(&self, pc: u32, address: u32, data: &[u8]) {
+        self.state.borrow_mut().trace.push_back(TraceStep {
+            pc,
+            registers: Vec::new(),
+            access: Some(MemoryAccess {
+                address,
+                len: data.len(),
+                data: Some(data.to_vec()),
+            }),
+            exception: None,
+        });
+    }
+
+    /// Add the entry into the handler of the exception `number` to the trace. The
+    /// registers are stacked on the main stack like by a Cortex-M core, and the core
+    /// halts if the vector catch for the exception is enabled.
+    pub fn add_trace_exception(&self, number: u32, handler: u32) {
+        self.state.borrow_mut().trace.push_back(TraceStep {
+            pc: handler,
+            registers: Vec::new(),
+            access: None,
+            exception: Some(number),
+        });
+    }
+
+    /// Add a Data Watchpoint and Trace unit with `comparators` comparators. The core
+    /// halts when a memory access of the trace matches one of them.
+    pub fn add_dwt(&self, comparators: u32) {
+        let mut registers =
+            vec![0; (dwt::DWT_COMP0 - dwt::DWT_CTRL) as usize + 16 * comparators as usize];
+        registers[..4].copy_from_slice(&(comparators << 28).to_le_bytes());
+        self.add_memory(dwt::DWT_CTRL, registers);
+    }
+
+    /// Add the Data Watchpoint and Trace unit of an ARMv8-M core with `comparators`
+    /// comparators, which watch up to a word each instead of using a `MASK` register.
+    pub fn add_armv8m_dwt(&self, comparators: u32) {
+        self.add_dwt(comparators);
+        self.add_memory(dwt::DWT_DEVARCH, 0x4770_1a02u32.to_le_bytes().to_vec());
+    }
+
+    /// The baud rate and TPIU clock SWO was configured with.
+    pub fn swo_config(&self) -> Option<(u32, u32)> {
+        self.state.borrow().swo_config
+    }
+
+    /// Add data to the SWO stream, as if the target had sent it.
+    pub fn add_swo_data(&self, data: &[u8]) {
+        self.state.borrow_mut().swo.extend_from_slice(data);
+    }
+
+    /// Halt the core at `address`, as if it had stopped there for `reason`.
+    pub fn halt_at(&self, address: u32, reason: HaltReason) {
+        let mut state = self.state.borrow_mut();
+
+        state.registers.insert(PC, address);
+        state.status = CoreStatus::Halted(reason);
+    }
+}
+
+impl SimulatedState {
+    fn check_responsive(&self) -> Result<(), Error> {
+        if self.responsive {
+            Ok(())
+        } else {
+            Err(Error::Probe(DebugProbeError::Timeout))
+        }
+    }
+
+    fn region(&self, address: u32, len: usize) -> Result<usize, Error> {
+        self.memory
+            .iter()
+            .position(|r| r.contains(address, len))
+            .ok_or_else(|| {
+                Error::Other(anyhow!(
+                    "Access to unmapped memory at {:#010x} ({} bytes)",
+                    address,
+                    len
+                ))
+            })
+    }
+
+    fn read(&self, address: u32, data: &mut [u8]) -> Result<(), Error> {
+        let region = &self.memory[self.region(address, data.len())?];
+        let offset = (address - region.start) as usize;
+
+        data.copy_from_slice(&region.data[offset..offset + data.len()]);
+
+        Ok(())
+    }
+
+    fn write(&mut self, address: u32, data: &[u8]) -> Result<(), Error> {
+        let index = self.region(address, data.len())?;
+
+        if !self.memory[index].writable {
+            return Err(Error::Other(anyhow!(
+                "Memory at {:#010x} is not writable",
+                address
+            )));
+        }
+
+        self.program(address, data)
+    }
+
+    /// Write to memory, including read-only memory.
+    fn program(&mut self, address: u32, data: &[u8]) -> Result<(), Error> {
+        let index = self.region(address, data.len())?;
+        let region = &mut self.memory[index];
+        let offset = (address - region.start) as usize;
+
+        region.data[offset..offset + data.len()].copy_from_slice(data);
+
+        Ok(())
+    }
+
+    fn pc(&self) -> u32 {
+        self.registers.get(&PC).copied().unwrap_or(0)
+    }
+
+    /// Check if the core would halt at `address`, because of a breakpoint.
+    fn is_breakpoint(&self, address: u32) -> bool {
+        let mut instruction = [0u8; 2];
+
+        self.breakpoints.contains(&address)
+            || (self.read(address, &mut instruction).is_ok() && is_bkpt(instruction))
+    }
+
+    fn read_word(&self, address: u32) -> u32 {
+        let mut data = [0u8; 4];
+        self.read(address, &mut data)
+            .map_or(0, |_| u32::from_le_bytes(data))
+    }
+
+    /// Apply the changes of an instruction in the trace, and return the new program
+    /// counter, and the reason to halt if a watchpoint or vector catch triggered.
+    fn execute(&mut self, step: TraceStep) -> (u32, Option<HaltReason>) {
+        let caught = match step.exception {
+            Some(number) => self.enter_exception(number),
+            None => false,
+        };
+
+        self.registers.extend(step.registers);
+        self.registers.insert(PC, step.pc);
+
+        let watchpoint = match step.access {
+            Some(access) => {
+                if let Some(data) = &access.data {
+                    self.write(access.address, data)
+                        .expect("Invalid write in the trace of simulated target");
+                }
+
+                self.watchpoint_matches(&access)
+            }
+            None => false,
+        };
+
+        let reason = if watchpoint {
+            Some(HaltReason::Watchpoint)
+        } else if caught {
+            Some(HaltReason::Exception)
+        } else {
+            None
+        };
+
+        (step.pc, reason)
+    }
+
+    /// Stack the registers for the exception `number`, and return if its vector catch
+    /// is enabled.
+    fn enter_exception(&mut self, number: u32) -> bool {
+        let register = |register| self.registers.get(&register).copied().unwrap_or(0);
+
+        // The frame is aligned to 8 bytes, which is marked in the stacked xPSR.
+        let sp = register(SP);
+        let frame = (sp - 32) & !7;
+        let realigned = if frame + 32 != sp { 1 << 9 } else { 0 };
+
+        let xpsr = register(XPSR);
+
+        let mut data = Vec::with_capacity(32);
+        for value in &[
+            register(0),
+            register(1),
+            register(2),
+            register(3),
+            register(12),
+            register(LR),
+            register(PC),
+            xpsr | realigned,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        self.write(frame, &data)
+            .expect("Exception with invalid stack pointer in the trace of simulated target");
+
+        self.registers.insert(SP, frame);
+        self.registers.insert(LR, 0xffff_fff9);
+        self.registers.insert(XPSR, (xpsr & !0x1ff) | number);
+
+        let vector_catch = match number {
+            3 => exceptions::VC_HARDERR,
+            4 => exceptions::VC_MMERR,
+            5 => exceptions::VC_BUSERR,
+            6 => exceptions::VC_STATERR | exceptions::VC_CHKERR | exceptions::VC_NOCPERR,
+            _ => 0,
+        };
+
+        self.read_word(dwt::DEMCR) & vector_catch != 0
+    }
+
+    /// Clear the match of the DWT comparators whose `FUNCTION` register was read, like
+    /// the hardware does.
+    fn clear_matches(&mut self, address: u32, len: usize) {
+        if self.region(dwt::DWT_CTRL, 4).is_err() {
+            return;
+        }
+
+        for index in 0..self.read_word(dwt::DWT_CTRL) >> 28 {
+            let function = dwt::DWT_COMP0 + 16 * index + 8;
+
+            if address <= function && u64::from(function) < u64::from(address) + len as u64 {
+                let value = self.read_word(function) & !dwt::FUNCTION_MATCHED;
+                let _ = self.write(function, &value.to_le_bytes());
+            }
+        }
+    }
+
+    /// Check the DWT comparators for `access`, and mark the ones which match it.
+    fn watchpoint_matches(&mut self, access: &MemoryAccess) -> bool {
+        if self.region(dwt::DWT_CTRL, 4).is_err() {
+            return false;
+        }
+
+        let start = u64::from(access.address);
+        let end = start + access.len as u64;
+
+        let armv8m = self.region(dwt::DWT_DEVARCH, 4).is_ok();
+        let mut matched = false;
+
+        for index in 0..self.read_word(dwt::DWT_CTRL) >> 28 {
+            let comparator = dwt::DWT_COMP0 + 16 * index;
+            let function = self.read_word(comparator + 8);
+
+            let (enabled, size) = if armv8m {
+                // A data address `MATCH`, whose `ACTION` halts the core.
+                let enabled = match function & 0xf {
+                    0b0100 => true,
+                    0b0101 => access.data.is_some(),
+                    0b0110 => access.data.is_none(),
+                    _ => false,
+                };
+
+                (
+                    enabled && (function >> 4) & 0b11 == 0b01,
+                    1u64 << ((function >> 10) & 0b11),
+                )
+            } else {
+                let enabled = match function & 0xf {
+                    0b0101 => access.data.is_none(),
+                    0b0110 => access.data.is_some(),
+                    0b0111 => true,
+                    _ => false,
+                };
+
+                (enabled, 1u64 << (self.read_word(comparator + 4) & 0x1f))
+            };
+
+            let watched = u64::from(self.read_word(comparator)) & !(size - 1);
+
+            if enabled && start < watched + size && watched < end {
+                let _ = self.write(
+                    comparator + 8,
+                    &(function | dwt::FUNCTION_MATCHED).to_le_bytes(),
+                );
+                matched = true;
+            }
+        }
+
+        matched
+    }
+
+    fn halted(&mut self, reason: HaltReason) -> CoreInformation {
+        self.status = CoreStatus::Halted(reason);
+
+        CoreInformation { pc: self.pc() }
+    }
+}
+
+impl TargetAccess for SimulatedTarget {
+    fn core(&mut self) -> Result<Box<dyn CoreAccess + '_>, Error> {
+        Ok(Box::new(self.clone()))
+    }
+
+    fn download(&mut self, path: &Path) -> Result<(), FileDownloadError> {
+        let data = std::fs::read(path)?;
+        let elf = object::File::parse(&data)
+            .map_err(|_| FileDownloadError::Object("Failed to parse the ELF file"))?;
+
+        let mut state = self.state.borrow_mut();
+
+        for segment in elf.segments() {
+            let segment_data = segment.data().map_err(|_| {
+                FileDownloadError::Object("Failed to read a segment of the ELF file")
+            })?;
+
+            if segment_data.is_empty() {
+                continue;
+            }
+
+            let address = segment.address() as u32;
+
+            if state.program(address, segment_data).is_err() {
+                state.memory.push(MemoryRegion {
+                    start: address,
+                    data: segment_data.to_vec(),
+                    writable: false,
+                });
+            }
+        }
+
+        state.entry_point = Some(elf.entry() as u32);
+
+        Ok(())
+    }
+
+    fn setup_swo(&mut self, config: &SwoConfig) -> Result<(), Error> {
+        self.state.borrow_mut().swo_config = Some((config.baud(), config.tpiu_clk()));
+
+        Ok(())
+    }
+
+    fn read_swo(&mut self) -> Result<Vec<u8>, Error> {
+        let mut state = self.state.borrow_mut();
+
+        if state.swo_config.is_none() {
+            return Err(Error::Other(anyhow!("SWO is not configured")));
+        }
+
+        Ok(std::mem::take(&mut state.swo))
+    }
+}
+
+impl CoreAccess for SimulatedTarget {
+    fn status(&mut self) -> Result<CoreStatus, Error> {
+        let state = self.state.borrow();
+        state.check_responsive()?;
+
+        Ok(state.status)
+    }
+
+    fn halt(&mut self, _timeout: Duration) -> Result<CoreInformation, Error> {
+        let mut state = self.state.borrow_mut();
+        state.check_responsive()?;
+
+        Ok(match state.status {
+            CoreStatus::Halted(_) => CoreInformation { pc: state.pc() },
+            _ => state.halted(HaltReason::Request),
+        })
+    }
+
+    fn run(&mut self) -> Result<(), Error> {
+        let mut state = self.state.borrow_mut();
+        state.check_responsive()?;
+
+        state.status = CoreStatus::Running;
+
+        // A BKPT instruction at the current address is executed right away.
+        let pc = state.pc();
+        if state.is_breakpoint(pc) && !state.breakpoints.contains(&pc) {
+            state.halted(HaltReason::Breakpoint);
+            return Ok(());
+        }
+
+        while let Some(step) = state.trace.pop_front() {
+            let (pc, reason) = state.execute(step);
+
+            if let Some(reason) = reason {
+                state.halted(reason);
+                break;
+            }
+
+            if state.is_breakpoint(pc) {
+                state.halted(HaltReason::Breakpoint);
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn step(&mut self) -> Result<CoreInformation, Error> {
+        let mut state = self.state.borrow_mut();
+        state.check_responsive()?;
+
+        // Executing a BKPT instruction doesn't advance the program counter.
+        let mut instruction = [0u8; 2];
+        if state.read(state.pc(), &mut instruction).is_ok() && is_bkpt(instruction) {
+            return Ok(state.halted(HaltReason::Breakpoint));
+        }
+
+        match state.trace.pop_front() {
+            Some(step) => {
+                state.execute(step);
+            }
+            // Without a trace, pretend that a 16-bit Thumb instruction was executed.
+            None => {
+                let pc = state.pc() + 2;
+                state.registers.insert(PC, pc);
+            }
+        }
+
+        Ok(state.halted(HaltReason::Step))
+    }
+
+    fn reset_and_halt(&mut self, _timeout: Duration) -> Result<CoreInformation, Error> {
+        let mut state = self.state.borrow_mut();
+        state.check_responsive()?;
+
+        // Load the initial stack pointer and reset vector from the vector table,
+        // if there is one.
+        let mut vector_table = [0u8; 8];
+        if state.read(0, &mut vector_table).is_ok() {
+            let sp = u32::from_le_bytes([
+                vector_table[0],
+                vector_table[1],
+                vector_table[2],
+                vector_table[3],
+            ]);
+            state.registers.insert(SP, sp);
+
+            let reset = u32::from_le_bytes([
+                vector_table[4],
+                vector_table[5],
+                vector_table[6],
+                vector_table[7],
+            ]);
+            state.registers.insert(PC, reset & !1);
+        } else if let Some(entry_point) = state.entry_point {
+            state.registers.insert(PC, entry_point & !1);
+        }
+
+        state.registers.insert(LR, 0xffff_ffff);
+
+        Ok(state.halted(HaltReason::Request))
+    }
+
+    fn read_core_reg(&mut self, address: CoreRegisterAddress) -> Result<u32, Error> {
+        Ok(self.register(address.0))
+    }
+
+    fn write_core_reg(&mut self, address: CoreRegisterAddress, value: u32) -> Result<(), Error> {
+        self.set_register(address.0, value);
+        Ok(())
+    }
+
+    fn program_counter(&self) -> CoreRegisterAddress {
+        CoreRegisterAddress(PC)
+    }
+
+    fn stack_pointer(&self) -> CoreRegisterAddress {
+        CoreRegisterAddress(SP)
+    }
+
+    fn return_address(&self) -> CoreRegisterAddress {
+        CoreRegisterAddress(LR)
+    }
+
+    fn read_8(&mut self, address: u32, data: &mut [u8]) -> Result<(), Error> {
+        let mut state = self.state.borrow_mut();
+
+        state.read(address, data)?;
+        state.clear_matches(address, data.len());
+
+        Ok(())
+    }
+
+    fn write_8(&mut self, address: u32, data: &[u8]) -> Result<(), Error> {
+        self.state.borrow_mut().write(address, data)
+    }
+
+    fn available_breakpoint_units(&mut self) -> Result<u32, Error> {
+        Ok(self.state.borrow().breakpoint_units)
+    }
+
+    fn set_hw_breakpoint(&mut self, address: u32) -> Result<(), Error> {
+        let mut state = self.state.borrow_mut();
+
+        if state.breakpoints.len() >= state.breakpoint_units as usize {
+            return Err(Error::Probe(DebugProbeError::BreakpointUnitsExceeded));
+        }
+
+        state.breakpoints.push(address);
+
+        Ok(())
+    }
+
+    fn clear_hw_breakpoint(&mut self, address: u32) -> Result<(), Error> {
+        let mut state = self.state.borrow_mut();
+
+        match state.breakpoints.iter().position(|&bp| bp == address) {
+            Some(index) => {
+                state.breakpoints.swap_remove(index);
+                Ok(())
+            }
+            None => Err(Error::Other(anyhow!(
+                "No breakpoint found at address {:#010x}",
+                address
+            ))),
+        }
+    }
+
+    fn architecture(&self) -> Architecture {
+        Architecture::Arm
+    }
+}
@@ -0,0 +1,250 @@
+//! Stepping through the program by source lines.
+//!
+//! The core is stepped instruction by instruction, until it reaches the start of a
+//! different source line. Calls which should not be stepped into are skipped by
+//! running the core until it reaches the return address.
+
+use crate::breakpoints::Breakpoints;
+use crate::debug_info::{DebugInfo, Statement};
+use crate::semihosting::{Effect, Semihosting};
+use crate::target::CoreAccess;
+use anyhow::anyhow;
+use log::{debug, warn};
+use std::ops::Range;
+
+/// Upper limit for the number of instructions executed for a single step. Every
+/// instruction takes several transactions with the probe, and no other request is
+/// handled while stepping.
+const MAX_STEPPED_INSTRUCTIONS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepKind {
+    /// Step to the next source line, stepping over calls (`next`).
+    Over,
+    /// Step to the next source line, stepping into calls (`stepIn`).
+    Into,
+    /// Run until the current function returns (`stepOut`).
+    Out,
+}
+
+/// The point the core runs to, to finish a call.
+#[derive(Debug)]
+struct ReturnPoint {
+    address: u32,
+    /// The stack pointer after the return, to detect recursive calls.
+    stack_pointer: u32,
+}
+
+/// The state of a source level step, which might have to wait for the core to reach
+/// a return address.
+#[derive(Debug)]
+pub struct Step {
+    kind: StepKind,
+    /// The line where the step started. The step ends at the start of another line.
+    start: Option<Statement>,
+    /// Address range of the function the core is currently in.
+    function: Option<Range<u64>>,
+    return_point: Option<ReturnPoint>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StepResult {
+    /// The step is finished, and the core is halted.
+    Done,
+    /// The core is running to a return address.
+    Running,
+    /// The program exited with a semihosting operation.
+    Exited(i64),
+}
+
+/// Execute a single instruction. The debugger performs software breakpoints and
+/// semihosting operations itself, because the core would only halt again at their
+/// breakpoint instruction.
+pub fn step_instruction(
+    core: &mut dyn CoreAccess,
+    breakpoints: &Breakpoints,
+    semihosting: &mut Semihosting,
+) -> Result<Effect, probe_rs::Error> {
+    if let Some(effect) = semihosting.step_over_call(core)? {
+        return Ok(effect);
+    }
+
+    if !breakpoints.step_over_software_breakpoint(core)? {
+        core.step()?;
+    }
+
+    Ok(Effect::None)
+}
+
+impl Step {
+    pub fn new(kind: StepKind, debug_info: &DebugInfo, pc: u32) -> Step {
+        Step {
+            kind,
+            start: debug_info.statement_at(u64::from(pc)),
+            function: debug_info.function_range(u64::from(pc)),
+            return_point: None,
+        }
+    }
+
+    /// Check if the core, which halted at `pc` while it was running for this step,
+    /// reached the return address it was running to.
+    pub fn is_return_point(&self, pc: u32, stack_pointer: u32) -> bool {
+        match &self.return_point {
+            Some(return_point) => {
+                // In a recursive call, the same return address is reached with a lower stack pointer.
+                return_point.address == pc && stack_pointer >= return_point.stack_pointer
+            }
+            None => false,
+        }
+    }
+
+    /// Check if `pc` is the return address the core is running to, which might have been
+    /// reached by a recursive call.
+    pub fn is_return_address(&self, pc: u32) -> bool {
+        self.return_point
+            .as_ref()
+            .map(|return_point| return_point.address == pc)
+            .unwrap_or(false)
+    }
+
+    /// Continue the step, until it is done or the core has to run to a return address.
+    pub fn resume(
+        &mut self,
+        debug_info: &DebugInfo,
+        core: &mut dyn CoreAccess,
+        breakpoints: &mut Breakpoints,
+        semihosting: &mut Semihosting,
+    ) -> Result<StepResult, anyhow::Error> {
+        let pc_register = core.program_counter();
+        let lr_register = core.return_address();
+
+        if self.return_point.take().is_some() {
+            breakpoints.clear_temporary(core)?;
+
+            let pc = core.read_core_reg(pc_register)?;
+
+            if self.kind == StepKind::Out || self.is_new_line(debug_info, pc) {
+                return Ok(StepResult::Done);
+            }
+        } else if self.kind == StepKind::Out {
+            let pc = core.read_core_reg(pc_register)?;
+
+            return match debug_info.return_address(core, pc)? {
+                Some((address, stack_pointer)) => {
+                    self.run_to(core, breakpoints, address, stack_pointer)
+                }
+                None => Err(anyhow!(
+                    "Unable to find the return address of the current function"
+                )),
+            };
+        }
+
+        let mut pc = core.read_core_reg(pc_register)?;
+        let mut lr = core.read_core_reg(lr_register)?;
+
+        for _ in 0..MAX_STEPPED_INSTRUCTIONS {
+            let previous_pc = pc;
+            let previous_lr = lr;
+
+            if let Effect::Exit(code) = step_instruction(core, breakpoints, semihosting)? {
+                return Ok(StepResult::Exited(code));
+            }
+
+            pc = core.read_core_reg(pc_register)?;
+            lr = core.read_core_reg(lr_register)?;
+
+            // A call stores the address of the next instruction in the link register.
+            // Calls are checked first, because a recursive call stays in the function.
+            let return_address = lr & !1;
+            let is_call =
+                lr != previous_lr && matches!(return_address.wrapping_sub(previous_pc), 2..=4);
+
+            if is_call {
+                let has_line_info = debug_info.statement_at(u64::from(pc)).is_some();
+
+                if self.kind == StepKind::Into && has_line_info {
+                    debug!("Stepped into function at {:#010x}", pc);
+
+                    // Continue until the first line after the start of the function.
+                    self.function = debug_info.function_range(u64::from(pc));
+                    self.start = debug_info.statement_at(u64::from(pc));
+                    continue;
+                }
+
+                let stack_pointer = core.read_core_reg(core.stack_pointer())?;
+
+                return self.run_to(core, breakpoints, return_address, stack_pointer);
+            }
+
+            if self.in_function(pc) {
+                if self.is_new_line(debug_info, pc) {
+                    return Ok(StepResult::Done);
+                }
+
+                continue;
+            }
+
+            // The function returned, or jumped somewhere else.
+            self.function = debug_info.function_range(u64::from(pc));
+
+            if self.function.is_none() || self.is_new_line(debug_info, pc) {
+                return Ok(StepResult::Done);
+            }
+        }
+
+        warn!(
+            "Stopping step after {} instructions without reaching a new line",
+            MAX_STEPPED_INSTRUCTIONS
+        );
+
+        Ok(StepResult::Done)
+    }
+
+    /// Let the core run until it returns to `address`.
+    fn run_to(
+        &mut self,
+        core: &mut dyn CoreAccess,
+        breakpoints: &mut Breakpoints,
+        address: u32,
+        stack_pointer: u32,
+    ) -> Result<StepResult, anyhow::Error> {
+        debug!("Running to return address {:#010x}", address);
+
+        breakpoints
+            .set_temporary(core, address)
+            .map_err(|message| anyhow!("Unable to step over function call: {}", message))?;
+
+        self.return_point = Some(ReturnPoint {
+            address,
+            stack_pointer,
+        });
+
+        breakpoints.step_over_software_breakpoint(core)?;
+        core.run()?;
+
+        Ok(StepResult::Running)
+    }
+
+    fn in_function(&self, pc: u32) -> bool {
+        match &self.function {
+            Some(range) => range.contains(&u64::from(pc)),
+            None => false,
+        }
+    }
+
+    /// Check if `pc` is at the start of a different line than the one the step started at.
+    fn is_new_line(&self, debug_info: &DebugInfo, pc: u32) -> bool {
+        match debug_info.statement_at(u64::from(pc)) {
+            Some(statement) => {
+                statement.address == u64::from(pc)
+                    && statement.is_stmt
+                    && statement.line != 0
+                    && !self
+                        .start
+                        .map(|start| start.same_line(&statement))
+                        .unwrap_or(false)
+            }
+            None => false,
+        }
+    }
+}
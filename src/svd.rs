@@ -0,0 +1,489 @@
+//! Peripheral registers of a device, as described by a CMSIS-SVD file.
+//!
+//! Only the parts of the format which are needed to show the registers are parsed:
+//! peripherals, clusters, registers, fields and their enumerated values.
+
+use roxmltree::Node;
+use std::{io, path::Path};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SvdError {
+    #[error("Unable to read SVD file")]
+    Io(#[from] io::Error),
+    #[error("Invalid XML in SVD file")]
+    Xml(#[from] roxmltree::Error),
+    #[error("Missing element <{0}> in <{1}>")]
+    MissingElement(&'static str, String),
+    #[error("Invalid number '{0}'")]
+    InvalidNumber(String),
+    #[error("Invalid bit range of field {0}")]
+    InvalidBitRange(String),
+    #[error("Peripheral {0} is derived from unknown peripheral {1}")]
+    UnknownPeripheral(String, String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub name: String,
+    pub peripherals: Vec<Peripheral>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Peripheral {
+    pub name: String,
+    pub description: Option<String>,
+    pub base_address: u32,
+    pub registers: Vec<Register>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Register {
+    /// Name of the register, prefixed by the names of the clusters it is contained in.
+    pub name: String,
+    pub description: Option<String>,
+    /// Offset from the base address of the peripheral.
+    pub address_offset: u32,
+    /// Size of the register in bits.
+    pub size: u32,
+    pub access: Access,
+    /// Set if reading the register has side effects, e.g. clearing flags.
+    pub read_action: bool,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: String,
+    pub description: Option<String>,
+    pub bit_offset: u32,
+    pub bit_width: u32,
+    pub enumerated_values: Vec<EnumeratedValue>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EnumeratedValue {
+    pub name: String,
+    pub description: Option<String>,
+    /// The value, or `None` for the default value, which matches all other values.
+    pub value: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+    WriteOnce,
+    ReadWriteOnce,
+}
+
+impl Register {
+    /// Check if the register can be read without side effects.
+    pub fn is_readable(&self) -> bool {
+        !self.read_action && !matches!(self.access, Access::WriteOnly | Access::WriteOnce)
+    }
+}
+
+impl Field {
+    /// Extract the value of the field from the value of its register.
+    pub fn extract(&self, register_value: u64) -> u64 {
+        let mask = if self.bit_width >= 64 {
+            u64::MAX
+        } else {
+            (1 << self.bit_width) - 1
+        };
+
+        register_value.checked_shr(self.bit_offset).unwrap_or(0) & mask
+    }
+
+    /// Get the enumerated value which describes `value`.
+    pub fn decode(&self, value: u64) -> Option<&EnumeratedValue> {
+        self.enumerated_values
+            .iter()
+            .find(|enumerated| enumerated.value == Some(value))
+            .or_else(|| {
+                self.enumerated_values
+                    .iter()
+                    .find(|enumerated| enumerated.value.is_none())
+            })
+    }
+}
+
+/// Properties which are inherited from the device to peripherals, clusters and registers.
+#[derive(Debug, Clone, Copy)]
+struct RegisterProperties {
+    size: u32,
+    access: Access,
+}
+
+impl RegisterProperties {
+    fn inherit(self, node: Node) -> Result<RegisterProperties, SvdError> {
+        Ok(RegisterProperties {
+            size: optional_number(node, "size")?
+                .map(|size| size as u32)
+                .unwrap_or(self.size),
+            access: child_text(node, "access")
+                .and_then(parse_access)
+                .unwrap_or(self.access),
+        })
+    }
+}
+
+impl Device {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Device, SvdError> {
+        let content = std::fs::read_to_string(path)?;
+
+        Device::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<Device, SvdError> {
+        let document = roxmltree::Document::parse(content)?;
+        let device = document.root_element();
+
+        let properties = RegisterProperties {
+            size: 32,
+            access: Access::ReadWrite,
+        }
+        .inherit(device)?;
+
+        let mut peripherals = Vec::new();
+        let mut derived = Vec::new();
+
+        if let Some(node) = child(device, "peripherals") {
+            for node in children(node, "peripheral") {
+                let peripheral = parse_peripheral(node, properties)?;
+
+                if let Some(base) = node.attribute("derivedFrom") {
+                    derived.push((peripherals.len(), base.to_owned()));
+                }
+
+                peripherals.push(peripheral);
+            }
+        }
+
+        // Derived peripherals have the same registers as their base, unless they
+        // declare their own.
+        for (index, base) in derived {
+            if !peripherals[index].registers.is_empty() {
+                continue;
+            }
+
+            let registers = peripherals
+                .iter()
+                .find(|peripheral| peripheral.name == base)
+                .map(|peripheral| peripheral.registers.clone())
+                .ok_or_else(|| {
+                    SvdError::UnknownPeripheral(peripherals[index].name.clone(), base)
+                })?;
+
+            peripherals[index].registers = registers;
+        }
+
+        Ok(Device {
+            name: child_text(device, "name").unwrap_or_default().to_owned(),
+            peripherals,
+        })
+    }
+}
+
+fn parse_peripheral(node: Node, properties: RegisterProperties) -> Result<Peripheral, SvdError> {
+    let properties = properties.inherit(node)?;
+
+    let mut registers = Vec::new();
+
+    if let Some(node) = child(node, "registers") {
+        parse_registers(node, properties, "", 0, &mut registers)?;
+    }
+
+    Ok(Peripheral {
+        name: required_text(node, "name")?.to_owned(),
+        description: description(node),
+        base_address: required_number(node, "baseAddress")? as u32,
+        registers,
+    })
+}
+
+/// Parse the registers and clusters in `node`. The registers in clusters are
+/// flattened, and prefixed with the name of the cluster.
+fn parse_registers(
+    node: Node,
+    properties: RegisterProperties,
+    prefix: &str,
+    offset: u32,
+    registers: &mut Vec<Register>,
+) -> Result<(), SvdError> {
+    for node in node.children().filter(Node::is_element) {
+        let is_cluster = match node.tag_name().name() {
+            "register" => false,
+            "cluster" => true,
+            _ => continue,
+        };
+
+        let properties = properties.inherit(node)?;
+        let address_offset = offset + required_number(node, "addressOffset")? as u32;
+
+        for (name, increment) in dimensions(node)? {
+            let address_offset = address_offset + increment;
+            let name = format!("{}{}", prefix, name);
+
+            if is_cluster {
+                parse_registers(
+                    node,
+                    properties,
+                    &format!("{}.", name),
+                    address_offset,
+                    registers,
+                )?;
+                continue;
+            }
+
+            let mut fields = Vec::new();
+            if let Some(node) = child(node, "fields") {
+                for node in children(node, "field") {
+                    fields.push(parse_field(node)?);
+                }
+            }
+
+            registers.push(Register {
+                name,
+                description: description(node),
+                address_offset,
+                size: properties.size,
+                access: properties.access,
+                read_action: child(node, "readAction").is_some(),
+                fields,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the names and address increments of the instances of a register or cluster,
+/// which can be an array with the `dim` elements.
+fn dimensions(node: Node) -> Result<Vec<(String, u32)>, SvdError> {
+    let name = required_text(node, "name")?;
+
+    let dim = match optional_number(node, "dim")? {
+        Some(dim) => dim as u32,
+        None => return Ok(vec![(name.to_owned(), 0)]),
+    };
+
+    let increment = required_number(node, "dimIncrement")? as u32;
+
+    let indices: Vec<String> = match child_text(node, "dimIndex") {
+        Some(index) if index.contains('-') => {
+            let mut range = index.splitn(2, '-');
+            let start = range.next().unwrap_or_default().trim();
+            let end = range.next().unwrap_or_default().trim();
+
+            match (start.parse::<u32>(), end.parse::<u32>()) {
+                (Ok(start), Ok(end)) => (start..=end).map(|i| i.to_string()).collect(),
+                // Letters, e.g. `A-D`
+                _ => {
+                    let start = start.chars().next().unwrap_or('A');
+                    (0..dim)
+                        .filter_map(|i| std::char::from_u32(start as u32 + i))
+                        .map(String::from)
+                        .collect()
+                }
+            }
+        }
+        Some(index) => index.split(',').map(|i| i.trim().to_owned()).collect(),
+        None => (0..dim).map(|i| i.to_string()).collect(),
+    };
+
+    Ok(indices
+        .iter()
+        .take(dim as usize)
+        .enumerate()
+        .map(|(i, index)| {
+            let name = if name.contains("[%s]") {
+                name.replace("[%s]", index)
+            } else {
+                name.replace("%s", index)
+            };
+
+            (name, i as u32 * increment)
+        })
+        .collect())
+}
+
+fn parse_field(node: Node) -> Result<Field, SvdError> {
+    let name = required_text(node, "name")?;
+
+    let invalid_range = || SvdError::InvalidBitRange(name.to_owned());
+    let width = |msb: u64, lsb: u64| {
+        msb.checked_sub(lsb)
+            .and_then(|width| width.checked_add(1))
+            .ok_or_else(invalid_range)
+    };
+
+    let (bit_offset, bit_width) = if let Some(offset) = optional_number(node, "bitOffset")? {
+        (offset, optional_number(node, "bitWidth")?.unwrap_or(1))
+    } else if let Some(lsb) = optional_number(node, "lsb")? {
+        let msb = required_number(node, "msb")?;
+
+        (lsb, width(msb, lsb)?)
+    } else {
+        // Given as `[msb:lsb]`
+        let range = required_text(node, "bitRange")?;
+        let mut bits = range.trim_matches(|c| c == '[' || c == ']').splitn(2, ':');
+
+        let msb = parse_number(bits.next().unwrap_or_default())?;
+        let lsb = parse_number(bits.next().unwrap_or_default())?;
+
+        (lsb, width(msb, lsb)?)
+    };
+
+    // Fields of registers wider than 64 bits are not supported.
+    if bit_offset >= 64 || bit_width == 0 || bit_width > 64 - bit_offset {
+        return Err(invalid_range());
+    }
+
+    // A field can have different values for reading and writing, the values for
+    // reading are the relevant ones.
+    let enumerated_values = children(node, "enumeratedValues")
+        .find(|values| child_text(*values, "usage").unwrap_or("read-write") != "write")
+        .map(|values| {
+            children(values, "enumeratedValue")
+                .map(|value| {
+                    let is_default = child_text(value, "isDefault") == Some("true");
+
+                    Ok(EnumeratedValue {
+                        name: required_text(value, "name")?.to_owned(),
+                        description: description(value),
+                        value: match child_text(value, "value") {
+                            Some(value) if !is_default => parse_enumerated_value(value),
+                            _ => None,
+                        },
+                    })
+                })
+                .collect::<Result<Vec<_>, SvdError>>()
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    Ok(Field {
+        name: name.to_owned(),
+        description: description(node),
+        bit_offset: bit_offset as u32,
+        bit_width: bit_width as u32,
+        enumerated_values,
+    })
+}
+
+/// Parse the value of an enumerated value. Values with "don't care" bits are ignored.
+fn parse_enumerated_value(value: &str) -> Option<u64> {
+    if value.starts_with('#') && value.contains(['x', 'X']) {
+        return None;
+    }
+
+    parse_number(value).ok()
+}
+
+fn parse_access(access: &str) -> Option<Access> {
+    match access {
+        "read-only" => Some(Access::ReadOnly),
+        "write-only" => Some(Access::WriteOnly),
+        "read-write" => Some(Access::ReadWrite),
+        "writeOnce" => Some(Access::WriteOnce),
+        "read-writeOnce" => Some(Access::ReadWriteOnce),
+        _ => None,
+    }
+}
+
+/// Parse a number in the format used in SVD files: decimal, hexadecimal with `0x`,
+/// or binary with `#` or `0b`.
+fn parse_number(text: &str) -> Result<u64, SvdError> {
+    let text = text.trim();
+
+    let result = if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16)
+    } else if let Some(binary) = text.strip_prefix('#').or_else(|| text.strip_prefix("0b")) {
+        u64::from_str_radix(binary, 2)
+    } else {
+        text.parse()
+    };
+
+    result.map_err(|_| SvdError::InvalidNumber(text.to_owned()))
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |child| child.has_tag_name(name))
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name)
+        .and_then(|child| child.text())
+        .map(str::trim)
+}
+
+fn required_text<'a>(node: Node<'a, '_>, name: &'static str) -> Result<&'a str, SvdError> {
+    child_text(node, name)
+        .ok_or_else(|| SvdError::MissingElement(name, node.tag_name().name().to_owned()))
+}
+
+fn optional_number(node: Node, name: &str) -> Result<Option<u64>, SvdError> {
+    child_text(node, name).map(parse_number).transpose()
+}
+
+fn required_number(node: Node, name: &'static str) -> Result<u64, SvdError> {
+    parse_number(required_text(node, name)?)
+}
+
+/// The description of an element, with the white space of the XML file removed.
+fn description(node: Node) -> Option<String> {
+    child_text(node, "description")
+        .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Device, SvdError};
+
+    fn device_with_field(field: &str) -> Result<Device, SvdError> {
+        Device::parse(&format!(
+            "<device><peripherals><peripheral>\
+             <name>GPIOA</name><baseAddress>0x40020000</baseAddress>\
+             <registers><register>\
+             <name>MODER</name><addressOffset>0</addressOffset>\
+             <fields><field><name>MODE</name>{}</field></fields>\
+             </register></registers>\
+             </peripheral></peripherals></device>",
+            field
+        ))
+    }
+
+    #[test]
+    fn field_bit_ranges() {
+        let device = device_with_field("<bitRange>[7:4]</bitRange>").unwrap();
+        let field = &device.peripherals[0].registers[0].fields[0];
+        assert_eq!((field.bit_offset, field.bit_width), (4, 4));
+        assert_eq!(field.extract(0xa5), 0xa);
+
+        for invalid in [
+            "<bitRange>[4:7]</bitRange>",
+            "<lsb>7</lsb><msb>4</msb>",
+            "<lsb>0</lsb><msb>0xffffffffffffffff</msb>",
+            "<bitOffset>64</bitOffset>",
+            "<bitOffset>60</bitOffset><bitWidth>8</bitWidth>",
+            "<bitOffset>0</bitOffset><bitWidth>0</bitWidth>",
+        ] {
+            let result = device_with_field(invalid);
+            assert!(
+                matches!(result, Err(SvdError::InvalidBitRange(ref name)) if name == "MODE"),
+                "{}",
+                invalid
+            );
+        }
+    }
+}
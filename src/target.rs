@@ -0,0 +1,165 @@
+//! Access to the debugged target.
+//!
+//! The debugger only talks to the target through the [`TargetAccess`] and [`CoreAccess`]
+//! traits. They are implemented for a `probe_rs::Session`, and for the
+//! [`SimulatedTarget`](crate::simulator::SimulatedTarget), which is used to test the
+//! debugger without any hardware.
+
+use probe_rs::{
+    architecture::arm::SwoConfig,
+    flashing::{download_file, FileDownloadError, Format},
+    Architecture, Core, CoreInformation, CoreRegisterAddress, CoreStatus, Error, MemoryInterface,
+    Session,
+};
+use std::{path::Path, time::Duration};
+
+/// A target which can be debugged.
+pub trait TargetAccess {
+    /// Get access to the core which is debugged.
+    fn core(&mut self) -> Result<Box<dyn CoreAccess + '_>, Error>;
+
+    /// Download the ELF file at `path` into the memory of the target.
+    fn download(&mut self, path: &Path) -> Result<(), FileDownloadError>;
+
+    /// Configure the target and the probe to send trace data over SWO.
+    fn setup_swo(&mut self, config: &SwoConfig) -> Result<(), Error>;
+
+    /// Read the SWO data which was received since the last call.
+    fn read_swo(&mut self) -> Result<Vec<u8>, Error>;
+}
+
+/// Access to a single core of a target.
+pub trait CoreAccess {
+    /// Get the current status of the core.
+    fn status(&mut self) -> Result<CoreStatus, Error>;
+
+    fn halt(&mut self, timeout: Duration) -> Result<CoreInformation, Error>;
+
+    fn run(&mut self) -> Result<(), Error>;
+
+    /// Steps one instruction and then enters halted state again.
+    fn step(&mut self) -> Result<CoreInformation, Error>;
+
+    fn reset_and_halt(&mut self, timeout: Duration) -> Result<CoreInformation, Error>;
+
+    fn read_core_reg(&mut self, address: CoreRegisterAddress) -> Result<u32, Error>;
+
+    fn write_core_reg(&mut self, address: CoreRegisterAddress, value: u32) -> Result<(), Error>;
+
+    /// The register containing the program counter.
+    fn program_counter(&self) -> CoreRegisterAddress;
+
+    /// The register containing the stack pointer.
+    fn stack_pointer(&self) -> CoreRegisterAddress;
+
+    /// The register containing the return address.
+    fn return_address(&self) -> CoreRegisterAddress;
+
+    fn read_8(&mut self, address: u32, data: &mut [u8]) -> Result<(), Error>;
+
+    fn write_8(&mut self, address: u32, data: &[u8]) -> Result<(), Error>;
+
+    fn read_word_32(&mut self, address: u32) -> Result<u32, Error> {
+        let mut data = [0u8; 4];
+        self.read_8(address, &mut data)?;
+
+        Ok(u32::from_le_bytes(data))
+    }
+
+    fn write_word_32(&mut self, address: u32, value: u32) -> Result<(), Error> {
+        self.write_8(address, &value.to_le_bytes())
+    }
+
+    /// Get the number of hardware breakpoints the core supports.
+    fn available_breakpoint_units(&mut self) -> Result<u32, Error>;
+
+    fn set_hw_breakpoint(&mut self, address: u32) -> Result<(), Error>;
+
+    fn clear_hw_breakpoint(&mut self, address: u32) -> Result<(), Error>;
+
+    fn architecture(&self) -> Architecture;
+}
+
+impl TargetAccess for Session {
+    fn core(&mut self) -> Result<Box<dyn CoreAccess + '_>, Error> {
+        Ok(Box::new(Session::core(self, 0)?))
+    }
+
+    fn download(&mut self, path: &Path) -> Result<(), FileDownloadError> {
+        download_file(self, path, Format::Elf)
+    }
+
+    fn setup_swo(&mut self, config: &SwoConfig) -> Result<(), Error> {
+        Session::setup_swv(self, config)
+    }
+
+    fn read_swo(&mut self) -> Result<Vec<u8>, Error> {
+        Session::read_swo(self)
+    }
+}
+
+impl<'probe> CoreAccess for Core<'probe> {
+    fn status(&mut self) -> Result<CoreStatus, Error> {
+        Core::status(self)
+    }
+
+    fn halt(&mut self, timeout: Duration) -> Result<CoreInformation, Error> {
+        Core::halt(self, timeout)
+    }
+
+    fn run(&mut self) -> Result<(), Error> {
+        Core::run(self)
+    }
+
+    fn step(&mut self) -> Result<CoreInformation, Error> {
+        Core::step(self)
+    }
+
+    fn reset_and_halt(&mut self, timeout: Duration) -> Result<CoreInformation, Error> {
+        Core::reset_and_halt(self, timeout)
+    }
+
+    fn read_core_reg(&mut self, address: CoreRegisterAddress) -> Result<u32, Error> {
+        Core::read_core_reg(self, address)
+    }
+
+    fn write_core_reg(&mut self, address: CoreRegisterAddress, value: u32) -> Result<(), Error> {
+        Core::write_core_reg(self, address, value)
+    }
+
+    fn program_counter(&self) -> CoreRegisterAddress {
+        self.registers().program_counter().into()
+    }
+
+    fn stack_pointer(&self) -> CoreRegisterAddress {
+        self.registers().stack_pointer().into()
+    }
+
+    fn return_address(&self) -> CoreRegisterAddress {
+        self.registers().return_address().into()
+    }
+
+    fn read_8(&mut self, address: u32, data: &mut [u8]) -> Result<(), Error> {
+        MemoryInterface::read_8(self, address, data)
+    }
+
+    fn write_8(&mut self, address: u32, data: &[u8]) -> Result<(), Error> {
+        MemoryInterface::write_8(self, address, data)
+    }
+
+    fn available_breakpoint_units(&mut self) -> Result<u32, Error> {
+        Core::get_available_breakpoint_units(self)
+    }
+
+    fn set_hw_breakpoint(&mut self, address: u32) -> Result<(), Error> {
+        Core::set_hw_breakpoint(self, address)
+    }
+
+    fn clear_hw_breakpoint(&mut self, address: u32) -> Result<(), Error> {
+        Core::clear_hw_breakpoint(self, address)
+    }
+
+    fn architecture(&self) -> Architecture {
+        Core::architecture(self)
+    }
+}
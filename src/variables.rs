@@ -0,0 +1,822 @@
+//! Values of variables, and the references which the client uses to expand them.
+//!
+//! Variables are only read from the target when the client requests them. Composite
+//! values like structures, arrays and pointers get a `variablesReference` from the
+//! [`VariableRegistry`], which is used to look up their children later on.
+
+use crate::dap_types;
+use crate::debug_info::{
+    DebugError, DebugInfo, Member, Registers, Type, TypeKind, TypeRef, ValueLocation, Variable,
+    FPSCR, FPU_S0, XPSR,
+};
+use crate::svd;
+use crate::target::CoreAccess;
+use std::convert::TryInto;
+
+/// Maximum number of bytes read for the preview of a string.
+const MAX_STRING_LENGTH: u64 = 256;
+
+/// Something the client can request the child variables of.
+#[derive(Debug, Clone)]
+pub enum VariableContainer {
+    /// The local variables of a stack frame.
+    Locals { frame_id: u64 },
+    /// The static variables of the program.
+    Statics,
+    /// The core registers of a stack frame.
+    Registers { frame_id: u64 },
+    /// The registers of the FPU in a stack frame.
+    FpuRegisters { frame_id: u64 },
+    /// The peripherals of the device, from the SVD file.
+    Peripherals,
+    /// The registers of a peripheral, by its index in the SVD device.
+    Peripheral { peripheral: usize },
+    /// The fields of a peripheral register.
+    PeripheralRegister { peripheral: usize, register: usize },
+    /// The members, elements or the pointee of a variable.
+    Children {
+        variable: Variable,
+        /// Expression which evaluates to the variable.
+        evaluate_name: String,
+    },
+    /// A single variable, like the target of a pointer created in an expression.
+    Variable {
+        variable: Variable,
+        evaluate_name: String,
+    },
+}
+
+/// Hands out the references for all variables shown to the client. The references
+/// are only valid until the core is resumed.
+#[derive(Debug, Default)]
+pub struct VariableRegistry {
+    containers: Vec<VariableContainer>,
+}
+
+impl VariableRegistry {
+    pub fn clear(&mut self) {
+        self.containers.clear();
+    }
+
+    /// Register a container, and return the reference for it.
+    pub fn insert(&mut self, container: VariableContainer) -> i64 {
+        self.containers.push(container);
+
+        // References have to be greater than 0.
+        self.containers.len() as i64
+    }
+
+    pub fn get(&self, reference: i64) -> Option<&VariableContainer> {
+        if reference <= 0 {
+            return None;
+        }
+
+        self.containers.get(reference as usize - 1)
+    }
+}
+
+/// Names of the core registers shown to the client, with their number in [`Registers`].
+const CORE_REGISTERS: [(&str, usize); 17] = [
+    ("R0", 0),
+    ("R1", 1),
+    ("R2", 2),
+    ("R3", 3),
+    ("R4", 4),
+    ("R5", 5),
+    ("R6", 6),
+    ("R7", 7),
+    ("R8", 8),
+    ("R9", 9),
+    ("R10", 10),
+    ("R11", 11),
+    ("R12", 12),
+    ("SP", 13),
+    ("LR", 14),
+    ("PC", 15),
+    ("xPSR", XPSR),
+];
+
+/// Get the number in [`Registers`] of a register name, as used in evaluate names
+/// like `$r0`, `$sp` or `$s0`. Names are not case sensitive.
+pub fn register_by_name(name: &str) -> Option<usize> {
+    let name = name.to_lowercase();
+
+    if let Some((_, register)) = CORE_REGISTERS
+        .iter()
+        .find(|(register_name, _)| register_name.to_lowercase() == name)
+    {
+        return Some(*register);
+    }
+
+    match name.as_str() {
+        "r13" => Some(13),
+        "r14" => Some(14),
+        "r15" => Some(15),
+        "fpscr" => Some(FPSCR),
+        _ => name
+            .strip_prefix('s')
+            .and_then(|index| index.parse::<usize>().ok())
+            .filter(|index| *index < 32)
+            .map(|index| FPU_S0 + index),
+    }
+}
+
+/// Get the core registers of a stack frame. The registers of the FPU are grouped
+/// in a variable, which gets a reference from `registry`.
+pub fn core_registers(
+    registers: &Registers,
+    registry: &mut VariableRegistry,
+    frame_id: u64,
+) -> Vec<dap_types::Variable> {
+    let mut variables: Vec<_> = CORE_REGISTERS
+        .iter()
+        .map(|(name, register)| register_variable(name, registers.get(*register)))
+        .collect();
+
+    if registers.has_fpu() {
+        variables.push(dap_types::Variable {
+            name: "FPU".to_owned(),
+            value: String::new(),
+            type_: None,
+            presentation_hint: None,
+            evaluate_name: None,
+            variables_reference: registry.insert(VariableContainer::FpuRegisters { frame_id }),
+            named_variables: Some(33),
+            indexed_variables: None,
+            memory_reference: None,
+        });
+    }
+
+    variables
+}
+
+/// Get the registers S0 to S31 and FPSCR of a stack frame.
+pub fn fpu_registers(registers: &Registers) -> Vec<dap_types::Variable> {
+    (0..32)
+        .map(|i| register_variable(&format!("S{}", i), registers.get(FPU_S0 + i)))
+        .chain(std::iter::once(register_variable(
+            "FPSCR",
+            registers.get(FPSCR),
+        )))
+        .collect()
+}
+
+fn register_variable(name: &str, value: Option<u32>) -> dap_types::Variable {
+    dap_types::Variable {
+        name: name.to_owned(),
+        // Registers which are not saved by the called functions are unknown in the callers.
+        value: value
+            .map(|value| format!("{:#010x}", value))
+            .unwrap_or_else(|| "<not available>".to_owned()),
+        type_: None,
+        presentation_hint: None,
+        evaluate_name: Some(format!("${}", name.to_lowercase())),
+        variables_reference: 0,
+        named_variables: None,
+        indexed_variables: None,
+        memory_reference: None,
+    }
+}
+
+/// Get the peripherals of a device.
+pub fn peripherals(
+    device: &svd::Device,
+    registry: &mut VariableRegistry,
+) -> Vec<dap_types::Variable> {
+    device
+        .peripherals
+        .iter()
+        .enumerate()
+        .map(|(index, peripheral)| dap_types::Variable {
+            name: peripheral.name.clone(),
+            value: format!("{:#010x}", peripheral.base_address),
+            type_: peripheral.description.clone(),
+            presentation_hint: None,
+            evaluate_name: None,
+            variables_reference: if peripheral.registers.is_empty() {
+                0
+            } else {
+                registry.insert(VariableContainer::Peripheral { peripheral: index })
+            },
+            named_variables: Some(peripheral.registers.len() as i64),
+            indexed_variables: None,
+            memory_reference: Some(format!("{:#010x}", peripheral.base_address)),
+        })
+        .collect()
+}
+
+/// Get the registers of a peripheral, with their current values.
+pub fn peripheral_registers(
+    device: &svd::Device,
+    core: &mut dyn CoreAccess,
+    registry: &mut VariableRegistry,
+    peripheral_index: usize,
+) -> Vec<dap_types::Variable> {
+    let peripheral = match device.peripherals.get(peripheral_index) {
+        Some(peripheral) => peripheral,
+        None => return Vec::new(),
+    };
+
+    peripheral
+        .registers
+        .iter()
+        .enumerate()
+        .map(|(index, register)| {
+            let address = peripheral.base_address + register.address_offset;
+
+            let value = match read_register(core, address, register) {
+                Ok(Some(value)) => format!(
+                    "{:#0width$x}",
+                    value,
+                    width = register.size as usize / 4 + 2
+                ),
+                Ok(None) => "<not readable>".to_owned(),
+                Err(e) => format!("<{}>", e),
+            };
+
+            dap_types::Variable {
+                name: register.name.clone(),
+                value,
+                type_: register.description.clone(),
+                presentation_hint: None,
+                evaluate_name: Some(format!("*(u{}*){:#010x}", register.size, address)),
+                variables_reference: if register.fields.is_empty() {
+                    0
+                } else {
+                    registry.insert(VariableContainer::PeripheralRegister {
+                        peripheral: peripheral_index,
+                        register: index,
+                    })
+                },
+                named_variables: Some(register.fields.len() as i64),
+                indexed_variables: None,
+                memory_reference: Some(format!("{:#010x}", address)),
+            }
+        })
+        .collect()
+}
+
+/// Get the fields of a peripheral register, decoded with their enumerated values.
+pub fn register_fields(
+    device: &svd::Device,
+    core: &mut dyn CoreAccess,
+    peripheral_index: usize,
+    register_index: usize,
+) -> Result<Vec<dap_types::Variable>, DebugError> {
+    let peripheral = &device.peripherals[peripheral_index];
+    let register = &peripheral.registers[register_index];
+
+    let value = read_register(
+        core,
+        peripheral.base_address + register.address_offset,
+        register,
+    )?;
+
+    Ok(register
+        .fields
+        .iter()
+        .map(|field| {
+            let value = match value {
+                Some(value) => {
+                    let value = field.extract(value);
+
+                    match field.decode(value) {
+                        Some(enumerated) => format!("{} ({})", enumerated.name, value),
+                        None if field.bit_width == 1 => value.to_string(),
+                        None => format!("{:#x}", value),
+                    }
+                }
+                None => "<not readable>".to_owned(),
+            };
+
+            let bits = if field.bit_width == 1 {
+                format!("[{}]", field.bit_offset)
+            } else {
+                format!(
+                    "[{}:{}]",
+                    field.bit_offset + field.bit_width - 1,
+                    field.bit_offset
+                )
+            };
+
+            dap_types::Variable {
+                name: field.name.clone(),
+                value,
+                type_: Some(match &field.description {
+                    Some(description) => format!("{} {}", bits, description),
+                    None => bits,
+                }),
+                presentation_hint: None,
+                evaluate_name: None,
+                variables_reference: 0,
+                named_variables: None,
+                indexed_variables: None,
+                memory_reference: None,
+            }
+        })
+        .collect())
+}
+
+/// Read a peripheral register. Registers which are write-only, or have side
+/// effects when they are read, are not read.
+fn read_register(
+    core: &mut dyn CoreAccess,
+    address: u32,
+    register: &svd::Register,
+) -> Result<Option<u64>, DebugError> {
+    if !register.is_readable() {
+        return Ok(None);
+    }
+
+    let size = u64::from(register.size / 8);
+
+    read_unsigned(core, &ValueLocation::Memory(address), size).map(Some)
+}
+
+/// Which children of a variable are requested, as given in the `variables` request.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChildFilter<'a> {
+    /// Either `indexed` or `named`.
+    pub filter: Option<&'a str>,
+    pub start: Option<i64>,
+    pub count: Option<i64>,
+}
+
+impl ChildFilter<'_> {
+    /// The range of the children which are requested, out of `len` children.
+    fn range(&self, len: u64) -> std::ops::Range<u64> {
+        let start = (self.start.unwrap_or(0).max(0) as u64).min(len);
+
+        let end = match self.count {
+            Some(count) if count > 0 => (start + count as u64).min(len),
+            _ => len,
+        };
+
+        start..end
+    }
+}
+
+/// The children of a variable.
+enum Children {
+    None,
+    Named(Vec<(Variable, String)>),
+    Indexed {
+        element: Option<TypeRef>,
+        location: ValueLocation,
+        count: u64,
+        stride: u64,
+    },
+}
+
+/// A variable, formatted to be shown to the client.
+struct Formatted {
+    value: String,
+    type_name: Option<String>,
+    children: Children,
+}
+
+/// Convert a variable into the form it is sent to the client. If it has children,
+/// it gets a reference from `registry`.
+pub fn to_dap_variable(
+    debug_info: &DebugInfo,
+    core: &mut dyn CoreAccess,
+    registry: &mut VariableRegistry,
+    variable: &Variable,
+    evaluate_name: String,
+) -> dap_types::Variable {
+    let formatted = match format_variable(debug_info, core, variable, &evaluate_name) {
+        Ok(formatted) => formatted,
+        Err(e) => Formatted {
+            value: format!("<{}>", e),
+            type_name: variable
+                .type_ref
+                .and_then(|type_ref| debug_info.type_name(type_ref).ok()),
+            children: Children::None,
+        },
+    };
+
+    let (named_variables, indexed_variables) = match &formatted.children {
+        Children::None => (None, None),
+        Children::Named(children) => (Some(children.len() as i64), None),
+        Children::Indexed { count, .. } => (None, Some(*count as i64)),
+    };
+
+    let variables_reference = match formatted.children {
+        Children::None => 0,
+        _ => registry.insert(VariableContainer::Children {
+            variable: variable.clone(),
+            evaluate_name: evaluate_name.clone(),
+        }),
+    };
+
+    let memory_reference =
+        memory_reference(debug_info, core, variable).map(|address| format!("{:#010x}", address));
+
+    dap_types::Variable {
+        name: variable.name.clone(),
+        value: formatted.value,
+        type_: formatted.type_name,
+        presentation_hint: None,
+        evaluate_name: Some(evaluate_name),
+        variables_reference,
+        named_variables,
+        indexed_variables,
+        memory_reference,
+    }
+}
+
+/// The address of the memory which a variable is stored in. For pointers, this is
+/// the address they point to, which is usually more interesting.
+fn memory_reference(
+    debug_info: &DebugInfo,
+    core: &mut dyn CoreAccess,
+    variable: &Variable,
+) -> Option<u32> {
+    let ty = variable
+        .type_ref
+        .and_then(|type_ref| debug_info.type_info(type_ref).ok());
+
+    if let Some(Type {
+        kind: TypeKind::Pointer(_),
+        size,
+        ..
+    }) = ty
+    {
+        return read_unsigned(core, &variable.location, if size == 0 { 4 } else { size })
+            .ok()
+            .map(|address| address as u32);
+    }
+
+    match variable.location {
+        ValueLocation::Memory(address) => Some(address),
+        _ => None,
+    }
+}
+
+/// Get the requested children of a variable, together with their evaluate names.
+pub fn children(
+    debug_info: &DebugInfo,
+    core: &mut dyn CoreAccess,
+    variable: &Variable,
+    evaluate_name: &str,
+    filter: ChildFilter,
+) -> Result<Vec<(Variable, String)>, DebugError> {
+    let children = format_variable(debug_info, core, variable, evaluate_name)?.children;
+
+    Ok(match children {
+        Children::Named(children) if filter.filter != Some("indexed") => {
+            let range = filter.range(children.len() as u64);
+
+            children
+                .into_iter()
+                .skip(range.start as usize)
+                .take((range.end - range.start) as usize)
+                .collect()
+        }
+        Children::Indexed {
+            element,
+            location,
+            count,
+            stride,
+        } if filter.filter != Some("named") => filter
+            .range(count)
+            .map(|index| {
+                let child = Variable {
+                    name: format!("[{}]", index),
+                    type_ref: element,
+                    location: location.offset(index * stride),
+                };
+
+                (child, format!("{}[{}]", operand(evaluate_name), index))
+            })
+            .collect(),
+        _ => Vec::new(),
+    })
+}
+
+fn format_variable(
+    debug_info: &DebugInfo,
+    core: &mut dyn CoreAccess,
+    variable: &Variable,
+    evaluate_name: &str,
+) -> Result<Formatted, DebugError> {
+    let type_ref = match variable.type_ref {
+        Some(type_ref) => type_ref,
+        None => {
+            return Ok(Formatted {
+                value: "<unknown type>".to_owned(),
+                type_name: None,
+                children: Children::None,
+            })
+        }
+    };
+
+    let ty = debug_info.type_info(type_ref)?;
+
+    if variable.location == ValueLocation::Unavailable {
+        return Ok(Formatted {
+            value: "<optimized out>".to_owned(),
+            type_name: Some(ty.name),
+            children: Children::None,
+        });
+    }
+
+    let location = &variable.location;
+
+    let (value, children) = match &ty.kind {
+        TypeKind::Base(encoding) => {
+            let bytes = read_value(core, location, ty.size)?;
+
+            (format_base(*encoding, &bytes), Children::None)
+        }
+        TypeKind::Pointer(pointee) => {
+            let address = read_unsigned(core, location, ty.size)?;
+
+            let children = match pointee {
+                Some(pointee) if address != 0 => Children::Named(vec![(
+                    Variable {
+                        name: format!("*{}", variable.name),
+                        type_ref: Some(*pointee),
+                        location: ValueLocation::Memory(address as u32),
+                    },
+                    format!("*{}", evaluate_name),
+                )]),
+                _ => Children::None,
+            };
+
+            (format!("{:#010x}", address), children)
+        }
+        TypeKind::Array {
+            element,
+            count,
+            stride,
+        } => (
+            ty.name.clone(),
+            Children::Indexed {
+                element: *element,
+                location: location.clone(),
+                count: *count,
+                stride: *stride,
+            },
+        ),
+        TypeKind::Enumeration(enumerators) => {
+            let value = read_unsigned(core, location, ty.size)?;
+            let mask = mask(ty.size);
+
+            let name = enumerators
+                .iter()
+                .find(|(_, enumerator)| enumerator & mask == value)
+                .map(|(name, _)| name.clone())
+                .unwrap_or_else(|| value.to_string());
+
+            (name, Children::None)
+        }
+        TypeKind::Structure { members, variants } => {
+            if let Some(formatted) = format_rust_slice(debug_info, core, &ty, location, members)? {
+                return Ok(formatted);
+            }
+
+            let mut value = ty.name.clone();
+            let mut children = members_of(location, members, evaluate_name);
+
+            if let Some(variants) = variants {
+                let discriminant = match &variants.discriminant {
+                    Some(discriminant) => {
+                        let size = match discriminant.type_ref {
+                            Some(type_ref) => debug_info.type_info(type_ref)?.size,
+                            None => 0,
+                        };
+
+                        Some((
+                            read_unsigned(core, &location.offset(discriminant.offset), size)?,
+                            mask(size),
+                        ))
+                    }
+                    None => None,
+                };
+
+                // The variant with the matching discriminant, or the default variant.
+                let variant = variants
+                    .variants
+                    .iter()
+                    .find(|variant| match (variant.discriminant_value, discriminant) {
+                        (Some(expected), Some((actual, mask))) => expected & mask == actual,
+                        _ => false,
+                    })
+                    .or_else(|| {
+                        variants
+                            .variants
+                            .iter()
+                            .find(|variant| variant.discriminant_value.is_none())
+                    });
+
+                if let Some(variant) = variant {
+                    value = variant.member.name.clone();
+
+                    let variant_location = location.offset(variant.member.offset);
+
+                    if let Some(variant_type) = variant.member.type_ref {
+                        if let TypeKind::Structure { members, .. } =
+                            debug_info.type_info(variant_type)?.kind
+                        {
+                            children.extend(members_of(&variant_location, &members, evaluate_name));
+                        }
+                    }
+                }
+            }
+
+            let children = if children.is_empty() {
+                Children::None
+            } else {
+                Children::Named(children)
+            };
+
+            (value, children)
+        }
+        TypeKind::Unknown => ("<unknown type>".to_owned(), Children::None),
+    };
+
+    Ok(Formatted {
+        value,
+        type_name: Some(ty.name),
+        children,
+    })
+}
+
+/// Rust slices and `&str` are structures with a pointer to the data and a length.
+fn format_rust_slice(
+    debug_info: &DebugInfo,
+    core: &mut dyn CoreAccess,
+    ty: &Type,
+    location: &ValueLocation,
+    members: &[Member],
+) -> Result<Option<Formatted>, DebugError> {
+    let is_slice = ty.name.starts_with("&[") || ty.name.starts_with("&mut [");
+
+    if ty.name != "&str" && !is_slice {
+        return Ok(None);
+    }
+
+    let data_ptr = members.iter().find(|m| m.name == "data_ptr");
+    let length = members.iter().find(|m| m.name == "length");
+
+    let (data_ptr, length) = match (data_ptr, length) {
+        (Some(data_ptr), Some(length)) => (data_ptr, length),
+        _ => return Ok(None),
+    };
+
+    let pointer_type = match data_ptr.type_ref {
+        Some(type_ref) => debug_info.type_info(type_ref)?,
+        None => return Ok(None),
+    };
+
+    let element = match pointer_type.kind {
+        TypeKind::Pointer(element) => element,
+        _ => return Ok(None),
+    };
+
+    let address = read_unsigned(core, &location.offset(data_ptr.offset), pointer_type.size)?;
+    let length = read_unsigned(core, &location.offset(length.offset), 4)?;
+
+    if !is_slice {
+        let mut data = vec![0; length.min(MAX_STRING_LENGTH) as usize];
+        core.read_8(address as u32, &mut data)?;
+
+        let mut value = format!("{:?}", String::from_utf8_lossy(&data));
+        if length > MAX_STRING_LENGTH {
+            value.push_str("...");
+        }
+
+        return Ok(Some(Formatted {
+            value,
+            type_name: Some(ty.name.clone()),
+            children: Children::None,
+        }));
+    }
+
+    let stride = match element {
+        Some(element) => debug_info.type_info(element)?.size,
+        None => 0,
+    };
+
+    Ok(Some(Formatted {
+        value: format!("len: {}", length),
+        type_name: Some(ty.name.clone()),
+        children: Children::Indexed {
+            element,
+            location: ValueLocation::Memory(address as u32),
+            count: length,
+            stride,
+        },
+    }))
+}
+
+fn members_of(
+    location: &ValueLocation,
+    members: &[Member],
+    evaluate_name: &str,
+) -> Vec<(Variable, String)> {
+    members
+        .iter()
+        .map(|member| {
+            let child = Variable {
+                name: member.name.clone(),
+                type_ref: member.type_ref,
+                location: location.offset(member.offset),
+            };
+
+            (child, format!("{}.{}", operand(evaluate_name), member.name))
+        })
+        .collect()
+}
+
+/// Put a dereference in parentheses, so that a field access or index applies to its result.
+fn operand(evaluate_name: &str) -> String {
+    if evaluate_name.starts_with('*') {
+        format!("({})", evaluate_name)
+    } else {
+        evaluate_name.to_owned()
+    }
+}
+
+/// Read `size` bytes of a value.
+pub fn read_value(
+    core: &mut dyn CoreAccess,
+    location: &ValueLocation,
+    size: u64,
+) -> Result<Vec<u8>, DebugError> {
+    let size = size as usize;
+
+    match location {
+        ValueLocation::Memory(address) => {
+            let mut data = vec![0; size];
+            core.read_8(*address, &mut data)?;
+            Ok(data)
+        }
+        ValueLocation::Register { value, .. } => {
+            let mut data = value.to_le_bytes().to_vec();
+            data.resize(size, 0);
+            Ok(data)
+        }
+        ValueLocation::Value(bytes) => {
+            let mut data = bytes.clone();
+            data.resize(size, 0);
+            Ok(data)
+        }
+        ValueLocation::Unavailable => Err(DebugError::UnsupportedExpression(
+            "Value is not available".to_owned(),
+        )),
+    }
+}
+
+/// Read an unsigned integer of up to 8 bytes.
+pub fn read_unsigned(
+    core: &mut dyn CoreAccess,
+    location: &ValueLocation,
+    size: u64,
+) -> Result<u64, DebugError> {
+    let mut data = read_value(core, location, size.min(8))?;
+    data.resize(8, 0);
+
+    Ok(u64::from_le_bytes(data[..].try_into().unwrap()))
+}
+
+/// Mask for the bits of a value with `size` bytes.
+fn mask(size: u64) -> u64 {
+    if size >= 8 {
+        u64::MAX
+    } else {
+        (1 << (size * 8)) - 1
+    }
+}
+
+/// Format the value of a base type, given by its DWARF encoding.
+pub fn format_base(encoding: gimli::DwAte, bytes: &[u8]) -> String {
+    let mut data = [0u8; 16];
+    let len = bytes.len().min(16);
+    data[..len].copy_from_slice(&bytes[..len]);
+    let unsigned = u128::from_le_bytes(data);
+
+    // Sign extend the value from its actual size.
+    let signed = if len == 0 || len == 16 {
+        unsigned as i128
+    } else {
+        let shift = 128 - len * 8;
+        ((unsigned << shift) as i128) >> shift
+    };
+
+    match encoding {
+        gimli::DW_ATE_boolean => (unsigned != 0).to_string(),
+        gimli::DW_ATE_signed => signed.to_string(),
+        gimli::DW_ATE_unsigned => unsigned.to_string(),
+        gimli::DW_ATE_float if len == 4 => f32::from_bits(unsigned as u32).to_string(),
+        gimli::DW_ATE_float if len == 8 => f64::from_bits(unsigned as u64).to_string(),
+        gimli::DW_ATE_UTF => match std::char::from_u32(unsigned as u32) {
+            Some(c) => format!("{:?}", c),
+            None => format!("{:#x}", unsigned),
+        },
+        gimli::DW_ATE_signed_char => format!("{} {:?}", signed, unsigned as u8 as char),
+        gimli::DW_ATE_unsigned_char => format!("{} {:?}", unsigned, unsigned as u8 as char),
+        _ => format!("{:#x}", unsigned),
+    }
+}
@@ -0,0 +1,724 @@
+//! Helpers to run the debugger against a simulated target.
+
+// Not every test uses all of the helpers.
+#![allow(dead_code)]
+
+use debugserver_types::Request;
+use gimli::{
+    write::{
+        Address, AttributeValue, CallFrameInstruction, CommonInformationEntry, DwarfUnit,
+        EndianVec, Expression, FrameDescriptionEntry, FrameTable, LineProgram, LineString,
+        Sections, UnitEntryId,
+    },
+    Encoding, Format, LineEncoding, LittleEndian, Register,
+};
+use object::{
+    write::{Object, StandardSegment, Symbol, SymbolSection},
+    Architecture, BinaryFormat, Endianness, SectionKind, SymbolFlags, SymbolKind, SymbolScope,
+};
+use probe_rs_debugadapter::{
+    debug_adapter::{DebugAdapter, DebugAdapterMessage},
+    debugger::{Debugger, HandleResult},
+    simulator::SimulatedTarget,
+};
+use serde_json::{json, Value};
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    path::PathBuf,
+    rc::Rc,
+    sync::OnceLock,
+};
+
+pub const SP: u16 = 13;
+pub const LR: u16 = 14;
+pub const PC: u16 = 15;
+
+/// Start of the `main` function in the test program.
+pub const MAIN: u32 = 0x0800_0100;
+/// Start of the `add_one` function in the test program.
+pub const ADD_ONE: u32 = 0x0800_0140;
+/// Address of the statement in line 21 of the test program.
+pub const LINE_21: u32 = 0x0800_0146;
+/// Start of the `HardFault` handler, which has no debug information.
+pub const HARD_FAULT: u32 = 0x0800_0180;
+/// Start of the panic handler `rust_begin_unwind`, which has no debug information.
+pub const PANIC_HANDLER: u32 = 0x0800_01a0;
+/// Start of `DefaultHandler_`, which has no debug information.
+pub const DEFAULT_HANDLER: u32 = 0x0800_01c0;
+
+pub const RAM_START: u32 = 0x2000_0000;
+pub const RAM_SIZE: usize = 0x1000;
+
+/// Address of the `point` variable of `main`.
+pub const POINT: u32 = RAM_START + 0x100;
+/// Address of the `buffer` variable of `main`, an array of 100 `u16`.
+pub const BUFFER: u32 = RAM_START + 0x200;
+
+/// Address of the static `app::TICKS: u32`.
+pub const TICKS: u32 = RAM_START + 0x120;
+/// Address of the static `app::ORIGIN: Point`.
+pub const ORIGIN: u32 = RAM_START + 0x128;
+
+/// Address of the RTT control block, the symbol `_SEGGER_RTT`.
+pub const RTT_CONTROL_BLOCK: u32 = RAM_START + 0x400;
+
+/// Format strings of the defmt log messages in the test program, by their index.
+pub const DEFMT_STRINGS: [(u16, &str, &str); 4] = [
+    (0, "defmt_encoding", "raw"),
+    (1, "defmt_timestamp", "{=u32:ms}"),
+    (2, "defmt_info", "temperature = {=i16}, sensor = {=str}"),
+    (3, "defmt_warn", "retry {=u8} of {=u8}"),
+];
+
+/// Source lines of the log messages with the indices 2 and 3.
+pub const DEFMT_LINES: [(u16, u64); 2] = [(2, 12), (3, 13)];
+
+/// Source file of the test program.
+pub const SOURCE_PATH: &str = "/work/app/src/main.rs";
+
+/// Build an ELF file with debug information for the following program:
+///
+/// ```text
+/// 10 fn main() -> ! {
+/// 11     let mut counter: u32 = 0;
+///        // Further locals of main, which are stored in RAM:
+///        // point: Point { x: i32, y: i32 }, buffer: [u16; 100], pointer: &Point,
+///        // state: Option<u32>, mode: Mode (`enum Mode { Slow, Fast }`)
+/// 12     loop {
+/// 13         counter = add_one(counter);
+/// 14     }
+/// ...
+/// 16 static mut TICKS: u32 = 0;
+/// 17 static ORIGIN: Point = Point { x: 0, y: 0 };
+/// ...
+/// 20 fn add_one(value: u32) -> u32 {
+/// 21     value + 1
+/// 22 }
+/// ```
+///
+/// The program is compiled for `0x0800_0000`, and contains no actual code, only the
+/// symbols `app::main` and `app::add_one`, and the symbols `HardFault`, `rust_begin_unwind`
+/// and `DefaultHandler_` without debug information. The RTT control block is at the
+/// data symbol `_SEGGER_RTT`. The `.defmt` section contains `DEFMT_STRINGS`, with the
+/// log statements at `DEFMT_LINES` in `main`.
+///
+/// The file is only written once, so that tests running in parallel don't read it
+/// while another test rewrites it.
+pub fn test_program() -> PathBuf {
+    static PATH: OnceLock<PathBuf> = OnceLock::new();
+
+    PATH.get_or_init(write_test_program).clone()
+}
+
+fn write_test_program() -> PathBuf {
+    let encoding = Encoding {
+        format: Format::Dwarf32,
+        version: 4,
+        address_size: 4,
+    };
+
+    let mut dwarf = DwarfUnit::new(encoding);
+
+    let mut line_program = LineProgram::new(
+        encoding,
+        LineEncoding::default(),
+        LineString::String(b"/work/app".to_vec()),
+        LineString::String(b"src/main.rs".to_vec()),
+        None,
+    );
+    let directory = line_program.add_directory(LineString::String(b"src".to_vec()));
+    let file = line_program.add_file(LineString::String(b"main.rs".to_vec()), directory, None);
+
+    line_program.begin_sequence(Some(Address::Constant(u64::from(MAIN))));
+    for (address, line) in &[
+        (0x100, 10),
+        (0x104, 11),
+        (0x10a, 12),
+        (0x110, 13),
+        (0x114, 14),
+        (0x140, 20),
+        (0x146, 21),
+        (0x14c, 22),
+    ] {
+        let row = line_program.row();
+        row.address_offset = address - 0x100;
+        row.file = file;
+        row.line = *line;
+        line_program.generate_row();
+    }
+    line_program.end_sequence(0x60);
+
+    dwarf.unit.line_program = line_program;
+
+    let root = dwarf.unit.root();
+    let cu = dwarf.unit.get_mut(root);
+    cu.set(
+        gimli::DW_AT_name,
+        AttributeValue::String(b"src/main.rs".to_vec()),
+    );
+    cu.set(
+        gimli::DW_AT_comp_dir,
+        AttributeValue::String(b"/work/app".to_vec()),
+    );
+    cu.set(
+        gimli::DW_AT_low_pc,
+        AttributeValue::Address(Address::Constant(u64::from(MAIN))),
+    );
+    cu.set(gimli::DW_AT_high_pc, AttributeValue::Udata(0x60));
+
+    let u32_type = dwarf.unit.add(root, gimli::DW_TAG_base_type);
+    let entry = dwarf.unit.get_mut(u32_type);
+    entry.set(gimli::DW_AT_name, AttributeValue::String(b"u32".to_vec()));
+    entry.set(gimli::DW_AT_byte_size, AttributeValue::Data1(4));
+    entry.set(
+        gimli::DW_AT_encoding,
+        AttributeValue::Encoding(gimli::DW_ATE_unsigned),
+    );
+
+    let namespace = dwarf.unit.add(root, gimli::DW_TAG_namespace);
+    dwarf
+        .unit
+        .get_mut(namespace)
+        .set(gimli::DW_AT_name, AttributeValue::String(b"app".to_vec()));
+
+    let mut frame_base = Expression::new();
+    frame_base.op(gimli::DW_OP_call_frame_cfa);
+
+    let main = dwarf.unit.add(namespace, gimli::DW_TAG_subprogram);
+    let entry = dwarf.unit.get_mut(main);
+    entry.set(gimli::DW_AT_name, AttributeValue::String(b"main".to_vec()));
+    entry.set(
+        gimli::DW_AT_low_pc,
+        AttributeValue::Address(Address::Constant(u64::from(MAIN))),
+    );
+    entry.set(gimli::DW_AT_high_pc, AttributeValue::Udata(0x40));
+    entry.set(
+        gimli::DW_AT_frame_base,
+        AttributeValue::Exprloc(frame_base.clone()),
+    );
+
+    let counter = dwarf.unit.add(main, gimli::DW_TAG_variable);
+    let entry = dwarf.unit.get_mut(counter);
+    let mut location = Expression::new();
+    location.op_fbreg(-12);
+    entry.set(
+        gimli::DW_AT_name,
+        AttributeValue::String(b"counter".to_vec()),
+    );
+    entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(u32_type));
+    entry.set(gimli::DW_AT_location, AttributeValue::Exprloc(location));
+
+    let point_type = add_composite_variables(&mut dwarf, root, main, u32_type);
+
+    add_static(&mut dwarf, namespace, "TICKS", u32_type, TICKS);
+    add_static(&mut dwarf, namespace, "ORIGIN", point_type, ORIGIN);
+
+    // The statics of the defmt log statements are in the namespace of the function.
+    let main_namespace = dwarf.unit.add(namespace, gimli::DW_TAG_namespace);
+    dwarf
+        .unit
+        .get_mut(main_namespace)
+        .set(gimli::DW_AT_name, AttributeValue::String(b"main".to_vec()));
+
+    for (index, line) in &DEFMT_LINES {
+        let variable = dwarf.unit.add(main_namespace, gimli::DW_TAG_variable);
+        let entry = dwarf.unit.get_mut(variable);
+        let mut location = Expression::new();
+        location.op_addr(Address::Constant(u64::from(*index)));
+        entry.set(
+            gimli::DW_AT_name,
+            AttributeValue::String(b"DEFMT_LOG_STATEMENT".to_vec()),
+        );
+        entry.set(gimli::DW_AT_location, AttributeValue::Exprloc(location));
+        entry.set(
+            gimli::DW_AT_decl_file,
+            AttributeValue::FileIndex(Some(file)),
+        );
+        entry.set(gimli::DW_AT_decl_line, AttributeValue::Udata(*line));
+    }
+
+    let add_one = dwarf.unit.add(namespace, gimli::DW_TAG_subprogram);
+    let entry = dwarf.unit.get_mut(add_one);
+    entry.set(
+        gimli::DW_AT_name,
+        AttributeValue::String(b"add_one".to_vec()),
+    );
+    entry.set(
+        gimli::DW_AT_low_pc,
+        AttributeValue::Address(Address::Constant(u64::from(ADD_ONE))),
+    );
+    entry.set(gimli::DW_AT_high_pc, AttributeValue::Udata(0x20));
+    entry.set(gimli::DW_AT_frame_base, AttributeValue::Exprloc(frame_base));
+
+    let value = dwarf.unit.add(add_one, gimli::DW_TAG_formal_parameter);
+    let entry = dwarf.unit.get_mut(value);
+    let mut location = Expression::new();
+    location.op_reg(Register(0));
+    entry.set(gimli::DW_AT_name, AttributeValue::String(b"value".to_vec()));
+    entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(u32_type));
+    entry.set(gimli::DW_AT_location, AttributeValue::Exprloc(location));
+
+    let mut sections = Sections::new(EndianVec::new(LittleEndian));
+    dwarf.write(&mut sections).unwrap();
+
+    // Both functions start with `push {r7, lr}`, `main` also reserves 8 bytes for locals.
+    let mut frames = FrameTable::default();
+    let mut cie = CommonInformationEntry::new(encoding, 2, -4, Register(14));
+    cie.add_instruction(CallFrameInstruction::Cfa(Register(13), 0));
+    let cie = frames.add_cie(cie);
+
+    let mut fde = FrameDescriptionEntry::new(Address::Constant(u64::from(MAIN)), 0x40);
+    fde.add_instruction(2, CallFrameInstruction::CfaOffset(8));
+    fde.add_instruction(2, CallFrameInstruction::Offset(Register(14), -4));
+    fde.add_instruction(2, CallFrameInstruction::Offset(Register(7), -8));
+    fde.add_instruction(4, CallFrameInstruction::CfaOffset(16));
+    frames.add_fde(cie, fde);
+
+    let mut fde = FrameDescriptionEntry::new(Address::Constant(u64::from(ADD_ONE)), 0x20);
+    fde.add_instruction(2, CallFrameInstruction::CfaOffset(8));
+    fde.add_instruction(2, CallFrameInstruction::Offset(Register(14), -4));
+    fde.add_instruction(2, CallFrameInstruction::Offset(Register(7), -8));
+    frames.add_fde(cie, fde);
+
+    let mut debug_frame = gimli::write::DebugFrame(EndianVec::new(LittleEndian));
+    frames.write_debug_frame(&mut debug_frame).unwrap();
+
+    let mut elf = Object::new(BinaryFormat::Elf, Architecture::Arm, Endianness::Little);
+
+    let debug_segment = elf.segment_name(StandardSegment::Debug).to_vec();
+    let mut add_section = |name: &str, data: &[u8]| {
+        let id = elf.add_section(
+            debug_segment.clone(),
+            name.as_bytes().to_vec(),
+            SectionKind::Debug,
+        );
+        elf.append_section_data(id, data, 1);
+    };
+
+    sections
+        .for_each(|id, data| {
+            if !data.slice().is_empty() {
+                add_section(id.name(), data.slice());
+            }
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+    add_section(".debug_frame", debug_frame.slice());
+
+    // Symbols of the functions, with the Thumb bit set like in a real program.
+    for (name, address, size) in &[
+        ("_ZN3app4main17h0123456789abcdefE", MAIN, 0x40),
+        ("_ZN3app7add_one17hfedcba9876543210E", ADD_ONE, 0x20),
+        ("HardFault", HARD_FAULT, 0x10),
+        ("rust_begin_unwind", PANIC_HANDLER, 0x10),
+        ("DefaultHandler_", DEFAULT_HANDLER, 0x10),
+    ] {
+        elf.add_symbol(Symbol {
+            name: name.as_bytes().to_vec(),
+            value: u64::from(address | 1),
+            size: *size,
+            kind: SymbolKind::Text,
+            scope: SymbolScope::Linkage,
+            weak: false,
+            section: SymbolSection::Absolute,
+            flags: SymbolFlags::None,
+        });
+    }
+
+    elf.add_symbol(Symbol {
+        name: b"_SEGGER_RTT".to_vec(),
+        value: u64::from(RTT_CONTROL_BLOCK),
+        size: 0x78,
+        kind: SymbolKind::Data,
+        scope: SymbolScope::Linkage,
+        weak: false,
+        section: SymbolSection::Absolute,
+        flags: SymbolFlags::None,
+    });
+
+    // The symbols of the format strings have their index as address.
+    let defmt = elf.add_section(Vec::new(), b".defmt".to_vec(), SectionKind::OtherString);
+    elf.append_section_data(defmt, &[0; DEFMT_STRINGS.len()], 1);
+
+    for (index, tag, data) in &DEFMT_STRINGS {
+        let name = json!({
+            "package": "app",
+            "tag": tag,
+            "data": data,
+            "disambiguator": index.to_string(),
+        });
+
+        elf.add_symbol(Symbol {
+            name: name.to_string().into_bytes(),
+            value: u64::from(*index),
+            size: 1,
+            kind: SymbolKind::Data,
+            scope: SymbolScope::Linkage,
+            weak: false,
+            section: SymbolSection::Section(defmt),
+            flags: SymbolFlags::None,
+        });
+    }
+
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("test_program.elf");
+    std::fs::write(&path, elf.write().unwrap()).unwrap();
+
+    path
+}
+
+/// Add the variables with composite types to `main`, and return the type `Point`.
+fn add_composite_variables(
+    dwarf: &mut DwarfUnit,
+    root: UnitEntryId,
+    main: UnitEntryId,
+    u32_type: UnitEntryId,
+) -> UnitEntryId {
+    let i32_type = add_type(dwarf, root, gimli::DW_TAG_base_type, "i32", 4);
+    dwarf.unit.get_mut(i32_type).set(
+        gimli::DW_AT_encoding,
+        AttributeValue::Encoding(gimli::DW_ATE_signed),
+    );
+    let u16_type = add_type(dwarf, root, gimli::DW_TAG_base_type, "u16", 2);
+    dwarf.unit.get_mut(u16_type).set(
+        gimli::DW_AT_encoding,
+        AttributeValue::Encoding(gimli::DW_ATE_unsigned),
+    );
+
+    let point_type = add_type(dwarf, root, gimli::DW_TAG_structure_type, "Point", 8);
+    add_member(dwarf, point_type, "x", i32_type, 0);
+    add_member(dwarf, point_type, "y", i32_type, 4);
+
+    let array_type = dwarf.unit.add(root, gimli::DW_TAG_array_type);
+    dwarf
+        .unit
+        .get_mut(array_type)
+        .set(gimli::DW_AT_type, AttributeValue::UnitRef(u16_type));
+    let subrange = dwarf.unit.add(array_type, gimli::DW_TAG_subrange_type);
+    dwarf
+        .unit
+        .get_mut(subrange)
+        .set(gimli::DW_AT_count, AttributeValue::Udata(100));
+
+    let pointer_type = add_type(dwarf, root, gimli::DW_TAG_pointer_type, "&Point", 4);
+    dwarf
+        .unit
+        .get_mut(pointer_type)
+        .set(gimli::DW_AT_type, AttributeValue::UnitRef(point_type));
+
+    // `Option<u32>`, as emitted by rustc: the variants are members of a variant part.
+    let option_type = add_type(dwarf, root, gimli::DW_TAG_structure_type, "Option<u32>", 8);
+    let variant_part = dwarf.unit.add(option_type, gimli::DW_TAG_variant_part);
+    let discriminant = add_member(dwarf, variant_part, "", u32_type, 0);
+    dwarf
+        .unit
+        .get_mut(variant_part)
+        .set(gimli::DW_AT_discr, AttributeValue::UnitRef(discriminant));
+    for (index, name) in ["None", "Some"].iter().enumerate() {
+        let variant_type = add_type(dwarf, option_type, gimli::DW_TAG_structure_type, name, 8);
+        if *name == "Some" {
+            add_member(dwarf, variant_type, "__0", u32_type, 4);
+        }
+
+        let variant = dwarf.unit.add(variant_part, gimli::DW_TAG_variant);
+        dwarf
+            .unit
+            .get_mut(variant)
+            .set(gimli::DW_AT_discr_value, AttributeValue::Data1(index as u8));
+        add_member(dwarf, variant, name, variant_type, 0);
+    }
+
+    let mode_type = add_type(dwarf, root, gimli::DW_TAG_enumeration_type, "Mode", 1);
+    for (value, name) in ["Slow", "Fast"].iter().enumerate() {
+        let enumerator = dwarf.unit.add(mode_type, gimli::DW_TAG_enumerator);
+        let entry = dwarf.unit.get_mut(enumerator);
+        entry.set(
+            gimli::DW_AT_name,
+            AttributeValue::String(name.as_bytes().to_vec()),
+        );
+        entry.set(
+            gimli::DW_AT_const_value,
+            AttributeValue::Udata(value as u64),
+        );
+    }
+
+    for (name, ty, address) in &[
+        ("point", point_type, POINT),
+        ("buffer", array_type, BUFFER),
+        ("pointer", pointer_type, RAM_START + 0x108),
+        ("state", option_type, RAM_START + 0x110),
+        ("mode", mode_type, RAM_START + 0x118),
+    ] {
+        let variable = dwarf.unit.add(main, gimli::DW_TAG_variable);
+        let entry = dwarf.unit.get_mut(variable);
+        let mut location = Expression::new();
+        location.op_addr(Address::Constant(u64::from(*address)));
+        entry.set(
+            gimli::DW_AT_name,
+            AttributeValue::String(name.as_bytes().to_vec()),
+        );
+        entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(*ty));
+        entry.set(gimli::DW_AT_location, AttributeValue::Exprloc(location));
+    }
+
+    point_type
+}
+
+fn add_static(
+    dwarf: &mut DwarfUnit,
+    namespace: UnitEntryId,
+    name: &str,
+    ty: UnitEntryId,
+    address: u32,
+) {
+    let variable = dwarf.unit.add(namespace, gimli::DW_TAG_variable);
+    let entry = dwarf.unit.get_mut(variable);
+    let mut location = Expression::new();
+    location.op_addr(Address::Constant(u64::from(address)));
+    entry.set(
+        gimli::DW_AT_name,
+        AttributeValue::String(name.as_bytes().to_vec()),
+    );
+    entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(ty));
+    entry.set(gimli::DW_AT_location, AttributeValue::Exprloc(location));
+}
+
+fn add_type(
+    dwarf: &mut DwarfUnit,
+    parent: UnitEntryId,
+    tag: gimli::DwTag,
+    name: &str,
+    size: u8,
+) -> UnitEntryId {
+    let id = dwarf.unit.add(parent, tag);
+    let entry = dwarf.unit.get_mut(id);
+    entry.set(
+        gimli::DW_AT_name,
+        AttributeValue::String(name.as_bytes().to_vec()),
+    );
+    entry.set(gimli::DW_AT_byte_size, AttributeValue::Data1(size));
+    id
+}
+
+fn add_member(
+    dwarf: &mut DwarfUnit,
+    parent: UnitEntryId,
+    name: &str,
+    ty: UnitEntryId,
+    offset: u8,
+) -> UnitEntryId {
+    let id = dwarf.unit.add(parent, gimli::DW_TAG_member);
+    let entry = dwarf.unit.get_mut(id);
+    if !name.is_empty() {
+        entry.set(
+            gimli::DW_AT_name,
+            AttributeValue::String(name.as_bytes().to_vec()),
+        );
+    }
+    entry.set(gimli::DW_AT_type, AttributeValue::UnitRef(ty));
+    entry.set(
+        gimli::DW_AT_data_member_location,
+        AttributeValue::Data1(offset),
+    );
+    id
+}
+
+/// Output of the debug adapter, shared between the adapter and the test.
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Sends requests to the debugger, and collects the messages it sends back.
+pub struct TestClient {
+    pub target: SimulatedTarget,
+    debugger: Debugger,
+    adapter: DebugAdapter<SharedBuffer>,
+    output: SharedBuffer,
+    seq: i64,
+    /// Events received since the last call to `take_events`.
+    events: Vec<Value>,
+}
+
+impl TestClient {
+    /// Create a client for a debugger connected to a simulated target, with
+    /// RAM at `RAM_START`.
+    pub fn new() -> TestClient {
+        let target = SimulatedTarget::new();
+        target.add_memory(RAM_START, vec![0; RAM_SIZE]);
+
+        let connected_target = target.clone();
+        let debugger = Debugger::with_connector(
+            env!("CARGO_TARGET_TMPDIR"),
+            Box::new(move |_chip| Ok(Box::new(connected_target.clone()))),
+        );
+
+        let output = SharedBuffer::default();
+
+        TestClient {
+            target,
+            debugger,
+            adapter: DebugAdapter::new(output.clone()),
+            output,
+            seq: 0,
+            events: Vec::new(),
+        }
+    }
+
+    /// Send a request, and return the response to it.
+    pub fn request(&mut self, command: &str, arguments: Value) -> Value {
+        self.seq += 1;
+
+        let request: Request = serde_json::from_value(json!({
+            "seq": self.seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        }))
+        .unwrap();
+
+        self.debugger
+            .handle(&mut self.adapter, &DebugAdapterMessage::Request(request))
+            .unwrap();
+
+        let mut response = None;
+
+        for message in self.take_messages() {
+            if message["type"] == "response" && message["request_seq"] == self.seq {
+                response = Some(message);
+            } else if message["type"] == "event" {
+                self.events.push(message);
+            }
+        }
+
+        response.unwrap_or_else(|| panic!("No response to request '{}'", command))
+    }
+
+    /// Let the debugger check the status of the core.
+    pub fn poll(&mut self) -> HandleResult {
+        let result = self.debugger.poll_core(&mut self.adapter).unwrap();
+
+        let messages = self.take_messages();
+        self.events.extend(messages);
+
+        result
+    }
+
+    /// End the session, like the debug adapter does when its message loop stops.
+    pub fn terminate(&mut self) {
+        self.debugger.terminate(&mut self.adapter).unwrap();
+
+        let messages = self.take_messages();
+        self.events.extend(messages);
+    }
+
+    /// Events which were sent by the debugger since the last call.
+    pub fn take_events(&mut self) -> Vec<Value> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// `stopped` events which were sent by the debugger since the last call to `take_events`.
+    pub fn take_stopped_events(&mut self) -> Vec<Value> {
+        self.take_events()
+            .into_iter()
+            .filter(|event| event["event"] == "stopped")
+            .collect()
+    }
+
+    /// Attach to the simulated target, with debug information from the test program.
+    pub fn attach(&mut self) -> Value {
+        self.attach_with(json!({}))
+    }
+
+    /// Attach to the simulated target, with additional arguments for the `attach` request.
+    pub fn attach_with(&mut self, arguments: Value) -> Value {
+        let program = test_program();
+
+        let mut request = json!({
+            "program": program,
+            "chip": "simulated",
+        });
+        for (key, value) in arguments.as_object().unwrap() {
+            request[key] = value.clone();
+        }
+
+        self.request("attach", request)
+    }
+
+    /// Flash the test program to the simulated target, with additional arguments for
+    /// the `launch` request.
+    pub fn launch_with(&mut self, arguments: Value) -> Value {
+        let program = test_program();
+
+        let mut request = json!({
+            "program": program,
+            "chip": "simulated",
+        });
+        for (key, value) in arguments.as_object().unwrap() {
+            request[key] = value.clone();
+        }
+
+        self.request("launch", request)
+    }
+
+    fn take_messages(&mut self) -> Vec<Value> {
+        let data = std::mem::take(&mut *self.output.0.borrow_mut());
+        let mut data = &data[..];
+        let mut messages = Vec::new();
+
+        while !data.is_empty() {
+            let header_end = data
+                .windows(4)
+                .position(|w| w == b"\r\n\r\n")
+                .expect("Missing end of header");
+            let header = std::str::from_utf8(&data[..header_end]).unwrap();
+            let len: usize = header
+                .trim_start_matches("Content-Length: ")
+                .parse()
+                .unwrap();
+
+            let body = &data[header_end + 4..header_end + 4 + len];
+            messages.push(serde_json::from_slice(body).unwrap());
+
+            data = &data[header_end + 4 + len..];
+        }
+
+        messages
+    }
+}
+
+/// Halt the target in `add_one`, called from line 13 of `main`, with `counter == 7`.
+pub fn halt_in_add_one(target: &SimulatedTarget) {
+    let sp = RAM_START + 0xfe8;
+
+    // Frame of `add_one`: saved r7 and lr
+    target.write_memory(sp, &0x2000_0ff8u32.to_le_bytes());
+    target.write_memory(sp + 4, &0x0800_0115u32.to_le_bytes());
+
+    // Frame of `main`: counter, saved r7 and lr
+    target.write_memory(sp + 0xc, &7u32.to_le_bytes());
+    target.write_memory(sp + 0x10, &0u32.to_le_bytes());
+    target.write_memory(sp + 0x14, &0xffff_ffffu32.to_le_bytes());
+
+    // Locals of `main` in RAM: point, pointer to point, state = Some(42), mode = Fast
+    target.write_memory(POINT, &(-3i32).to_le_bytes());
+    target.write_memory(POINT + 4, &4i32.to_le_bytes());
+    target.write_memory(RAM_START + 0x108, &POINT.to_le_bytes());
+    target.write_memory(RAM_START + 0x110, &[1, 0, 0, 0, 42, 0, 0, 0]);
+    target.write_memory(RAM_START + 0x118, &[1]);
+    for i in 0..100u16 {
+        target.write_memory(BUFFER + u32::from(i) * 2, &(i * 3).to_le_bytes());
+    }
+
+    target.set_register(0, 7);
+    target.set_register(SP, sp);
+    target.halt_at(LINE_21, probe_rs::HaltReason::Breakpoint);
+}
@@ -29,7 +29,7 @@ fn basic_init_flow() -> Result<(), Box<dyn std::error::Error>> {
 
         println!("Writing input..");
 
-        cmd_in.write(initialize_request.as_bytes())?;
+        cmd_in.write_all(initialize_request.as_bytes())?;
 
         let mut reader = BufReader::new(cmd_out);
 
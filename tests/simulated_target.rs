@@ -0,0 +1,2515 @@
+mod common;
+
+use common::{TestClient, ADD_ONE, LINE_21, LR, MAIN, PC, RAM_SIZE, RAM_START, SOURCE_PATH, SP};
+use probe_rs::{CoreStatus, HaltReason};
+use probe_rs_debugadapter::debugger::HandleResult;
+use serde_json::{json, Value};
+
+#[test]
+fn attach() {
+    let mut client = TestClient::new();
+
+    let response = client.attach();
+
+    assert_eq!(response["success"], true);
+}
+
+fn output(events: &[Value]) -> Vec<&str> {
+    events
+        .iter()
+        .filter(|event| event["event"] == "output")
+        .map(|event| event["body"]["output"].as_str().unwrap())
+        .collect()
+}
+
+#[test]
+fn launch() {
+    let mut client = TestClient::new();
+
+    let response = client.launch_with(json!({}));
+    assert_eq!(response["success"], true);
+
+    let events = client.take_events();
+    let lines = output(&events);
+    assert!(lines.iter().any(|line| line.starts_with("Flashing ")));
+    assert!(lines.contains(&"Flashing finished\n"));
+
+    let response = client.request("configurationDone", json!({}));
+    assert_eq!(response["success"], true);
+
+    let events = client.take_stopped_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["body"]["reason"], "entry");
+    assert_eq!(
+        client.target.status(),
+        CoreStatus::Halted(HaltReason::Request)
+    );
+}
+
+#[test]
+fn launch_without_halt_after_reset() {
+    let mut client = TestClient::new();
+
+    let response = client.launch_with(json!({ "halt_after_reset": false }));
+    assert_eq!(response["success"], true);
+
+    let response = client.request("configurationDone", json!({}));
+    assert_eq!(response["success"], true);
+
+    assert!(client.take_stopped_events().is_empty());
+    assert_eq!(client.target.status(), CoreStatus::Running);
+}
+
+#[test]
+fn launch_with_failed_download() {
+    let mut client = TestClient::new();
+
+    let response = client.launch_with(json!({ "program": "/nonexistent/app" }));
+    assert_eq!(response["success"], false);
+    assert!(!output(&client.take_events()).contains(&"Flashing finished\n"));
+
+    // The session can still be used for another attempt.
+    let response = client.launch_with(json!({}));
+    assert_eq!(response["success"], true);
+
+    let response = client.request("configurationDone", json!({}));
+    assert_eq!(response["success"], true);
+    assert_eq!(client.take_stopped_events().len(), 1);
+}
+
+#[test]
+fn set_breakpoints() {
+    let mut client = TestClient::new();
+    client.attach();
+
+    let response = client.request(
+        "setBreakpoints",
+        json!({
+            "source": { "path": SOURCE_PATH },
+            "breakpoints": [ { "line": 21 }, { "line": 30 } ],
+        }),
+    );
+
+    let breakpoints = &response["body"]["breakpoints"];
+    assert_eq!(breakpoints[0]["verified"], true);
+    assert_eq!(breakpoints[0]["line"], 21);
+    assert_eq!(breakpoints[1]["verified"], false);
+
+    assert_eq!(client.target.breakpoints(), vec![LINE_21]);
+}
+
+#[test]
+fn set_breakpoints_replaces_previous_breakpoints() {
+    let mut client = TestClient::new();
+    client.attach();
+    client.target.set_breakpoint_units(2);
+
+    let set_breakpoints = |client: &mut TestClient, lines: &[u64]| {
+        let breakpoints: Vec<_> = lines.iter().map(|line| json!({ "line": line })).collect();
+
+        let response = client.request(
+            "setBreakpoints",
+            json!({
+                "source": { "path": SOURCE_PATH },
+                "breakpoints": breakpoints,
+            }),
+        );
+
+        response["body"]["breakpoints"].as_array().unwrap().clone()
+    };
+
+    let first = set_breakpoints(&mut client, &[13, 21]);
+    assert_eq!(client.target.breakpoints().len(), 2);
+
+    // Moving breakpoints around must not use up the hardware units.
+    for line in &[10, 11, 12, 22] {
+        let breakpoints = set_breakpoints(&mut client, &[21, *line]);
+
+        assert_eq!(breakpoints[0]["id"], first[1]["id"]);
+        assert_eq!(breakpoints[1]["verified"], true);
+        assert_ne!(breakpoints[1]["id"], first[0]["id"]);
+    }
+
+    set_breakpoints(&mut client, &[]);
+    assert!(client.target.breakpoints().is_empty());
+}
+
+fn set_function_breakpoints(client: &mut TestClient, names: &[&str]) -> Vec<Value> {
+    let breakpoints: Vec<_> = names.iter().map(|name| json!({ "name": name })).collect();
+
+    let response = client.request(
+        "setFunctionBreakpoints",
+        json!({ "breakpoints": breakpoints }),
+    );
+    assert_eq!(response["success"], true);
+
+    response["body"]["breakpoints"].as_array().unwrap().clone()
+}
+
+#[test]
+fn function_breakpoints() {
+    let mut client = TestClient::new();
+    client.attach();
+
+    // Demangled, shortened and mangled names, placed after the prologue.
+    for (name, address) in &[
+        ("app::add_one", LINE_21),
+        ("add_one", LINE_21),
+        ("_ZN3app4main17h0123456789abcdefE", MAIN + 4),
+        ("HardFault", common::HARD_FAULT),
+    ] {
+        let breakpoints = set_function_breakpoints(&mut client, &[name]);
+        assert_eq!(breakpoints[0]["verified"], true, "{}", name);
+        assert_eq!(client.target.breakpoints(), vec![*address], "{}", name);
+    }
+
+    let breakpoints = set_function_breakpoints(&mut client, &["app::*", "missing"]);
+    assert_eq!(breakpoints[0]["verified"], true);
+    assert_eq!(breakpoints[1]["verified"], false);
+    assert_eq!(breakpoints[1]["message"], "No function found for 'missing'");
+
+    let mut addresses = client.target.breakpoints();
+    addresses.sort_unstable();
+    assert_eq!(addresses, vec![MAIN + 4, LINE_21]);
+
+    // Source breakpoints are independent of function breakpoints.
+    client.request(
+        "setBreakpoints",
+        json!({
+            "source": { "path": SOURCE_PATH },
+            "breakpoints": [ { "line": 13 } ],
+        }),
+    );
+    set_function_breakpoints(&mut client, &[]);
+    assert_eq!(client.target.breakpoints(), vec![MAIN + 0x10]);
+}
+
+#[test]
+fn continue_until_breakpoint() {
+    let mut client = TestClient::new();
+    client.attach();
+
+    client.request(
+        "setBreakpoints",
+        json!({
+            "source": { "path": SOURCE_PATH },
+            "breakpoints": [ { "line": 21 } ],
+        }),
+    );
+
+    client
+        .target
+        .set_trace(vec![MAIN, MAIN + 4, MAIN + 0x10, ADD_ONE, LINE_21]);
+
+    let response = client.request("continue", json!({ "threadId": 0 }));
+    assert_eq!(response["success"], true);
+
+    assert_eq!(
+        client.target.status(),
+        CoreStatus::Halted(HaltReason::Breakpoint)
+    );
+    assert_eq!(client.target.register(PC), LINE_21);
+
+    client.poll();
+
+    let events = client.take_stopped_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["body"]["reason"], "breakpoint");
+    assert_eq!(
+        events[0]["body"]["hitBreakpointIds"]
+            .as_array()
+            .unwrap()
+            .len(),
+        1
+    );
+
+    // The core is halted now, so there is nothing more to report.
+    client.poll();
+    assert!(client.take_stopped_events().is_empty());
+}
+
+/// Continue until the trace of the core ends, while `add_one` is called with `value`
+/// from 1 to `count`, and there is a breakpoint in line 21. Returns the values of
+/// `value` the core stopped at, and the output which was logged.
+fn run_through_line_21(
+    client: &mut TestClient,
+    breakpoint: Value,
+    count: u32,
+) -> (Vec<u32>, Vec<Value>) {
+    common::halt_in_add_one(&client.target);
+
+    let response = client.request(
+        "setBreakpoints",
+        json!({
+            "source": { "path": SOURCE_PATH },
+            "breakpoints": [ breakpoint ],
+        }),
+    );
+    assert_eq!(response["body"]["breakpoints"][0]["verified"], true);
+
+    for value in 1..=count {
+        client.target.add_trace_step(MAIN + 0x10, &[]);
+        client.target.add_trace_step(LINE_21, &[(0, value)]);
+    }
+
+    let mut stops = Vec::new();
+    let mut output = Vec::new();
+
+    client.take_events();
+    client.request("continue", json!({ "threadId": 0 }));
+
+    while client.target.status() != CoreStatus::Running {
+        client.poll();
+
+        for event in client.take_events() {
+            match event["event"].as_str() {
+                Some("stopped") => {
+                    stops.push(client.target.register(0));
+                    client.request("continue", json!({ "threadId": 0 }));
+                }
+                Some("output") => output.push(event["body"]["output"].clone()),
+                _ => (),
+            }
+        }
+    }
+
+    (stops, output)
+}
+
+#[test]
+fn conditional_breakpoints() {
+    let mut client = TestClient::new();
+    client.attach();
+
+    for (breakpoint, expected) in &[
+        (json!({ "line": 21 }), vec![1, 2, 3, 4, 5, 6]),
+        (json!({ "line": 21, "condition": "value == 3" }), vec![3]),
+        (
+            json!({ "line": 21, "condition": "value > 2 && value != 5" }),
+            vec![3, 4, 6],
+        ),
+        (json!({ "line": 21, "hitCondition": "4" }), vec![4, 5, 6]),
+        (json!({ "line": 21, "hitCondition": "% 2" }), vec![2, 4, 6]),
+        (json!({ "line": 21, "hitCondition": "== 2" }), vec![2]),
+        // The hits are only counted when the condition is true.
+        (
+            json!({ "line": 21, "condition": "value >= 3", "hitCondition": "2" }),
+            vec![4, 5, 6],
+        ),
+    ] {
+        // Remove the previous breakpoint, so that the hit count starts from 0.
+        client.request(
+            "setBreakpoints",
+            json!({ "source": { "path": SOURCE_PATH }, "breakpoints": [] }),
+        );
+
+        let (stops, _) = run_through_line_21(&mut client, breakpoint.clone(), 6);
+        assert_eq!(stops, *expected, "{}", breakpoint);
+    }
+}
+
+#[test]
+fn invalid_breakpoint_conditions_stop() {
+    let mut client = TestClient::new();
+    client.attach();
+
+    let (stops, output) = run_through_line_21(
+        &mut client,
+        json!({ "line": 21, "condition": "missing == 1" }),
+        2,
+    );
+    assert_eq!(stops, vec![1, 2]);
+    assert_eq!(
+        output[0],
+        "Unable to evaluate the condition 'missing == 1' of breakpoint 0: Unknown variable 'missing'\n"
+    );
+}
+
+#[test]
+fn logpoints() {
+    let mut client = TestClient::new();
+    client.attach();
+
+    let (stops, output) = run_through_line_21(
+        &mut client,
+        json!({ "line": 21, "logMessage": "add_one({value}) at {{{$pc}}} {missing}" }),
+        2,
+    );
+    assert!(stops.is_empty());
+    assert_eq!(
+        output,
+        vec![
+            json!("add_one(1) at {0x08000146} <Unknown variable 'missing'>\n"),
+            json!("add_one(2) at {0x08000146} <Unknown variable 'missing'>\n"),
+        ]
+    );
+}
+
+fn data_breakpoint_info(client: &mut TestClient, arguments: Value) -> Value {
+    let response = client.request("dataBreakpointInfo", arguments);
+    assert_eq!(response["success"], true);
+
+    response["body"].clone()
+}
+
+fn set_data_breakpoints(client: &mut TestClient, breakpoints: Value) -> Vec<Value> {
+    let response = client.request("setDataBreakpoints", json!({ "breakpoints": breakpoints }));
+    assert_eq!(response["success"], true);
+
+    response["body"]["breakpoints"].as_array().unwrap().clone()
+}
+
+/// Address, mask and function of the DWT comparator `index`.
+fn dwt_comparator(client: &TestClient, index: u32) -> (u32, u32, u32) {
+    let data = client
+        .target
+        .read_memory(0xe000_1020 + 16 * index, 12)
+        .unwrap();
+    let word = |i: usize| u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+
+    (word(0), word(4), word(8))
+}
+
+#[test]
+fn data_breakpoint_information() {
+    let mut client = TestClient::new();
+    client.attach();
+    common::halt_in_add_one(&client.target);
+
+    let body = data_breakpoint_info(&mut client, json!({ "name": "app::TICKS" }));
+    assert_eq!(body["dataId"], "0x20000120/4");
+    assert_eq!(body["description"], "app::TICKS (4 bytes at 0x20000120)");
+    assert_eq!(body["accessTypes"], json!(["read", "write", "readWrite"]));
+
+    let locals = scope_reference(&mut client, 1, "Locals");
+    let body = data_breakpoint_info(
+        &mut client,
+        json!({ "variablesReference": locals, "name": "point" }),
+    );
+    assert_eq!(body["dataId"], "0x20000100/8");
+
+    let registers = scope_reference(&mut client, 0, "Registers");
+    let body = data_breakpoint_info(
+        &mut client,
+        json!({ "variablesReference": registers, "name": "R0" }),
+    );
+    assert_eq!(body["dataId"], Value::Null);
+    assert_eq!(body["description"], "'R0' is stored in a register");
+
+    let body = data_breakpoint_info(&mut client, json!({ "name": "$r0 == 7" }));
+    assert_eq!(body["dataId"], Value::Null);
+    assert_eq!(body["description"], "'$r0 == 7' is not stored in memory");
+
+    let body = data_breakpoint_info(&mut client, json!({ "name": "missing" }));
+    assert_eq!(body["dataId"], Value::Null);
+    assert_eq!(body["description"], "Unknown variable 'missing'");
+}
+
+#[test]
+fn data_breakpoints() {
+    let mut client = TestClient::new();
+    client.target.add_dwt(4);
+    client.attach();
+
+    let breakpoints = set_data_breakpoints(
+        &mut client,
+        json!([
+            { "dataId": "0x20000120/4", "accessType": "write", "hitCondition": "2" },
+            { "dataId": "0x20000128/8", "accessType": "readWrite" },
+        ]),
+    );
+    assert_eq!(breakpoints[0]["verified"], true);
+    assert_eq!(breakpoints[1]["verified"], true);
+    let id = breakpoints[0]["id"].clone();
+
+    assert_eq!(dwt_comparator(&client, 0), (common::TICKS, 2, 0b0110));
+    assert_eq!(dwt_comparator(&client, 1), (common::ORIGIN, 3, 0b0111));
+
+    // Reads of TICKS don't stop, and neither does the first write because of the hit
+    // condition.
+    client.target.add_trace_step(MAIN + 4, &[]);
+    client.target.add_trace_read(MAIN + 6, common::TICKS, 4);
+    client
+        .target
+        .add_trace_write(MAIN + 8, common::TICKS, &1u32.to_le_bytes());
+    client
+        .target
+        .add_trace_write(MAIN + 10, common::TICKS, &2u32.to_le_bytes());
+    client.target.add_trace_step(MAIN + 12, &[]);
+
+    client.request("continue", json!({ "threadId": 0 }));
+    client.take_events();
+
+    client.poll();
+    assert!(client.take_stopped_events().is_empty());
+
+    client.poll();
+    let events = client.take_stopped_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["body"]["reason"], "data breakpoint");
+    assert_eq!(events[0]["body"]["hitBreakpointIds"], json!([id]));
+    assert_eq!(client.target.register(PC), MAIN + 10);
+    assert_eq!(
+        client.target.read_memory(common::TICKS, 4),
+        Some(2u32.to_le_bytes().to_vec())
+    );
+
+    // Breakpoints on the same data keep their id.
+    let breakpoints = set_data_breakpoints(
+        &mut client,
+        json!([{ "dataId": "0x20000120/4", "accessType": "write" }]),
+    );
+    assert_eq!(breakpoints[0]["id"], id);
+    assert_eq!(dwt_comparator(&client, 1).2, 0);
+
+    set_data_breakpoints(&mut client, json!([]));
+    assert_eq!(dwt_comparator(&client, 0).2, 0);
+}
+
+#[test]
+fn data_breakpoints_without_free_comparators() {
+    let mut client = TestClient::new();
+    client.target.add_dwt(2);
+    client.attach();
+
+    // The buffer of 200 bytes needs comparators for 128, 64 and 8 bytes.
+    let breakpoints = set_data_breakpoints(
+        &mut client,
+        json!([
+            { "dataId": "0x20000200/200" },
+            { "dataId": "0x20000002/4" },
+            { "dataId": "0x20000120/4" },
+            { "dataId": "invalid" },
+        ]),
+    );
+    assert_eq!(breakpoints[0]["verified"], false);
+    assert_eq!(
+        breakpoints[0]["message"],
+        "Watching 200 bytes at 0x20000200 needs 3 DWT comparators, but only 2 of 2 are free"
+    );
+
+    // Unaligned data is watched by two comparators, which leaves none for the next one.
+    assert_eq!(breakpoints[1]["verified"], true);
+    assert_eq!(dwt_comparator(&client, 0), (0x2000_0002, 1, 0b0110));
+    assert_eq!(dwt_comparator(&client, 1), (0x2000_0004, 1, 0b0110));
+    assert_eq!(breakpoints[2]["verified"], false);
+
+    assert_eq!(breakpoints[3]["verified"], false);
+    assert_eq!(
+        breakpoints[3]["message"],
+        "Invalid data breakpoint 'invalid'"
+    );
+}
+
+#[test]
+fn data_breakpoints_on_armv8m() {
+    let mut client = TestClient::new();
+    client.target.add_armv8m_dwt(4);
+    client.attach();
+
+    let breakpoints = set_data_breakpoints(
+        &mut client,
+        json!([
+            { "dataId": "0x20000120/4", "accessType": "write" },
+            { "dataId": "0x20000128/8", "accessType": "readWrite" },
+        ]),
+    );
+    assert_eq!(breakpoints[0]["verified"], true);
+    assert_eq!(breakpoints[1]["verified"], true);
+
+    // A comparator watches at most a word, given by DATAVSIZE instead of MASK.
+    assert_eq!(dwt_comparator(&client, 0), (common::TICKS, 0, 0x815));
+    assert_eq!(dwt_comparator(&client, 1), (common::ORIGIN, 0, 0x814));
+    assert_eq!(dwt_comparator(&client, 2), (common::ORIGIN + 4, 0, 0x814));
+
+    client.target.add_trace_read(MAIN + 4, common::TICKS, 4);
+    client
+        .target
+        .add_trace_read(MAIN + 6, common::ORIGIN + 4, 4);
+    client.target.add_trace_step(MAIN + 8, &[]);
+
+    client.request("continue", json!({ "threadId": 0 }));
+    client.take_events();
+    client.poll();
+
+    let events = client.take_stopped_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(
+        events[0]["body"]["hitBreakpointIds"],
+        json!([breakpoints[1]["id"]])
+    );
+    assert_eq!(client.target.register(PC), MAIN + 6);
+
+    // Comparators of an unknown DWT are not programmed.
+    let mut client = TestClient::new();
+    client.target.add_armv8m_dwt(4);
+    client
+        .target
+        .write_memory(0xe000_1fbc, &0x4770_1a03u32.to_le_bytes());
+    client.attach();
+
+    let breakpoints = set_data_breakpoints(&mut client, json!([{ "dataId": "0x20000120/4" }]));
+    assert_eq!(breakpoints[0]["verified"], false);
+    assert_eq!(
+        breakpoints[0]["message"],
+        "Unsupported DWT architecture 0x47701a03"
+    );
+    assert_eq!(dwt_comparator(&client, 0), (0, 0, 0));
+}
+
+fn set_exception_breakpoints(client: &mut TestClient, filters: &[&str]) {
+    let response = client.request("setExceptionBreakpoints", json!({ "filters": filters }));
+    assert_eq!(response["success"], true);
+}
+
+#[test]
+fn panic_exception_breakpoint() {
+    let mut client = TestClient::new();
+    client.attach();
+
+    set_exception_breakpoints(&mut client, &["rust_panic", "default_handler"]);
+
+    let mut addresses = client.target.breakpoints();
+    addresses.sort_unstable();
+    assert_eq!(
+        addresses,
+        vec![common::PANIC_HANDLER, common::DEFAULT_HANDLER]
+    );
+
+    client
+        .target
+        .set_trace(vec![MAIN, MAIN + 4, common::PANIC_HANDLER]);
+    client.request("continue", json!({ "threadId": 0 }));
+    client.take_events();
+    client.poll();
+
+    let events = client.take_stopped_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["body"]["reason"], "exception");
+    assert_eq!(events[0]["body"]["text"], "panic");
+    assert_eq!(events[0]["body"]["hitBreakpointIds"], Value::Null);
+
+    let response = client.request("exceptionInfo", json!({ "threadId": 0 }));
+    assert_eq!(response["body"]["exceptionId"], "panic");
+    assert_eq!(response["body"]["description"], "The program panicked");
+    assert_eq!(response["body"]["breakMode"], "always");
+
+    set_exception_breakpoints(&mut client, &[]);
+    assert!(client.target.breakpoints().is_empty());
+}
+
+#[test]
+fn fault_exception_breakpoints() {
+    let mut client = TestClient::new();
+    client.attach();
+    common::halt_in_add_one(&client.target);
+
+    set_exception_breakpoints(&mut client, &["hard_fault"]);
+    let demcr = client.target.read_memory(0xe000_edfc, 4).unwrap();
+    assert_eq!(
+        u32::from_le_bytes([demcr[0], demcr[1], demcr[2], demcr[3]]),
+        1 << 10
+    );
+    assert!(client.target.breakpoints().is_empty());
+
+    let sp = client.target.register(SP);
+    client.target.add_trace_exception(3, common::HARD_FAULT);
+    client.request("continue", json!({ "threadId": 0 }));
+    client.take_events();
+    client.poll();
+
+    let events = client.take_stopped_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["body"]["reason"], "exception");
+    assert_eq!(events[0]["body"]["text"], "HardFault");
+    assert_eq!(
+        events[0]["body"]["description"],
+        "Paused on HardFault exception"
+    );
+    assert_eq!(client.target.register(PC), common::HARD_FAULT);
+    assert_eq!(
+        client.target.read_memory(sp - 8, 4),
+        Some(LINE_21.to_le_bytes().to_vec())
+    );
+
+    let response = client.request("exceptionInfo", json!({ "threadId": 0 }));
+    assert_eq!(response["body"]["exceptionId"], "HardFault");
+
+    // A BusFault is only caught by the filter for the configurable faults.
+    client.target.add_trace_exception(5, common::HARD_FAULT + 8);
+    client.request("continue", json!({ "threadId": 0 }));
+    assert_eq!(client.target.status(), CoreStatus::Running);
+
+    let response = client.request("exceptionInfo", json!({ "threadId": 0 }));
+    assert_eq!(response["success"], false);
+    assert_eq!(
+        response["message"],
+        "The core is not stopped at an exception"
+    );
+}
+
+#[test]
+fn fault_analysis() {
+    let mut client = TestClient::new();
+
+    // CFSR, HFSR, DFSR, MMFAR and BFAR of a precise bus error escalated to a HardFault.
+    let mut registers = Vec::new();
+    for value in &[(1u32 << 9) | (1 << 15), 1 << 30, 0, 0, 0x4000_1234] {
+        registers.extend_from_slice(&value.to_le_bytes());
+    }
+    client.target.add_memory(0xe000_ed28, registers);
+
+    client.attach();
+    common::halt_in_add_one(&client.target);
+    set_exception_breakpoints(&mut client, &["hard_fault"]);
+
+    client.target.add_trace_exception(3, common::HARD_FAULT);
+    client.request("continue", json!({ "threadId": 0 }));
+    client.poll();
+
+    let response = client.request("exceptionInfo", json!({ "threadId": 0 }));
+    assert_eq!(response["success"], true);
+
+    let body = &response["body"];
+    assert_eq!(body["exceptionId"], "HardFault");
+    assert_eq!(
+        body["description"],
+        "HardFault exception: forced, a configurable fault was escalated to a HardFault, \
+         precise data bus error at 0x40001234\n\
+         Faulting instruction: 0x08000146 in app::add_one at /work/app/src/main.rs:21"
+    );
+    assert_eq!(
+        body["details"]["message"],
+        "forced, a configurable fault was escalated to a HardFault\n\
+         precise data bus error at 0x40001234"
+    );
+    assert_eq!(
+        body["details"]["stackTrace"],
+        "0x08000146 in app::add_one at /work/app/src/main.rs:21"
+    );
+}
+
+#[test]
+fn stack_trace() {
+    let mut client = TestClient::new();
+    client.attach();
+    common::halt_in_add_one(&client.target);
+
+    let response = client.request("stackTrace", json!({ "threadId": 0 }));
+
+    let frames = response["body"]["stackFrames"].as_array().unwrap();
+    assert_eq!(frames.len(), 2);
+
+    assert_eq!(frames[0]["name"], "app::add_one");
+    assert_eq!(frames[0]["line"], 21);
+    assert_eq!(frames[0]["source"]["path"], SOURCE_PATH);
+
+    assert_eq!(frames[1]["name"], "app::main");
+    assert_eq!(frames[1]["line"], 13);
+}
+
+#[test]
+fn exception_stack_trace() {
+    let mut client = TestClient::new();
+    client.attach();
+    common::halt_in_add_one(&client.target);
+    set_exception_breakpoints(&mut client, &["hard_fault"]);
+
+    client.target.add_trace_exception(3, common::HARD_FAULT);
+    client.request("continue", json!({ "threadId": 0 }));
+    client.poll();
+
+    let response = client.request("stackTrace", json!({ "threadId": 0 }));
+    let frames = response["body"]["stackFrames"].as_array().unwrap();
+
+    let names: Vec<_> = frames.iter().map(|frame| frame["name"].clone()).collect();
+    assert_eq!(
+        names,
+        vec![
+            json!("HardFault"),
+            json!("<exception entry>"),
+            json!("app::add_one"),
+            json!("app::main")
+        ]
+    );
+
+    assert_eq!(frames[0]["presentationHint"], "normal");
+    assert_eq!(frames[1]["presentationHint"], "label");
+    assert_eq!(frames[1]["source"], Value::Null);
+
+    // The interrupted instruction, not the one before it.
+    assert_eq!(frames[2]["line"], 21);
+    assert_eq!(frames[2]["instructionPointerReference"], "0x08000146");
+    assert_eq!(frames[3]["line"], 13);
+}
+
+#[test]
+fn extended_exception_frame_on_process_stack() {
+    let mut client = TestClient::new();
+    client.attach();
+    common::halt_in_add_one(&client.target);
+
+    // MVFR0 is only non-zero if the core has an FPU.
+    client
+        .target
+        .add_read_only_memory(0xe000_ef40, 0x1011_0021u32.to_le_bytes().to_vec());
+
+    // Extended frame of `add_one` on the process stack: R0 to R3, R12, LR, PC, xPSR,
+    // S0 to S15, FPSCR and a reserved word.
+    let psp = RAM_START + 0xfe8 - 0x68;
+    let mut frame = vec![7, 0, 0, 0, 0, 0x0800_0115, common::LINE_21, 0x0100_0000];
+    frame.extend((0..16).map(|i| 0x3f80_0000 + i));
+    frame.extend(&[0x0300_0000, 0]);
+    for (i, value) in frame.iter().enumerate() {
+        client
+            .target
+            .write_memory(psp + 4 * i as u32, &value.to_le_bytes());
+    }
+
+    // A handler running on the main stack, which returns to thread mode.
+    client.target.set_register(SP, RAM_START + 0x800);
+    client.target.set_register(18, psp);
+    client.target.set_register(LR, 0xffff_ffed);
+    client.target.set_register(16, 0x0100_0003);
+    client
+        .target
+        .halt_at(common::HARD_FAULT, probe_rs::HaltReason::Exception);
+
+    let response = client.request("stackTrace", json!({ "threadId": 0 }));
+    let frames = response["body"]["stackFrames"].as_array().unwrap().clone();
+    assert_eq!(frames.len(), 4);
+    assert_eq!(frames[2]["name"], "app::add_one");
+    assert_eq!(frames[3]["name"], "app::main");
+
+    let response = client.request("scopes", json!({ "frameId": frames[2]["id"] }));
+    let scope = find(response["body"]["scopes"].as_array().unwrap(), "Registers");
+    let registers = children(
+        &mut client,
+        json!({ "variablesReference": scope["variablesReference"] }),
+    );
+    assert_eq!(find(&registers, "SP")["value"], "0x20000fe8");
+    assert_eq!(find(&registers, "PC")["value"], "0x08000146");
+
+    let fpu = children(
+        &mut client,
+        json!({ "variablesReference": find(&registers, "FPU")["variablesReference"] }),
+    );
+    assert_eq!(find(&fpu, "S1")["value"], "0x3f800001");
+    assert_eq!(find(&fpu, "FPSCR")["value"], "0x03000000");
+}
+
+#[test]
+fn variables() {
+    let mut client = TestClient::new();
+    client.attach();
+    common::halt_in_add_one(&client.target);
+
+    let response = client.request("stackTrace", json!({ "threadId": 0 }));
+    let frames = response["body"]["stackFrames"].as_array().unwrap().clone();
+
+    let mut values = Vec::new();
+
+    for frame in frames {
+        let response = client.request("scopes", json!({ "frameId": frame["id"] }));
+        let scope = &response["body"]["scopes"][0];
+        assert_eq!(scope["name"], "Locals");
+
+        let response = client.request(
+            "variables",
+            json!({ "variablesReference": scope["variablesReference"] }),
+        );
+        let variables = &response["body"]["variables"];
+
+        values.push((variables[0]["name"].clone(), variables[0]["value"].clone()));
+    }
+
+    assert_eq!(
+        values,
+        vec![(json!("value"), json!("7")), (json!("counter"), json!("7"))]
+    );
+}
+
+/// Get the local variables of `main`, while the core is halted in `add_one`.
+fn main_locals(client: &mut TestClient) -> Vec<Value> {
+    common::halt_in_add_one(&client.target);
+
+    let response = client.request("stackTrace", json!({ "threadId": 0 }));
+    let frame = response["body"]["stackFrames"][1].clone();
+
+    let response = client.request("scopes", json!({ "frameId": frame["id"] }));
+    let reference = response["body"]["scopes"][0]["variablesReference"].clone();
+
+    children(client, json!({ "variablesReference": reference }))
+}
+
+fn children(client: &mut TestClient, arguments: Value) -> Vec<Value> {
+    let response = client.request("variables", arguments);
+    assert_eq!(response["success"], true);
+
+    response["body"]["variables"].as_array().unwrap().clone()
+}
+
+fn find<'a>(variables: &'a [Value], name: &str) -> &'a Value {
+    variables
+        .iter()
+        .find(|variable| variable["name"] == name)
+        .unwrap_or_else(|| panic!("Variable {} not found", name))
+}
+
+#[test]
+fn struct_members() {
+    let mut client = TestClient::new();
+    client.attach();
+
+    let locals = main_locals(&mut client);
+    let point = find(&locals, "point");
+    assert_eq!(point["type"], "Point");
+    assert_eq!(point["namedVariables"], 2);
+    assert_eq!(find(&locals, "counter")["variablesReference"], 0);
+
+    let members = children(
+        &mut client,
+        json!({ "variablesReference": point["variablesReference"] }),
+    );
+
+    let members: Vec<_> = members
+        .iter()
+        .map(|m| {
+            (
+                m["name"].clone(),
+                m["value"].clone(),
+                m["evaluateName"].clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        members,
+        vec![
+            (json!("x"), json!("-3"), json!("point.x")),
+            (json!("y"), json!("4"), json!("point.y"))
+        ]
+    );
+}
+
+#[test]
+fn array_elements_are_paged() {
+    let mut client = TestClient::new();
+    client.attach();
+
+    let locals = main_locals(&mut client);
+    let buffer = find(&locals, "buffer");
+    assert_eq!(buffer["type"], "[u16; 100]");
+    assert_eq!(buffer["indexedVariables"], 100);
+
+    let elements = children(
+        &mut client,
+        json!({
+            "variablesReference": buffer["variablesReference"],
+            "filter": "indexed",
+            "start": 10,
+            "count": 3,
+        }),
+    );
+
+    let elements: Vec<_> = elements
+        .iter()
+        .map(|e| {
+            (
+                e["name"].clone(),
+                e["value"].clone(),
+                e["evaluateName"].clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        elements,
+        vec![
+            (json!("[10]"), json!("30"), json!("buffer[10]")),
+            (json!("[11]"), json!("33"), json!("buffer[11]")),
+            (json!("[12]"), json!("36"), json!("buffer[12]")),
+        ]
+    );
+}
+
+#[test]
+fn pointer_is_dereferenced() {
+    let mut client = TestClient::new();
+    client.attach();
+
+    let locals = main_locals(&mut client);
+    let pointer = find(&locals, "pointer");
+    assert_eq!(pointer["value"], "0x20000100");
+
+    let pointee = children(
+        &mut client,
+        json!({ "variablesReference": pointer["variablesReference"] }),
+    );
+    assert_eq!(pointee[0]["name"], "*pointer");
+    assert_eq!(pointee[0]["type"], "Point");
+
+    let members = children(
+        &mut client,
+        json!({ "variablesReference": pointee[0]["variablesReference"] }),
+    );
+    assert_eq!(members[1]["value"], "4");
+    assert_eq!(members[1]["evaluateName"], "(*pointer).y");
+}
+
+#[test]
+fn enums() {
+    let mut client = TestClient::new();
+    client.attach();
+
+    let locals = main_locals(&mut client);
+    assert_eq!(find(&locals, "mode")["value"], "Fast");
+
+    let state = find(&locals, "state");
+    assert_eq!(state["value"], "Some");
+    assert_eq!(state["type"], "Option<u32>");
+
+    let fields = children(
+        &mut client,
+        json!({ "variablesReference": state["variablesReference"] }),
+    );
+    assert_eq!(fields[0]["name"], "__0");
+    assert_eq!(fields[0]["value"], "42");
+}
+
+#[test]
+fn statics() {
+    let mut client = TestClient::new();
+    client.attach();
+    common::halt_in_add_one(&client.target);
+    client
+        .target
+        .write_memory(common::TICKS, &1234u32.to_le_bytes());
+    client
+        .target
+        .write_memory(common::ORIGIN + 4, &(-1i32).to_le_bytes());
+
+    let response = client.request("stackTrace", json!({ "threadId": 0 }));
+    let frame = response["body"]["stackFrames"][0].clone();
+
+    let response = client.request("scopes", json!({ "frameId": frame["id"] }));
+    let scope = find(response["body"]["scopes"].as_array().unwrap(), "Statics");
+    assert_eq!(scope["expensive"], true);
+
+    let statics = children(
+        &mut client,
+        json!({ "variablesReference": scope["variablesReference"] }),
+    );
+
+    let names: Vec<_> = statics.iter().map(|s| s["name"].clone()).collect();
+    assert_eq!(names, vec![json!("app::ORIGIN"), json!("app::TICKS")]);
+    assert_eq!(statics[1]["value"], "1234");
+
+    let members = children(
+        &mut client,
+        json!({ "variablesReference": statics[0]["variablesReference"] }),
+    );
+    assert_eq!(members[1]["value"], "-1");
+    assert_eq!(members[1]["evaluateName"], "app::ORIGIN.y");
+}
+
+const TEST_SVD: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<device schemaVersion="1.1">
+  <name>TEST</name>
+  <size>32</size>
+  <access>read-write</access>
+  <peripherals>
+    <peripheral>
+      <name>GPIOA</name>
+      <description>General purpose
+        I/O</description>
+      <baseAddress>0x20000800</baseAddress>
+      <registers>
+        <register>
+          <name>MODE</name>
+          <addressOffset>0x0</addressOffset>
+          <fields>
+            <field>
+              <name>PIN0</name>
+              <bitRange>[1:0]</bitRange>
+              <enumeratedValues>
+                <enumeratedValue><name>Input</name><value>0</value></enumeratedValue>
+                <enumeratedValue><name>Output</name><value>1</value></enumeratedValue>
+                <enumeratedValue><name>Analog</name><value>#11</value></enumeratedValue>
+              </enumeratedValues>
+            </field>
+            <field>
+              <name>PIN1</name>
+              <lsb>2</lsb>
+              <msb>3</msb>
+              <enumeratedValues>
+                <enumeratedValue><name>Input</name><value>0</value></enumeratedValue>
+                <enumeratedValue><name>Other</name><isDefault>true</isDefault></enumeratedValue>
+              </enumeratedValues>
+            </field>
+            <field>
+              <name>LOCK</name>
+              <bitOffset>31</bitOffset>
+              <bitWidth>1</bitWidth>
+            </field>
+          </fields>
+        </register>
+        <register>
+          <dim>2</dim>
+          <dimIncrement>4</dimIncrement>
+          <name>DATA%s</name>
+          <addressOffset>0x4</addressOffset>
+          <size>16</size>
+        </register>
+        <register>
+          <name>CLEAR</name>
+          <addressOffset>0xc</addressOffset>
+          <access>write-only</access>
+        </register>
+      </registers>
+    </peripheral>
+    <peripheral derivedFrom="GPIOA">
+      <name>GPIOB</name>
+      <baseAddress>0x20000900</baseAddress>
+    </peripheral>
+  </peripherals>
+</device>
+"#;
+
+#[test]
+fn peripherals() {
+    let svd_path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test.svd");
+    std::fs::write(&svd_path, TEST_SVD).unwrap();
+
+    let mut client = TestClient::new();
+    client.attach_with(json!({ "svd_file": svd_path }));
+    common::halt_in_add_one(&client.target);
+    client
+        .target
+        .write_memory(RAM_START + 0x800, &0x8000_000du32.to_le_bytes());
+    client
+        .target
+        .write_memory(RAM_START + 0x808, &0x1234u16.to_le_bytes());
+
+    let response = client.request("stackTrace", json!({ "threadId": 0 }));
+    let frame = response["body"]["stackFrames"][0].clone();
+    let response = client.request("scopes", json!({ "frameId": frame["id"] }));
+    let scope = find(
+        response["body"]["scopes"].as_array().unwrap(),
+        "Peripherals",
+    );
+
+    let peripherals = children(
+        &mut client,
+        json!({ "variablesReference": scope["variablesReference"] }),
+    );
+    assert_eq!(peripherals.len(), 2);
+    assert_eq!(peripherals[0]["type"], "General purpose I/O");
+    assert_eq!(peripherals[1]["name"], "GPIOB");
+    assert_eq!(peripherals[1]["value"], "0x20000900");
+    assert_eq!(peripherals[1]["namedVariables"], 4);
+
+    let registers = children(
+        &mut client,
+        json!({ "variablesReference": peripherals[0]["variablesReference"] }),
+    );
+    let values: Vec<_> = registers
+        .iter()
+        .map(|r| (r["name"].clone(), r["value"].clone()))
+        .collect();
+    assert_eq!(
+        values,
+        vec![
+            (json!("MODE"), json!("0x8000000d")),
+            (json!("DATA0"), json!("0x0000")),
+            (json!("DATA1"), json!("0x1234")),
+            (json!("CLEAR"), json!("<not readable>")),
+        ]
+    );
+    assert_eq!(registers[2]["evaluateName"], "*(u16*)0x20000808");
+
+    let fields = children(
+        &mut client,
+        json!({ "variablesReference": registers[0]["variablesReference"] }),
+    );
+    let values: Vec<_> = fields
+        .iter()
+        .map(|f| (f["name"].clone(), f["value"].clone()))
+        .collect();
+    assert_eq!(
+        values,
+        vec![
+            (json!("PIN0"), json!("Output (1)")),
+            (json!("PIN1"), json!("Other (3)")),
+            (json!("LOCK"), json!("1")),
+        ]
+    );
+}
+
+/// Get the registers of all stack frames, while the core is halted in `add_one`.
+fn frame_registers(client: &mut TestClient) -> Vec<Vec<Value>> {
+    common::halt_in_add_one(&client.target);
+    client.target.set_register(16, 0x6100_0000);
+
+    let response = client.request("stackTrace", json!({ "threadId": 0 }));
+    let frames = response["body"]["stackFrames"].as_array().unwrap().clone();
+
+    frames
+        .iter()
+        .map(|frame| {
+            let response = client.request("scopes", json!({ "frameId": frame["id"] }));
+            let scope = find(response["body"]["scopes"].as_array().unwrap(), "Registers");
+
+            children(
+                client,
+                json!({ "variablesReference": scope["variablesReference"] }),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn registers_of_each_frame() {
+    let mut client = TestClient::new();
+    client.attach();
+
+    let frames = frame_registers(&mut client);
+
+    let value = |frame: usize, name: &str| find(&frames[frame], name)["value"].clone();
+
+    assert_eq!(value(0, "R0"), "0x00000007");
+    assert_eq!(value(0, "SP"), "0x20000fe8");
+    assert_eq!(value(0, "PC"), "0x08000146");
+    assert_eq!(value(0, "xPSR"), "0x61000000");
+    assert_eq!(find(&frames[0], "PC")["evaluateName"], "$pc");
+
+    // Registers of `main`, restored from the stack frame of `add_one`
+    assert_eq!(value(1, "R0"), "<not available>");
+    assert_eq!(value(1, "R7"), "0x20000ff8");
+    assert_eq!(value(1, "SP"), "0x20000ff0");
+    assert_eq!(value(1, "PC"), "0x08000114");
+    assert_eq!(value(1, "xPSR"), "<not available>");
+
+    // The test target has no FPU.
+    assert!(frames[0].iter().all(|register| register["name"] != "FPU"));
+}
+
+#[test]
+fn fpu_registers() {
+    let mut client = TestClient::new();
+    client.attach();
+
+    // MVFR0 is only non-zero if the core has an FPU.
+    client
+        .target
+        .add_read_only_memory(0xe000_ef40, 0x1011_0021u32.to_le_bytes().to_vec());
+    client.target.set_register(64, 0x3f80_0000);
+    client.target.set_register(64 + 16, 0x4000_0000);
+
+    let frames = frame_registers(&mut client);
+
+    let fpu: Vec<_> = frames
+        .iter()
+        .map(|registers| {
+            let fpu = find(registers, "FPU");
+            children(
+                &mut client,
+                json!({ "variablesReference": fpu["variablesReference"] }),
+            )
+        })
+        .collect();
+
+    assert_eq!(find(&fpu[0], "S0")["value"], "0x3f800000");
+    assert_eq!(find(&fpu[0], "S16")["value"], "0x40000000");
+    assert_eq!(find(&fpu[0], "FPSCR")["value"], "0x00000000");
+
+    // S16 to S31 are preserved by calls, the other registers are not.
+    assert_eq!(find(&fpu[1], "S0")["value"], "<not available>");
+    assert_eq!(find(&fpu[1], "S16")["value"], "0x40000000");
+}
+
+#[test]
+fn read_memory() {
+    let mut client = TestClient::new();
+    client.attach();
+    client.target.write_memory(RAM_START + 0x10, &[1, 2, 3, 4]);
+
+    let response = client.request(
+        "readMemory",
+        json!({ "memoryReference": "0x20000000", "offset": 0x10, "count": 4 }),
+    );
+    assert_eq!(response["success"], true);
+    assert_eq!(response["body"]["address"], "0x20000010");
+    assert_eq!(response["body"]["data"], base64::encode([1, 2, 3, 4]));
+    assert_eq!(response["body"].get("unreadableBytes"), None);
+}
+
+#[test]
+fn read_memory_past_end_of_ram() {
+    let mut client = TestClient::new();
+    client.attach();
+    client.target.write_memory(RAM_START + 0xffe, &[0xaa, 0xbb]);
+
+    let response = client.request(
+        "readMemory",
+        json!({ "memoryReference": "0x20000ffe", "count": 8 }),
+    );
+    assert_eq!(response["success"], true);
+    assert_eq!(response["body"]["data"], base64::encode([0xaa, 0xbb]));
+    assert_eq!(response["body"]["unreadableBytes"], 6);
+}
+
+#[test]
+fn read_memory_is_limited() {
+    let mut client = TestClient::new();
+    client.attach();
+
+    let response = client.request(
+        "readMemory",
+        json!({ "memoryReference": "0x20000000", "count": i32::MAX }),
+    );
+    assert_eq!(response["success"], true);
+
+    let data = base64::decode(response["body"]["data"].as_str().unwrap()).unwrap();
+    assert_eq!(data.len(), RAM_SIZE);
+    assert_eq!(response["body"]["unreadableBytes"], 0x10000 - RAM_SIZE);
+}
+
+#[test]
+fn invalid_memory_references() {
+    let mut client = TestClient::new();
+    client.attach();
+
+    for (reference, offset, message) in &[
+        (
+            "0xffffffffffffffff",
+            1,
+            "Memory reference '0xffffffffffffffff' is outside of the address space",
+        ),
+        (
+            "0x20000000",
+            i64::MAX,
+            "Memory reference '0x20000000' is outside of the address space",
+        ),
+        (
+            "0xfffffffc",
+            4,
+            "Address 0x100000000 is outside of the address space",
+        ),
+        ("sp", 0, "Invalid memory reference 'sp'"),
+    ] {
+        let response = client.request(
+            "readMemory",
+            json!({ "memoryReference": reference, "offset": offset, "count": 4 }),
+        );
+        assert_eq!(response["success"], false, "{}", reference);
+        assert_eq!(response["message"], *message, "{}", reference);
+    }
+}
+
+#[test]
+fn write_memory() {
+    let mut client = TestClient::new();
+    client.attach();
+
+    let response = client.request(
+        "writeMemory",
+        json!({
+            "memoryReference": "0x20000020",
+            "offset": 2,
+            "data": base64::encode([5, 6, 7]),
+        }),
+    );
+    assert_eq!(response["success"], true);
+    assert_eq!(response["body"]["bytesWritten"], 3);
+    assert_eq!(
+        client.target.read_memory(RAM_START + 0x22, 3),
+        Some(vec![5, 6, 7])
+    );
+
+    let response = client.request(
+        "writeMemory",
+        json!({
+            "memoryReference": "0x20000ffe",
+            "data": base64::encode([1, 2, 3, 4]),
+        }),
+    );
+    assert_eq!(response["success"], false);
+
+    let response = client.request(
+        "writeMemory",
+        json!({
+            "memoryReference": "0x20000ffe",
+            "allowPartial": true,
+            "data": base64::encode([1, 2, 3, 4]),
+        }),
+    );
+    assert_eq!(response["body"]["bytesWritten"], 2);
+}
+
+#[test]
+fn memory_reference_of_variables() {
+    let mut client = TestClient::new();
+    client.attach();
+
+    let locals = main_locals(&mut client);
+    assert_eq!(find(&locals, "point")["memoryReference"], "0x20000100");
+    assert_eq!(find(&locals, "pointer")["memoryReference"], "0x20000100");
+    assert_eq!(find(&locals, "counter")["memoryReference"], "0x20000ff4");
+}
+
+/// Evaluate an expression in the frame of `main`, while the core is halted in `add_one`.
+fn evaluate_in_main(client: &mut TestClient, expression: &str) -> Value {
+    let response = client.request("stackTrace", json!({ "threadId": 0 }));
+    let frame_id = response["body"]["stackFrames"][1]["id"].clone();
+
+    client.request(
+        "evaluate",
+        json!({ "expression": expression, "frameId": frame_id, "context": "watch" }),
+    )
+}
+
+#[test]
+fn evaluate_variables() {
+    let mut client = TestClient::new();
+    client.attach();
+    common::halt_in_add_one(&client.target);
+    client
+        .target
+        .write_memory(common::ORIGIN + 4, &(-1i32).to_le_bytes());
+
+    for (expression, value) in &[
+        ("counter", "7"),
+        ("point.x", "-3"),
+        ("buffer[10]", "30"),
+        ("(*pointer).y", "4"),
+        ("pointer.y", "4"),
+        ("state.__0", "42"),
+        ("state.0", "42"),
+        ("mode", "Fast"),
+        ("app::ORIGIN.y", "-1"),
+        ("ORIGIN.y", "-1"),
+    ] {
+        let response = evaluate_in_main(&mut client, expression);
+        assert_eq!(response["success"], true, "{}", expression);
+        assert_eq!(response["body"]["result"], *value, "{}", expression);
+    }
+
+    let response = evaluate_in_main(&mut client, "*pointer");
+    assert_eq!(response["body"]["type"], "Point");
+    assert_eq!(response["body"]["memoryReference"], "0x20000100");
+
+    let members = children(
+        &mut client,
+        json!({ "variablesReference": response["body"]["variablesReference"] }),
+    );
+    assert_eq!(members[1]["value"], "4");
+    assert_eq!(members[1]["evaluateName"], "(*pointer).y");
+}
+
+#[test]
+fn evaluate_registers_and_memory() {
+    let mut client = TestClient::new();
+    client.attach();
+    common::halt_in_add_one(&client.target);
+    client
+        .target
+        .write_memory(RAM_START + 0x808, &0xbeefu16.to_le_bytes());
+
+    let response = client.request("stackTrace", json!({ "threadId": 0 }));
+    let frame_id = response["body"]["stackFrames"][0]["id"].clone();
+
+    let evaluate = |client: &mut TestClient, expression: &str| {
+        client.request(
+            "evaluate",
+            json!({ "expression": expression, "frameId": frame_id, "context": "hover" }),
+        )["body"]
+            .clone()
+    };
+
+    assert_eq!(evaluate(&mut client, "$r0")["result"], "0x00000007");
+    assert_eq!(evaluate(&mut client, "$pc")["result"], "0x08000146");
+    assert_eq!(
+        evaluate(&mut client, "*(u16*)0x20000808")["result"],
+        "48879"
+    );
+    assert_eq!(
+        evaluate(&mut client, "*(u32*)0x2000_0100")["result"],
+        "4294967293"
+    );
+    // `i8` is not in the debug information of the program.
+    assert_eq!(evaluate(&mut client, "*(i8*)0x20000100")["result"], "-3");
+    assert_eq!(evaluate(&mut client, "(u16)-1")["result"], "65535");
+
+    let pointer = evaluate(&mut client, "(Point*)0x20000100");
+    assert_eq!(pointer["result"], "0x20000100");
+    assert_eq!(pointer["type"], "*Point");
+
+    let pointee = children(
+        &mut client,
+        json!({ "variablesReference": pointer["variablesReference"] }),
+    );
+    assert_eq!(pointee[0]["type"], "Point");
+
+    let members = children(
+        &mut client,
+        json!({ "variablesReference": pointee[0]["variablesReference"] }),
+    );
+    assert_eq!(members[0]["value"], "-3");
+    assert_eq!(members[0]["evaluateName"], "(*(Point*)0x20000100).x");
+}
+
+#[test]
+fn evaluate_errors() {
+    let mut client = TestClient::new();
+    client.attach();
+    common::halt_in_add_one(&client.target);
+
+    for (expression, message) in &[
+        ("missing", "Unknown variable 'missing'"),
+        ("buffer[100]", "Index 100 is out of bounds for [u16; 100]"),
+        (
+            "pointer[0x7fff_ffff_ffff_ffff]",
+            "Index 9223372036854775807 is out of range",
+        ),
+        ("*counter", "Cannot dereference a value of type u32"),
+        ("point.z", "No field z in Point"),
+        ("(Unknown*)0", "Unknown type 'Unknown'"),
+        ("point.", "Syntax error: Expected a field name"),
+    ] {
+        let response = evaluate_in_main(&mut client, expression);
+        assert_eq!(response["success"], false, "{}", expression);
+        assert_eq!(response["message"], *message, "{}", expression);
+    }
+}
+
+/// Reference of the scope `name` of a frame, while the core is halted in `add_one`.
+fn scope_reference(client: &mut TestClient, frame: usize, name: &str) -> Value {
+    let response = client.request("stackTrace", json!({ "threadId": 0 }));
+    let frame_id = response["body"]["stackFrames"][frame]["id"].clone();
+
+    let response = client.request("scopes", json!({ "frameId": frame_id }));
+    find(response["body"]["scopes"].as_array().unwrap(), name)["variablesReference"].clone()
+}
+
+fn set_variable(client: &mut TestClient, reference: &Value, name: &str, value: &str) -> Value {
+    client.request(
+        "setVariable",
+        json!({ "variablesReference": reference, "name": name, "value": value }),
+    )
+}
+
+/// Areas of the `invalidated` events sent since the last call.
+fn invalidated_areas(client: &mut TestClient) -> Vec<Value> {
+    client
+        .take_events()
+        .into_iter()
+        .filter(|event| event["event"] == "invalidated")
+        .map(|event| event["body"]["areas"].clone())
+        .collect()
+}
+
+#[test]
+fn set_variables() {
+    let mut client = TestClient::new();
+    client.attach();
+    common::halt_in_add_one(&client.target);
+
+    let locals = scope_reference(&mut client, 1, "Locals");
+    client.take_events();
+
+    let response = set_variable(&mut client, &locals, "counter", "0x2a");
+    assert_eq!(response["success"], true);
+    assert_eq!(response["body"]["value"], "42");
+    assert_eq!(response["body"]["type"], "u32");
+    assert_eq!(
+        client.target.read_memory(RAM_START + 0xff4, 4),
+        Some(42u32.to_le_bytes().to_vec())
+    );
+    assert_eq!(invalidated_areas(&mut client), vec![json!(["variables"])]);
+
+    let response = set_variable(&mut client, &locals, "mode", "Mode::Slow");
+    assert_eq!(response["body"]["value"], "Slow");
+    assert_eq!(
+        client.target.read_memory(RAM_START + 0x118, 1),
+        Some(vec![0])
+    );
+
+    let variables = children(&mut client, json!({ "variablesReference": locals }));
+    let point = find(&variables, "point")["variablesReference"].clone();
+    let response = set_variable(&mut client, &point, "x", "-10");
+    assert_eq!(response["body"]["value"], "-10");
+    assert_eq!(
+        client.target.read_memory(common::POINT, 4),
+        Some((-10i32).to_le_bytes().to_vec())
+    );
+
+    let buffer = find(&variables, "buffer")["variablesReference"].clone();
+    let response = set_variable(&mut client, &buffer, "[99]", "42");
+    assert_eq!(response["body"]["value"], "42");
+    assert_eq!(
+        client.target.read_memory(common::BUFFER + 198, 2),
+        Some(vec![42, 0])
+    );
+
+    let statics = scope_reference(&mut client, 0, "Statics");
+    let response = set_variable(&mut client, &statics, "app::TICKS", "1000");
+    assert_eq!(response["body"]["value"], "1000");
+    assert_eq!(
+        client.target.read_memory(common::TICKS, 4),
+        Some(1000u32.to_le_bytes().to_vec())
+    );
+}
+
+#[test]
+fn set_variable_in_registers() {
+    let mut client = TestClient::new();
+    client.attach();
+    common::halt_in_add_one(&client.target);
+
+    // `value` is held in r0.
+    let locals = scope_reference(&mut client, 0, "Locals");
+    client.take_events();
+
+    let response = set_variable(&mut client, &locals, "value", "9");
+    assert_eq!(response["success"], true);
+    assert_eq!(response["body"]["value"], "9");
+    assert_eq!(client.target.register(0), 9);
+    assert_eq!(invalidated_areas(&mut client), vec![json!(["all"])]);
+
+    let registers = scope_reference(&mut client, 0, "Registers");
+    let response = set_variable(&mut client, &registers, "R1", "0x1234");
+    assert_eq!(response["body"]["value"], "0x00001234");
+    assert_eq!(client.target.register(1), 0x1234);
+
+    let registers = scope_reference(&mut client, 1, "Registers");
+    let response = set_variable(&mut client, &registers, "R4", "1");
+    assert_eq!(response["success"], false);
+    assert_eq!(
+        response["message"],
+        "Registers can only be changed in the top stack frame"
+    );
+}
+
+#[test]
+fn set_variable_errors() {
+    let mut client = TestClient::new();
+    client.attach();
+    common::halt_in_add_one(&client.target);
+
+    let locals = scope_reference(&mut client, 1, "Locals");
+
+    for (name, value, message) in &[
+        ("counter", "-1", "-1 is out of range for u32"),
+        (
+            "counter",
+            "0x1_0000_0000",
+            "0x1_0000_0000 is out of range for u32",
+        ),
+        ("mode", "Medium", "Unknown variable 'Medium'"),
+        ("point", "0", "Values of type Point can not be changed"),
+        ("missing", "0", "Unknown variable 'missing'"),
+    ] {
+        let response = set_variable(&mut client, &locals, name, value);
+        assert_eq!(response["success"], false, "{} = {}", name, value);
+        assert_eq!(response["message"], *message, "{} = {}", name, value);
+    }
+
+    assert_eq!(
+        client.target.read_memory(RAM_START + 0xff4, 4),
+        Some(7u32.to_le_bytes().to_vec())
+    );
+}
+
+#[test]
+fn set_expression() {
+    let mut client = TestClient::new();
+    client.attach();
+    common::halt_in_add_one(&client.target);
+    client.take_events();
+
+    for (expression, value, result, bytes) in &[
+        ("*(u16*)0x20000808", "0xbeef", "48879", &[0xef, 0xbe][..]),
+        ("*(i8*)0x20000808", "-2", "-2", &[0xfe]),
+        ("*(f32*)0x20000808", "1.5", "1.5", &1.5f32.to_le_bytes()),
+        (
+            "*(f64*)0x20000808",
+            "-0.25",
+            "-0.25",
+            &(-0.25f64).to_le_bytes(),
+        ),
+        ("*(bool*)0x20000808", "true", "true", &[1]),
+        ("*(char*)0x20000808", "'A'", "'A'", &[0x41, 0, 0, 0]),
+    ] {
+        let response = client.request(
+            "setExpression",
+            json!({ "expression": expression, "value": value }),
+        );
+        assert_eq!(response["success"], true, "{}", expression);
+        assert_eq!(response["body"]["value"], *result, "{}", expression);
+        assert_eq!(
+            client.target.read_memory(RAM_START + 0x808, bytes.len()),
+            Some(bytes.to_vec()),
+            "{}",
+            expression
+        );
+    }
+
+    assert_eq!(invalidated_areas(&mut client).len(), 6);
+
+    let response = client.request(
+        "setExpression",
+        json!({ "expression": "*(bool*)0x20000808", "value": "yes" }),
+    );
+    assert_eq!(response["message"], "'yes' is not a valid bool");
+
+    let response = client.request(
+        "setExpression",
+        json!({ "expression": "counter + 1", "value": "1" }),
+    );
+    assert_eq!(response["success"], false);
+}
+
+/// Thumb code of `add_one`, at `ADD_ONE`.
+const ADD_ONE_CODE: [u8; 14] = [
+    0x80, 0xb5, // push {r7, lr}
+    0x6f, 0x46, // mov r7, sp
+    0x00, 0xbf, // nop
+    0x01, 0x30, // adds r0, #1
+    0xd0, 0xf8, 0x00, 0x10, // ldr.w r1, [r0]
+    0x80, 0xbd, // pop {r7, pc}
+];
+
+#[test]
+fn disassemble() {
+    let mut client = TestClient::new();
+    client.attach();
+    client.target.set_breakpoint_units(0);
+
+    let mut code = vec![0; 0x100];
+    code[0x40..0x40 + ADD_ONE_CODE.len()].copy_from_slice(&ADD_ONE_CODE);
+    client.target.add_memory(MAIN, code);
+
+    // The software breakpoint is not visible in the disassembly.
+    client.request(
+        "setBreakpoints",
+        json!({
+            "source": { "path": SOURCE_PATH },
+            "breakpoints": [ { "line": 21 } ],
+        }),
+    );
+    assert_eq!(
+        client.target.read_memory(LINE_21, 2),
+        Some(vec![0x00, 0xbe])
+    );
+
+    let response = client.request(
+        "disassemble",
+        json!({
+            "memoryReference": "0x08000146",
+            "instructionOffset": -3,
+            "instructionCount": 6,
+        }),
+    );
+    assert_eq!(response["success"], true);
+
+    let instructions = response["body"]["instructions"].as_array().unwrap();
+    let listing: Vec<_> = instructions
+        .iter()
+        .map(|i| {
+            (
+                i["address"].clone(),
+                i["instruction"].clone(),
+                i["symbol"].clone(),
+                i["line"].clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        listing,
+        vec![
+            (
+                json!("0x08000140"),
+                json!("push {r7, lr}"),
+                json!("app::add_one"),
+                json!(20)
+            ),
+            (
+                json!("0x08000142"),
+                json!("mov r7, sp"),
+                json!("app::add_one+0x2"),
+                json!(20)
+            ),
+            (
+                json!("0x08000144"),
+                json!("nop"),
+                json!("app::add_one+0x4"),
+                json!(20)
+            ),
+            (
+                json!("0x08000146"),
+                json!("adds r0, #1"),
+                json!("app::add_one+0x6"),
+                json!(21)
+            ),
+            (
+                json!("0x08000148"),
+                json!("ldr.w r1, [r0]"),
+                json!("app::add_one+0x8"),
+                json!(21)
+            ),
+            (
+                json!("0x0800014c"),
+                json!("pop {r7, pc}"),
+                json!("app::add_one+0xc"),
+                json!(22)
+            ),
+        ]
+    );
+
+    assert_eq!(instructions[0]["location"]["path"], SOURCE_PATH);
+    assert_eq!(instructions[1].get("location"), None);
+    assert_eq!(instructions[4]["instructionBytes"], "d0 f8 00 10");
+}
+
+#[test]
+fn disassemble_unreadable_memory() {
+    let mut client = TestClient::new();
+    client.attach();
+
+    let response = client.request(
+        "disassemble",
+        json!({
+            "memoryReference": "0x10000000",
+            "instructionOffset": -1,
+            "instructionCount": 2,
+        }),
+    );
+
+    let instructions = response["body"]["instructions"].as_array().unwrap();
+    assert_eq!(instructions.len(), 2);
+    assert_eq!(instructions[0]["address"], "0x0ffffffe");
+    assert_eq!(instructions[1]["address"], "0x10000000");
+    assert_eq!(instructions[1]["instruction"], "<unreadable>");
+    assert_eq!(instructions[1].get("instructionBytes"), None);
+}
+
+#[test]
+fn stack_frames_have_instruction_pointer() {
+    let mut client = TestClient::new();
+    client.attach();
+    common::halt_in_add_one(&client.target);
+
+    let response = client.request("stackTrace", json!({ "threadId": 0 }));
+    let frames = &response["body"]["stackFrames"];
+    assert_eq!(frames[0]["instructionPointerReference"], "0x08000146");
+    assert_eq!(frames[1]["instructionPointerReference"], "0x08000114");
+}
+
+#[test]
+fn next() {
+    let mut client = TestClient::new();
+    client.attach();
+    common::halt_in_add_one(&client.target);
+
+    let response = client.request("next", json!({ "threadId": 0 }));
+    assert_eq!(response["success"], true);
+
+    let events = client.take_stopped_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["body"]["reason"], "step");
+
+    // Line 22 starts three instructions later.
+    assert_eq!(client.target.register(PC), LINE_21 + 6);
+}
+
+#[test]
+fn next_by_instruction() {
+    let mut client = TestClient::new();
+    client.attach();
+    common::halt_in_add_one(&client.target);
+
+    client.request(
+        "next",
+        json!({ "threadId": 0, "granularity": "instruction" }),
+    );
+
+    assert_eq!(client.take_stopped_events().len(), 1);
+    assert_eq!(client.target.register(PC), LINE_21 + 2);
+}
+
+#[test]
+fn next_steps_over_calls() {
+    let mut client = TestClient::new();
+    client.attach();
+    common::halt_in_add_one(&client.target);
+    client.target.halt_at(MAIN + 0x10, HaltReason::Request);
+    client.target.set_register(SP, RAM_START + 0xff0);
+
+    // Call of `add_one`, and the execution of the function
+    client
+        .target
+        .add_trace_step(ADD_ONE, &[(LR, MAIN + 0x14 + 1)]);
+    for pc in &[ADD_ONE + 2, LINE_21, LINE_21 + 6, MAIN + 0x14] {
+        client.target.add_trace_step(*pc, &[]);
+    }
+
+    client.request("next", json!({ "threadId": 0 }));
+
+    // The core runs to the return address.
+    assert_eq!(client.target.breakpoints(), vec![MAIN + 0x14]);
+    assert!(client.take_stopped_events().is_empty());
+
+    client.poll();
+
+    let events = client.take_stopped_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["body"]["reason"], "step");
+
+    assert_eq!(client.target.register(PC), MAIN + 0x14);
+    assert!(client.target.breakpoints().is_empty());
+}
+
+#[test]
+fn next_steps_over_recursive_calls() {
+    let mut client = TestClient::new();
+    client.attach();
+    common::halt_in_add_one(&client.target);
+    let sp = client.target.register(SP);
+
+    // `add_one` calls itself, the callee starts inside of the function of the step.
+    client
+        .target
+        .add_trace_step(ADD_ONE, &[(LR, LINE_21 + 4 + 1)]);
+    client.target.add_trace_step(ADD_ONE + 2, &[(SP, sp - 8)]);
+    for pc in &[LINE_21, LINE_21 + 6] {
+        client.target.add_trace_step(*pc, &[]);
+    }
+    client.target.add_trace_step(LINE_21 + 4, &[(SP, sp)]);
+    client.target.add_trace_step(LINE_21 + 6, &[]);
+
+    client.request("next", json!({ "threadId": 0 }));
+
+    assert_eq!(client.target.breakpoints(), vec![LINE_21 + 4]);
+    assert!(client.take_stopped_events().is_empty());
+
+    client.poll();
+
+    let events = client.take_stopped_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["body"]["reason"], "step");
+
+    // The step ends at line 22 of the caller, after the call returned.
+    assert_eq!(client.target.register(PC), LINE_21 + 6);
+    assert_eq!(client.target.register(SP), sp);
+    assert!(client.target.breakpoints().is_empty());
+}
+
+#[test]
+fn step_in() {
+    let mut client = TestClient::new();
+    client.attach();
+    client.target.halt_at(MAIN + 0x10, HaltReason::Request);
+
+    client
+        .target
+        .add_trace_step(ADD_ONE, &[(LR, MAIN + 0x14 + 1)]);
+    for pc in &[ADD_ONE + 2, LINE_21] {
+        client.target.add_trace_step(*pc, &[]);
+    }
+
+    client.request("stepIn", json!({ "threadId": 0 }));
+
+    let events = client.take_stopped_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["body"]["reason"], "step");
+
+    assert_eq!(client.target.register(PC), LINE_21);
+}
+
+#[test]
+fn step_out() {
+    let mut client = TestClient::new();
+    client.attach();
+    common::halt_in_add_one(&client.target);
+
+    client.target.set_trace(vec![LINE_21 + 6]);
+    client
+        .target
+        .add_trace_step(MAIN + 0x14, &[(SP, RAM_START + 0xff0)]);
+
+    client.request("stepOut", json!({ "threadId": 0 }));
+    client.poll();
+
+    let events = client.take_stopped_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["body"]["reason"], "step");
+
+    let response = client.request("stackTrace", json!({ "threadId": 0 }));
+    let frames = response["body"]["stackFrames"].as_array().unwrap();
+    assert_eq!(frames[0]["name"], "app::main");
+    assert_eq!(frames[0]["line"], 14);
+}
+
+#[test]
+fn unresponsive_core() {
+    let mut client = TestClient::new();
+    client.attach();
+
+    client.request("continue", json!({ "threadId": 0 }));
+    client.take_events();
+
+    client.target.set_responsive(false);
+
+    // The client is told that the core stopped, instead of the session ending.
+    assert_eq!(client.poll(), HandleResult::Continue);
+
+    let events = client.take_events();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0]["event"], "output");
+    assert!(events[0]["body"]["output"]
+        .as_str()
+        .unwrap()
+        .starts_with("Unable to check the status of the core"));
+    assert_eq!(events[1]["event"], "stopped");
+
+    for command in &["continue", "next", "stepIn", "stepOut"] {
+        let response = client.request(command, json!({ "threadId": 0 }));
+        assert_eq!(response["success"], false, "{}", command);
+    }
+
+    client.target.set_responsive(true);
+
+    let response = client.request("continue", json!({ "threadId": 0 }));
+    assert_eq!(response["success"], true);
+}
+
+#[test]
+fn software_breakpoint_in_ram() {
+    let mut client = TestClient::new();
+    client.attach();
+    client.target.set_breakpoint_units(1);
+
+    // Code which was loaded into RAM
+    let code = vec![0x11; 0x100];
+    client.target.add_memory(MAIN, code);
+
+    let response = client.request(
+        "setBreakpoints",
+        json!({
+            "source": { "path": SOURCE_PATH },
+            "breakpoints": [ { "line": 13 }, { "line": 21 } ],
+        }),
+    );
+
+    let breakpoints = &response["body"]["breakpoints"];
+    assert_eq!(breakpoints[0]["verified"], true);
+    assert_eq!(breakpoints[1]["verified"], true);
+
+    assert_eq!(client.target.breakpoints(), vec![MAIN + 0x10]);
+    assert_eq!(
+        client.target.read_memory(LINE_21, 2),
+        Some(vec![0x00, 0xbe])
+    );
+
+    client
+        .target
+        .set_trace(vec![MAIN, ADD_ONE, LINE_21, LINE_21 + 2]);
+    client.request("continue", json!({ "threadId": 0 }));
+    client.poll();
+
+    let events = client.take_stopped_events();
+    assert_eq!(events[0]["body"]["reason"], "breakpoint");
+    assert_eq!(
+        events[0]["body"]["hitBreakpointIds"],
+        json!([breakpoints[1]["id"]])
+    );
+
+    // Continuing executes the original instruction, and puts the breakpoint back afterwards.
+    client.target.set_trace(vec![LINE_21 + 2, LINE_21 + 4]);
+    client.request("continue", json!({ "threadId": 0 }));
+
+    assert_eq!(client.target.status(), CoreStatus::Running);
+    assert_eq!(client.target.register(PC), LINE_21 + 4);
+    assert_eq!(
+        client.target.read_memory(LINE_21, 2),
+        Some(vec![0x00, 0xbe])
+    );
+
+    client.request(
+        "setBreakpoints",
+        json!({
+            "source": { "path": SOURCE_PATH },
+            "breakpoints": [],
+        }),
+    );
+
+    assert!(client.target.breakpoints().is_empty());
+    assert_eq!(
+        client.target.read_memory(LINE_21, 2),
+        Some(vec![0x11, 0x11])
+    );
+}
+
+#[test]
+fn breakpoint_without_free_unit_in_flash() {
+    let mut client = TestClient::new();
+    client.attach();
+    client.target.set_breakpoint_units(1);
+    client.target.add_read_only_memory(MAIN, vec![0x11; 0x100]);
+
+    let response = client.request(
+        "setBreakpoints",
+        json!({
+            "source": { "path": SOURCE_PATH },
+            "breakpoints": [ { "line": 13 }, { "line": 21 } ],
+        }),
+    );
+
+    let breakpoints = &response["body"]["breakpoints"];
+    assert_eq!(breakpoints[0]["verified"], true);
+    assert_eq!(breakpoints[1]["verified"], false);
+
+    let message = breakpoints[1]["message"].as_str().unwrap();
+    assert!(message.contains("hardware breakpoint units are in use"));
+    assert!(message.contains("not in RAM"));
+
+    assert_eq!(
+        client.target.read_memory(LINE_21, 2),
+        Some(vec![0x11, 0x11])
+    );
+}
+
+/// Buffers of the RTT up channels 0 and 1 in the test program.
+const RTT_UP_BUFFERS: [(u32, u32); 2] = [(RAM_START + 0x500, 64), (RAM_START + 0x540, 16)];
+
+/// Buffer of the RTT down channel 0 in the test program.
+const RTT_DOWN_BUFFER: (u32, u32) = (RAM_START + 0x580, 8);
+
+/// Write an RTT control block with the up channels `RTT_UP_BUFFERS` and the down
+/// channel `RTT_DOWN_BUFFER` to `address`.
+fn write_rtt_control_block(client: &TestClient, address: u32) {
+    let mut block = b"SEGGER RTT\0\0\0\0\0\0".to_vec();
+    block.extend_from_slice(&(RTT_UP_BUFFERS.len() as u32).to_le_bytes());
+    block.extend_from_slice(&1u32.to_le_bytes());
+
+    for (buffer, size) in RTT_UP_BUFFERS.iter().chain(Some(&RTT_DOWN_BUFFER)) {
+        for value in &[0, *buffer, *size, 0, 0, 0] {
+            block.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    client.target.write_memory(address, &block);
+}
+
+/// Address of the write or read offset of the up channel `channel`.
+fn rtt_up_offset(channel: u32, read: bool) -> u32 {
+    common::RTT_CONTROL_BLOCK + 24 + 24 * channel + if read { 16 } else { 12 }
+}
+
+/// The output events sent since the last call, as category and text.
+fn take_output(client: &mut TestClient) -> Vec<(Value, Value)> {
+    client
+        .take_events()
+        .into_iter()
+        .filter(|event| event["event"] == "output")
+        .map(|event| {
+            (
+                event["body"]["category"].clone(),
+                event["body"]["output"].clone(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn rtt_output() {
+    let mut client = TestClient::new();
+    client.attach_with(json!({
+        "rtt": {
+            "channels": [
+                { "channel": 0 },
+                { "channel": 1, "category": "log" },
+            ],
+        },
+    }));
+    write_rtt_control_block(&client, common::RTT_CONTROL_BLOCK);
+    client.take_events();
+
+    let (buffer, _) = RTT_UP_BUFFERS[0];
+    client.target.add_trace_write(MAIN, buffer, b"Hello\n");
+    client
+        .target
+        .add_trace_write(MAIN + 2, rtt_up_offset(0, false), &6u32.to_le_bytes());
+
+    client.request("continue", json!({ "threadId": 0 }));
+    client.poll();
+
+    assert_eq!(
+        take_output(&mut client),
+        vec![(json!("stdout"), json!("Hello\n"))]
+    );
+    assert_eq!(
+        client.target.read_memory(rtt_up_offset(0, true), 4),
+        Some(6u32.to_le_bytes().to_vec())
+    );
+
+    // Nothing new was written.
+    client.poll();
+    assert!(take_output(&mut client).is_empty());
+
+    // Data which wraps around the end of the buffer, written while the core runs.
+    let (buffer, size) = RTT_UP_BUFFERS[1];
+    client.target.write_memory(buffer + size - 3, b"wra");
+    client.target.write_memory(buffer, b"p\n");
+    client
+        .target
+        .write_memory(rtt_up_offset(1, true), &(size - 3).to_le_bytes());
+    client
+        .target
+        .write_memory(rtt_up_offset(1, false), &2u32.to_le_bytes());
+
+    client.poll();
+    assert_eq!(
+        take_output(&mut client),
+        vec![(json!("log"), json!("wrap\n"))]
+    );
+}
+
+#[test]
+fn rtt_control_block_found_by_scan() {
+    let mut client = TestClient::new();
+    client.attach_with(json!({
+        "rtt": { "scan_range": { "start": "0x2000_0000", "end": RAM_START + 0x1000 } },
+    }));
+    client.request("continue", json!({ "threadId": 0 }));
+    client.take_events();
+
+    // The firmware has not initialized the control block yet.
+    client.poll();
+    assert!(take_output(&mut client).is_empty());
+
+    // The ID crosses the boundary between two reads of the scan.
+    let control_block = RAM_START + 0x3f8;
+    write_rtt_control_block(&client, control_block);
+
+    let (buffer, _) = RTT_UP_BUFFERS[1];
+    client.target.write_memory(buffer, b"up\n");
+    client
+        .target
+        .write_memory(control_block + 24 + 24 + 12, &3u32.to_le_bytes());
+
+    // Data written right before the core halts is still shown.
+    client.target.halt_at(MAIN, HaltReason::Request);
+    client.poll();
+
+    assert_eq!(
+        take_output(&mut client),
+        vec![(json!("stdout"), json!("up\n"))]
+    );
+}
+
+#[test]
+fn rtt_input() {
+    let mut client = TestClient::new();
+    client.attach_with(json!({ "rtt": { "down_channel": 0 } }));
+    write_rtt_control_block(&client, common::RTT_CONTROL_BLOCK);
+    client.request("continue", json!({ "threadId": 0 }));
+
+    let descriptor = common::RTT_CONTROL_BLOCK + 24 + 2 * 24;
+    let (buffer, _) = RTT_DOWN_BUFFER;
+
+    // The debug console writes to the down channel while the core is running.
+    let response = client.request(
+        "evaluate",
+        json!({ "expression": "help", "context": "repl" }),
+    );
+    assert_eq!(response["success"], true);
+    assert_eq!(
+        client.target.read_memory(buffer, 5),
+        Some(b"help\n".to_vec())
+    );
+    assert_eq!(
+        client.target.read_memory(descriptor + 12, 4),
+        Some(5u32.to_le_bytes().to_vec())
+    );
+
+    // Only two bytes are free, one byte of the buffer always stays empty.
+    let response = client.request("probe-rs/rttWrite", json!({ "data": "0123456" }));
+    assert_eq!(response["success"], true);
+    assert_eq!(
+        response["body"],
+        json!({ "bytesWritten": 2, "bytesPending": 5 })
+    );
+    assert_eq!(
+        client.target.read_memory(buffer + 5, 2),
+        Some(b"01".to_vec())
+    );
+
+    // The rest is written once the target has read the buffer.
+    client
+        .target
+        .write_memory(descriptor + 16, &7u32.to_le_bytes());
+    client.poll();
+
+    assert_eq!(
+        client.target.read_memory(buffer + 7, 1),
+        Some(b"2".to_vec())
+    );
+    assert_eq!(client.target.read_memory(buffer, 4), Some(b"3456".to_vec()));
+    assert_eq!(
+        client.target.read_memory(descriptor + 12, 4),
+        Some(4u32.to_le_bytes().to_vec())
+    );
+
+    let response = client.request("probe-rs/rttWrite", json!({ "channel": 3, "data": "x" }));
+    assert_eq!(response["success"], false);
+    assert_eq!(response["message"], "The target has no RTT down channel 3");
+}
+
+#[test]
+fn rtt_defmt_output() {
+    let mut client = TestClient::new();
+    client.attach_with(json!({
+        "rtt": { "channels": [{ "channel": 0, "category": "log", "format": "defmt" }] },
+    }));
+    write_rtt_control_block(&client, common::RTT_CONTROL_BLOCK);
+    client.request("continue", json!({ "threadId": 0 }));
+    client.take_events();
+
+    // Two frames, with a timestamp in milliseconds.
+    let mut frames = vec![2, 0, 0xd2, 0x04, 0, 0, 0xfb, 0xff, 3];
+    frames.extend_from_slice(b"out");
+    frames.extend_from_slice(&[3, 0, 0xdc, 0x05, 0, 0, 1, 3]);
+
+    // Only the start of the second frame was written yet.
+    let (buffer, _) = RTT_UP_BUFFERS[0];
+    client.target.write_memory(buffer, &frames);
+    client
+        .target
+        .write_memory(rtt_up_offset(0, false), &15u32.to_le_bytes());
+
+    client.poll();
+    let events = client.take_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["event"], "output");
+    let body = &events[0]["body"];
+    assert_eq!(body["category"], "log");
+    assert_eq!(
+        body["output"],
+        "1.234 INFO  temperature = -5, sensor = out\n"
+    );
+    assert_eq!(body["source"]["path"], common::SOURCE_PATH);
+    assert_eq!(body["line"], 12);
+
+    client.target.write_memory(
+        rtt_up_offset(0, false),
+        &(frames.len() as u32).to_le_bytes(),
+    );
+    client.poll();
+    assert_eq!(
+        take_output(&mut client),
+        vec![(json!("log"), json!("1.500 WARN  retry 1 of 3\n"))]
+    );
+
+    // Data which isn't a frame of the program.
+    client.target.write_memory(buffer + 20, &[0x10, 0x27]);
+    client
+        .target
+        .write_memory(rtt_up_offset(0, false), &22u32.to_le_bytes());
+    client.poll();
+    assert_eq!(
+        take_output(&mut client),
+        vec![(
+            json!("stderr"),
+            json!("Invalid defmt data on RTT channel 0: Unknown format string index 10000\n")
+        )]
+    );
+}
+
+/// Halt the core at a `BKPT 0xAB` instruction at `address`, for the semihosting
+/// `operation` with `parameter`, and let the debugger handle it.
+fn semihosting_call(client: &mut TestClient, address: u32, operation: u32, parameter: u32) {
+    client.target.write_memory(address, &[0xab, 0xbe]);
+    client.target.set_register(0, operation);
+    client.target.set_register(1, parameter);
+    client.target.halt_at(address, HaltReason::Breakpoint);
+    client.poll();
+}
+
+#[test]
+fn semihosting() {
+    let mut client = TestClient::new();
+    client.target.add_memory(MAIN, vec![0; 0x40]);
+    client.attach();
+
+    // SYS_WRITE0, executed by the running program.
+    client.target.write_memory(RAM_START + 0x700, b"Hello\n\0");
+    client.target.write_memory(MAIN + 0x10, &[0xab, 0xbe]);
+    client
+        .target
+        .add_trace_step(MAIN + 0x10, &[(0, 0x04), (1, RAM_START + 0x700)]);
+    client.request("continue", json!({ "threadId": 0 }));
+    client.take_events();
+
+    client.poll();
+    assert_eq!(
+        take_output(&mut client),
+        vec![(json!("stdout"), json!("Hello\n"))]
+    );
+    assert_eq!(client.target.status(), CoreStatus::Running);
+    assert_eq!(client.target.register(PC), MAIN + 0x12);
+
+    // SYS_OPEN of the console for appending, which is stderr.
+    let block = RAM_START + 0x740;
+    client.target.write_memory(RAM_START + 0x720, b":tt");
+    for (i, value) in [RAM_START + 0x720, 8, 3].iter().enumerate() {
+        client
+            .target
+            .write_memory(block + 4 * i as u32, &value.to_le_bytes());
+    }
+    semihosting_call(&mut client, MAIN + 0x20, 0x01, block);
+    assert_eq!(client.target.register(0), 3);
+
+    // SYS_WRITE to the handle.
+    client.target.write_memory(RAM_START + 0x720, b"oops\n");
+    for (i, value) in [3, RAM_START + 0x720, 5].iter().enumerate() {
+        client
+            .target
+            .write_memory(block + 4 * i as u32, &value.to_le_bytes());
+    }
+    semihosting_call(&mut client, MAIN + 0x20, 0x05, block);
+    assert_eq!(client.target.register(0), 0);
+    assert_eq!(
+        take_output(&mut client),
+        vec![(json!("stderr"), json!("oops\n"))]
+    );
+
+    // Too much data for a single operation is written partially.
+    for (i, value) in [3, RAM_START, 0x10000].iter().enumerate() {
+        client
+            .target
+            .write_memory(block + 4 * i as u32, &value.to_le_bytes());
+    }
+    semihosting_call(&mut client, MAIN + 0x20, 0x05, block);
+    assert_eq!(client.target.register(0), 0xf000);
+    assert_eq!(
+        take_output(&mut client)[0].1.as_str().unwrap().len(),
+        0x1000
+    );
+
+    // SYS_CLOCK in centiseconds, and SYS_TIME in seconds since 1970.
+    semihosting_call(&mut client, MAIN + 0x20, 0x10, 0);
+    assert!(client.target.register(0) < 100);
+    semihosting_call(&mut client, MAIN + 0x20, 0x11, 0);
+    assert!(client.target.register(0) > 1_600_000_000);
+    assert_eq!(client.target.status(), CoreStatus::Running);
+
+    // SYS_EXIT_EXTENDED with the exit code 3.
+    for (i, value) in [0x2_0026u32, 3].iter().enumerate() {
+        client
+            .target
+            .write_memory(block + 4 * i as u32, &value.to_le_bytes());
+    }
+    semihosting_call(&mut client, MAIN + 0x30, 0x20, block);
+
+    let events = client.take_events();
+    let names: Vec<_> = events.iter().map(|event| &event["event"]).collect();
+    assert_eq!(names, vec!["output", "exited", "terminated"]);
+    assert_eq!(events[0]["body"]["output"], "Program exited with code 3\n");
+    assert_eq!(events[1]["body"]["exitCode"], 3);
+    assert!(client.target.status().is_halted());
+
+    // The message loop stops polling, and the session ends without a second `terminated`.
+    assert_eq!(client.poll(), HandleResult::Stop);
+    client.terminate();
+    assert!(client.take_events().is_empty());
+}
+
+#[test]
+fn semihosting_while_stepping() {
+    let mut client = TestClient::new();
+    client.target.add_memory(ADD_ONE, vec![0; 0x40]);
+    client.attach();
+    common::halt_in_add_one(&client.target);
+    client.take_events();
+
+    // SYS_WRITE0 in the middle of line 21.
+    client.target.write_memory(RAM_START + 0x700, b"step\n\0");
+    client.target.write_memory(LINE_21 + 2, &[0xab, 0xbe]);
+    client.target.set_register(0, 0x04);
+    client.target.set_register(1, RAM_START + 0x700);
+
+    client.request("next", json!({ "threadId": 0 }));
+
+    assert_eq!(
+        take_output(&mut client),
+        vec![(json!("stdout"), json!("step\n"))]
+    );
+    assert_eq!(client.target.register(PC), LINE_21 + 6);
+
+    // Stepping by instruction performs the operation, instead of halting at it again.
+    client.target.halt_at(LINE_21 + 2, HaltReason::Request);
+    client.request(
+        "next",
+        json!({ "threadId": 0, "granularity": "instruction" }),
+    );
+
+    let events = client.take_events();
+    assert_eq!(events[0]["body"]["output"], "step\n");
+    assert_eq!(events[1]["event"], "stopped");
+    assert_eq!(client.target.register(PC), LINE_21 + 4);
+}
+
+#[test]
+fn swo_output() {
+    let mut client = TestClient::new();
+    client.attach_with(json!({
+        "swo": {
+            "baud_rate": 2_000_000,
+            "prescaler": 1,
+            "stimulus_ports": [ { "port": 0 }, { "port": 2, "category": "log" } ],
+        },
+    }));
+    assert_eq!(client.target.swo_config(), Some((2_000_000, 4_000_000)));
+    assert_eq!(
+        client.target.read_memory(0xe000_0e00, 4),
+        Some(0b101u32.to_le_bytes().to_vec())
+    );
+
+    client.request("continue", json!({ "threadId": 0 }));
+    client.take_events();
+
+    // Text on the ports 0 to 2, and the entry into SysTick.
+    client.target.add_swo_data(&[0x03, b'p', b'o', b'r', b't']);
+    client
+        .target
+        .add_swo_data(&[0x09, b'1', 0x11, b'2', 0x01, b'\n']);
+    client.target.add_swo_data(&[0x0e, 0x0f, 0x10]);
+    client.poll();
+
+    assert_eq!(
+        take_output(&mut client),
+        vec![
+            (json!("stdout"), json!("port")),
+            (json!("log"), json!("2")),
+            (json!("stdout"), json!("\n")),
+            (json!("trace"), json!("Exception entered SysTick\n")),
+        ]
+    );
+}
+
+#[test]
+fn swo_with_invalid_stimulus_port() {
+    let mut client = TestClient::new();
+    client.attach_with(
+        json!({ "swo": { "baud_rate": 1_000_000, "stimulus_ports": [ { "port": 40 } ] } }),
+    );
+
+    assert_eq!(client.target.swo_config(), None);
+    assert_eq!(
+        take_output(&mut client)[0],
+        (
+            json!("console"),
+            json!("SWO is not available: There is no stimulus port 40\n")
+        )
+    );
+}